@@ -2,18 +2,35 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::path::PathBuf;
+use std::{
+    mem,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, Result};
+use crossterm::event::KeyCode;
 use serde::{Deserialize, Serialize};
 
-use crate::ui::ScreenEnum;
+use crate::{
+    config::{ConfOption, THEMES},
+    library::track::{SortDirection, SortKey, MAX_RATING},
+    media_system::Repeat,
+    search::MatchMode,
+    smart_playlist::SmartPlaylist,
+    station::Station,
+    ui::ScreenEnum,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum Command {
     Quit,
     Down,
     Up,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    PageUp,
     NextPanel,
     PrevPanel,
     Play,
@@ -21,33 +38,230 @@ pub enum Command {
     Stop,
     TogglePlay,
     ToggleShuffle,
+    Reshuffle,
     ToggleRepeat,
+    SetRepeat(Repeat),
     QueueAndPlay,
+    /// Queue the selection to play straight after the current track
+    PlayNext,
     GotoTop,
     GotoBottom,
+    /// Select the item at an index in the current list
+    GotoIndex(usize),
     GotoScreen(ScreenEnum),
     NewPlaylist(Option<String>),
+    /// Create or redefine a smart playlist
+    NewSmartPlaylist(SmartPlaylist),
+    /// Save a radio station, replacing any with the same name
+    AddStation(Station),
+    RemoveStation(String),
+    SaveQueue(Option<String>),
     PlaylistAdd,
     SelectPlaylist,
+    /// Remove all tracks from the highlighted playlist
+    ClearPlaylist,
+    /// Copy the highlighted playlist
+    DuplicatePlaylist,
     PrevTrack,
     NextTrack,
     EnterCommand,
     AddPath(PathBuf),
+    RemovePath(PathBuf),
+    /// Bring the library up to date with files that changed on disk
+    RescanPaths(Vec<PathBuf>),
     PlayTrack(PathBuf),
+    /// Insert the track at the path into the queue at the given index, and
+    /// play it straight away if true
+    QueueTrack(PathBuf, usize, bool),
+    /// Take the track at the given index out of the queue
+    RemoveQueued(usize),
+    /// Take the track at the given index out of the queue and play it
+    PlayQueued(usize),
+    PlayFolder(PathBuf, bool),
+    SortLibrary(SortKey),
+    /// Sort the main screen's track panel, toggling the direction if none is
+    /// given
+    SortBy(SortKey, Option<SortDirection>),
+    /// Restrict the main screen to a genre, or clear the restriction
+    FilterGenre(Option<String>),
+    /// Restrict the main screen to tracks rated at least this many stars, or
+    /// clear the restriction
+    FilterRating(Option<u8>),
+    /// Clear all restrictions on the main screen
+    ClearFilters,
+    /// Rate the selected track, with zero clearing its rating
+    Rate(u8),
+    /// Give the selected track the highest rating, or clear it if it already
+    /// has it
+    ToggleFavorite,
+    ExportPlaylist(String, PathBuf),
+    Search,
+    QueueAllVisible,
+    StopAfter(Option<u32>),
+    /// Stop playback and clear the queue after a while, or cancel the sleep
+    /// timer
+    SleepTimer(Option<Duration>),
+    Rescan,
+    /// Show the most played tracks
+    Stats,
+    Expand,
+    Collapse,
+    SetLoopA,
+    SetLoopB,
+    GotoTime,
+    SeekForward,
+    SeekBackward,
+    VolumeUp,
+    VolumeDown,
+    /// Set the volume to a percentage
+    SetVolume(u8),
+    ToggleMute,
+    SpeedUp,
+    SpeedDown,
+    /// Return to normal playback speed
+    SpeedReset,
+    /// Set the playback speed to a percentage of normal speed
+    SetSpeed(u16),
+    /// Switch the playback bar between the time elapsed and remaining
+    ToggleRemaining,
+    /// Show the log of import and playback errors
+    ShowErrors,
+    /// Switch to a built-in color theme
+    SetTheme(String),
+    SetMatchMode(Option<MatchMode>),
+    ReloadConfig,
+    /// Turn an option on or off, saving the config file as well if true
+    SetOption(ConfOption, bool, bool),
+    /// Switch between Nerd Font icons and plain characters
+    ToggleIcons,
+    /// Show the playing track's lyrics, or go back to the screen before
+    ToggleLyrics,
     Nop,
 }
 
 impl Command {
+    /// How the command is described in the help screen, or `None` for
+    /// commands that are only run internally or need arguments typed in the
+    /// command line
+    pub const fn label(&self) -> Option<&'static str> {
+        Some(match self {
+            Self::Quit => "Quit",
+            Self::Down => "Down",
+            Self::Up => "Up",
+            Self::HalfPageDown => "Half page down",
+            Self::HalfPageUp => "Half page up",
+            Self::PageDown => "Page down",
+            Self::PageUp => "Page up",
+            Self::NextPanel => "Next panel",
+            Self::PrevPanel => "Previous panel",
+            Self::Play => "Play",
+            Self::Pause => "Pause",
+            Self::Stop => "Stop",
+            Self::TogglePlay => "Play/Pause",
+            Self::ToggleShuffle => "Shuffle",
+            Self::Reshuffle => "Reshuffle",
+            Self::ToggleRepeat => "Repeat",
+            Self::SetRepeat(Repeat::Off) => "Repeat off",
+            Self::SetRepeat(Repeat::On) => "Repeat all",
+            Self::SetRepeat(Repeat::One) => "Repeat one",
+            Self::QueueAndPlay => "Enqueue",
+            Self::PlayNext => "Play next",
+            Self::GotoTop => "Goto top",
+            Self::GotoBottom => "Goto bottom",
+            Self::GotoScreen(ScreenEnum::Main) => "Main screen",
+            Self::GotoScreen(ScreenEnum::Playlists) => "Playlist screen",
+            Self::GotoScreen(ScreenEnum::Help) => "Help screen",
+            Self::GotoScreen(ScreenEnum::Tree) => "Library tree",
+            Self::GotoScreen(ScreenEnum::History) => "Recently played",
+            Self::GotoScreen(ScreenEnum::Stations) => "Radio stations",
+            Self::GotoScreen(ScreenEnum::Errors) | Self::ShowErrors => "Error log",
+            Self::GotoScreen(ScreenEnum::Config) => "Current config",
+            Self::GotoScreen(ScreenEnum::Lyrics) | Self::ToggleLyrics => "Lyrics",
+            Self::NewPlaylist(_) => "New playlist",
+            Self::SaveQueue(_) => "Save queue",
+            Self::PlaylistAdd => "Add to playlist",
+            Self::SelectPlaylist => "Select playlist",
+            Self::ClearPlaylist => "Clear playlist",
+            Self::DuplicatePlaylist => "Copy playlist",
+            Self::PrevTrack => "Previous track",
+            Self::NextTrack => "Next track",
+            Self::EnterCommand => "Command line",
+            Self::SortLibrary(_) => "Sort library",
+            Self::SortBy(..) => "Sort tracks",
+            Self::FilterGenre(_) => "Filter by genre",
+            Self::FilterRating(_) => "Filter by rating",
+            Self::ClearFilters => "Clear filters",
+            Self::Rate(_) => "Rate track",
+            Self::ToggleFavorite => "Toggle favorite",
+            Self::Search => "Search",
+            Self::QueueAllVisible => "Enqueue all shown",
+            Self::StopAfter(_) => "Stop after",
+            Self::SleepTimer(_) => "Sleep timer",
+            Self::Rescan => "Rescan library",
+            Self::Stats => "Most played",
+            Self::Expand => "Expand",
+            Self::Collapse => "Collapse",
+            Self::SetLoopA => "Loop start",
+            Self::SetLoopB => "Loop end",
+            Self::GotoTime => "Go to time",
+            Self::SeekForward => "Seek forward",
+            Self::SeekBackward => "Seek back",
+            Self::VolumeUp => "Volume up",
+            Self::VolumeDown => "Volume down",
+            Self::SetVolume(_) => "Set volume",
+            Self::ToggleMute => "Mute",
+            Self::SpeedUp => "Speed up",
+            Self::SpeedDown => "Speed down",
+            Self::SpeedReset => "Normal speed",
+            Self::SetSpeed(_) => "Set speed",
+            Self::ToggleRemaining => "Time remaining",
+            Self::SetTheme(_) => "Set theme",
+            Self::SetMatchMode(_) => "Search match mode",
+            Self::ReloadConfig => "Reload config",
+            Self::ToggleIcons => "Nerd Font icons",
+            Self::GotoIndex(_)
+            | Self::NewSmartPlaylist(_)
+            | Self::AddStation(_)
+            | Self::RemoveStation(_)
+            | Self::AddPath(_)
+            | Self::RemovePath(_)
+            | Self::RescanPaths(_)
+            | Self::PlayTrack(_)
+            | Self::QueueTrack(..)
+            | Self::RemoveQueued(_)
+            | Self::PlayQueued(_)
+            | Self::PlayFolder(..)
+            | Self::ExportPlaylist(..)
+            | Self::SetOption(..)
+            | Self::Nop => return None,
+        })
+    }
+
     pub fn parse(command: &str) -> Result<Self> {
         let mut tokens = command.split_whitespace();
         match tokens.next() {
             Some("q" | "quit" | "exit") => Ok(Self::Quit),
             Some("s" | "shuf" | "shuffle") => Ok(Self::ToggleShuffle),
-            Some("r" | "rep" | "repeat") => Ok(Self::ToggleRepeat),
+            Some("reshuffle") => Ok(Self::Reshuffle),
+            Some("stats") => Ok(Self::Stats),
+            Some("r" | "rep" | "repeat") => match tokens.next() {
+                None => Ok(Self::ToggleRepeat),
+                Some("off") => Ok(Self::SetRepeat(Repeat::Off)),
+                Some("on" | "all") => Ok(Self::SetRepeat(Repeat::On)),
+                Some("one" | "track") => Ok(Self::SetRepeat(Repeat::One)),
+                Some(mode) => Err(anyhow!(
+                    "repeat: Invalid mode: {mode} (expected off, on, or one)"
+                )),
+            },
             Some("screen") => match tokens.next() {
                 Some("1" | "main") => Ok(Self::GotoScreen(ScreenEnum::Main)),
                 Some("2" | "playlist" | "playlists") => Ok(Self::GotoScreen(ScreenEnum::Playlists)),
                 Some("0" | "help") => Ok(Self::GotoScreen(ScreenEnum::Help)),
+                Some("3" | "tree") => Ok(Self::GotoScreen(ScreenEnum::Tree)),
+                Some("4" | "history") => Ok(Self::GotoScreen(ScreenEnum::History)),
+                Some("5" | "stations") => Ok(Self::GotoScreen(ScreenEnum::Stations)),
+                Some("config") => Ok(Self::GotoScreen(ScreenEnum::Config)),
+                Some("lyrics") => Ok(Self::GotoScreen(ScreenEnum::Lyrics)),
                 Some(other) => Err(anyhow!("screen: Invalid screen identifier: {}", other)),
                 None => Err(anyhow!("screen: Missing argument SCREEN_ID")),
             },
@@ -56,16 +270,433 @@ impl Command {
                 Some((_, p)) => Ok(Self::AddPath(p.into())),
                 None => Err(anyhow!("add: Missing argument PATH")),
             },
+            Some("remove" | "rm") => match command.split_once(' ') {
+                Some((_, p)) => Ok(Self::RemovePath(p.into())),
+                None => Err(anyhow!("remove: Missing argument PATH")),
+            },
             Some("new-playlist" | "n") => match command.split_once(' ') {
                 Some((_, name)) => Ok(Self::NewPlaylist(Some(name.into()))),
                 None => Ok(Self::NewPlaylist(None)),
             },
+            Some("smart-playlist" | "smart") => match command.split_once(' ') {
+                Some((_, args)) => match args.split_once(':') {
+                    Some((name, query)) if !name.trim().is_empty() => {
+                        Ok(Self::NewSmartPlaylist(SmartPlaylist {
+                            name: name.trim().to_owned(),
+                            query: query.parse().map_err(|e| anyhow!("smart-playlist: {e}"))?,
+                        }))
+                    }
+                    _ => Err(anyhow!("smart-playlist: Expected NAME: QUERY")),
+                },
+                None => Err(anyhow!("smart-playlist: Missing arguments NAME: QUERY")),
+            },
+            Some("station") => match command.splitn(4, ' ').collect::<Vec<_>>()[..] {
+                [_, "add", url, name] if !name.trim().is_empty() => Ok(Self::AddStation(Station {
+                    name: name.trim().to_owned(),
+                    url: url.to_owned(),
+                })),
+                [_, "add", ..] => Err(anyhow!("station: Expected add URL NAME")),
+                [_, "remove" | "rm", ..] => match command.splitn(3, ' ').nth(2) {
+                    Some(name) if !name.trim().is_empty() => {
+                        Ok(Self::RemoveStation(name.trim().to_owned()))
+                    }
+                    _ => Err(anyhow!("station: Missing argument NAME")),
+                },
+                [_, other, ..] => Err(anyhow!("station: Invalid subcommand: {}", other)),
+                _ => Err(anyhow!("station: Missing subcommand")),
+            },
+            Some("save-queue" | "sq") => match command.split_once(' ') {
+                Some((_, name)) => Ok(Self::SaveQueue(Some(name.into()))),
+                None => Ok(Self::SaveQueue(None)),
+            },
+            Some("clear-playlist") => Ok(Self::ClearPlaylist),
+            Some("duplicate-playlist" | "dup") => Ok(Self::DuplicatePlaylist),
+            Some("play-folder" | "pf") => match command.split_once(' ') {
+                Some((_, args)) => match args.strip_prefix("-r ") {
+                    Some(dir) => Ok(Self::PlayFolder(dir.into(), true)),
+                    None => Ok(Self::PlayFolder(args.into(), false)),
+                },
+                None => Err(anyhow!("play-folder: Missing argument DIR")),
+            },
             Some("play" | "p") => match command.split_once(' ') {
                 Some((_, path)) => Ok(Self::PlayTrack(path.into())),
                 None => Err(anyhow!("play: Missing argument PATH")),
             },
+            Some("play-next") => Ok(Self::PlayNext),
+            Some("search" | "/") => Ok(Self::Search),
+            Some("rescan") => Ok(Self::Rescan),
+            Some("loop-a") => Ok(Self::SetLoopA),
+            Some("loop-b") => Ok(Self::SetLoopB),
+            Some("goto-time" | "time") => Ok(Self::GotoTime),
+            Some("seek-forward" | "ff") => Ok(Self::SeekForward),
+            Some("seek-backward" | "rew") => Ok(Self::SeekBackward),
+            Some("volume-up" | "vol+") => Ok(Self::VolumeUp),
+            Some("volume-down" | "vol-") => Ok(Self::VolumeDown),
+            Some("rate") => match tokens.next() {
+                Some(rating) => match rating.parse() {
+                    Ok(rating) if rating <= MAX_RATING => Ok(Self::Rate(rating)),
+                    _ => Err(anyhow!(
+                        "rate: Invalid rating: {rating} (expected 0-{MAX_RATING})"
+                    )),
+                },
+                None => Err(anyhow!("rate: Missing argument RATING")),
+            },
+            Some("favorite" | "fav") => Ok(Self::ToggleFavorite),
+            Some("volume" | "vol") => match tokens.next() {
+                Some(percent) => match percent.parse() {
+                    Ok(percent) if percent <= 100 => Ok(Self::SetVolume(percent)),
+                    _ => Err(anyhow!(
+                        "volume: Invalid volume: {percent} (expected 0-100)"
+                    )),
+                },
+                None => Err(anyhow!("volume: Missing argument PERCENT")),
+            },
+            Some("mute") => Ok(Self::ToggleMute),
+            Some("speed-up") => Ok(Self::SpeedUp),
+            Some("speed-down") => Ok(Self::SpeedDown),
+            Some("speed-reset") => Ok(Self::SpeedReset),
+            Some("speed") => match tokens.next() {
+                Some(percent) => match percent.parse() {
+                    Ok(percent) if (50..=200).contains(&percent) => Ok(Self::SetSpeed(percent)),
+                    _ => Err(anyhow!("speed: Invalid speed: {percent} (expected 50-200)")),
+                },
+                None => Err(anyhow!("speed: Missing argument PERCENT")),
+            },
+            Some("remaining") => Ok(Self::ToggleRemaining),
+            Some("errors") => Ok(Self::ShowErrors),
+            Some("theme") => match tokens.next() {
+                Some(name) => Ok(Self::SetTheme(name.to_owned())),
+                None => Err(anyhow!(
+                    "theme: Missing argument NAME (one of {})",
+                    THEMES.join(", ")
+                )),
+            },
+            Some("reload-config" | "reload") => Ok(Self::ReloadConfig),
+            Some("config") => Ok(Self::GotoScreen(ScreenEnum::Config)),
+            Some("icons") => Ok(Self::ToggleIcons),
+            Some("lyrics") => Ok(Self::ToggleLyrics),
+            Some("set") => {
+                let mut name = tokens.next();
+                let save = name == Some("-s");
+                if save {
+                    name = tokens.next();
+                }
+                match (name, tokens.next()) {
+                    (Some(name), Some(value)) => {
+                        let option = name.parse().map_err(|e| anyhow!("set: {e}"))?;
+                        let value = match value {
+                            "true" | "on" | "yes" => true,
+                            "false" | "off" | "no" => false,
+                            _ => {
+                                return Err(anyhow!(
+                                    "set: Invalid value: {value} (expected true or false)"
+                                ))
+                            }
+                        };
+                        Ok(Self::SetOption(option, value, save))
+                    }
+                    (Some(_), None) => Err(anyhow!("set: Missing argument VALUE")),
+                    (None, _) => Err(anyhow!("set: Missing argument OPTION")),
+                }
+            }
+            Some("match-mode" | "match") => match tokens.next() {
+                Some(mode) => Ok(Self::SetMatchMode(Some(mode.parse()?))),
+                None => Ok(Self::SetMatchMode(None)),
+            },
+            Some("stop-after") => match tokens.next() {
+                Some("off") => Ok(Self::StopAfter(None)),
+                Some(count) => match count.parse() {
+                    Ok(count) if count > 0 => Ok(Self::StopAfter(Some(count))),
+                    _ => Err(anyhow!("stop-after: Invalid track count: {}", count)),
+                },
+                None => Err(anyhow!("stop-after: Missing argument COUNT")),
+            },
+            Some("sleep") => match tokens.next() {
+                Some("off") => Ok(Self::SleepTimer(None)),
+                Some(time) => match parse_sleep_time(time) {
+                    Some(duration) => Ok(Self::SleepTimer(Some(duration))),
+                    None => Err(anyhow!(
+                        "sleep: Invalid time: {time} (expected minutes, or a number ending in s, m, or h)"
+                    )),
+                },
+                None => Err(anyhow!("sleep: Missing argument TIME")),
+            },
+            Some("export") => match command.splitn(3, ' ').collect::<Vec<_>>()[..] {
+                [_, name, path] => Ok(Self::ExportPlaylist(name.into(), path.into())),
+                [_, _] => Err(anyhow!("export: Missing argument PATH")),
+                _ => Err(anyhow!("export: Missing argument PLAYLIST")),
+            },
+            Some("filter") => match command.splitn(3, ' ').collect::<Vec<_>>()[..] {
+                [_, "genre", genre] => Ok(Self::FilterGenre(Some(genre.into()))),
+                [_, "genre"] => Err(anyhow!("filter: Missing argument GENRE")),
+                [_, "rating", rating] => {
+                    match rating.strip_prefix(">=").unwrap_or(rating).parse() {
+                        Ok(rating) if (1..=MAX_RATING).contains(&rating) => {
+                            Ok(Self::FilterRating(Some(rating)))
+                        }
+                        _ => Err(anyhow!(
+                            "filter: Invalid rating: {rating} (expected 1-{MAX_RATING})"
+                        )),
+                    }
+                }
+                [_, "rating"] => Err(anyhow!("filter: Missing argument RATING")),
+                [_, "clear"] => Ok(Self::ClearFilters),
+                [_, other, ..] => Err(anyhow!("filter: Invalid filter: {}", other)),
+                _ => Err(anyhow!("filter: Missing argument FILTER")),
+            },
+            Some("sort") => match tokens.next() {
+                Some("library") => match tokens.next() {
+                    Some(key) => Ok(Self::SortLibrary(key.parse()?)),
+                    None => Err(anyhow!("sort: Missing argument KEY")),
+                },
+                Some(key) => Ok(Self::SortBy(
+                    key.parse()?,
+                    tokens.next().map(str::parse).transpose()?,
+                )),
+                None => Err(anyhow!("sort: Missing argument TARGET")),
+            },
             Some(other) => Err(anyhow!("Invalid command: {}", other)),
             None => Ok(Self::Nop),
         }
     }
 }
+
+/// Parse a sleep timer length such as `45`, `30m`, `90s`, or `1h`, taken as
+/// minutes without a unit
+fn parse_sleep_time(time: &str) -> Option<Duration> {
+    let (number, seconds_per) = match time.chars().last()? {
+        's' => (&time[..time.len() - 1], 1),
+        'm' => (&time[..time.len() - 1], 60),
+        'h' => (&time[..time.len() - 1], 60 * 60),
+        _ => (time, 60),
+    };
+    let number: u64 = number.parse().ok().filter(|number| *number > 0)?;
+    number.checked_mul(seconds_per).map(Duration::from_secs)
+}
+
+/// A count typed before a command in normal mode, as in `5G` to go to the
+/// fifth item or `3j` to move down three times. Digits only start a count
+/// when they aren't bound to a command themselves, but once a count is
+/// started every digit adds to it.
+#[derive(Default)]
+pub struct CountPrefix {
+    /// The count so far and when it was last typed
+    count: Option<(usize, Instant)>,
+
+    /// Whether the last command was a counted `GotoTop`, so that the second
+    /// `g` of `42gg` doesn't then go to the top
+    after_counted_top: bool,
+}
+
+impl CountPrefix {
+    /// How long a count waits for the rest of the command
+    const TIMEOUT: Duration = Duration::from_secs(3);
+
+    /// The most times a counted movement is repeated
+    const MAX_REPEAT: usize = 1000;
+
+    /// Take a key and the command bound to it, returning the commands to run
+    pub fn apply(&mut self, key: KeyCode, command: Command) -> Vec<Command> {
+        let count = self
+            .count
+            .take()
+            .filter(|(_, typed_at)| typed_at.elapsed() < Self::TIMEOUT)
+            .map(|(count, _)| count);
+        let after_counted_top = mem::take(&mut self.after_counted_top);
+
+        let digit = match key {
+            KeyCode::Char(c) => c.to_digit(10).map(|d| d as usize),
+            _ => None,
+        };
+        match (digit, count, command) {
+            (Some(digit), Some(count), _) => {
+                self.count = Some((
+                    count.saturating_mul(10).saturating_add(digit),
+                    Instant::now(),
+                ));
+                Vec::new()
+            }
+            (Some(digit), None, Command::Nop) if digit > 0 => {
+                self.count = Some((digit, Instant::now()));
+                Vec::new()
+            }
+            (_, Some(count), Command::GotoTop) => {
+                self.after_counted_top = true;
+                vec![Command::GotoIndex(count - 1)]
+            }
+            (_, Some(count), Command::GotoBottom) => vec![Command::GotoIndex(count - 1)],
+            (_, Some(count), command @ (Command::Up | Command::Down)) => {
+                vec![command; count.min(Self::MAX_REPEAT)]
+            }
+            (_, None, Command::GotoTop) if after_counted_top => Vec::new(),
+            (_, _, command) => vec![command],
+        }
+    }
+}
+
+/// The command names offered by tab completion, each of which must be one
+/// that `Command::parse` recognises
+const COMMAND_NAMES: &[&str] = &[
+    "add",
+    "clear-playlist",
+    "config",
+    "duplicate-playlist",
+    "errors",
+    "exit",
+    "export",
+    "favorite",
+    "filter",
+    "goto-time",
+    "help",
+    "icons",
+    "loop-a",
+    "loop-b",
+    "lyrics",
+    "match-mode",
+    "mute",
+    "new-playlist",
+    "play",
+    "play-folder",
+    "play-next",
+    "quit",
+    "rate",
+    "reload-config",
+    "remaining",
+    "remove",
+    "repeat",
+    "rescan",
+    "reshuffle",
+    "save-queue",
+    "screen",
+    "search",
+    "seek-backward",
+    "seek-forward",
+    "set",
+    "shuffle",
+    "sleep",
+    "smart-playlist",
+    "sort",
+    "speed",
+    "speed-down",
+    "speed-reset",
+    "speed-up",
+    "station",
+    "stats",
+    "stop-after",
+    "theme",
+    "volume",
+    "volume-down",
+    "volume-up",
+];
+
+/// Completion candidates for a partially typed command, each being the whole
+/// completed command line. Completes command names at the first token and
+/// paths for the arguments of commands that take one.
+pub fn complete(input: &str) -> Vec<String> {
+    match input.split_once(' ') {
+        None => COMMAND_NAMES
+            .iter()
+            .filter(|name| name.starts_with(input))
+            .map(|name| (*name).to_owned())
+            .collect(),
+        Some((
+            command @ ("a" | "add" | "remove" | "rm" | "play" | "p" | "play-folder" | "pf"),
+            path,
+        )) => {
+            let (flag, path) = match path.strip_prefix("-r ") {
+                Some(path) if matches!(command, "play-folder" | "pf") => ("-r ", path),
+                _ => ("", path),
+            };
+            complete_path(path)
+                .into_iter()
+                .map(|path| format!("{command} {flag}{path}"))
+                .collect()
+        }
+        Some(_) => Vec::new(),
+    }
+}
+
+/// Complete a partially typed path to the directory entries it could name,
+/// with `~` expanded to the home directory. Directories end with a slash so
+/// that completion can continue into them.
+fn complete_path(path: &str) -> Vec<String> {
+    let path = match (path.strip_prefix('~'), dirs_next::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{rest}", home.display())
+        }
+        _ => path.to_owned(),
+    };
+    let (dir, prefix) = match path.rsplit_once('/') {
+        Some((dir, prefix)) => (format!("{dir}/"), prefix),
+        None => (String::new(), path.as_str()),
+    };
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { &dir }) else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden entries are only offered once a dot has been typed
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let separator = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{dir}{name}{separator}"))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_after_takes_a_positive_count_or_off() {
+        assert_eq!(
+            Command::parse("stop-after 3").unwrap(),
+            Command::StopAfter(Some(3))
+        );
+        assert_eq!(
+            Command::parse("stop-after off").unwrap(),
+            Command::StopAfter(None)
+        );
+        assert!(Command::parse("stop-after 0").is_err());
+        assert!(Command::parse("stop-after -1").is_err());
+        assert!(Command::parse("stop-after").is_err());
+    }
+
+    #[test]
+    fn sort_takes_a_key_and_an_optional_direction() {
+        assert_eq!(
+            Command::parse("sort year desc").unwrap(),
+            Command::SortBy(SortKey::Year, Some(SortDirection::Descending))
+        );
+        assert_eq!(
+            Command::parse("sort title").unwrap(),
+            Command::SortBy(SortKey::Title, None)
+        );
+        assert_eq!(
+            Command::parse("sort library added").unwrap(),
+            Command::SortLibrary(SortKey::Added)
+        );
+        assert!(Command::parse("sort year sideways").is_err());
+        assert!(Command::parse("sort").is_err());
+    }
+
+    #[test]
+    fn every_completed_name_is_a_command() {
+        for name in COMMAND_NAMES {
+            if let Err(e) = Command::parse(name) {
+                assert!(
+                    e.to_string().starts_with(&format!("{name}: ")),
+                    "{name} is offered for completion but does not parse: {e}"
+                );
+            }
+        }
+        assert!(Command::parse("frobnicate").is_err());
+    }
+}
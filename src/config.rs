@@ -2,21 +2,381 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::{collections::HashMap, fs::File, path::Path};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::Display,
+    fs::File,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use anyhow::Result;
-use crossterm::event::KeyCode;
+use anyhow::{anyhow, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use map_macro::hash_map;
 use ratatui::style::Color;
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{
+        self,
+        value::{EnumAccessDeserializer, MapAccessDeserializer},
+        EnumAccess, IntoDeserializer, MapAccess, Visitor,
+    },
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_yml::Value;
 
-use crate::{command::Command, ui::ScreenEnum, Load, Save};
+use crate::{
+    command::Command,
+    library::{
+        track::{ColumnLayout, ColumnWidth, TrackColumn},
+        DEFAULT_EXTENSIONS,
+    },
+    scrobble::ScrobbleCredentials,
+    search::MatchMode,
+    ui::ScreenEnum,
+    util::write_atomically,
+    Load, Save,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
+    /// Colors may be written as names, `#rrggbb` or `#rgb` hex codes, or
+    /// 256-color palette indices. They are saved as names and hex codes.
+    #[serde(deserialize_with = "deserialize_colors")]
     pub colors: HashMap<UiColor, Color>,
-    pub keybinds: HashMap<KeyCode, Command>,
+
+    /// A built-in color theme, one of `THEMES`. Entries in `colors` take
+    /// precedence over the theme's.
+    #[serde(default)]
+    pub theme: Option<String>,
+    pub keybinds: HashMap<KeyBind, Command>,
     pub options: HashMap<ConfOption, bool>,
+
+    /// Numeric settings, such as step sizes
+    #[serde(default)]
+    pub numbers: HashMap<NumOption, f64>,
+
+    /// The columns of the track list, in order, with their relative widths
+    #[serde(default = "default_columns")]
+    pub columns: Vec<ColumnWidth>,
+
+    /// The minimum track list width at which each column is shown. Columns
+    /// without a threshold are always shown.
+    #[serde(default)]
+    pub column_thresholds: HashMap<TrackColumn, u16>,
+
+    /// How search text is matched against the library
+    #[serde(default)]
+    pub match_mode: MatchMode,
+
+    /// Last.fm credentials used when scrobbling is enabled
+    #[serde(default)]
+    pub scrobble_credentials: Option<ScrobbleCredentials>,
+
+    /// Shell command run when an MPRIS client asks for the player to be
+    /// raised, such as `wmctrl -i -a $WINDOWID`. Read at startup.
+    #[serde(default)]
+    pub raise_command: Option<String>,
+
+    /// File extensions of the audio files added to the library. Files with
+    /// other extensions are skipped.
+    #[serde(default = "default_extensions")]
+    pub extensions: Vec<String>,
+
+    /// A file of ReplayGain track gains used for tracks whose tags have
+    /// none, each line being a track's path and its gain separated by a tab
+    #[serde(default)]
+    pub replay_gain_file: Option<PathBuf>,
+}
+
+/// A key together with the modifiers held with it. In the config, a key
+/// without modifiers can be given as a bare key code.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct KeyBind {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBind {
+    /// The key pressed while holding Ctrl
+    pub const fn ctrl(code: KeyCode) -> Self {
+        Self {
+            code,
+            modifiers: KeyModifiers::CONTROL,
+        }
+    }
+}
+
+impl From<KeyCode> for KeyBind {
+    fn from(code: KeyCode) -> Self {
+        Self {
+            code,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+}
+
+/// Shift is left out for characters and Shift+Tab, as it is already part of
+/// the key code, so that `G` matches a binding for `G` however the terminal
+/// reports it
+impl From<KeyEvent> for KeyBind {
+    fn from(ke: KeyEvent) -> Self {
+        let modifiers = match ke.code {
+            KeyCode::Char(_) | KeyCode::BackTab => ke.modifiers - KeyModifiers::SHIFT,
+            _ => ke.modifiers,
+        };
+        Self {
+            code: ke.code,
+            modifiers,
+        }
+    }
+}
+
+/// Named keys such as `Enter` come before characters, which are in
+/// alphabetical order with lowercase first. A key without modifiers comes
+/// before the same key with them.
+impl Ord for KeyBind {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.code, other.code) {
+            (KeyCode::Char(a), KeyCode::Char(b)) => a
+                .to_lowercase()
+                .cmp(b.to_lowercase())
+                .then_with(|| b.is_lowercase().cmp(&a.is_lowercase())),
+            (KeyCode::Char(_), _) => Ordering::Greater,
+            (_, KeyCode::Char(_)) => Ordering::Less,
+            (a, b) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        }
+        .then_with(|| self.modifiers.bits().cmp(&other.modifiers.bits()))
+    }
+}
+
+impl PartialOrd for KeyBind {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Written as in the help screen, such as `Ctrl+d` or `Page Down`
+impl Display for KeyBind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl+"),
+            (KeyModifiers::ALT, "Alt+"),
+            (KeyModifiers::SUPER, "Super+"),
+            (KeyModifiers::SHIFT, "Shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::Backspace => f.write_str("Backspace"),
+            KeyCode::Enter => f.write_str("Enter"),
+            KeyCode::Left => f.write_str("Left"),
+            KeyCode::Right => f.write_str("Right"),
+            KeyCode::Up => f.write_str("Up"),
+            KeyCode::Down => f.write_str("Down"),
+            KeyCode::Home => f.write_str("Home"),
+            KeyCode::End => f.write_str("End"),
+            KeyCode::PageUp => f.write_str("Page Up"),
+            KeyCode::PageDown => f.write_str("Page Down"),
+            KeyCode::Tab => f.write_str("Tab"),
+            KeyCode::BackTab => f.write_str("Shift+Tab"),
+            KeyCode::Delete => f.write_str("Delete"),
+            KeyCode::Insert => f.write_str("Insert"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            KeyCode::Null => f.write_str("Null"),
+            KeyCode::Esc => f.write_str("Esc"),
+            KeyCode::CapsLock => f.write_str("Caps Lock"),
+            KeyCode::ScrollLock => f.write_str("Scroll Lock"),
+            KeyCode::NumLock => f.write_str("Num Lock"),
+            KeyCode::PrintScreen => f.write_str("Print Screen"),
+            KeyCode::Pause => f.write_str("Pause"),
+            KeyCode::Menu => f.write_str("Menu"),
+            _ => f.write_str("Invalid Key Code"),
+        }
+    }
+}
+
+/// How a `KeyBind` with modifiers is written in the config
+#[derive(Serialize, Deserialize)]
+struct KeyBindFields {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Serialize for KeyBind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.modifiers.is_empty() {
+            self.code.serialize(serializer)
+        } else {
+            KeyBindFields {
+                code: self.code,
+                modifiers: self.modifiers,
+            }
+            .serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyBind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(KeyBindVisitor)
+    }
+}
+
+/// Reads either a bare key code, which YAML writes as a tagged value or a
+/// plain string, or a map of a key code and its modifiers
+struct KeyBindVisitor;
+
+impl<'de> Visitor<'de> for KeyBindVisitor {
+    type Value = KeyBind;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a key code, or a key code with modifiers")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        KeyCode::deserialize(v.into_deserializer()).map(KeyBind::from)
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        KeyCode::deserialize(EnumAccessDeserializer::new(data)).map(KeyBind::from)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let fields = KeyBindFields::deserialize(MapAccessDeserializer::new(map))?;
+        Ok(KeyBind {
+            code: fields.code,
+            modifiers: fields.modifiers,
+        })
+    }
+}
+
+/// Common CSS color names that aren't among the terminal's named colors
+const CSS_COLORS: [(&str, (u8, u8, u8)); 28] = [
+    ("aqua", (0x00, 0xff, 0xff)),
+    ("beige", (0xf5, 0xf5, 0xdc)),
+    ("brown", (0xa5, 0x2a, 0x2a)),
+    ("chocolate", (0xd2, 0x69, 0x1e)),
+    ("coral", (0xff, 0x7f, 0x50)),
+    ("crimson", (0xdc, 0x14, 0x3c)),
+    ("fuchsia", (0xff, 0x00, 0xff)),
+    ("gold", (0xff, 0xd7, 0x00)),
+    ("indigo", (0x4b, 0x00, 0x82)),
+    ("ivory", (0xff, 0xff, 0xf0)),
+    ("khaki", (0xf0, 0xe6, 0x8c)),
+    ("lavender", (0xe6, 0xe6, 0xfa)),
+    ("lime", (0x00, 0xff, 0x00)),
+    ("maroon", (0x80, 0x00, 0x00)),
+    ("navy", (0x00, 0x00, 0x80)),
+    ("olive", (0x80, 0x80, 0x00)),
+    ("orange", (0xff, 0xa5, 0x00)),
+    ("orchid", (0xda, 0x70, 0xd6)),
+    ("pink", (0xff, 0xc0, 0xcb)),
+    ("plum", (0xdd, 0xa0, 0xdd)),
+    ("purple", (0x80, 0x00, 0x80)),
+    ("salmon", (0xfa, 0x80, 0x72)),
+    ("skyblue", (0x87, 0xce, 0xeb)),
+    ("tan", (0xd2, 0xb4, 0x8c)),
+    ("teal", (0x00, 0x80, 0x80)),
+    ("tomato", (0xff, 0x63, 0x47)),
+    ("turquoise", (0x40, 0xe0, 0xd0)),
+    ("violet", (0xee, 0x82, 0xee)),
+];
+
+/// Parse a color written as a terminal color name, a CSS color name, a
+/// `#rrggbb` or `#rgb` hex code, or a palette index
+fn parse_color(s: &str) -> Option<Color> {
+    if let Ok(color) = s.parse() {
+        return Some(color);
+    }
+    let name = s.to_lowercase().replace([' ', '-', '_'], "");
+    if let Some((_, (r, g, b))) = CSS_COLORS.iter().find(|(css, _)| *css == name) {
+        return Some(Color::Rgb(*r, *g, *b));
+    }
+    // Each digit of the short form is doubled, so `#f80` is `#ff8800`
+    let hex = s.strip_prefix('#').filter(|hex| hex.len() == 3)?;
+    let mut digits = hex.chars().map(|c| c.to_digit(16));
+    let mut next = || {
+        digits
+            .next()
+            .flatten()
+            .and_then(|d| u8::try_from(d * 17).ok())
+    };
+    Some(Color::Rgb(next()?, next()?, next()?))
+}
+
+fn deserialize_colors<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<UiColor, Color>, D::Error> {
+    /// A color in the config, read with `parse_color`
+    struct ConfigColor(Color);
+
+    impl<'de> Deserialize<'de> for ConfigColor {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(ColorVisitor).map(ConfigColor)
+        }
+    }
+
+    let colors = HashMap::<UiColor, ConfigColor>::deserialize(deserializer)?;
+    Ok(colors
+        .into_iter()
+        .map(|(ui_color, color)| (ui_color, color.0))
+        .collect())
+}
+
+/// Reads a color from a string, a palette index, or the older tagged `Rgb`
+/// and `Indexed` forms
+struct ColorVisitor;
+
+impl<'de> Visitor<'de> for ColorVisitor {
+    type Value = Color;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a color name, a #rrggbb hex code, or a palette index")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        parse_color(v).ok_or_else(|| {
+            E::custom(format!(
+                "invalid color '{v}': expected a color name such as 'red' or 'orange', \
+                 a hex code such as '#1e1e2e', or a palette index from 0 to 255"
+            ))
+        })
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        u8::try_from(v)
+            .map(Color::Indexed)
+            .map_err(|_| E::custom(format!("invalid color index {v}: expected 0 to 255")))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        LegacyColor::deserialize(EnumAccessDeserializer::new(data)).map(Color::from)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        LegacyColor::deserialize(MapAccessDeserializer::new(map)).map(Color::from)
+    }
+}
+
+/// How RGB and palette colors were written before they could be strings
+#[derive(Deserialize)]
+enum LegacyColor {
+    Rgb(u8, u8, u8),
+    Indexed(u8),
+}
+
+impl From<LegacyColor> for Color {
+    fn from(color: LegacyColor) -> Self {
+        match color {
+            LegacyColor::Rgb(r, g, b) => Self::Rgb(r, g, b),
+            LegacyColor::Indexed(index) => Self::Indexed(index),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -26,59 +386,211 @@ pub enum UiColor {
     Bg,
     HighlightBg,
     OffPanelHighlight,
+    GaugePaused,
+    /// The played part of the progress bar. Defaults to `Fg`.
+    GaugeFilled,
+    /// The unplayed part of the progress bar. Defaults to `Bg`.
+    GaugeEmpty,
+    /// Panel borders
+    Border,
+    /// Panel titles
+    Title,
+    /// The command line prompt
+    CommandPrompt,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum ConfOption {
     NerdFontIcons,
     GaplessPlayback,
+    StopAfterCountsSkips,
+    /// Hold the sleep timer while playback is paused, so that it only
+    /// counts time spent playing
+    SleepTimerPausable,
+    /// Show the UI before sorting the library and building the library
+    /// screens, for faster startup with large libraries
+    DeferLibraryLoad,
+    /// Normalize each track's volume by its ReplayGain track gain
+    ReplayGain,
+    /// Submit played tracks to Last.fm using `scrobble_credentials`
+    Scrobbling,
+    /// Show a desktop notification when a track starts playing
+    Notifications,
+    /// Select and play list items with the mouse
+    Mouse,
+    /// Show the time remaining in the playback bar rather than the time
+    /// elapsed
+    ShowRemaining,
+    /// Pick up audio files added to, changed in, or removed from the
+    /// library's directories while running. The directories are listed
+    /// again after each burst of changes, which takes a while for very large
+    /// libraries, but nothing is done while they are left alone.
+    WatchLibrary,
+    /// Wrap around from the end of a list to the start, and back, when moving
+    /// the selection one step
+    WrapNavigation,
+    /// Scroll the current track's details across the info panel when they
+    /// don't fit, rather than cutting them short
+    MarqueeNowPlaying,
+    /// Show bars measuring the audio across a range of frequencies. Costs
+    /// some CPU while playing.
+    Visualizer,
+}
+
+/// Options are named as in the config file
+impl FromStr for ConfOption {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::deserialize(s.into_deserializer())
+            .map_err(|_: de::value::Error| anyhow!("Unknown option: {s}"))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum NumOption {
+    /// Seconds to skip when seeking forward or backward
+    SeekSeconds,
+    /// Amount to change the volume by, where 1.0 is full volume
+    VolumeStep,
+    /// Amount to change the playback speed by, where 1.0 is normal speed
+    SpeedStep,
+    /// Seconds consecutive tracks overlap for while crossfading. Zero
+    /// disables crossfading. Takes precedence over gapless playback.
+    CrossfadeSeconds,
+    /// Most recently played tracks to list on the history screen
+    HistoryLength,
+    /// How many directories deep to look for audio files when adding a
+    /// directory to the library
+    ScanDepth,
+    /// Milliseconds to wait for input between checks on playback. Longer
+    /// waits use less CPU.
+    PollMillis,
 }
 
 impl Default for Config {
     fn default() -> Self {
-        Self {
+        let mut config = Self {
             colors: hash_map! {
                 UiColor::OffPanelHighlight => Color::Red,
                 UiColor::HighlightFg => Color::Black,
                 UiColor::HighlightBg => Color::White,
+                UiColor::GaugePaused => Color::Yellow,
             },
             keybinds: hash_map! {
-                KeyCode::Char('k') => Command::Up,
-                KeyCode::Up => Command::Up,
-                KeyCode::Char('j') => Command::Down,
-                KeyCode::Down => Command::Down,
                 KeyCode::Char(' ') => Command::TogglePlay,
                 KeyCode::Char(',') => Command::PrevTrack,
                 KeyCode::Char('.') => Command::NextTrack,
                 KeyCode::Enter => Command::QueueAndPlay,
+                KeyCode::Char('a') => Command::PlayNext,
                 KeyCode::Char('r') => Command::ToggleRepeat,
                 KeyCode::Char('s') => Command::ToggleShuffle,
+                KeyCode::Char('z') => Command::Reshuffle,
                 KeyCode::Char('g') => Command::GotoTop,
                 KeyCode::Char('G') => Command::GotoBottom,
                 KeyCode::Tab => Command::NextPanel,
                 KeyCode::BackTab => Command::PrevPanel,
                 KeyCode::Char('1') => Command::GotoScreen(ScreenEnum::Main),
                 KeyCode::Char('2') => Command::GotoScreen(ScreenEnum::Playlists),
-                KeyCode::Char('0') => Command::GotoScreen(ScreenEnum::Help),
-                KeyCode::F(1) => Command::GotoScreen(ScreenEnum::Help),
+                KeyCode::Char('3') => Command::GotoScreen(ScreenEnum::Tree),
+                KeyCode::Char('4') => Command::GotoScreen(ScreenEnum::History),
+                KeyCode::Char('5') => Command::GotoScreen(ScreenEnum::Stations),
+                KeyCode::Char('6') => Command::ShowErrors,
+                KeyCode::Char('L') => Command::ToggleLyrics,
+                KeyCode::Right => Command::Expand,
+                KeyCode::Left => Command::Collapse,
                 KeyCode::Char('n') => Command::NewPlaylist(None),
                 KeyCode::Char('p') => Command::PlaylistAdd,
+                KeyCode::Char('S') => Command::SaveQueue(None),
                 KeyCode::Char('x') => Command::SelectPlaylist,
                 KeyCode::Char('q') => Command::Quit,
                 KeyCode::Char(':') => Command::EnterCommand,
-            },
+                KeyCode::Char('/') => Command::Search,
+                KeyCode::F(5) => Command::Rescan,
+                KeyCode::Char('[') => Command::SetLoopA,
+                KeyCode::Char(']') => Command::SetLoopB,
+                KeyCode::Char('t') => Command::GotoTime,
+                KeyCode::Char('>') => Command::SeekForward,
+                KeyCode::Char('<') => Command::SeekBackward,
+                KeyCode::Char('-') => Command::VolumeDown,
+                KeyCode::Char('m') => Command::ToggleMute,
+                KeyCode::Char('M') => Command::SetMatchMode(None),
+                KeyCode::Char('R') => Command::ReloadConfig,
+                KeyCode::Char('f') => Command::ToggleFavorite,
+                KeyCode::Char('T') => Command::ToggleRemaining,
+                KeyCode::Char('}') => Command::SpeedUp,
+                KeyCode::Char('{') => Command::SpeedDown,
+                KeyCode::Char('\\') => Command::SpeedReset,
+                KeyCode::Char('i') => Command::ToggleIcons,
+            }
+            .into_iter()
+            .map(|(code, command)| (KeyBind::from(code), command))
+            .chain([
+                (KeyBind::ctrl(KeyCode::Char('d')), Command::HalfPageDown),
+                (KeyBind::ctrl(KeyCode::Char('u')), Command::HalfPageUp),
+            ])
+            .collect(),
             options: hash_map! {
                 ConfOption::NerdFontIcons => true,
                 ConfOption::GaplessPlayback => true,
+                ConfOption::StopAfterCountsSkips => false,
+                ConfOption::SleepTimerPausable => false,
+                ConfOption::DeferLibraryLoad => false,
+                ConfOption::ReplayGain => false,
+                ConfOption::Mouse => true,
+                ConfOption::Scrobbling => false,
+                ConfOption::Notifications => false,
+                ConfOption::ShowRemaining => false,
+                ConfOption::WatchLibrary => false,
+                ConfOption::WrapNavigation => true,
+                ConfOption::MarqueeNowPlaying => false,
+                ConfOption::Visualizer => false,
             },
-        }
+            numbers: hash_map! {
+                NumOption::SeekSeconds => 5.0,
+                NumOption::VolumeStep => 0.05,
+                NumOption::SpeedStep => 0.1,
+                NumOption::CrossfadeSeconds => 0.0,
+                NumOption::HistoryLength => 100.0,
+                NumOption::ScanDepth => 32.0,
+                NumOption::PollMillis => 100.0,
+            },
+            columns: default_columns(),
+            column_thresholds: hash_map! {
+                TrackColumn::Artist => 50,
+                TrackColumn::Album => 70,
+                TrackColumn::Year => 90,
+                TrackColumn::Rating => 110,
+            },
+            match_mode: MatchMode::default(),
+            scrobble_credentials: None,
+            raise_command: None,
+            theme: None,
+            extensions: default_extensions(),
+            replay_gain_file: None,
+        };
+        config.bind(&[KeyCode::Char('k'), KeyCode::Up], Command::Up);
+        config.bind(&[KeyCode::Char('j'), KeyCode::Down], Command::Down);
+        config.bind(
+            &[KeyCode::PageDown.into(), KeyBind::ctrl(KeyCode::Char('f'))],
+            Command::PageDown,
+        );
+        config.bind(
+            &[KeyCode::PageUp.into(), KeyBind::ctrl(KeyCode::Char('b'))],
+            Command::PageUp,
+        );
+        config.bind(
+            &[KeyCode::Char('0'), KeyCode::F(1)],
+            Command::GotoScreen(ScreenEnum::Help),
+        );
+        config.bind(&[KeyCode::Char('+'), KeyCode::Char('=')], Command::VolumeUp);
+        config
     }
 }
 
 impl Save for Config {
     fn save<P: AsRef<Path>>(&self, file_path: P) -> Result<()> {
-        let config_file = File::create(file_path)?;
-        Ok(serde_yml::to_writer(config_file, self)?)
+        write_atomically(file_path, |file| Ok(serde_yml::to_writer(file, self)?))
     }
 }
 
@@ -87,17 +599,467 @@ impl Load for Config {
     where
         Self: Sized,
     {
-        let config_file = File::open(file_path)?;
-        Ok(serde_yml::from_reader(config_file)?)
+        Self::load_with_warnings(file_path).map(|(config, _)| config)
     }
 }
 
+/// A mistake in the config file that doesn't stop it from loading
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigWarning {
+    /// A top-level key that isn't a setting. It is ignored.
+    UnknownKey(String),
+    /// An entry in a section, such as `options`, that isn't recognized. It is
+    /// left out.
+    UnknownEntry {
+        section: &'static str,
+        entry: String,
+    },
+    /// A binding that no key press can match, as Shift is already part of a
+    /// character key. `shadowed_by` is the binding the key press runs instead,
+    /// if there is one.
+    UnreachableKeybind {
+        key: KeyBind,
+        shadowed_by: Option<KeyBind>,
+    },
+    /// A `theme` that isn't one of `THEMES`. The default colors are kept.
+    UnknownTheme(String),
+}
+
+impl Display for ConfigWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownKey(key) => write!(f, "config: Unknown setting '{key}' is ignored"),
+            Self::UnknownEntry { section, entry } => {
+                write!(f, "config: Unknown entry '{entry}' in {section} is ignored")
+            }
+            Self::UnreachableKeybind {
+                key,
+                shadowed_by: Some(other),
+            } => write!(
+                f,
+                "config: Keybind {key} conflicts with {other} and never runs"
+            ),
+            Self::UnreachableKeybind {
+                key,
+                shadowed_by: None,
+            } => write!(
+                f,
+                "config: Keybind {key} never runs, as Shift is part of the character"
+            ),
+            Self::UnknownTheme(name) => write!(
+                f,
+                "config: Unknown theme '{name}' is ignored (expected one of {})",
+                THEMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// Write a YAML value as it appears in the config, for warnings
+fn describe(value: &Value) -> String {
+    serde_yml::to_string(value).map_or_else(|_| format!("{value:?}"), |s| s.trim().to_owned())
+}
+
+/// Leave out the entries of a section of the config that `is_known` rejects,
+/// with a warning for each
+fn retain_known(
+    config: &mut Value,
+    section: &'static str,
+    warnings: &mut Vec<ConfigWarning>,
+    is_known: impl Fn(&Value, &Value) -> bool,
+) {
+    let Some(entries) = config.get_mut(section).and_then(Value::as_mapping_mut) else {
+        return;
+    };
+    entries.retain(|key, value| {
+        let known = is_known(key, value);
+        if !known {
+            warnings.push(ConfigWarning::UnknownEntry {
+                section,
+                entry: describe(key),
+            });
+        }
+        known
+    });
+}
+
+/// Commands without which a user could be locked out of part of the player,
+/// with how they are named in warnings
+const ESSENTIAL_COMMANDS: [(Command, &str); 7] = [
+    (Command::Quit, "quit"),
+    (Command::EnterCommand, "the command line"),
+    (Command::NextPanel, "the next panel"),
+    (Command::GotoScreen(ScreenEnum::Main), "the main screen"),
+    (Command::GotoScreen(ScreenEnum::Playlists), "playlists"),
+    (Command::GotoScreen(ScreenEnum::Tree), "the library tree"),
+    (Command::GotoScreen(ScreenEnum::Help), "the help screen"),
+];
+
+/// The names of the built-in color themes
+pub const THEMES: [&str; 4] = ["dark", "light", "gruvbox", "nord"];
+
+/// The colors of a built-in theme, or `None` if there is no theme by that
+/// name
+fn theme_colors(name: &str) -> Option<HashMap<UiColor, Color>> {
+    let rgb = |hex: u32| {
+        let [_, r, g, b] = hex.to_be_bytes();
+        Color::Rgb(r, g, b)
+    };
+    Some(match name {
+        "dark" => Config::default().colors,
+        "light" => hash_map! {
+            UiColor::Fg => Color::Black,
+            UiColor::Bg => Color::White,
+            UiColor::HighlightFg => Color::White,
+            UiColor::HighlightBg => Color::Blue,
+            UiColor::OffPanelHighlight => Color::Red,
+            UiColor::GaugePaused => rgb(0x00b5_8900),
+            UiColor::GaugeFilled => Color::Blue,
+            UiColor::GaugeEmpty => Color::White,
+            UiColor::Border => Color::Gray,
+            UiColor::Title => Color::Blue,
+            UiColor::CommandPrompt => Color::Blue,
+        },
+        "gruvbox" => hash_map! {
+            UiColor::Fg => rgb(0x00eb_dbb2),
+            UiColor::Bg => rgb(0x0028_2828),
+            UiColor::HighlightFg => rgb(0x0028_2828),
+            UiColor::HighlightBg => rgb(0x00d7_9921),
+            UiColor::OffPanelHighlight => rgb(0x00fb_4934),
+            UiColor::GaugePaused => rgb(0x00fa_bd2f),
+            UiColor::GaugeFilled => rgb(0x00b8_bb26),
+            UiColor::GaugeEmpty => rgb(0x003c_3836),
+            UiColor::Border => rgb(0x0066_5c54),
+            UiColor::Title => rgb(0x0083_a598),
+            UiColor::CommandPrompt => rgb(0x00fe_8019),
+        },
+        "nord" => hash_map! {
+            UiColor::Fg => rgb(0x00d8_dee9),
+            UiColor::Bg => rgb(0x002e_3440),
+            UiColor::HighlightFg => rgb(0x002e_3440),
+            UiColor::HighlightBg => rgb(0x0088_c0d0),
+            UiColor::OffPanelHighlight => rgb(0x00bf_616a),
+            UiColor::GaugePaused => rgb(0x00eb_cb8b),
+            UiColor::GaugeFilled => rgb(0x0081_a1c1),
+            UiColor::GaugeEmpty => rgb(0x003b_4252),
+            UiColor::Border => rgb(0x004c_566a),
+            UiColor::Title => rgb(0x008f_bcbb),
+            UiColor::CommandPrompt => rgb(0x0088_c0d0),
+        },
+        _ => return None,
+    })
+}
+
+fn unknown_theme(name: &str) -> anyhow::Error {
+    anyhow!(
+        "Unknown theme: {name} (expected one of {})",
+        THEMES.join(", ")
+    )
+}
+
+/// The extensions of every format the library can read
+fn default_extensions() -> Vec<String> {
+    DEFAULT_EXTENSIONS.map(String::from).to_vec()
+}
+
+/// Every column at the same width
+fn default_columns() -> Vec<ColumnWidth> {
+    [
+        TrackColumn::Title,
+        TrackColumn::Artist,
+        TrackColumn::Album,
+        TrackColumn::Year,
+        TrackColumn::Length,
+        TrackColumn::Rating,
+    ]
+    .into_iter()
+    .map(|column| ColumnWidth { column, weight: 1 })
+    .collect()
+}
+
 impl Config {
-    pub fn get_command_keys(&self, command: &Command) -> Vec<KeyCode> {
-        self.keybinds
-            .clone()
+    /// Load a config file, leaving out unknown settings and entries rather
+    /// than failing on them, and filling in options that aren't set with
+    /// their defaults. Returns the mistakes found alongside the config.
+    pub fn load_with_warnings<P: AsRef<Path>>(file_path: P) -> Result<(Self, Vec<ConfigWarning>)> {
+        let config_file = File::open(file_path)?;
+        let mut value: Value = serde_yml::from_reader(config_file)?;
+        let mut warnings = Vec::new();
+
+        let defaults = serde_yml::to_value(Self::default())?;
+        if let (Some(entries), Some(known)) = (value.as_mapping_mut(), defaults.as_mapping()) {
+            entries.retain(|key, _| {
+                let is_known = known.contains_key(key);
+                if !is_known {
+                    warnings.push(ConfigWarning::UnknownKey(describe(key)));
+                }
+                is_known
+            });
+        }
+        retain_known(&mut value, "colors", &mut warnings, |key, _| {
+            UiColor::deserialize(key).is_ok()
+        });
+        retain_known(&mut value, "options", &mut warnings, |key, _| {
+            ConfOption::deserialize(key).is_ok()
+        });
+        retain_known(&mut value, "numbers", &mut warnings, |key, _| {
+            NumOption::deserialize(key).is_ok()
+        });
+        retain_known(&mut value, "column_thresholds", &mut warnings, |key, _| {
+            TrackColumn::deserialize(key).is_ok()
+        });
+        retain_known(&mut value, "keybinds", &mut warnings, |key, command| {
+            KeyBind::deserialize(key).is_ok() && Command::deserialize(command).is_ok()
+        });
+
+        let mut config: Self = serde_yml::from_value(value)?;
+        if let Some(name) = &config.theme {
+            match theme_colors(name) {
+                Some(mut colors) => {
+                    colors.extend(config.colors.drain());
+                    config.colors = colors;
+                }
+                None => {
+                    warnings.push(ConfigWarning::UnknownTheme(name.clone()));
+                    config.theme = None;
+                }
+            }
+        }
+        let defaults = Self::default();
+        for (option, value) in defaults.options {
+            config.options.entry(option).or_insert(value);
+        }
+        for (option, value) in defaults.numbers {
+            config.numbers.entry(option).or_insert(value);
+        }
+        warnings.extend(config.keybind_warnings());
+        Ok((config, warnings))
+    }
+
+    /// Bindings with Shift on a character key, which no key press matches
+    fn keybind_warnings(&self) -> Vec<ConfigWarning> {
+        let mut warnings: Vec<_> = self
+            .keybinds
+            .keys()
+            .filter(|key| {
+                matches!(key.code, KeyCode::Char(_) | KeyCode::BackTab)
+                    && key.modifiers.contains(KeyModifiers::SHIFT)
+            })
+            .map(|key| {
+                let matched = KeyBind {
+                    code: key.code,
+                    modifiers: key.modifiers - KeyModifiers::SHIFT,
+                };
+                ConfigWarning::UnreachableKeybind {
+                    key: *key,
+                    shadowed_by: self.keybinds.contains_key(&matched).then_some(matched),
+                }
+            })
+            .collect();
+        warnings.sort_by_key(ToString::to_string);
+        warnings
+    }
+
+    /// Warnings for the commands needed to get around and out of the player
+    /// that have no key bound to them
+    pub fn check_bindings(&self) -> Vec<String> {
+        ESSENTIAL_COMMANDS
+            .into_iter()
+            .filter(|(command, _)| !self.keybinds.values().any(|bound| bound == command))
+            .map(|(_, name)| format!("No key is bound to {name}"))
+            .collect()
+    }
+
+    /// Get the value of an option, falling back on its default value if it
+    /// isn't set.
+    pub fn get_option(&self, option: &ConfOption) -> bool {
+        self.options.get(option).copied().unwrap_or_else(|| {
+            *Self::default()
+                .options
+                .get(option)
+                .expect("All options have default values")
+        })
+    }
+
+    /// Turn an option on or off
+    pub fn set_option(&mut self, option: ConfOption, value: bool) {
+        self.options.insert(option, value);
+    }
+
+    /// The settings in effect, defaults included, as headed sections of
+    /// lines for display
+    pub fn summary(&self) -> Vec<(String, Vec<String>)> {
+        let sorted = |mut lines: Vec<String>| {
+            lines.sort();
+            lines
+        };
+        let defaults = Self::default();
+        let general = vec![
+            format!("Theme: {}", self.theme.as_deref().unwrap_or("none")),
+            format!("Search matching: {}", self.match_mode),
+            format!(
+                "Columns: {}",
+                self.columns
+                    .iter()
+                    .map(|c| format!("{:?} ({})", c.column, c.weight))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            format!("Extensions: {}", self.extensions.join(", ")),
+            format!(
+                "Raise command: {}",
+                self.raise_command.as_deref().unwrap_or("none")
+            ),
+        ];
+        let options = defaults
+            .options
+            .keys()
+            .map(|option| format!("{option:?}: {}", self.get_option(option)))
+            .collect();
+        let numbers = defaults
+            .numbers
+            .keys()
+            .map(|option| format!("{option:?}: {}", self.get_number(option)))
+            .collect();
+        let colors = self
+            .colors
+            .iter()
+            .map(|(ui_color, color)| format!("{ui_color:?}: {color}"))
+            .collect();
+        let mut keybinds: Vec<_> = self.keybinds.iter().collect();
+        keybinds.sort_by_key(|(key, _)| **key);
+        let keybinds = keybinds
             .into_iter()
-            .filter_map(|(k, v)| if v == *command { Some(k) } else { None })
-            .collect::<Vec<_>>()
+            .map(|(key, command)| {
+                let command = command
+                    .label()
+                    .map_or_else(|| format!("{command:?}"), str::to_owned);
+                format!("{key}: {command}")
+            })
+            .collect();
+        vec![
+            ("General".to_owned(), general),
+            ("Options".to_owned(), sorted(options)),
+            ("Numbers".to_owned(), sorted(numbers)),
+            ("Colors".to_owned(), sorted(colors)),
+            ("Keybinds".to_owned(), keybinds),
+        ]
+    }
+
+    /// Get the value of a numeric setting, falling back on its default value
+    /// if it isn't set.
+    pub fn get_number(&self, option: &NumOption) -> f64 {
+        self.numbers.get(option).copied().unwrap_or_else(|| {
+            *Self::default()
+                .numbers
+                .get(option)
+                .expect("All numeric settings have default values")
+        })
+    }
+
+    /// Switch to a built-in color theme, replacing the current colors. An
+    /// unknown name leaves the colors as they are.
+    pub fn apply_theme(&mut self, name: &str) -> Result<()> {
+        self.colors = theme_colors(name).ok_or_else(|| unknown_theme(name))?;
+        self.theme = Some(name.to_owned());
+        Ok(())
+    }
+
+    /// The track list layout made from the column settings
+    pub fn column_layout(&self) -> ColumnLayout {
+        ColumnLayout {
+            columns: self.columns.clone(),
+            thresholds: self.column_thresholds.clone(),
+            nerd_font_icons: self.get_option(&ConfOption::NerdFontIcons),
+        }
+    }
+
+    /// Bind several keys to the same command, replacing anything they were
+    /// bound to before. A key can only run one command, but a command can
+    /// have any number of keys, so `bind(&[KeyCode::Char('j'), KeyCode::Down],
+    /// Command::Down)` lets either key move down.
+    pub fn bind<K: Into<KeyBind> + Copy>(&mut self, keys: &[K], command: Command) {
+        for key in keys {
+            self.keybinds.insert((*key).into(), command.clone());
+        }
+    }
+
+    /// Every key bound to a command. `keybinds` maps keys to commands, so this
+    /// looks up the other way. The keys are sorted so that they are listed
+    /// the same way every time.
+    pub fn get_command_keys(&self, command: &Command) -> Vec<KeyBind> {
+        let mut keys: Vec<_> = self
+            .keybinds
+            .iter()
+            .filter_map(|(k, v)| if v == command { Some(*k) } else { None })
+            .collect();
+        keys.sort();
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::TestDir;
+
+    #[test]
+    fn keys_sort_named_keys_first_then_characters_alphabetically() {
+        let mut keys: Vec<KeyBind> = vec![
+            KeyCode::Char('b').into(),
+            KeyBind::ctrl(KeyCode::Char('a')),
+            KeyCode::Char('A').into(),
+            KeyCode::Char('a').into(),
+            KeyCode::Down.into(),
+            KeyCode::Char('1').into(),
+            KeyCode::Enter.into(),
+        ];
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                KeyCode::Enter.into(),
+                KeyCode::Down.into(),
+                KeyCode::Char('1').into(),
+                KeyCode::Char('a').into(),
+                KeyBind::ctrl(KeyCode::Char('a')),
+                KeyCode::Char('A').into(),
+                KeyCode::Char('b').into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn command_keys_are_listed_in_order() {
+        let mut config = Config::default();
+        config.bind(
+            &[KeyCode::Char('x'), KeyCode::Char('X'), KeyCode::F(2)],
+            Command::Nop,
+        );
+        assert_eq!(
+            config.get_command_keys(&Command::Nop),
+            [
+                KeyCode::F(2).into(),
+                KeyCode::Char('x').into(),
+                KeyCode::Char('X').into()
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_themes_are_a_warning() {
+        let dir = TestDir::new("unknown_theme");
+        let path = dir.path().join("config.yml");
+        let config = Config {
+            theme: Some("neon".to_owned()),
+            colors: hash_map! { UiColor::Fg => Color::Red },
+            ..Config::default()
+        };
+        std::fs::write(&path, serde_yml::to_string(&config).unwrap()).unwrap();
+        let (config, warnings) = Config::load_with_warnings(&path).unwrap();
+        assert_eq!(warnings, [ConfigWarning::UnknownTheme("neon".to_owned())]);
+        assert_eq!(config.theme, None);
+        assert_eq!(config.colors.get(&UiColor::Fg), Some(&Color::Red));
     }
 }
@@ -0,0 +1,211 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Album covers, extracted and decoded on a background thread and drawn with
+//! half-block characters, two pixels to a cell.
+
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+
+use anyhow::Result;
+use image::ImageReader;
+use ratatui::{buffer::Buffer, layout::Rect, style::Color, widgets::Widget};
+
+use crate::library::album::Album;
+
+/// The most pixels across or down a cover is kept at. Covers are drawn a
+/// cell per pixel across, so this is plenty for a terminal pane.
+const THUMBNAIL_SIZE: u32 = 128;
+
+/// Whether the terminal can draw covers. Each cell shows two pixels in
+/// colours of their own, which takes 24-bit colour.
+pub fn supported() -> bool {
+    env::var("COLORTERM").is_ok_and(|colorterm| matches!(colorterm.as_str(), "truecolor" | "24bit"))
+}
+
+/// A decoded cover, shrunk to fit `THUMBNAIL_SIZE`
+#[derive(Debug)]
+pub struct CoverImage {
+    width: u32,
+    height: u32,
+    /// The colour of each pixel, row by row from the top left
+    pixels: Vec<[u8; 3]>,
+}
+
+impl CoverImage {
+    /// Decode an image file, going by its contents rather than its
+    /// extension
+    pub fn load(path: &Path) -> Result<Self> {
+        let image = ImageReader::open(path)?
+            .with_guessed_format()?
+            .decode()?
+            .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+            .to_rgb8();
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.pixels().map(|pixel| pixel.0).collect(),
+        })
+    }
+
+    /// The colour of the pixel at the given position, which must be inside
+    /// the image
+    fn pixel(&self, x: u32, y: u32) -> Color {
+        let [r, g, b] = self.pixels[(y * self.width + x) as usize];
+        Color::Rgb(r, g, b)
+    }
+
+    /// The largest size the cover can be drawn at in an area, in pixels,
+    /// keeping its shape. A cell is two pixels tall.
+    fn fit(&self, area: Rect) -> (u32, u32) {
+        let (max_width, max_height) = (u32::from(area.width), u32::from(area.height) * 2);
+        if self.width == 0 || self.height == 0 {
+            return (0, 0);
+        }
+        if max_width * self.height <= max_height * self.width {
+            (max_width, max_width * self.height / self.width)
+        } else {
+            (max_height * self.width / self.height, max_height)
+        }
+    }
+}
+
+/// Draw the cover as large as fits, centred, with an upper half block in
+/// each cell coloured by the pixel above and backed by the pixel below
+impl Widget for &CoverImage {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let (width, height) = self.fit(area);
+        if width == 0 || height == 0 {
+            return;
+        }
+        // The fitted size is no larger than the area, so these fit in a u16
+        #[allow(clippy::cast_possible_truncation)]
+        let left = area.x + (area.width - width as u16) / 2;
+        #[allow(clippy::cast_possible_truncation)]
+        let top = area.y + (area.height - height.div_ceil(2) as u16) / 2;
+        let source = |x: u32, y: u32| self.pixel(x * self.width / width, y * self.height / height);
+
+        for row in 0..height.div_ceil(2) {
+            for col in 0..width {
+                let upper = source(col, row * 2);
+                let lower = if row * 2 + 1 < height {
+                    source(col, row * 2 + 1)
+                } else {
+                    Color::Reset
+                };
+                #[allow(clippy::cast_possible_truncation)]
+                if let Some(cell) = buf.cell_mut((left + col as u16, top + row as u16)) {
+                    cell.set_char('▀').set_fg(upper).set_bg(lower);
+                }
+            }
+        }
+    }
+}
+
+/// Extracts and decodes albums' covers on a background thread, keeping
+/// each once it has been found so that an album is only read once
+pub struct CoverLoader {
+    requests: Sender<(u64, Album)>,
+    results: Receiver<(u64, Option<Arc<CoverImage>>)>,
+
+    /// The covers found so far by album, `None` for albums without one
+    covers: HashMap<u64, Option<Arc<CoverImage>>>,
+
+    /// Albums whose covers have been asked for
+    requested: HashSet<u64>,
+}
+
+impl CoverLoader {
+    pub fn start() -> Self {
+        let (requests, receiver) = mpsc::channel::<(u64, Album)>();
+        let (sender, results) = mpsc::channel();
+        thread::spawn(move || {
+            for (id, album) in receiver {
+                let cover = album
+                    .cover_art()
+                    .and_then(|path| CoverImage::load(&path).ok())
+                    .map(Arc::new);
+                if sender.send((id, cover)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            requests,
+            results,
+            covers: HashMap::new(),
+            requested: HashSet::new(),
+        }
+    }
+
+    /// The album's cover, if it has one and it has been found. Asks for it
+    /// to be found if it hasn't been already.
+    pub fn get(&mut self, album: &Album) -> Option<Arc<CoverImage>> {
+        let id = album.cover_id()?;
+        if self.requested.insert(id) {
+            let _ = self.requests.send((id, album.clone()));
+        }
+        self.covers.get(&id).cloned().flatten()
+    }
+
+    /// Keep the covers found since this was last called. Returns whether
+    /// there were any.
+    pub fn receive(&mut self) -> bool {
+        let mut received = false;
+        for (id, cover) in self.results.try_iter() {
+            self.covers.insert(id, cover);
+            received = true;
+        }
+        received
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32) -> CoverImage {
+        CoverImage {
+            width,
+            height,
+            pixels: vec![[0, 0, 0]; (width * height) as usize],
+        }
+    }
+
+    #[test]
+    fn fit_keeps_the_cover_square_in_pixels() {
+        let square = image(100, 100);
+        // 20 rows are 40 pixels tall
+        assert_eq!(square.fit(Rect::new(0, 0, 60, 20)), (40, 40));
+        assert_eq!(square.fit(Rect::new(0, 0, 30, 20)), (30, 30));
+        assert_eq!(image(100, 50).fit(Rect::new(0, 0, 60, 20)), (60, 30));
+        assert_eq!(square.fit(Rect::new(0, 0, 0, 20)), (0, 0));
+    }
+
+    #[test]
+    fn render_centres_the_cover_and_stays_inside_the_area() {
+        let mut cover = image(2, 2);
+        cover.pixels = vec![[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+        let area = Rect::new(1, 1, 6, 3);
+        let mut buf = Buffer::empty(Rect::new(0, 0, 8, 5));
+        (&cover).render(area, &mut buf);
+
+        // Drawn 6 pixels wide and tall, in the 3 rows
+        let cell = &buf[(1, 1)];
+        assert_eq!(cell.symbol(), "▀");
+        assert_eq!(cell.fg, Color::Rgb(255, 0, 0));
+        assert_eq!(cell.bg, Color::Rgb(255, 0, 0));
+        assert_eq!(buf[(6, 3)].bg, Color::Rgb(255, 255, 255));
+        assert_eq!(buf[(0, 0)].symbol(), " ");
+        assert_eq!(buf[(7, 4)].symbol(), " ");
+    }
+}
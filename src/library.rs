@@ -5,19 +5,22 @@
 use anyhow::{anyhow, Result};
 use lofty::{
     file::{AudioFile, TaggedFileExt},
+    picture::{MimeType, PictureType},
     probe::Probe,
-    tag::Accessor,
+    tag::{Accessor, ItemKey},
 };
 use rodio::{Decoder, Source};
 use std::{
-    collections::HashSet,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
     fs::{self, File},
-    io::BufReader,
+    io::{BufReader, Write},
+    mem,
     path::{self, Path, PathBuf},
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
-use crate::{playlist::Playlist, Load, Save};
+use crate::{lyrics::Lyrics, playlist::Playlist, stream, util::write_atomically, Load, Save};
 
 pub mod album;
 pub mod artist;
@@ -25,23 +28,70 @@ pub mod track;
 
 use album::Album;
 use artist::Artist;
-use track::Track;
+use track::{SortKey, Track};
 
 #[derive(Clone)]
 pub struct Library {
     pub tracks: Playlist,
     known_paths: HashSet<PathBuf>,
+
+    /// The order the library's tracks are kept in. Persisted in the library
+    /// file as a `#SORT:` directive.
+    pub sort_key: SortKey,
+
+    /// The file extensions of the audio files added to the library
+    extensions: HashSet<String>,
+
+    /// How many directories deep `add_path` descends
+    max_depth: usize,
+
+    /// Files `add_path` couldn't read, with the reasons why
+    import_errors: Vec<(PathBuf, String)>,
 }
 
+/// The directory depth scanned when none is configured
+pub const DEFAULT_SCAN_DEPTH: usize = 32;
+
+/// The file extensions accepted when none are configured
+pub const DEFAULT_EXTENSIONS: [&str; 8] =
+    ["mp3", "flac", "aiff", "m4a", "ogg", "opus", "aac", "wav"];
+
+/// Read a track's metadata, along with an artist and album containing just
+/// it. URLs are taken as remote tracks without reading anything.
 pub fn get_track_data<P: AsRef<Path>>(path: P) -> Result<(Track, Artist, Album)> {
     let path = path.as_ref();
+    let track = match path.to_str().filter(|p| stream::is_url(p)) {
+        Some(url) => Track::from_url(url),
+        None => read_track(path)?,
+    };
+
+    let mut artist = Artist::default().name(track.grouping_artist());
+
+    let mut album = Album::default().name(track.album.as_str()).year(track.year);
+
+    album.tracks.push(track.clone());
+
+    artist.albums.push(Album::default().name("All Albums"));
+    artist.albums[0].tracks.push(track.clone());
+    artist.albums.push(album.clone());
+
+    Ok((track, artist, album))
+}
+
+/// Files larger than this in bytes aren't decoded to measure their length
+const MAX_DECODE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Read a local audio file's metadata
+fn read_track(path: &Path) -> Result<Track> {
     if !path.is_file() {
         return Err(anyhow!("{} is not a file", path.display()));
     }
 
     let tagged_file = Probe::open(path)?.read()?;
     let mut length = tagged_file.properties().duration();
-    if length == Duration::ZERO {
+    // Counting the samples means decoding the whole file, which is too slow
+    // for large files. Their length is left as zero, i.e. unknown.
+    if length == Duration::ZERO && fs::metadata(path)?.len() <= MAX_DECODE_SIZE {
         let source = Decoder::new(BufReader::new(File::open(path)?))?;
         let sample_rate = f64::from(source.sample_rate());
         let channels = f64::from(source.channels());
@@ -54,39 +104,251 @@ pub fn get_track_data<P: AsRef<Path>>(path: P) -> Result<(Track, Artist, Album)>
         .expect("There is no good reason a path should not be convertable to a string")
         .to_string();
 
-    let track = if let Some(tag) = tagged_file.primary_tag() {
+    Ok(if let Some(tag) = tagged_file.primary_tag() {
         Track {
             title: tag.title().as_deref().map(std::borrow::ToOwned::to_owned),
             artist: tag.artist().as_deref().unwrap_or("Unknown").to_owned(),
+            album_artist: tag
+                .get_string(&ItemKey::AlbumArtist)
+                .map(std::borrow::ToOwned::to_owned),
             album: tag.album().as_deref().unwrap_or("Unknown").to_owned(),
             year: tag.year(),
             number: tag.track(),
+            disc: tag.disk(),
             length,
             file_path,
+            genre: tag.genre().as_deref().map(std::borrow::ToOwned::to_owned),
+            gain: tag
+                .get_string(&ItemKey::ReplayGainTrackGain)
+                .and_then(parse_gain),
+            play_count: 0,
+            last_played: None,
+            rating: None,
+            added: None,
+            missing: false,
+            extra_tags: Vec::new(),
         }
     } else {
         Track {
             title: None,
             artist: "Unknown".to_owned(),
+            album_artist: None,
             album: "Unknown".to_owned(),
             year: None,
             number: None,
+            disc: None,
             length,
             file_path,
+            genre: None,
+            gain: None,
+            play_count: 0,
+            last_played: None,
+            rating: None,
+            added: None,
+            missing: false,
+            extra_tags: Vec::new(),
         }
+    })
+}
+
+/// Parse a ReplayGain value such as `-6.50 dB` into a number of dB
+pub fn parse_gain(gain: &str) -> Option<f32> {
+    gain.trim()
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .trim()
+        .parse()
+        .ok()
+        .filter(|gain: &f32| gain.is_finite())
+}
+
+/// ReplayGain track gains kept apart from the tracks' tags, by file path, for
+/// libraries analyzed by tools that don't write tags
+#[derive(Default)]
+pub struct GainSidecar {
+    gains: HashMap<String, f32>,
+}
+
+impl GainSidecar {
+    /// Read a sidecar file. Each line is a track's path and its gain, such as
+    /// `-6.50 dB`, separated by a tab. Blank lines, lines starting with `#`,
+    /// and lines without a valid gain are skipped.
+    pub fn load<P: AsRef<Path>>(file_path: P) -> Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(file_path)?))
+    }
+
+    fn parse(text: &str) -> Self {
+        let gains = text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.rsplit_once('\t'))
+            .filter_map(|(path, gain)| Some((path.to_owned(), parse_gain(gain)?)))
+            .collect();
+        Self { gains }
+    }
+
+    /// A track's gain in dB, from its tags if they have one and otherwise
+    /// from the sidecar
+    pub fn track_gain(&self, track: &Track) -> Option<f32> {
+        track
+            .gain
+            .or_else(|| self.gains.get(&track.file_path).copied())
+    }
+
+    /// The factor to scale a track's volume by to apply its ReplayGain, or
+    /// unity if it has none
+    pub fn gain_factor(&self, track: &Track) -> f32 {
+        self.track_gain(track)
+            .map_or(1.0, |gain| 10_f32.powf(gain / 20.0))
+    }
+}
+
+/// Get the path to an image file of a track's embedded cover art, extracting
+/// it into the cache directory if it hasn't been already. Covers are cached
+/// per album, so tracks from the same album share a file. Returns `None` if the
+/// track has no embedded picture or it can't be saved.
+pub fn cover_art(track: &Track) -> Option<PathBuf> {
+    let cover_dir = dirs_next::cache_dir()?.join("rmup").join("covers");
+    let stem = format!("{:016x}", cover_id(track));
+
+    for ext in ["jpg", "png", "gif", "bmp", "tiff", "img"] {
+        let path = cover_dir.join(&stem).with_extension(ext);
+        if path.is_file() {
+            return Some(path);
+        }
+    }
+
+    let tagged_file = Probe::open(&track.file_path).ok()?.read().ok()?;
+    let pictures = tagged_file.primary_tag()?.pictures();
+    let picture = pictures
+        .iter()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())?;
+    let ext = match picture.mime_type() {
+        Some(MimeType::Jpeg) => "jpg",
+        Some(MimeType::Png) => "png",
+        Some(MimeType::Gif) => "gif",
+        Some(MimeType::Bmp) => "bmp",
+        Some(MimeType::Tiff) => "tiff",
+        _ => "img",
     };
 
-    let mut artist = Artist::default().name(track.artist.as_str());
+    let path = cover_dir.join(&stem).with_extension(ext);
+    fs::create_dir_all(&cover_dir).ok()?;
+    fs::write(&path, picture.data()).ok()?;
+    Some(path)
+}
 
-    let mut album = Album::default().name(track.album.as_str()).year(track.year);
+/// Identify the cover a track shares with the rest of its album. Tracks
+/// without an album each have their own. The id names the cached cover file,
+/// so it is taken from a digest that is the same from run to run.
+pub fn cover_id(track: &Track) -> u64 {
+    let key = if track.album == "Unknown" {
+        track.file_path.clone()
+    } else {
+        format!("{}\0{}", track.grouping_artist(), track.album)
+    };
+    let digest = md5::compute(key);
+    u64::from_be_bytes(digest.0[..8].try_into().expect("MD5 digests are 16 bytes"))
+}
 
-    album.tracks.push(track.clone());
+/// Read a track's lyrics from an `.lrc` file beside it, or failing that from
+/// its lyrics tag (`USLT` in ID3, `LYRICS` in Vorbis comments). Returns `None`
+/// for streamed tracks and tracks without lyrics.
+pub fn read_lyrics(track: &Track) -> Option<Lyrics> {
+    if stream::is_url(&track.file_path) {
+        return None;
+    }
+    let sidecar = Path::new(&track.file_path).with_extension("lrc");
+    let text = match fs::read_to_string(sidecar) {
+        Ok(text) => text,
+        Err(_) => {
+            let tagged_file = Probe::open(&track.file_path).ok()?.read().ok()?;
+            tagged_file
+                .primary_tag()?
+                .get_string(&ItemKey::Lyrics)?
+                .to_owned()
+        }
+    };
+    (!text.trim().is_empty()).then(|| Lyrics::parse(&text))
+}
 
-    artist.albums.push(Album::default().name("All Albums"));
-    artist.albums[0].tracks.push(track.clone());
-    artist.albums.push(album.clone());
+/// Carry a track's play statistics, rating, added time, and unknown playlist
+/// directives over to its re-read metadata
+fn keep_stats(new_track: &mut Track, old_track: &Track) {
+    new_track.play_count = old_track.play_count;
+    new_track.last_played = old_track.last_played;
+    new_track.rating = old_track.rating;
+    new_track.added = old_track.added;
+    new_track.extra_tags.clone_from(&old_track.extra_tags);
+}
 
-    Ok((track, artist, album))
+/// A path made absolute with any symlinks resolved, or just made absolute
+/// if it no longer exists
+fn resolve_path(path: &Path) -> Result<PathBuf> {
+    fs::canonicalize(path).or_else(|_| Ok(path::absolute(path)?))
+}
+
+fn default_extensions() -> HashSet<String> {
+    DEFAULT_EXTENSIONS.into_iter().map(String::from).collect()
+}
+
+/// Check whether a path has one of the given extensions
+pub fn is_audio_file<P: AsRef<Path>>(path: P, extensions: &HashSet<String>) -> bool {
+    path.as_ref()
+        .extension()
+        .is_some_and(|ext| extensions.contains(ext.to_string_lossy().as_ref()))
+}
+
+/// Build an ad-hoc album out of the audio files in a directory, ignoring
+/// their album tags. Tracks are ordered by file path, and files that can't be
+/// read are skipped.
+pub fn folder_album<P: AsRef<Path>>(
+    dir: P,
+    recursive: bool,
+    extensions: &HashSet<String>,
+) -> Result<Album> {
+    fn collect(
+        dir: &Path,
+        recursive: bool,
+        extensions: &HashSet<String>,
+        paths: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                if recursive {
+                    collect(&path, recursive, extensions, paths)?;
+                }
+            } else if is_audio_file(&path, extensions) {
+                paths.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let dir = dir.as_ref();
+    if !dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", dir.display()));
+    }
+
+    let name = dir.file_name().map_or_else(
+        || dir.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+
+    let mut paths = Vec::new();
+    collect(dir, recursive, extensions, &mut paths)?;
+    paths.sort();
+
+    let mut album = Album::default().name(&name);
+    for path in paths {
+        if let Ok((mut track, _, _)) = get_track_data(path) {
+            track.album.clone_from(&name);
+            album.tracks.push(track);
+        }
+    }
+
+    Ok(album)
 }
 
 impl Library {
@@ -94,18 +356,67 @@ impl Library {
         Self {
             tracks: Playlist::new("Library"),
             known_paths: HashSet::new(),
+            sort_key: SortKey::default(),
+            extensions: default_extensions(),
+            max_depth: DEFAULT_SCAN_DEPTH,
+            import_errors: Vec::new(),
         }
     }
 
+    /// Replace the set of file extensions accepted by `add_path`
+    pub fn set_extensions(&mut self, extensions: &[String]) {
+        self.extensions = extensions.iter().map(|e| e.to_lowercase()).collect();
+    }
+
+    pub const fn extensions(&self) -> &HashSet<String> {
+        &self.extensions
+    }
+
+    /// Take the files `add_path` has failed to read since this was last
+    /// called, with the reasons why
+    pub fn take_import_errors(&mut self) -> Vec<(PathBuf, String)> {
+        mem::take(&mut self.import_errors)
+    }
+
+    /// Set how many directories deep `add_path` descends
+    pub const fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    pub const fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Sort the library's tracks by its sort key
+    pub fn sort(&mut self) {
+        let sort_key = self.sort_key;
+        self.tracks.tracks.sort_by(|a, b| sort_key.compare(a, b));
+    }
+
     pub fn add_path<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let path = path.as_ref();
+        self.add_path_at_depth(path.as_ref(), 0, &mut HashSet::new())
+    }
+
+    /// Add a path found `depth` directories below the one given to
+    /// `add_path`. Directories past the maximum depth are skipped, as are
+    /// directories already visited under another name, so that symlink
+    /// cycles don't make the scan loop forever.
+    fn add_path_at_depth(
+        &mut self,
+        path: &Path,
+        depth: usize,
+        visited: &mut HashSet<PathBuf>,
+    ) -> Result<()> {
         if !path.exists() {
             return Err(anyhow!("{}: No such file or directory", path.display()));
         }
         if path.is_dir() {
+            if depth > self.max_depth || !visited.insert(fs::canonicalize(path)?) {
+                return Ok(());
+            }
             for entry in fs::read_dir(path)? {
                 let entry = entry?;
-                self.add_path(entry.path())?;
+                self.add_path_at_depth(&entry.path(), depth + 1, visited)?;
             }
         } else {
             if self.known_paths.contains::<PathBuf>(&path::absolute(path)?) {
@@ -113,30 +424,153 @@ impl Library {
             }
 
             self.known_paths.insert(path::absolute(path)?);
-            if let Some(ext) = path.extension() {
-                let ext = ext.to_string_lossy().into_owned();
-                match ext.as_str() {
-                    "mp3" | "flac" | "aiff" | "m4a" | "ogg" | "opus" | "aac" | "wav" => {}
-                    _ => {
-                        return Ok(());
-                    }
-                }
-            } else {
+            if !is_audio_file(path, &self.extensions) {
                 return Ok(());
             }
-            let (track, _, _) = get_track_data(path)?;
+            // An unreadable file is noted rather than ending the scan
+            match get_track_data(path) {
+                Ok((mut track, _, _)) => {
+                    track.added = Some(SystemTime::now());
+                    self.tracks.tracks.push(track);
+                }
+                Err(e) => self.import_errors.push((path.to_path_buf(), e.to_string())),
+            }
+        }
 
-            // Add track to library
-            self.tracks.tracks.push(track);
+        Ok(())
+    }
+}
+
+impl Library {
+    /// Remove a track from the library, or all tracks under a directory.
+    /// Paths are compared with symlinks resolved, so either the link or its
+    /// target can be given. Returns the number of tracks removed.
+    pub fn remove_path<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        let path = resolve_path(path.as_ref())?;
+        let track_count = self.tracks.tracks.len();
+        self.tracks.tracks.retain(|t| {
+            resolve_path(Path::new(&t.file_path)).map_or(true, |p| !p.starts_with(&path))
+        });
+
+        let removed = track_count - self.tracks.tracks.len();
+        if removed == 0 {
+            return Err(anyhow!("{}: Not in library", path.display()));
         }
+        self.known_paths
+            .retain(|p| resolve_path(p).map_or(true, |p| !p.starts_with(&path)));
+        Ok(removed)
+    }
 
+    /// Re-read the metadata of every track in the library, dropping tracks
+    /// whose files no longer exist. Tracks whose files exist but can't be
+    /// read keep their old metadata. Returns the number of tracks rescanned
+    /// and the number removed.
+    pub fn rescan(&mut self) -> (usize, usize) {
+        let mut rescanned = 0;
+        let mut removed = 0;
+        let tracks = std::mem::take(&mut self.tracks.tracks);
+        for track in tracks {
+            if !track.exists() {
+                removed += 1;
+                continue;
+            }
+            match get_track_data(&track.file_path) {
+                Ok((mut new_track, _, _)) => {
+                    rescanned += 1;
+                    keep_stats(&mut new_track, &track);
+                    self.tracks.tracks.push(new_track);
+                }
+                Err(_) => self.tracks.tracks.push(track),
+            }
+        }
+        self.known_paths.retain(|p| p.exists());
+        (rescanned, removed)
+    }
+
+    /// Bring the library up to date with a single file: re-read its track if
+    /// it is already in the library, add it if it's new, or drop it if it no
+    /// longer exists
+    pub fn rescan_path<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path::absolute(path)?;
+        let existing = self
+            .tracks
+            .tracks
+            .iter()
+            .position(|t| path::absolute(&t.file_path).is_ok_and(|p| p == path));
+
+        if !path.exists() {
+            if let Some(index) = existing {
+                self.tracks.tracks.remove(index);
+            }
+            self.known_paths.remove(&path);
+            return Ok(());
+        }
+        if !is_audio_file(&path, &self.extensions) {
+            return Ok(());
+        }
+
+        let (mut track, _, _) = get_track_data(&path)?;
+        if let Some(index) = existing {
+            keep_stats(&mut track, &self.tracks.tracks[index]);
+            self.tracks.tracks[index] = track;
+        } else {
+            track.added = Some(SystemTime::now());
+            self.known_paths.insert(path);
+            self.tracks.tracks.push(track);
+        }
         Ok(())
     }
+
+    /// The top-most directories holding the library's files
+    pub fn root_dirs(&self) -> Vec<PathBuf> {
+        let dirs: HashSet<PathBuf> = self
+            .known_paths
+            .iter()
+            .filter_map(|p| path::absolute(p).ok()?.parent().map(Path::to_path_buf))
+            .collect();
+        dirs.iter()
+            .filter(|dir| !dir.ancestors().skip(1).any(|a| dirs.contains(a)))
+            .cloned()
+            .collect()
+    }
+
+    /// Count a play of the given track, if it is in the library. Returns
+    /// whether it was found.
+    pub fn record_play(&mut self, track: &Track) -> bool {
+        let Some(t) = self
+            .tracks
+            .tracks
+            .iter_mut()
+            .find(|t| t.file_path == track.file_path)
+        else {
+            return false;
+        };
+        t.play_count += 1;
+        t.last_played = Some(SystemTime::now());
+        true
+    }
+
+    /// The most played tracks in the library, most played first
+    pub fn most_played(&self, count: usize) -> Vec<&Track> {
+        let mut tracks: Vec<&Track> = self
+            .tracks
+            .tracks
+            .iter()
+            .filter(|t| t.play_count > 0)
+            .collect();
+        tracks.sort_by_key(|t| Reverse(t.play_count));
+        tracks.truncate(count);
+        tracks
+    }
 }
 
 impl Save for Library {
     fn save<P: AsRef<Path>>(&self, file_path: P) -> Result<()> {
-        self.tracks.save(file_path)
+        write_atomically(file_path, |file| {
+            self.tracks.write_m3u8(file)?;
+            writeln!(file, "#SORT:{}", self.sort_key)?;
+            Ok(())
+        })
     }
 }
 
@@ -145,14 +579,243 @@ impl Load for Library {
     where
         Self: Sized,
     {
-        let tracks = Playlist::load(file_path)?;
+        let text = fs::read_to_string(&file_path)?;
+        let tracks = Playlist::read_m3u8(text.as_bytes(), &file_path)?;
         let mut known_paths = HashSet::new();
         tracks.tracks.iter().for_each(|t| {
             known_paths.insert(PathBuf::from(&t.file_path));
         });
+
+        // The playlist parser skips the sort directive as a comment, so look
+        // for it separately. An unknown key leaves the default order.
+        let mut sort_key = SortKey::default();
+        let mut import_errors = Vec::new();
+        for key in text.lines().filter_map(|line| line.strip_prefix("#SORT:")) {
+            match key.parse() {
+                Ok(key) => sort_key = key,
+                Err(e) => import_errors.push((file_path.as_ref().to_path_buf(), e.to_string())),
+            }
+        }
+
         Ok(Self {
             tracks,
             known_paths,
+            sort_key,
+            extensions: default_extensions(),
+            max_depth: DEFAULT_SCAN_DEPTH,
+            import_errors,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{write_wav, TestDir};
+
+    fn track(file_path: &str, gain: Option<f32>) -> Track {
+        Track {
+            gain,
+            ..Track::from_url(file_path)
+        }
+    }
+
+    #[test]
+    fn tag_gain_takes_precedence_over_sidecar() {
+        let sidecar = GainSidecar::parse("/music/a.flac\t-3.00 dB\n/music/b.flac\t2.5\n");
+        assert_eq!(
+            sidecar.track_gain(&track("/music/a.flac", Some(-6.5))),
+            Some(-6.5)
+        );
+        assert_eq!(
+            sidecar.track_gain(&track("/music/a.flac", None)),
+            Some(-3.0)
+        );
+        assert_eq!(sidecar.track_gain(&track("/music/b.flac", None)), Some(2.5));
+        assert_eq!(sidecar.track_gain(&track("/music/c.flac", None)), None);
+    }
+
+    #[test]
+    fn sidecar_skips_lines_without_a_gain() {
+        let sidecar = GainSidecar::parse(
+            "# path\tgain\n\n/music/a.flac\tloud\n/music/b.flac\n/music/tab\tin name.flac\t-1 dB\n",
+        );
+        assert_eq!(sidecar.gains.len(), 1);
+        assert_eq!(
+            sidecar.track_gain(&track("/music/tab\tin name.flac", None)),
+            Some(-1.0)
+        );
+    }
+
+    #[test]
+    fn gain_factor_is_unity_without_a_gain() {
+        let sidecar = GainSidecar::default();
+        assert!((sidecar.gain_factor(&track("/music/a.flac", None)) - 1.0).abs() < f32::EPSILON);
+        assert!((sidecar.gain_factor(&track("/music/a.flac", Some(-20.0))) - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn added_time_and_sort_key_survive_saving() {
+        let dir = TestDir::new("added_time_and_sort_key_survive_saving");
+        let file_path = dir.path().join("library.m3u8");
+        let mut library = Library::new();
+        library.sort_key = SortKey::Added;
+        for (file_path, secs) in [("/music/b.flac", 20), ("/music/a.flac", 10)] {
+            library.tracks.tracks.push(Track {
+                added: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+                ..Track::from_url(file_path)
+            });
+        }
+        library.save(&file_path).unwrap();
+
+        let mut loaded = Library::load(&file_path).unwrap();
+        assert_eq!(loaded.sort_key, SortKey::Added);
+        loaded.sort();
+        let paths: Vec<&str> = loaded
+            .tracks
+            .tracks
+            .iter()
+            .map(|t| t.file_path.as_str())
+            .collect();
+        assert_eq!(paths, ["/music/a.flac", "/music/b.flac"]);
+        assert_eq!(
+            loaded.tracks.tracks[0].added,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn unknown_sort_key_falls_back_to_the_default() {
+        let dir = TestDir::new("unknown_sort_key_falls_back_to_the_default");
+        let file_path = dir.path().join("library.m3u8");
+        fs::write(&file_path, "#EXTM3U\n/music/a.flac\n#SORT:mood\n").unwrap();
+
+        let mut library = Library::load(&file_path).unwrap();
+        assert_eq!(library.sort_key, SortKey::default());
+        assert_eq!(library.tracks.tracks.len(), 1);
+        let import_errors = library.take_import_errors();
+        assert_eq!(import_errors.len(), 1);
+        assert_eq!(import_errors[0].0, file_path);
+    }
+
+    fn library_of(paths: &[&Path]) -> Library {
+        let mut library = Library::new();
+        for path in paths {
+            let file_path = path.to_str().unwrap();
+            library.tracks.tracks.push(Track::from_url(file_path));
+            library.known_paths.insert(path.to_path_buf());
+        }
+        library
+    }
+
+    #[test]
+    fn remove_path_leaves_known_paths_alone_when_nothing_matches() {
+        let mut library = library_of(&[Path::new("/music/a/1.flac")]);
+        assert!(library.remove_path("/music/b").is_err());
+        assert_eq!(library.known_paths.len(), 1);
+        assert_eq!(library.tracks.tracks.len(), 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn remove_path_removes_a_directory_through_a_symlink() {
+        let dir = TestDir::new("remove_path_symlink");
+        let music = dir.path().join("music");
+        fs::create_dir(&music).unwrap();
+        let kept = dir.path().join("kept.flac");
+        for file in [music.join("1.flac"), music.join("2.flac"), kept.clone()] {
+            fs::write(file, "").unwrap();
+        }
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&music, &link).unwrap();
+        let mut library = library_of(&[&link.join("1.flac"), &music.join("2.flac"), &kept]);
+
+        assert_eq!(library.remove_path(&music).unwrap(), 2);
+        assert_eq!(library.tracks.tracks.len(), 1);
+        assert_eq!(library.known_paths, HashSet::from([kept]));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn add_path_stops_at_symlink_loops() {
+        let dir = TestDir::new("add_path_symlink_loop");
+        let music = dir.path().join("music");
+        fs::create_dir(&music).unwrap();
+        write_wav(&music.join("a.wav"));
+        std::os::unix::fs::symlink(&music, music.join("loop")).unwrap();
+
+        let mut library = Library::new();
+        library.add_path(&music).unwrap();
+        assert_eq!(library.tracks.tracks.len(), 1);
+        assert!(library.take_import_errors().is_empty());
+    }
+
+    #[test]
+    fn add_path_stops_at_the_maximum_depth() {
+        let dir = TestDir::new("add_path_max_depth");
+        let deep = dir.path().join("a").join("b");
+        fs::create_dir_all(&deep).unwrap();
+        write_wav(&dir.path().join("a").join("1.wav"));
+        write_wav(&deep.join("2.wav"));
+
+        let mut library = Library::new();
+        library.set_max_depth(1);
+        library.add_path(dir.path()).unwrap();
+        assert_eq!(library.tracks.tracks.len(), 1);
+    }
+
+    #[test]
+    fn folder_album_orders_tracks_by_path_under_the_folder_name() {
+        let dir = TestDir::new("folder_album");
+        let album_dir = dir.path().join("Live Set");
+        let disc = album_dir.join("disc 2");
+        fs::create_dir_all(&disc).unwrap();
+        write_wav(&album_dir.join("b.wav"));
+        write_wav(&album_dir.join("a.wav"));
+        write_wav(&disc.join("c.wav"));
+        fs::write(album_dir.join("notes.txt"), "not audio").unwrap();
+        fs::write(album_dir.join("broken.wav"), "not audio either").unwrap();
+
+        let album = folder_album(&album_dir, false, &default_extensions()).unwrap();
+        assert_eq!(album.name, "Live Set");
+        let files: Vec<&str> = album
+            .tracks
+            .iter()
+            .map(|t| t.file_path.rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(files, ["a.wav", "b.wav"]);
+        assert!(album.tracks.iter().all(|t| t.album == "Live Set"));
+
+        let album = folder_album(&album_dir, true, &default_extensions()).unwrap();
+        assert_eq!(album.tracks.len(), 3);
+        assert!(album.tracks[2].file_path.ends_with("disc 2/c.wav"));
+    }
+
+    #[test]
+    fn folder_album_needs_a_directory() {
+        let dir = TestDir::new("folder_album_file");
+        let file = dir.path().join("a.wav");
+        write_wav(&file);
+        assert!(folder_album(&file, false, &default_extensions()).is_err());
+        assert!(folder_album(dir.path().join("missing"), false, &default_extensions()).is_err());
+    }
+
+    #[test]
+    fn cover_ids_are_shared_by_an_album_and_stable() {
+        let track = |file_path: &str, album: &str| Track {
+            artist: "Artist".to_owned(),
+            album: album.to_owned(),
+            ..Track::from_url(file_path)
+        };
+        let id = cover_id(&track("/music/1.flac", "Album"));
+        assert_eq!(id, cover_id(&track("/music/2.flac", "Album")));
+        assert_ne!(id, cover_id(&track("/music/1.flac", "Other")));
+        assert_ne!(
+            cover_id(&track("/music/1.flac", "Unknown")),
+            cover_id(&track("/music/2.flac", "Unknown"))
+        );
+        // The first half of the MD5 of "Artist\0Album", which mustn't change
+        // or covers already cached would be extracted again
+        assert_eq!(id, 0xbb51fc665b751532);
+    }
+}
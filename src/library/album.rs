@@ -4,10 +4,10 @@
 
 use ratatui::widgets::ListItem;
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
+use std::{cmp::Ordering, path::PathBuf, time::Duration};
 
 use super::track::Track;
-use crate::util::to_width;
+use crate::util::{half_panel_width, with_track_summary};
 
 #[derive(Clone, Default, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct Album {
@@ -31,6 +31,36 @@ impl Album {
         self.year = year;
         self
     }
+
+    /// The combined length of all the tracks in the album
+    pub fn total_duration(&self) -> Duration {
+        self.tracks.iter().map(|t| t.length).sum()
+    }
+
+    /// The number of distinct discs the album's tracks are on, taking tracks
+    /// without a disc number as being on the first disc
+    pub fn disc_count(&self) -> usize {
+        let mut discs: Vec<u32> = self.tracks.iter().map(|t| t.disc.unwrap_or(1)).collect();
+        discs.sort_unstable();
+        discs.dedup();
+        discs.len()
+    }
+
+    /// Identify the album's cover, as `library::cover_id` does for its
+    /// tracks. "All Albums" has none of its own.
+    pub fn cover_id(&self) -> Option<u64> {
+        if self.name == "All Albums" {
+            return None;
+        }
+        self.tracks.first().map(super::cover_id)
+    }
+
+    /// Get the path to an image file of the album's cover art, from the
+    /// first of its tracks with a picture embedded. See `library::cover_art`.
+    pub fn cover_art(&self) -> Option<PathBuf> {
+        self.cover_id()?;
+        self.tracks.iter().find_map(super::cover_art)
+    }
 }
 
 /// Albums sort alphabetically
@@ -54,15 +84,53 @@ impl PartialOrd for Album {
 
 impl<'a> From<&Album> for ListItem<'a> {
     fn from(val: &Album) -> Self {
-        let title = val.name.clone();
+        // Every disc of every album makes up "All Albums", so its count means
+        // nothing
+        let title = match val.disc_count() {
+            discs if discs > 1 && val.name != "All Albums" => {
+                format!("{} ({discs} discs)", val.name)
+            }
+            _ => val.name.clone(),
+        };
         let year = val.year.map_or_else(String::new, |y| y.to_string());
-        let term_width = crossterm::terminal::size().unwrap_or((80, 24)).0 as usize;
         // The albums pane takes up half of the terminal width
-        let block_width = term_width / 2;
-        // The 2 sides of the block take up 1 char each
-        let text_width = block_width.saturating_sub(2);
-        // The year takes up 4 chars. What remains is for the album title
+        let text_width = half_panel_width();
+        // The year takes up 4 chars. What remains is for the album title and
+        // its track summary
         let title_width = text_width.saturating_sub(4);
-        ListItem::new(format!("{}{}", to_width(&title, title_width, false), year))
+        ListItem::new(format!(
+            "{}{}",
+            with_track_summary(&title, val.tracks.len(), val.total_duration(), title_width),
+            year
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn album_on_discs(discs: &[Option<u32>]) -> Album {
+        let tracks = discs
+            .iter()
+            .map(|&disc| Track {
+                disc,
+                ..Track::from_url("/music/a.flac")
+            })
+            .collect();
+        Album {
+            tracks,
+            ..Album::default().name("Album")
+        }
+    }
+
+    #[test]
+    fn disc_count_counts_distinct_discs() {
+        assert_eq!(album_on_discs(&[]).disc_count(), 0);
+        assert_eq!(album_on_discs(&[None, None]).disc_count(), 1);
+        // Tracks without a disc number are on the first disc
+        assert_eq!(album_on_discs(&[None, Some(1)]).disc_count(), 1);
+        assert_eq!(album_on_discs(&[Some(1), Some(2), Some(2)]).disc_count(), 2);
+        assert_eq!(album_on_discs(&[None, Some(3)]).disc_count(), 2);
     }
 }
@@ -2,10 +2,12 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use ratatui::widgets::ListItem;
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
+use std::{cmp::Ordering, time::Duration};
 
 use super::album::Album;
+use crate::util::{half_panel_width, with_track_summary};
 
 #[derive(Clone, Default, Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub struct Artist {
@@ -27,6 +29,13 @@ impl Artist {
     pub fn get_album_index(&self, name: &str) -> Option<usize> {
         self.albums.iter().position(|a| a.name == name)
     }
+
+    /// The combined length of all of the artist's tracks
+    pub fn total_duration(&self) -> Duration {
+        self.albums
+            .first()
+            .map_or(Duration::ZERO, Album::total_duration)
+    }
 }
 
 /// Artists sort alphabetically
@@ -47,3 +56,15 @@ impl PartialOrd for Artist {
         Some(self.cmp(other))
     }
 }
+
+impl<'a> From<&Artist> for ListItem<'a> {
+    fn from(val: &Artist) -> Self {
+        let track_count = val.albums.first().map_or(0, |a| a.tracks.len());
+        ListItem::new(with_track_summary(
+            &val.name,
+            track_count,
+            val.total_duration(),
+            half_panel_width(),
+        ))
+    }
+}
@@ -2,12 +2,23 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use crate::util::to_width;
-use ratatui::widgets::ListItem;
+use crate::{stream, util::to_width};
+use anyhow::{anyhow, Error};
+use ratatui::{
+    style::{Modifier, Style},
+    widgets::ListItem,
+};
 use serde::{Deserialize, Serialize};
-use std::{cmp::Ordering, fmt::Display, time::Duration};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::Display,
+    path::Path,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
 
-#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Track {
     /// Track name from metadata, if no name is present, filename will be
     /// displayed instead
@@ -16,6 +27,11 @@ pub struct Track {
     /// Artist name from metadata if present
     pub artist: String,
 
+    /// Album artist name from metadata if present. Tracks are grouped under
+    /// this rather than `artist` when it is set.
+    #[serde(default)]
+    pub album_artist: Option<String>,
+
     /// Album name from metadata if present
     pub album: String,
 
@@ -25,24 +41,124 @@ pub struct Track {
     /// Track number if present
     pub number: Option<u32>,
 
+    /// Disc number if present
+    #[serde(default)]
+    pub disc: Option<u32>,
+
     /// Track duration
     pub length: Duration,
 
     /// Path to the audio file
     pub file_path: String,
+
+    /// Genre from metadata if present
+    #[serde(default)]
+    pub genre: Option<String>,
+
+    /// ReplayGain track gain in dB, from metadata if present
+    #[serde(default)]
+    pub gain: Option<f32>,
+
+    /// Number of times the track has been played through at least halfway
+    #[serde(default)]
+    pub play_count: u32,
+
+    /// When the track was last counted as played
+    #[serde(default)]
+    pub last_played: Option<SystemTime>,
+
+    /// Star rating from 1 to 5, if the track has been rated
+    #[serde(default)]
+    pub rating: Option<u8>,
+
+    /// When the track was added to the library, if known
+    #[serde(default)]
+    pub added: Option<SystemTime>,
+
+    /// Whether the audio file could not be found when the track was loaded
+    #[serde(skip)]
+    pub missing: bool,
+
+    /// Playlist directives that aren't understood, written back out as they
+    /// were read when the track is saved. Not compared or sorted on.
+    #[serde(default)]
+    pub extra_tags: Vec<String>,
 }
 
-/// Tracks sort first by artist. If they have the same artist, then they sort by
-/// album. If they're on the same album, they then sort by track number. If
+impl Track {
+    /// The artist this track is grouped under: the album artist if present,
+    /// otherwise the track artist
+    pub fn grouping_artist(&self) -> &str {
+        self.album_artist.as_deref().unwrap_or(&self.artist)
+    }
+
+    /// A track streamed from a URL. Nothing is known about it but where it
+    /// is.
+    pub fn from_url(url: &str) -> Self {
+        Self {
+            title: None,
+            artist: "Unknown".to_owned(),
+            album_artist: None,
+            album: "Unknown".to_owned(),
+            year: None,
+            number: None,
+            disc: None,
+            length: Duration::ZERO,
+            file_path: url.to_owned(),
+            genre: None,
+            gain: None,
+            play_count: 0,
+            last_played: None,
+            rating: None,
+            added: None,
+            missing: false,
+            extra_tags: Vec::new(),
+        }
+    }
+
+    /// Check whether the audio file for this track currently exists. Streamed
+    /// tracks are assumed to.
+    pub fn exists(&self) -> bool {
+        stream::is_url(&self.file_path) || Path::new(&self.file_path).is_file()
+    }
+}
+
+/// Tracks sort first by artist, using the album artist where present so that
+/// compilations stay together. If they have the same artist, then they sort by
+/// album. If they're on the same album, they then sort by disc number, taking
+/// tracks without one as being on the first disc, and then by track number. If
 /// track number is not applicable to one or both of them, then they sort by
 /// title. If title is not applicable to one or both of them, then the filename
 /// is substituted for the title.
+// Play statistics, ratings, when the track was added, and whether the file is
+// missing are left out so that they don't affect sorting or deduplication
+impl PartialEq for Track {
+    fn eq(&self, other: &Self) -> bool {
+        self.title == other.title
+            && self.artist == other.artist
+            && self.album_artist == other.album_artist
+            && self.album == other.album
+            && self.year == other.year
+            && self.number == other.number
+            && self.disc == other.disc
+            && self.length == other.length
+            && self.file_path == other.file_path
+            && self.genre == other.genre
+            && self.gain == other.gain
+    }
+}
+
+// Gains are never NaN, as only finite values are read from tags and playlists
+impl Eq for Track {}
+
 impl Ord for Track {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self.artist != other.artist {
-            self.artist.cmp(&other.artist)
+        if self.grouping_artist() != other.grouping_artist() {
+            self.grouping_artist().cmp(other.grouping_artist())
         } else if self.album != other.album {
             self.album.cmp(&other.album)
+        } else if self.disc.unwrap_or(1) != other.disc.unwrap_or(1) {
+            self.disc.unwrap_or(1).cmp(&other.disc.unwrap_or(1))
         } else if let (Some(self_num), Some(other_num)) = (self.number, other.number) {
             self_num.cmp(&other_num)
         } else {
@@ -67,41 +183,256 @@ impl PartialOrd for Track {
     }
 }
 
-impl<'a> From<&Track> for ListItem<'a> {
-    fn from(val: &Track) -> Self {
-        let title = val.title.as_ref().unwrap_or(&val.file_path);
-        let artist = &val.artist;
-        let album = &val.album;
-        let year = val.year.map_or_else(String::new, |y| y.to_string());
-        let length = format!(
-            "{}:{:02}",
-            val.length.as_secs() / 60,
-            val.length.as_secs() % 60
-        );
+/// A column in the track list
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum TrackColumn {
+    /// The track number, as in `01.`
+    Number,
+    Title,
+    Artist,
+    Album,
+    Year,
+    Length,
+    Rating,
+}
+
+/// A column in the track list and its share of the list's width
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub struct ColumnWidth {
+    pub column: TrackColumn,
+    pub weight: u16,
+}
+
+/// How the track list is laid out: which columns are shown in what order and
+/// at what relative widths, and the minimum list width for each column
+#[derive(Debug, Clone, Default)]
+pub struct ColumnLayout {
+    pub columns: Vec<ColumnWidth>,
+    pub thresholds: HashMap<TrackColumn, u16>,
+    /// Whether to draw ratings with Nerd Font star glyphs
+    pub nerd_font_icons: bool,
+}
+
+const NF_STAR: char = '\u{f005}';
+const NF_STAR_EMPTY: char = '\u{f006}';
 
-        let box_width = crossterm::terminal::size()
-            .unwrap_or((80, 24))
-            .0
-            .saturating_sub(2) as usize;
-
-        let col = box_width / 5;
-        let col_widths = match box_width % 5 {
-            0 => (col, col, col, col, col),
-            1 => (col + 1, col, col, col, col),
-            2 => (col + 1, col + 1, col, col, col),
-            3 => (col + 1, col + 1, col + 1, col, col),
-            4 => (col + 1, col + 1, col + 1, col + 1, col),
-            _ => unreachable!("Any number mod 5 will be within 0..=4"),
+/// The highest star rating a track can have
+pub const MAX_RATING: u8 = 5;
+
+impl Track {
+    /// Build the list item for this track from its laid out columns, as
+    /// made by `columns_text`
+    pub fn list_item<'a>(&self, columns_text: String) -> ListItem<'a> {
+        let item = ListItem::new(columns_text);
+
+        // Strike through tracks whose files have gone missing
+        if self.missing {
+            item.style(Style::default().add_modifier(Modifier::CROSSED_OUT))
+        } else {
+            item
+        }
+    }
+
+    /// Lay out the track's columns to fill exactly `box_width` characters.
+    /// Columns whose threshold is wider than `box_width` are dropped; columns
+    /// without a threshold are always shown. The year column falls back on
+    /// `album_year` for tracks without a year of their own.
+    pub fn columns_text(
+        &self,
+        box_width: u16,
+        layout: &ColumnLayout,
+        album_year: Option<u32>,
+    ) -> String {
+        use TrackColumn::{Album, Artist, Length, Number, Rating, Title, Year};
+
+        let columns: Vec<ColumnWidth> = layout
+            .columns
+            .iter()
+            .filter(|c| {
+                c.weight > 0
+                    && layout
+                        .thresholds
+                        .get(&c.column)
+                        .is_none_or(|threshold| box_width >= *threshold)
+            })
+            .copied()
+            .collect();
+        if columns.is_empty() {
+            return " ".repeat(box_width as usize);
+        }
+
+        // Split the width by weight, giving the characters lost to rounding
+        // down to the leftmost columns
+        let box_width = box_width as usize;
+        let total_weight: usize = columns.iter().map(|c| c.weight as usize).sum();
+        let widths: Vec<usize> = columns
+            .iter()
+            .map(|c| box_width * c.weight as usize / total_weight)
+            .collect();
+        let extra = box_width - widths.iter().sum::<usize>();
+
+        columns
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (column, width))| {
+                let width = if i < extra { width + 1 } else { width };
+                match column.column {
+                    Number => to_width(
+                        &self.number.map_or_else(String::new, |n| format!("{n:02}.")),
+                        width,
+                        true,
+                    ),
+                    Title => to_width(&self.to_string(), width, false),
+                    Artist => to_width(&self.artist, width, false),
+                    Album => to_width(&self.album, width, false),
+                    Year => to_width(
+                        &self
+                            .year
+                            .or(album_year)
+                            .map_or_else(String::new, |y| y.to_string()),
+                        width,
+                        true,
+                    ),
+                    Length => to_width(
+                        &format!(
+                            "{}:{:02}",
+                            self.length.as_secs() / 60,
+                            self.length.as_secs() % 60
+                        ),
+                        width,
+                        true,
+                    ),
+                    Rating => to_width(&self.stars(layout.nerd_font_icons), width, false),
+                }
+            })
+            .collect()
+    }
+
+    /// The track's rating drawn as stars, or nothing if it is unrated
+    fn stars(&self, nerd_font_icons: bool) -> String {
+        let Some(rating) = self.rating else {
+            return String::new();
         };
+        let (full, empty) = if nerd_font_icons {
+            (NF_STAR, NF_STAR_EMPTY)
+        } else {
+            ('*', ' ')
+        };
+        (0..MAX_RATING)
+            .map(|i| if i < rating { full } else { empty })
+            .collect()
+    }
+}
+
+/// A key by which a list of tracks can be sorted
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+pub enum SortKey {
+    /// The default track ordering (artist, album, disc, track number, title)
+    #[default]
+    Artist,
+    Album,
+    Title,
+    Year,
+    Length,
+    /// The order in which the tracks were added
+    Added,
+}
+
+impl SortKey {
+    /// Compare two tracks by this key, falling back on the default track
+    /// ordering for ties. `Added` puts tracks without an added time first and
+    /// considers tracks added at the same time equal, so that a stable sort
+    /// leaves them in place.
+    pub fn compare(self, a: &Track, b: &Track) -> Ordering {
+        match self {
+            Self::Artist => a.cmp(b),
+            Self::Album => a.album.cmp(&b.album).then_with(|| a.cmp(b)),
+            Self::Title => a
+                .to_string()
+                .to_lowercase()
+                .cmp(&b.to_string().to_lowercase())
+                .then_with(|| a.cmp(b)),
+            Self::Year => a.year.cmp(&b.year).then_with(|| a.cmp(b)),
+            Self::Length => a.length.cmp(&b.length).then_with(|| a.cmp(b)),
+            Self::Added => a.added.cmp(&b.added),
+        }
+    }
+}
 
-        ListItem::new(format!(
-            "{}{}{}{}{}",
-            to_width(title, col_widths.0, false),
-            to_width(artist, col_widths.1, false),
-            to_width(album, col_widths.2, false),
-            to_width(&year, col_widths.3, true),
-            to_width(&length, col_widths.4, true),
-        ))
+impl FromStr for SortKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "artist" => Ok(Self::Artist),
+            "album" => Ok(Self::Album),
+            "title" => Ok(Self::Title),
+            "year" => Ok(Self::Year),
+            "length" | "duration" => Ok(Self::Length),
+            "added" => Ok(Self::Added),
+            other => Err(anyhow!("Invalid sort key: {other}")),
+        }
+    }
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Artist => "artist",
+            Self::Album => "album",
+            Self::Title => "title",
+            Self::Year => "year",
+            Self::Length => "length",
+            Self::Added => "added",
+        })
+    }
+}
+
+/// Which way a sort runs
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+pub enum SortDirection {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+impl SortDirection {
+    /// The opposite direction
+    pub const fn reversed(self) -> Self {
+        match self {
+            Self::Ascending => Self::Descending,
+            Self::Descending => Self::Ascending,
+        }
+    }
+
+    /// Apply this direction to an ascending comparison
+    pub fn apply(self, ordering: Ordering) -> Ordering {
+        match self {
+            Self::Ascending => ordering,
+            Self::Descending => ordering.reverse(),
+        }
+    }
+}
+
+impl FromStr for SortDirection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asc" | "ascending" => Ok(Self::Ascending),
+            "desc" | "descending" => Ok(Self::Descending),
+            other => Err(anyhow!("Invalid sort direction: {other}")),
+        }
+    }
+}
+
+impl Display for SortDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Ascending => "ascending",
+            Self::Descending => "descending",
+        })
     }
 }
 
@@ -114,3 +445,183 @@ impl Display for Track {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_file_does_not_affect_equality() {
+        let track = Track::from_url("/music/a.flac");
+        let missing = Track {
+            missing: true,
+            ..track.clone()
+        };
+        assert_eq!(track, missing);
+    }
+
+    fn added_at(file_path: &str, secs: Option<u64>) -> Track {
+        Track {
+            added: secs.map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+            ..Track::from_url(file_path)
+        }
+    }
+
+    #[test]
+    fn added_sorts_by_added_time() {
+        let mut tracks = [
+            added_at("/music/c.flac", Some(20)),
+            added_at("/music/a.flac", Some(30)),
+            added_at("/music/b.flac", None),
+            added_at("/music/d.flac", Some(10)),
+        ];
+        tracks.sort_by(|a, b| SortKey::Added.compare(a, b));
+        let paths: Vec<&str> = tracks.iter().map(|t| t.file_path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/music/b.flac",
+                "/music/d.flac",
+                "/music/c.flac",
+                "/music/a.flac"
+            ]
+        );
+    }
+
+    #[test]
+    fn added_leaves_tracks_added_together_in_place() {
+        let mut tracks = [
+            added_at("/music/b.flac", Some(10)),
+            added_at("/music/a.flac", Some(10)),
+        ];
+        tracks.sort_by(|a, b| SortKey::Added.compare(a, b));
+        assert_eq!(tracks[0].file_path, "/music/b.flac");
+    }
+
+    fn layout() -> ColumnLayout {
+        use TrackColumn::{Album, Artist, Length, Title, Year};
+        ColumnLayout {
+            columns: [Title, Artist, Album, Year, Length]
+                .into_iter()
+                .map(|column| ColumnWidth { column, weight: 1 })
+                .collect(),
+            thresholds: HashMap::from([(Album, 60), (Year, 40)]),
+            nerd_font_icons: false,
+        }
+    }
+
+    fn sample() -> Track {
+        Track {
+            title: Some("Title".to_owned()),
+            artist: "Artist".to_owned(),
+            album: "Album".to_owned(),
+            year: Some(1999),
+            length: Duration::from_secs(187),
+            ..Track::from_url("/music/a.flac")
+        }
+    }
+
+    #[test]
+    fn columns_are_hidden_below_their_thresholds() {
+        let track = sample();
+        let layout = layout();
+
+        let wide = track.columns_text(60, &layout, None);
+        assert!(wide.contains("Album") && wide.contains("1999"));
+
+        let medium = track.columns_text(59, &layout, None);
+        assert!(!medium.contains("Album") && medium.contains("1999"));
+
+        let narrow = track.columns_text(39, &layout, None);
+        assert!(!narrow.contains("Album") && !narrow.contains("1999"));
+        assert!(narrow.contains("Title") && narrow.contains("Artist") && narrow.contains("3:07"));
+    }
+
+    #[test]
+    fn columns_fill_the_width_exactly() {
+        let track = sample();
+        let layout = layout();
+        for width in [0, 1, 7, 39, 40, 59, 60, 61, 123] {
+            let text = track.columns_text(width, &layout, None);
+            assert_eq!(text.chars().count(), width as usize, "width {width}");
+        }
+        let nothing_shown = ColumnLayout::default();
+        assert_eq!(track.columns_text(10, &nothing_shown, None), " ".repeat(10));
+    }
+
+    #[test]
+    fn year_falls_back_on_the_album_year() {
+        let track = Track {
+            year: None,
+            ..sample()
+        };
+        assert!(track
+            .columns_text(80, &layout(), Some(2004))
+            .contains("2004"));
+        assert!(sample()
+            .columns_text(80, &layout(), Some(2004))
+            .contains("1999"));
+    }
+
+    #[test]
+    fn sort_keys_fall_back_on_the_default_order() {
+        let track = |artist: &str, title: &str, year| Track {
+            title: Some(title.to_owned()),
+            artist: artist.to_owned(),
+            year,
+            ..Track::from_url(&format!("/music/{artist}/{title}.flac"))
+        };
+        let a = track("A", "zebra", Some(2001));
+        let b = track("B", "Apple", Some(2001));
+        let c = track("C", "apple", None);
+
+        assert_eq!(SortKey::Title.compare(&a, &b), Ordering::Greater);
+        // Titles equal but for case are ordered by artist
+        assert_eq!(SortKey::Title.compare(&b, &c), Ordering::Less);
+        assert_eq!(SortKey::Year.compare(&a, &b), Ordering::Less);
+        assert_eq!(SortKey::Year.compare(&c, &a), Ordering::Less);
+        assert_eq!(SortKey::Artist.compare(&a, &b), Ordering::Less);
+    }
+
+    #[test]
+    fn sort_direction_reverses_the_comparison() {
+        use SortDirection::{Ascending, Descending};
+        assert_eq!(Ascending.apply(Ordering::Less), Ordering::Less);
+        assert_eq!(Descending.apply(Ordering::Less), Ordering::Greater);
+        assert_eq!(Descending.apply(Ordering::Equal), Ordering::Equal);
+        assert_eq!(Ascending.reversed(), Descending);
+        assert_eq!("desc".parse::<SortDirection>().unwrap(), Descending);
+        assert_eq!("duration".parse::<SortKey>().unwrap(), SortKey::Length);
+        assert!("rating".parse::<SortKey>().is_err());
+    }
+
+    #[test]
+    fn tracks_sort_by_disc_before_track_number() {
+        let track = |disc, number| Track {
+            disc,
+            number: Some(number),
+            ..Track::from_url(&format!("/music/{disc:?}-{number}.flac"))
+        };
+        let mut tracks = [
+            track(Some(2), 1),
+            track(Some(1), 2),
+            track(None, 3),
+            track(Some(3), 1),
+            track(Some(1), 1),
+        ];
+        tracks.sort();
+        let order: Vec<(Option<u32>, Option<u32>)> =
+            tracks.iter().map(|t| (t.disc, t.number)).collect();
+        // No disc number counts as the first disc
+        assert_eq!(
+            order,
+            [
+                (Some(1), Some(1)),
+                (Some(1), Some(2)),
+                (None, Some(3)),
+                (Some(2), Some(1)),
+                (Some(3), Some(1))
+            ]
+        );
+    }
+}
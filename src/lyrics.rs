@@ -0,0 +1,170 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Lyrics as found in `.lrc` files and lyrics tags, either timed line by
+//! line or as plain text.
+
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lyrics {
+    /// Lines with the time each starts at, earliest first
+    Synced(Vec<(Duration, String)>),
+    /// Lines without any timing, shown all at once
+    Unsynced(Vec<String>),
+}
+
+impl Lyrics {
+    /// Read lyrics as LRC if any line is timed, or as plain text otherwise
+    pub fn parse(text: &str) -> Self {
+        let lines = parse_lrc(text);
+        if lines.is_empty() {
+            Self::Unsynced(
+                text.lines()
+                    .map(|line| line.trim_end().to_owned())
+                    .collect(),
+            )
+        } else {
+            Self::Synced(lines)
+        }
+    }
+
+    /// The text of each line, in order
+    pub fn lines(&self) -> Vec<&str> {
+        match self {
+            Self::Synced(lines) => lines.iter().map(|(_, line)| line.as_str()).collect(),
+            Self::Unsynced(lines) => lines.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// The index of the line being sung at a point in the track, or `None`
+    /// before the first line or if the lyrics aren't timed
+    pub fn current_line(&self, progress: Duration) -> Option<usize> {
+        match self {
+            Self::Synced(lines) => lines
+                .partition_point(|(start, _)| *start <= progress)
+                .checked_sub(1),
+            Self::Unsynced(_) => None,
+        }
+    }
+}
+
+/// Parse the timed lines of LRC text, such as `[01:23.45]Some words`. A line
+/// with several timestamps is repeated at each of them, an `[offset:ms]` tag
+/// moves every line earlier by that many milliseconds, and other tags and
+/// untimed lines are skipped.
+pub fn parse_lrc(text: &str) -> Vec<(Duration, String)> {
+    let mut offset_ms: i64 = 0;
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut times = Vec::new();
+        while let Some((tag, after)) = rest
+            .strip_prefix('[')
+            .and_then(|tagged| tagged.split_once(']'))
+        {
+            if let Some(time) = parse_timestamp(tag) {
+                times.push(time);
+            } else if let Some(offset) = tag.strip_prefix("offset:") {
+                offset_ms = offset.trim().parse().unwrap_or(offset_ms);
+            }
+            rest = after;
+        }
+        lines.extend(times.into_iter().map(|time| (time, rest.trim().to_owned())));
+    }
+
+    if offset_ms != 0 {
+        let shift = Duration::from_millis(offset_ms.unsigned_abs());
+        // A line pushed past the longest possible time could never be sung
+        lines = lines
+            .into_iter()
+            .filter_map(|(time, line)| {
+                let time = if offset_ms > 0 {
+                    Some(time.saturating_sub(shift))
+                } else {
+                    time.checked_add(shift)
+                };
+                Some((time?, line))
+            })
+            .collect();
+    }
+    lines.sort_by_key(|(time, _)| *time);
+    lines
+}
+
+/// Parse an LRC timestamp of minutes and seconds, such as `01:23.45`, with
+/// the fraction of a second optional. Returns `None` for anything else,
+/// including times too long to represent.
+fn parse_timestamp(tag: &str) -> Option<Duration> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    // Some files separate the hundredths with a colon as well
+    let seconds: f64 = seconds.trim().replacen(':', ".", 1).parse().ok()?;
+    let seconds = Duration::try_from_secs_f64(seconds).ok()?;
+    Duration::from_secs(minutes.checked_mul(60)?).checked_add(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn lines_are_repeated_at_each_timestamp_in_order() {
+        let lines =
+            parse_lrc("[ar:Someone]\n[00:10.50][01:00]Chorus\n[00:05:25] Verse \nUntimed\n");
+        assert_eq!(
+            lines,
+            vec![
+                (secs(5.25), "Verse".to_owned()),
+                (secs(10.5), "Chorus".to_owned()),
+                (secs(60.0), "Chorus".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn offset_moves_lines_earlier_or_later() {
+        let text = "[offset:+1500]\n[00:01.00]First\n[00:03.00]Second\n";
+        assert_eq!(
+            parse_lrc(text),
+            vec![
+                (Duration::ZERO, "First".to_owned()),
+                (secs(1.5), "Second".to_owned()),
+            ]
+        );
+        let text = "[offset:-500]\n[00:01.00]First\n";
+        assert_eq!(parse_lrc(text), vec![(secs(1.5), "First".to_owned())]);
+    }
+
+    #[test]
+    fn malformed_timestamps_are_skipped() {
+        let text = format!(
+            "[00:1e30]Huge\n[{}:00]Overflow\n[-1:00]Negative\n[00:-5]Negative\n[00:NaN]NaN\n[00:02]Fine\n",
+            u64::MAX / 2
+        );
+        assert_eq!(parse_lrc(&text), vec![(secs(2.0), "Fine".to_owned())]);
+        assert!(parse_timestamp(&format!("{}:59", u64::MAX / 60)).is_none());
+    }
+
+    #[test]
+    fn negative_offset_drops_lines_it_would_overflow() {
+        let minutes = Duration::MAX.as_secs() / 60;
+        let text = format!("[offset:-60000]\n[{minutes}:00]Late\n[00:01]Early\n");
+        assert_eq!(parse_lrc(&text), vec![(secs(61.0), "Early".to_owned())]);
+    }
+
+    #[test]
+    fn current_line_follows_progress() {
+        let lyrics = Lyrics::parse("[00:01]One\n[00:02]Two\n[00:04]Three\n");
+        assert_eq!(lyrics.current_line(secs(0.5)), None);
+        assert_eq!(lyrics.current_line(secs(1.0)), Some(0));
+        assert_eq!(lyrics.current_line(secs(3.9)), Some(1));
+        assert_eq!(lyrics.current_line(secs(100.0)), Some(2));
+        assert_eq!(Lyrics::parse("Just text").current_line(secs(1.0)), None);
+    }
+}
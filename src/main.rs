@@ -7,69 +7,123 @@
     clippy::module_name_repetitions,
     clippy::future_not_send
 )]
+#![cfg_attr(all(test, feature = "bench"), feature(test))]
 
 use std::{
     collections::VecDeque,
     env, fs, io,
-    path::Path,
+    path::{Path, PathBuf},
     process,
     sync::Arc,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use async_std::sync::Mutex;
 
 use anyhow::{anyhow, Result};
-use config::ConfOption;
+use config::{ConfOption, ConfigWarning, NumOption};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use getopts::Options;
 use media_system::MediaSystem;
 use ratatui::{backend::CrosstermBackend, Terminal};
+use scrobble::Scrobbler;
 
 #[cfg(target_os = "linux")]
 use mpris_server::Server;
 
 mod command;
 mod config;
+mod cover;
 mod library;
+mod lyrics;
 mod media_system;
+mod notify;
+mod output;
 mod playlist;
+mod queue;
+mod scrobble;
+mod search;
+mod smart_playlist;
+mod station;
+mod stream;
 mod traits;
 mod ui;
 mod util;
+mod visualizer;
+mod watch;
 
+#[cfg(not(target_os = "linux"))]
+mod media_controls;
 #[cfg(target_os = "linux")]
 mod mpris;
 
-use library::{get_track_data, Library};
+use library::{folder_album, get_track_data, GainSidecar, Library};
 use traits::{Load, Save};
-use ui::UI;
+use ui::{ScreenEnum, UI};
+use visualizer::Visualizer;
+use watch::LibraryWatcher;
 
 use command::Command::{
-    AddPath, Down, EnterCommand, GotoBottom, GotoScreen, GotoTop, NewPlaylist, NextPanel,
-    NextTrack, Nop, Pause, Play, PlayTrack, PlaylistAdd, PrevPanel, PrevTrack, QueueAndPlay, Quit,
-    SelectPlaylist, Stop, TogglePlay, ToggleRepeat, ToggleShuffle, Up,
+    AddPath, AddStation, ClearFilters, ClearPlaylist, Collapse, Down, DuplicatePlaylist,
+    EnterCommand, Expand, ExportPlaylist, FilterGenre, FilterRating, GotoBottom, GotoIndex,
+    GotoScreen, GotoTime, GotoTop, HalfPageDown, HalfPageUp, NewPlaylist, NewSmartPlaylist,
+    NextPanel, NextTrack, Nop, PageDown, PageUp, Pause, Play, PlayFolder, PlayNext, PlayQueued,
+    PlayTrack, PlaylistAdd, PrevPanel, PrevTrack, QueueAllVisible, QueueAndPlay, QueueTrack, Quit,
+    Rate, ReloadConfig, RemovePath, RemoveQueued, RemoveStation, Rescan, RescanPaths, Reshuffle,
+    SaveQueue, Search, SeekBackward, SeekForward, SelectPlaylist, SetLoopA, SetLoopB, SetMatchMode,
+    SetOption, SetRepeat, SetSpeed, SetTheme, SetVolume, ShowErrors, SleepTimer, SortBy,
+    SortLibrary, SpeedDown, SpeedReset, SpeedUp, Stats, Stop, StopAfter, ToggleFavorite,
+    ToggleIcons, ToggleLyrics, ToggleMute, TogglePlay, ToggleRemaining, ToggleRepeat,
+    ToggleShuffle, Up, VolumeDown, VolumeUp,
 };
-use ui::MovementDirection::{Bottom, Next, Prev, Top};
+use ui::MovementDirection::{self, Bottom, HalfDown, HalfUp, Next, Prev, Top};
 
-use crate::{command::Command, config::Config, media_system::MediaState, playlist::Playlist};
+use crate::{
+    command::{Command, CountPrefix},
+    config::Config,
+    library::track::MAX_RATING,
+    media_system::{MediaState, Queueable},
+    playlist::Playlist,
+    smart_playlist::SmartPlaylist,
+    station::StationList,
+};
 
+#[cfg(not(target_os = "linux"))]
+use crate::media_controls::MediaKeys;
 #[cfg(target_os = "linux")]
 use crate::mpris::MprisPlayer;
 
 pub enum Mode {
     Normal,
     PlaylistEntry,
+    QueueSaveEntry,
     CommandEntry,
+    SearchEntry,
+    GotoTimeEntry,
 }
 
 #[cfg(target_os = "linux")]
 const BUS_NAME: &str = "xyz.jcheatum.RMuP";
 
+/// How many tracks the `stats` command lists
+const STATS_COUNT: usize = 5;
+
+/// How long before the end of a track to start preparing the next one for
+/// gapless playback
+const PRELOAD_TIME: Duration = Duration::from_secs(5);
+
+/// How often the UI is redrawn when nothing else has changed, so that the
+/// playback time keeps up
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long recorded plays may go unsaved, so that the whole library isn't
+/// written out after every track
+const STATS_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
 #[async_std::main]
 async fn main() -> Result<()> {
     let argv: Vec<String> = env::args().collect();
@@ -91,20 +145,32 @@ async fn main() -> Result<()> {
         process::exit(0);
     }
 
-    let data_dir = dirs_next::data_dir()
-        .expect("TODO: Handle other OSes")
-        .join("rmup");
+    let data_dir = util::resolve_data_dir()?;
     if !data_dir.exists() {
-        fs::create_dir(&data_dir)?;
+        fs::create_dir_all(&data_dir)?;
     }
 
-    let config_dir = dirs_next::config_dir()
-        .expect("TODO: Handle other OSes")
-        .join("rmup");
+    let config_dir = util::resolve_config_dir()?;
     if !config_dir.exists() {
-        fs::create_dir(&config_dir)?;
+        fs::create_dir_all(&config_dir)?;
     }
 
+    let config_file_path = if matches.opt_present("c") {
+        let path = matches
+            .opt_str("c")
+            .ok_or_else(|| anyhow!("Option '-c' requires an argument"))?;
+        PathBuf::from(path)
+    } else {
+        config_dir.join("config.yaml")
+    };
+    let (mut config, config_warnings) = if matches.opt_present("c") || config_file_path.exists() {
+        Config::load_with_warnings(&config_file_path)?
+    } else {
+        let c = Config::default();
+        c.save(&config_file_path)?;
+        (c, Vec::new())
+    };
+
     let lib_file_path = data_dir.join("library.m3u8");
     let mut lib = if matches.opt_present("l") {
         let path = matches
@@ -116,99 +182,170 @@ async fn main() -> Result<()> {
     } else {
         Library::new()
     };
-    lib.tracks.tracks.sort();
+    configure_library(&mut lib, &config);
 
     if matches.opt_present("a") {
         let path = matches
             .opt_str("a")
             .ok_or_else(|| anyhow!("Option '-a' requires an argument"))?;
         lib.add_path(path)?;
-        lib.tracks.tracks.sort();
+        lib.sort();
         lib.save(&lib_file_path)?;
     }
+    let mut import_errors = lib.take_import_errors();
 
-    let config_file_path = config_dir.join("config.yaml");
-    let config = if matches.opt_present("c") {
-        let path = matches
-            .opt_str("c")
-            .ok_or_else(|| anyhow!("Option '-c' requires an argument"))?;
-        Config::load(path)?
-    } else if config_file_path.exists() {
-        Config::load(config_file_path)?
-    } else {
-        let c = Config::default();
-        c.save(config_file_path)?;
-        c
-    };
+    // A deferred library is sorted by the UI once it has drawn its first frame
+    if !config.get_option(&ConfOption::DeferLibraryLoad) {
+        lib.sort();
+    }
 
     let playlist_dir = data_dir.join("playlists");
     if !Path::new(&playlist_dir).exists() {
         fs::create_dir(&playlist_dir)?;
     }
-    let playlists: Vec<Playlist> = fs::read_dir(&playlist_dir)?
-        .filter_map(|entry| {
-            entry.map_or(None, |entry| {
-                entry.path().extension().and_then(|ext| match ext.to_str() {
-                    Some("m3u8" | "m3u") => Some(entry.path()),
-                    _ => None,
-                })
-            })
+    let playlist_paths: Vec<PathBuf> = fs::read_dir(&playlist_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    let mut playlists: Vec<Playlist> = playlist_paths
+        .iter()
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|ext| matches!(ext.to_str(), Some("m3u8" | "m3u" | "pls")))
         })
         .filter_map(|p| Playlist::load(p).ok())
         .collect();
+    for playlist in &mut playlists {
+        import_errors.extend(playlist.take_skipped());
+    }
+    for path in playlist_paths
+        .iter()
+        .filter(|p| p.extension().is_some_and(|ext| ext == "smart"))
+    {
+        match SmartPlaylist::load(path) {
+            Ok(smart) => playlists.push(smart.evaluate(&lib)),
+            Err(e) => import_errors.push((path.clone(), e.to_string())),
+        }
+    }
 
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
+    if config.get_option(&ConfOption::Mouse) {
+        execute!(stdout, EnableMouseCapture)?;
+    }
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut app_ui = UI::new(&lib, &config, &playlists);
-    let state = Arc::new(Mutex::new(MediaState::default()));
+    let stations_file_path = data_dir.join("stations.json");
+    let mut stations = if stations_file_path.exists() {
+        StationList::load(&stations_file_path).unwrap_or_else(|e| {
+            import_errors.push((stations_file_path.clone(), e.to_string()));
+            StationList::default()
+        })
+    } else {
+        StationList::default()
+    };
+
+    let mut app_ui = UI::new(&lib, &config, &playlists, &stations.stations);
+    let history_file_path = data_dir.join("command_history");
+    app_ui.command_line.load_history(&history_file_path)?;
+    log_import_errors(&mut app_ui, import_errors);
+    log_config_warnings(&mut app_ui, config_warnings);
+    warn_unbound(&mut app_ui, &config);
+    let state = Arc::new(Mutex::new(MediaState {
+        show_remaining: config.get_option(&ConfOption::ShowRemaining),
+        ..MediaState::default()
+    }));
     let command_queue = Arc::new(Mutex::new(VecDeque::<Command>::new()));
     #[cfg(target_os = "linux")]
     let server = Arc::new(Mutex::new(
-        Server::new(
+        Server::new_with_track_list(
             BUS_NAME,
-            MprisPlayer::new(command_queue.clone(), state.clone()),
+            MprisPlayer::new(
+                command_queue.clone(),
+                state.clone(),
+                config.raise_command.clone(),
+                &config.extensions,
+            ),
         )
         .await?,
     ));
-    let default_config = Config::default();
+    #[cfg(not(target_os = "linux"))]
+    let media_keys = MediaKeys::start(command_queue.clone()).ok();
     let mut media_system = MediaSystem::new(
         #[cfg(target_os = "linux")]
         server,
+        #[cfg(not(target_os = "linux"))]
+        media_keys,
         state,
-        *config
-            .options
-            .get(&ConfOption::GaplessPlayback)
-            .unwrap_or_else(|| {
-                default_config
-                    .options
-                    .get(&ConfOption::GaplessPlayback)
-                    .expect("Has default value")
-            }),
+        config.get_option(&ConfOption::GaplessPlayback),
+        config.get_option(&ConfOption::ReplayGain),
+        crossfade_duration(&config),
     )
     .await?;
+    media_system.set_scrobbler(build_scrobbler(&config));
+    media_system.set_gain_sidecar(load_gain_sidecar(&config));
+    let visualizer = start_visualizer(&config);
+    media_system.set_visualizer(visualizer.clone());
+    app_ui.set_visualizer(visualizer);
+    media_system.set_notifications(config.get_option(&ConfOption::Notifications));
+    let mut _watcher = start_watcher(&config, &lib, &command_queue);
 
     let result: Result<()>;
-    let poll_duration = Duration::from_millis(100);
+    let mut poll_duration = poll_interval(&config);
     let mut time = SystemTime::now();
+    // The UI is only drawn when something may have changed it, or when the
+    // playback time may have moved on
+    let mut redraw = true;
+    let mut last_draw = Instant::now();
     let mut mode = Mode::Normal;
+    let mut count_prefix = CountPrefix::default();
+    // When the first play not yet saved to the library was recorded
+    let mut unsaved_plays: Option<Instant> = None;
 
     loop {
-        app_ui
-            .draw(&mut terminal, media_system.state(), &config, &mode)
-            .await?;
+        let (track, progress) = {
+            let state = media_system.state().lock().await;
+            (state.current_track.clone(), state.current_track_progress)
+        };
+        redraw |= app_ui.step_marquee(track.as_ref().map(|track| track.file_path.as_str()));
+        redraw |= app_ui.follow_lyrics(track.as_ref(), progress);
+        redraw |= app_ui.follow_cover();
+        // The visualizer's bars move with the music
+        redraw |= app_ui.showing_visualizer() && media_system.state().lock().await.playing;
+        if redraw || last_draw.elapsed() >= REDRAW_INTERVAL {
+            app_ui
+                .draw(
+                    &mut terminal,
+                    media_system.state(),
+                    media_system.queue_position().await,
+                    &config,
+                    &mode,
+                )
+                .await?;
+            redraw = app_ui.finish_loading();
+            last_draw = Instant::now();
+        }
 
         if event::poll(poll_duration)? {
-            if let Event::Key(ke) = event::read()? {
+            let event = event::read()?;
+            redraw = true;
+            if let (Event::Mouse(me), Mode::Normal) = (&event, &mode) {
+                let command = app_ui.handle_mouse(*me);
+                command_queue.lock().await.push_back(command);
+            }
+            if let Event::Key(ke) = event {
                 if ke.kind == KeyEventKind::Press || ke.kind == KeyEventKind::Repeat {
                     match (&mode, ke.code) {
+                        (Mode::Normal, KeyCode::Esc) if app_ui.showing_guide() => {
+                            app_ui.dismiss_guide();
+                        }
+
                         // Standard UI interaction
                         (Mode::Normal, _) => {
+                            let command = app_ui.get_key_command(ke, &config);
                             let mut guard = command_queue.lock().await;
-                            guard.push_back(app_ui.get_key_command(ke, &config));
+                            guard.extend(count_prefix.apply(ke.code, command));
                         }
 
                         // Command/playlist entry
@@ -216,14 +353,39 @@ async fn main() -> Result<()> {
                             let playlist_name = app_ui.command_line.get_contents();
                             let playlist = Playlist::new(&playlist_name);
                             app_ui.add_playlist(&playlist);
-                            playlist.save(playlist_dir.join(format!("{}.m3u8", playlist.name)))?;
+                            playlist.save_to_dir(&playlist_dir)?;
                             app_ui.command_line.reset();
                             mode = Mode::Normal;
                         }
 
+                        (Mode::QueueSaveEntry, KeyCode::Enter) => {
+                            let playlist_name = app_ui.command_line.get_contents();
+                            app_ui.command_line.reset();
+                            mode = Mode::Normal;
+                            // Saving over a playlist would leave it listed twice
+                            if app_ui.find_playlist(&playlist_name).is_some() {
+                                app_ui.command_line.show_message(&format!(
+                                    "Playlist already exists: {playlist_name}"
+                                ));
+                            } else {
+                                let mut playlist = Playlist::new(&playlist_name);
+                                playlist.add(&mut media_system.queue_snapshot());
+                                app_ui.add_playlist(&playlist);
+                                playlist.save_to_dir(&playlist_dir)?;
+                            }
+                        }
+
                         (Mode::CommandEntry, KeyCode::Enter) => {
                             let command = app_ui.command_line.get_contents();
                             app_ui.command_line.reset();
+                            if let Err(e) = app_ui
+                                .command_line
+                                .push_history(&command, &history_file_path)
+                            {
+                                app_ui
+                                    .command_line
+                                    .show_message(&format!("Failed to save history: {e}"));
+                            }
                             match Command::parse(&command) {
                                 Ok(cmd) => {
                                     let mut guard = command_queue.lock().await;
@@ -239,12 +401,67 @@ async fn main() -> Result<()> {
                             mode = Mode::Normal;
                         }
 
-                        (Mode::PlaylistEntry | Mode::CommandEntry, KeyCode::Esc) => {
+                        (Mode::CommandEntry, KeyCode::Up) => app_ui.command_line.history_prev(),
+
+                        (Mode::CommandEntry, KeyCode::Down) => app_ui.command_line.history_next(),
+
+                        (Mode::CommandEntry, KeyCode::Tab) => app_ui.command_line.complete(),
+
+                        // Search entry filters as you type. Enter plays the
+                        // results and keeps the filter, Esc clears it.
+                        (Mode::SearchEntry, KeyCode::Enter) => {
+                            app_ui.command_line.reset();
+                            command_queue.lock().await.push_back(QueueAllVisible);
+                            mode = Mode::Normal;
+                        }
+
+                        (Mode::SearchEntry, KeyCode::Esc) => {
                             app_ui.command_line.reset();
+                            app_ui.set_filter("");
                             mode = Mode::Normal;
                         }
 
-                        (Mode::PlaylistEntry | Mode::CommandEntry, _) => {
+                        (Mode::SearchEntry, KeyCode::Up | KeyCode::Down) => {
+                            app_ui.switch_item(if ke.code == KeyCode::Up { Prev } else { Next });
+                        }
+
+                        (Mode::SearchEntry, _) => {
+                            app_ui.command_line.textarea.input(ke);
+                            app_ui.set_filter(&app_ui.command_line.get_contents());
+                        }
+
+                        // Invalid or out-of-range times keep the overlay open
+                        // with an error so the input can be corrected
+                        (Mode::GotoTimeEntry, KeyCode::Enter) => {
+                            let timecode = app_ui.goto_time.input.get_contents();
+                            let result = match util::parse_timecode(&timecode) {
+                                Ok(position) => media_system.seek_to(position).await,
+                                Err(e) => Err(e),
+                            };
+                            match result {
+                                Ok(()) => {
+                                    time = SystemTime::now();
+                                    mode = Mode::Normal;
+                                }
+                                Err(e) => app_ui.goto_time.show_error(&e.to_string()),
+                            }
+                        }
+
+                        (Mode::GotoTimeEntry, KeyCode::Esc) => mode = Mode::Normal,
+
+                        (Mode::GotoTimeEntry, _) => {
+                            app_ui.goto_time.input.textarea.input(ke);
+                        }
+
+                        (
+                            Mode::PlaylistEntry | Mode::QueueSaveEntry | Mode::CommandEntry,
+                            KeyCode::Esc,
+                        ) => {
+                            app_ui.command_line.reset();
+                            mode = Mode::Normal;
+                        }
+
+                        (Mode::PlaylistEntry | Mode::QueueSaveEntry | Mode::CommandEntry, _) => {
                             app_ui.command_line.textarea.input(ke);
                         }
                     }
@@ -255,6 +472,7 @@ async fn main() -> Result<()> {
         let mut guard = command_queue.lock().await;
         if let Some(cmd) = guard.pop_front() {
             drop(guard);
+            redraw = true;
             match cmd {
                 Quit => {
                     result = Ok(());
@@ -286,34 +504,159 @@ async fn main() -> Result<()> {
                     time = SystemTime::now();
                 }
                 ToggleShuffle => media_system.toggle_shuffle().await,
+                Reshuffle => media_system.reshuffle(),
                 ToggleRepeat => media_system.toggle_repeat().await,
+                SetRepeat(repeat) => media_system.set_repeat(repeat).await,
                 QueueAndPlay => {
-                    let queueable = app_ui.get_selected(false);
-                    media_system.enqueue_and_play(&queueable).await?;
+                    // Tracks picked from the history and stations are played on
+                    // their own rather than replacing the queue
+                    if let Some(track) = app_ui.standalone_track() {
+                        media_system.play_track(&track, true).await?;
+                    } else {
+                        let queueable = app_ui.get_selected(false);
+                        media_system.enqueue_and_play(&queueable).await?;
+                    }
                     time = SystemTime::now();
                 }
+                PlayNext => {
+                    let queueable = app_ui.get_selected(true);
+                    let count = queueable.get_tracks().len();
+                    if count > 0 {
+                        media_system.enqueue_front(&queueable);
+                        app_ui.command_line.show_message(&format!(
+                            "Playing next: {count} {}",
+                            if count == 1 { "track" } else { "tracks" }
+                        ));
+                    }
+                }
+                HalfPageDown => app_ui.switch_item(HalfDown),
+                HalfPageUp => app_ui.switch_item(HalfUp),
+                PageDown => app_ui.switch_item(MovementDirection::PageDown),
+                PageUp => app_ui.switch_item(MovementDirection::PageUp),
+                GotoIndex(index) => app_ui.goto_index(index),
                 GotoTop => app_ui.switch_item(Top),
                 GotoBottom => app_ui.switch_item(Bottom),
                 GotoScreen(s) => app_ui.switch_screen(s),
+                ShowErrors => app_ui.switch_screen(ScreenEnum::Errors),
+                SetTheme(name) => match config.apply_theme(&name) {
+                    Ok(()) => {
+                        app_ui.apply_config(&config);
+                        app_ui.command_line.show_message(&format!("Theme: {name}"));
+                    }
+                    Err(e) => app_ui.command_line.show_message(&e.to_string()),
+                },
                 NewPlaylist(None) => {
                     mode = Mode::PlaylistEntry;
                     app_ui.command_line.clear_contents();
                     app_ui.command_line.set_prompt("New playlist: ");
                 }
+                NewSmartPlaylist(smart_playlist) => {
+                    match app_ui.set_smart_playlist(&smart_playlist) {
+                        Ok(()) => {
+                            smart_playlist.save(
+                                playlist_dir.join(format!("{}.smart", smart_playlist.name)),
+                            )?;
+                            app_ui.command_line.show_message(&format!(
+                                "Saved smart playlist {}",
+                                smart_playlist.name
+                            ));
+                        }
+                        Err(e) => app_ui.command_line.show_message(&e.to_string()),
+                    }
+                }
+                AddStation(station) => {
+                    let message = format!("Saved station {}", station.name);
+                    match stations
+                        .stations
+                        .iter_mut()
+                        .find(|s| s.name == station.name)
+                    {
+                        Some(existing) => *existing = station,
+                        None => stations.stations.push(station),
+                    }
+                    stations.save(&stations_file_path)?;
+                    app_ui.set_stations(&stations.stations);
+                    app_ui.command_line.show_message(&message);
+                }
+                RemoveStation(name) => {
+                    let count = stations.stations.len();
+                    stations.stations.retain(|s| s.name != name);
+                    if stations.stations.len() == count {
+                        app_ui
+                            .command_line
+                            .show_message(&format!("station: No such station: {name}"));
+                    } else {
+                        stations.save(&stations_file_path)?;
+                        app_ui.set_stations(&stations.stations);
+                    }
+                }
                 NewPlaylist(Some(playlist_name)) => {
                     let playlist = Playlist::new(&playlist_name);
                     app_ui.add_playlist(&playlist);
-                    playlist.save(playlist_dir.join(format!("{}.m3u8", playlist.name)))?;
+                    playlist.save_to_dir(&playlist_dir)?;
+                }
+                SaveQueue(None) => {
+                    mode = Mode::QueueSaveEntry;
+                    app_ui.command_line.clear_contents();
+                    app_ui.command_line.set_prompt("Save queue as: ");
+                }
+                SaveQueue(Some(playlist_name))
+                    if app_ui.find_playlist(&playlist_name).is_some() =>
+                {
+                    app_ui
+                        .command_line
+                        .show_message(&format!("Playlist already exists: {playlist_name}"));
+                }
+                SaveQueue(Some(playlist_name)) => {
+                    let mut playlist = Playlist::new(&playlist_name);
+                    playlist.add(&mut media_system.queue_snapshot());
+                    app_ui.add_playlist(&playlist);
+                    playlist.save_to_dir(&playlist_dir)?;
+                    app_ui.command_line.show_message(&format!(
+                        "Saved {} tracks to {}",
+                        playlist.tracks.len(),
+                        playlist.name
+                    ));
                 }
                 PlaylistAdd => {
                     app_ui.add_selected_to_playlist();
-                    if let Some(pl) = app_ui.selected_playlist() {
-                        pl.save(playlist_dir.join(format!("{}.m3u8", pl.name)))?;
+                    if let Some(pl) = app_ui.selected_playlist().filter(|pl| pl.query.is_none()) {
+                        pl.save_to_dir(&playlist_dir)?;
                     }
                 }
                 SelectPlaylist => app_ui.select_current_playlist(),
+                ClearPlaylist => {
+                    if let Some(pl) = app_ui.clear_highlighted_playlist() {
+                        pl.save_to_dir(&playlist_dir)?;
+                        let message = format!("Cleared {}", pl.name);
+                        app_ui.command_line.show_message(&message);
+                    }
+                }
+                DuplicatePlaylist => {
+                    if let Some(pl) = app_ui.duplicate_highlighted_playlist() {
+                        pl.save_to_dir(&playlist_dir)?;
+                        let message = format!("Created {}", pl.name);
+                        app_ui.command_line.show_message(&message);
+                    }
+                }
                 PrevTrack => media_system.play_prev().await?,
-                NextTrack => media_system.play_next(true).await?,
+                NextTrack => {
+                    if config.get_option(&ConfOption::StopAfterCountsSkips)
+                        && media_system.count_down_stop_after().await
+                    {
+                        media_system.stop().await?;
+                    } else {
+                        media_system.play_next(true).await?;
+                    }
+                }
+                StopAfter(count) => media_system.set_stop_after(count).await,
+                SleepTimer(duration) => match media_system.set_sleep_timer(duration).await {
+                    Ok(()) => app_ui.command_line.show_message(&duration.map_or_else(
+                        || "Sleep timer off".to_owned(),
+                        |duration| format!("Sleeping in {}", util::format_duration(duration)),
+                    )),
+                    Err(e) => app_ui.command_line.show_message(&e.to_string()),
+                },
                 EnterCommand => {
                     mode = Mode::CommandEntry;
                     app_ui.command_line.reset();
@@ -323,9 +666,11 @@ async fn main() -> Result<()> {
                     let mut l = app_ui.library.clone();
                     match l.add_path(p) {
                         Ok(()) => {
-                            l.tracks.tracks.sort();
+                            let import_errors = l.take_import_errors();
+                            l.sort();
                             l.save(&lib_file_path)?;
                             app_ui.update_library(l);
+                            log_import_errors(&mut app_ui, import_errors);
                         }
                         Err(e) => {
                             app_ui
@@ -335,42 +680,620 @@ async fn main() -> Result<()> {
                         }
                     }
                 }
-                PlayTrack(path) => {
-                    let (track, _, _) = get_track_data(path)?;
-                    media_system.play_track(&track, true).await?;
+                RemovePath(p) => {
+                    let mut l = app_ui.library.clone();
+                    match l.remove_path(p) {
+                        Ok(removed) => {
+                            l.save(&lib_file_path)?;
+                            app_ui.update_library(l);
+                            app_ui
+                                .command_line
+                                .show_message(&format!("Removed {removed} tracks"));
+                        }
+                        Err(e) => app_ui.command_line.show_message(&e.to_string()),
+                    }
+                }
+                SetLoopA => media_system.set_loop_a().await,
+                SetLoopB => media_system.set_loop_b().await,
+                GotoTime => {
+                    mode = Mode::GotoTimeEntry;
+                    app_ui.goto_time.reset();
+                }
+                SeekForward | SeekBackward => {
+                    let mut seconds = config.get_number(&NumOption::SeekSeconds);
+                    if cmd == SeekBackward {
+                        seconds = -seconds;
+                    }
+                    if let Err(e) = media_system.seek_by(seconds).await {
+                        app_ui.command_line.show_message(&e.to_string());
+                    }
+                    time = SystemTime::now();
+                }
+                ReloadConfig => match Config::load_with_warnings(&config_file_path) {
+                    Ok((new_config, warnings)) => {
+                        // A time display toggled at runtime is kept unless the
+                        // reloaded config changes the option itself
+                        let show_remaining = new_config.get_option(&ConfOption::ShowRemaining);
+                        if show_remaining != config.get_option(&ConfOption::ShowRemaining) {
+                            media_system.state().lock().await.show_remaining = show_remaining;
+                        }
+                        config = new_config;
+                        apply_config(
+                            &config,
+                            &mut app_ui,
+                            &mut media_system,
+                            &mut _watcher,
+                            &command_queue,
+                        )
+                        .await?;
+                        poll_duration = poll_interval(&config);
+                        app_ui.command_line.show_message("Reloaded config");
+                        log_config_warnings(&mut app_ui, warnings);
+                        warn_unbound(&mut app_ui, &config);
+                    }
+                    Err(e) => app_ui
+                        .command_line
+                        .show_message(&format!("Failed to reload config: {e}")),
+                },
+                SetOption(option, value, save) => {
+                    config.set_option(option, value);
+                    if option == ConfOption::ShowRemaining {
+                        media_system.state().lock().await.show_remaining = value;
+                    }
+                    apply_config(
+                        &config,
+                        &mut app_ui,
+                        &mut media_system,
+                        &mut _watcher,
+                        &command_queue,
+                    )
+                    .await?;
+                    let mut message = format!("{option:?}: {value}");
+                    if save {
+                        match config.save(&config_file_path) {
+                            Ok(()) => message.push_str(" (saved)"),
+                            Err(e) => message = format!("Failed to save config: {e}"),
+                        }
+                    }
+                    app_ui.command_line.show_message(&message);
+                }
+                ToggleIcons => {
+                    let icons = !config.get_option(&ConfOption::NerdFontIcons);
+                    config.set_option(ConfOption::NerdFontIcons, icons);
+                    // Rebuilds the track list, which shows icons for ratings
+                    app_ui.apply_config(&config);
+                    app_ui.command_line.show_message(if icons {
+                        "Nerd Font icons on"
+                    } else {
+                        "Nerd Font icons off"
+                    });
+                }
+                ToggleLyrics => app_ui.toggle_lyrics(),
+                SetMatchMode(match_mode) => {
+                    let match_mode = app_ui.set_match_mode(match_mode);
+                    app_ui
+                        .command_line
+                        .show_message(&format!("Search matching: {match_mode}"));
+                }
+                VolumeUp | VolumeDown => {
+                    #[allow(clippy::cast_possible_truncation)]
+                    let mut step = config.get_number(&NumOption::VolumeStep) as f32;
+                    if cmd == VolumeDown {
+                        step = -step;
+                    }
+                    let volume = media_system.change_volume(step).await;
+                    show_volume(&mut app_ui, volume, media_system.state()).await;
+                }
+                SetVolume(percent) => {
+                    let volume = media_system.set_volume(f32::from(percent) / 100.0).await;
+                    show_volume(&mut app_ui, volume, media_system.state()).await;
+                }
+                SpeedUp | SpeedDown => {
+                    #[allow(clippy::cast_possible_truncation)]
+                    let mut step = config.get_number(&NumOption::SpeedStep) as f32;
+                    if cmd == SpeedDown {
+                        step = -step;
+                    }
+                    let speed = media_system.change_speed(step).await;
+                    show_speed(&mut app_ui, speed);
+                }
+                SpeedReset => {
+                    let speed = media_system.set_speed(1.0).await;
+                    show_speed(&mut app_ui, speed);
+                }
+                SetSpeed(percent) => {
+                    let speed = media_system.set_speed(f32::from(percent) / 100.0).await;
+                    show_speed(&mut app_ui, speed);
+                }
+                ToggleMute => {
+                    let message = if media_system.toggle_mute().await {
+                        "Muted"
+                    } else {
+                        "Unmuted"
+                    };
+                    app_ui.command_line.show_message(message);
+                }
+                ToggleRemaining => {
+                    let message = if media_system.toggle_show_remaining().await {
+                        "Showing time remaining"
+                    } else {
+                        "Showing time elapsed"
+                    };
+                    app_ui.command_line.show_message(message);
+                }
+                Expand => app_ui.expand(),
+                Collapse => app_ui.collapse(),
+                Rescan => {
+                    let mut l = app_ui.library.clone();
+                    let (rescanned, removed) = l.rescan();
+                    l.sort();
+                    l.save(&lib_file_path)?;
+                    app_ui.update_library(l);
+                    app_ui.command_line.show_message(&format!(
+                        "Rescanned {rescanned} tracks, removed {removed} missing"
+                    ));
+                }
+                RescanPaths(paths) => {
+                    let mut l = app_ui.library.clone();
+                    for path in &paths {
+                        if let Err(e) = l.rescan_path(path) {
+                            app_ui
+                                .command_line
+                                .show_message(&format!("{}: {e}", path.display()));
+                        }
+                    }
+                    l.sort();
+                    l.save(&lib_file_path)?;
+                    app_ui.update_library(l);
+                }
+                Stats => {
+                    let most_played = app_ui.library.most_played(STATS_COUNT);
+                    let message = if most_played.is_empty() {
+                        "No tracks played yet".to_owned()
+                    } else {
+                        format!(
+                            "Most played: {}",
+                            most_played
+                                .iter()
+                                .map(|t| format!("{t} ({})", t.play_count))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    };
+                    app_ui.command_line.show_message(&message);
+                }
+                SortLibrary(sort_key) => {
+                    let mut l = app_ui.library.clone();
+                    l.sort_key = sort_key;
+                    l.sort();
+                    l.save(&lib_file_path)?;
+                    app_ui.update_library(l);
+                }
+                SortBy(key, direction) => {
+                    let direction = app_ui.sort_tracks(key, direction);
+                    app_ui
+                        .command_line
+                        .show_message(&format!("Sorted tracks by {key}, {direction}"));
+                }
+                FilterGenre(genre) => {
+                    if let Err(e) = app_ui.set_genre_filter(genre) {
+                        app_ui.command_line.show_message(&e.to_string());
+                    }
+                }
+                FilterRating(rating) => {
+                    if let Err(e) = app_ui.set_rating_filter(rating) {
+                        app_ui.command_line.show_message(&e.to_string());
+                    }
+                }
+                ClearFilters => app_ui.clear_filters(),
+                Rate(rating) => {
+                    rate_selected(&mut app_ui, &lib_file_path, |_| {
+                        (rating > 0).then_some(rating)
+                    })?;
+                }
+                ToggleFavorite => {
+                    rate_selected(&mut app_ui, &lib_file_path, |rating| {
+                        (rating != Some(MAX_RATING)).then_some(MAX_RATING)
+                    })?;
+                }
+                ExportPlaylist(name, path) => {
+                    let message = match app_ui.find_playlist(&name) {
+                        Some(playlist) => match playlist.save_as(&path) {
+                            Ok(()) => format!("Exported {} to {}", name, path.display()),
+                            Err(e) => e.to_string(),
+                        },
+                        None => format!("export: No such playlist: {name}"),
+                    };
+                    app_ui.command_line.show_message(&message);
+                }
+                Search => {
+                    mode = Mode::SearchEntry;
+                    app_ui.switch_screen(ScreenEnum::Main);
+                    app_ui.command_line.reset();
+                    app_ui.command_line.set_prompt("/");
+                }
+                QueueAllVisible => {
+                    let queueable = app_ui.visible_tracks();
+                    media_system.enqueue_and_play(&queueable).await?;
+                    time = SystemTime::now();
+                }
+                PlayFolder(dir, recursive) => {
+                    match folder_album(dir, recursive, app_ui.library.extensions()) {
+                        Ok(album) => {
+                            media_system
+                                .enqueue_and_play(&Queueable::Album(album))
+                                .await?;
+                            time = SystemTime::now();
+                        }
+                        Err(e) => app_ui.command_line.show_message(&e.to_string()),
+                    }
+                }
+                PlayTrack(path) => match get_track_data(&path) {
+                    Ok((track, _, _)) => {
+                        media_system.play_track(&track, true).await?;
+                        time = SystemTime::now();
+                    }
+                    Err(e) => app_ui
+                        .command_line
+                        .show_message(&format!("play: {}: {e}", path.display())),
+                },
+                QueueTrack(path, index, play) => match get_track_data(&path) {
+                    Ok((track, _, _)) => {
+                        let index = media_system.insert_queued(&track, index);
+                        if play {
+                            media_system.play_queued(index).await?;
+                            time = SystemTime::now();
+                        }
+                    }
+                    Err(e) => app_ui
+                        .command_line
+                        .show_message(&format!("queue: {}: {e}", path.display())),
+                },
+                RemoveQueued(index) => media_system.remove_queued(index),
+                PlayQueued(index) => {
+                    media_system.play_queued(index).await?;
                     time = SystemTime::now();
                 }
                 Nop => {}
             }
         }
 
-        if media_system.state().lock().await.playing {
-            media_system.update_progress(time.elapsed()?).await;
+        if media_system.finish_loading().await {
             time = SystemTime::now();
+            redraw = true;
+        }
+        redraw |= media_system.finish_cover().await;
+
+        // The baseline moves on every time round, even while paused, so that
+        // time spent paused isn't counted once playback resumes
+        let elapsed = time.elapsed()?;
+        time = SystemTime::now();
+        if !media_system.state().lock().await.playing
+            && config.get_option(&ConfOption::SleepTimerPausable)
+        {
+            media_system.hold_sleep_timer(elapsed).await;
+        }
+        if media_system.sleep_timer_expired().await {
+            media_system.stop().await?;
+            media_system.clear_queue();
+            if let Mode::Normal = mode {
+                app_ui
+                    .command_line
+                    .show_message("Sleep timer: playback stopped");
+            }
+            redraw = true;
+        }
+        if let Some(error) = media_system.check_output().await {
+            if let Mode::Normal = mode {
+                app_ui
+                    .command_line
+                    .show_message("Audio output lost, playback paused");
+            }
+            app_ui.log_error(format!("Audio output: {error}"));
+            redraw = true;
+        }
+        if media_system.state().lock().await.playing {
+            media_system.update_progress(elapsed).await;
         }
 
-        let play_next_cond = if media_system.gapless_playback() {
+        // If playback is about to stop, let the current track finish instead
+        // of crossfading or queueing the next one gaplessly. Crossfading takes
+        // precedence over gapless playback, and only one track fades out at a
+        // time.
+        let stop_after_pending = media_system.stop_after_pending().await;
+        let crossfade = !media_system.crossfade().is_zero()
+            && !stop_after_pending
+            && !media_system.crossfading();
+        if !crossfade
+            && media_system.gapless_playback()
+            && !stop_after_pending
+            && media_system.time_remaining().await < PRELOAD_TIME
+        {
+            media_system.preload_next().await;
+        }
+        let play_next_cond = if crossfade {
+            media_system.time_remaining().await < media_system.crossfade()
+        } else if media_system.gapless_playback() && !stop_after_pending {
             media_system.time_remaining().await < Duration::from_secs_f32(0.1)
         } else {
             media_system.sink_empty()
         };
 
-        if play_next_cond && !media_system.queue_empty() {
-            media_system.play_next(false).await?;
-            time = SystemTime::now();
+        if play_next_cond
+            && media_system.has_next().await
+            && !media_system.stopped().await
+            && !media_system.loading()
+        {
+            redraw = true;
+            if media_system.count_down_stop_after().await {
+                media_system.stop().await?;
+            } else if crossfade {
+                media_system.crossfade_next().await?;
+                time = SystemTime::now();
+            } else {
+                media_system.play_next(false).await?;
+                time = SystemTime::now();
+            }
+        }
+
+        if let (Some(error), Mode::Normal) = (media_system.take_scrobble_errors().last(), &mode) {
+            app_ui.command_line.show_message(error);
+            redraw = true;
+        }
+
+        for track in media_system.take_played() {
+            if app_ui.library.record_play(&track) && unsaved_plays.is_none() {
+                unsaved_plays = Some(Instant::now());
+            }
+        }
+        if unsaved_plays.is_some_and(|since| since.elapsed() >= STATS_SAVE_INTERVAL) {
+            unsaved_plays = None;
+            if let Err(error) = app_ui.library.save(&lib_file_path) {
+                app_ui.log_error(format!("Saving play counts: {error}"));
+            }
+        }
+
+        let play_errors = media_system.take_play_errors();
+        if let (Some(error), Mode::Normal) = (play_errors.last(), &mode) {
+            app_ui.command_line.show_message(error);
+            redraw = true;
+        }
+        for error in play_errors {
+            app_ui.log_error(error);
+        }
+
+        let skipped = media_system.take_skipped();
+        if let (Some(track), Mode::Normal) = (skipped.last(), &mode) {
+            app_ui
+                .command_line
+                .show_message(&format!("Skipping missing file: {}", track.file_path));
+            redraw = true;
+        }
+
+        if app_ui.current_screen() == ScreenEnum::History {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let history_length = config.get_number(&NumOption::HistoryLength) as usize;
+            app_ui.update_history(media_system.history_snapshot(), history_length);
         }
 
-        app_ui.update_lists();
+        media_system.publish_queue().await;
+        // The lists only need rebuilding once input or a command may have
+        // moved a selection; otherwise the built widgets are kept
+        if redraw {
+            app_ui.update_lists();
+        }
     }
 
+    let stats_saved = match unsaved_plays {
+        Some(_) => app_ui.library.save(&lib_file_path),
+        None => Ok(()),
+    };
+
     // Restore terminal
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
     terminal.show_cursor()?;
 
+    if let Err(error) = stats_saved {
+        eprintln!("Error saving play counts: {error}");
+    }
+
     result
 }
 
+/// How long to wait for input each time round the main loop
+fn poll_interval(config: &Config) -> Duration {
+    // Too short an interval would spin the loop, and too long a one would
+    // make playback late to move on to the next track
+    let millis = config
+        .get_number(&NumOption::PollMillis)
+        .clamp(10.0, 1000.0);
+    Duration::try_from_secs_f64(millis / 1000.0).unwrap_or(Duration::from_millis(100))
+}
+
+/// The configured crossfade length, treating invalid values as no crossfade
+fn crossfade_duration(config: &Config) -> Duration {
+    Duration::try_from_secs_f64(config.get_number(&NumOption::CrossfadeSeconds))
+        .unwrap_or(Duration::ZERO)
+}
+
+/// Change the rating of the selected track in the library, given its current
+/// rating, and save the library
+fn rate_selected(
+    app_ui: &mut UI<'_>,
+    lib_file_path: &Path,
+    rate: impl FnOnce(Option<u8>) -> Option<u8>,
+) -> Result<()> {
+    let Some(track) = app_ui.selected_track() else {
+        app_ui.command_line.show_message("rate: No track selected");
+        return Ok(());
+    };
+
+    // Go by the library's copy of the track, as a playlist's may be stale
+    let mut l = app_ui.library.clone();
+    let Some(library_track) = l
+        .tracks
+        .tracks
+        .iter_mut()
+        .find(|t| t.file_path == track.file_path)
+    else {
+        app_ui
+            .command_line
+            .show_message(&format!("rate: Not in library: {track}"));
+        return Ok(());
+    };
+    let rating = rate(library_track.rating);
+    library_track.rating = rating;
+    l.save(lib_file_path)?;
+    app_ui.update_library(l);
+    app_ui.command_line.show_message(&rating.map_or_else(
+        || format!("Cleared rating of {track}"),
+        |rating| format!("Rated {track}: {rating}/{MAX_RATING}"),
+    ));
+    Ok(())
+}
+
+/// Show the volume on the command line, noting whether it is muted
+async fn show_volume(app_ui: &mut UI<'_>, volume: f32, media_state: &Arc<Mutex<MediaState>>) {
+    let muted = if media_state.lock().await.muted {
+        " (muted)"
+    } else {
+        ""
+    };
+    app_ui
+        .command_line
+        .show_message(&format!("Volume: {:.0}%{muted}", volume * 100.0));
+}
+
+fn show_speed(app_ui: &mut UI<'_>, speed: f32) {
+    app_ui
+        .command_line
+        .show_message(&format!("Speed: {:.0}%", speed * 100.0));
+}
+
+/// Record files that failed to import in the error log, and say how many
+/// there were
+fn log_import_errors(app_ui: &mut UI, import_errors: Vec<(PathBuf, String)>) {
+    if import_errors.is_empty() {
+        return;
+    }
+    let count = import_errors.len();
+    for (path, error) in import_errors {
+        app_ui.log_error(format!("{}: {error}", path.display()));
+    }
+    app_ui.command_line.show_message(&format!(
+        "{count} {} failed to import (see :errors)",
+        if count == 1 { "file" } else { "files" }
+    ));
+}
+
+/// Record mistakes found in the config in the error log, and point them out
+fn log_config_warnings(app_ui: &mut UI, warnings: Vec<ConfigWarning>) {
+    if warnings.is_empty() {
+        return;
+    }
+    let count = warnings.len();
+    for warning in warnings {
+        app_ui.log_error(warning.to_string());
+    }
+    app_ui.command_line.show_message(&format!(
+        "{count} {} in config (see :errors)",
+        if count == 1 { "problem" } else { "problems" }
+    ));
+}
+
+/// Point out essential commands left without a key. Shown after any other
+/// config problems, as being unable to quit matters more.
+fn warn_unbound(app_ui: &mut UI, config: &Config) {
+    let warnings = config.check_bindings();
+    if !warnings.is_empty() {
+        app_ui.command_line.show_message(&warnings.join("; "));
+    }
+}
+
+/// Put a changed config into effect across the UI, playback, and library
+async fn apply_config(
+    config: &Config,
+    app_ui: &mut UI<'_>,
+    media_system: &mut MediaSystem,
+    watcher: &mut Option<LibraryWatcher>,
+    command_queue: &Arc<Mutex<VecDeque<Command>>>,
+) -> Result<()> {
+    app_ui.apply_config(config);
+    if config.get_option(&ConfOption::Mouse) {
+        execute!(io::stdout(), EnableMouseCapture)?;
+    } else {
+        execute!(io::stdout(), DisableMouseCapture)?;
+    }
+    media_system.set_gapless_playback(config.get_option(&ConfOption::GaplessPlayback));
+    media_system.set_replay_gain(config.get_option(&ConfOption::ReplayGain));
+    media_system.set_crossfade(crossfade_duration(config));
+    media_system.set_scrobbler(build_scrobbler(config));
+    media_system.set_gain_sidecar(load_gain_sidecar(config));
+    if app_ui.showing_visualizer() != config.get_option(&ConfOption::Visualizer) {
+        let visualizer = start_visualizer(config);
+        media_system.set_visualizer(visualizer.clone());
+        app_ui.set_visualizer(visualizer);
+    }
+    media_system.set_notifications(config.get_option(&ConfOption::Notifications));
+    let mut l = app_ui.library.clone();
+    configure_library(&mut l, config);
+    app_ui.update_library(l);
+    // Dropping the old watcher stops it
+    *watcher = None;
+    *watcher = start_watcher(config, &app_ui.library, command_queue);
+    Ok(())
+}
+
+/// Apply the library's scan settings from the config
+fn configure_library(library: &mut Library, config: &Config) {
+    library.set_extensions(&config.extensions);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    library.set_max_depth(config.get_number(&NumOption::ScanDepth) as usize);
+}
+
+/// A watcher over the library's directories, if watching is enabled
+fn start_watcher(
+    config: &Config,
+    library: &Library,
+    command_queue: &Arc<Mutex<VecDeque<Command>>>,
+) -> Option<LibraryWatcher> {
+    config
+        .get_option(&ConfOption::WatchLibrary)
+        .then(|| LibraryWatcher::start(library, Arc::clone(command_queue)).ok())
+        .flatten()
+}
+
+/// A visualizer measuring the audio, if it is enabled
+fn start_visualizer(config: &Config) -> Option<Visualizer> {
+    config
+        .get_option(&ConfOption::Visualizer)
+        .then(Visualizer::start)
+}
+
+/// A scrobbler using the configured credentials, if scrobbling is enabled
+fn build_scrobbler(config: &Config) -> Option<Scrobbler> {
+    if config.get_option(&ConfOption::Scrobbling) {
+        config.scrobble_credentials.clone().map(Scrobbler::new)
+    } else {
+        None
+    }
+}
+
+/// Read the configured ReplayGain sidecar, leaving it empty if there is none
+/// or it can't be read
+fn load_gain_sidecar(config: &Config) -> GainSidecar {
+    config
+        .replay_gain_file
+        .as_ref()
+        .and_then(|file_path| GainSidecar::load(file_path).ok())
+        .unwrap_or_default()
+}
+
 fn print_usage(program: &str, opts: &Options) {
     let brief = format!("Usage: {program} [options]");
     print!("{}", opts.usage(&brief));
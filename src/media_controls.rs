@@ -0,0 +1,119 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! The OS media controls on platforms without MPRIS, so that media keys and
+//! the system's now playing display work as they do through `mpris`
+
+use std::{collections::VecDeque, path::Path, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use async_std::{sync::Mutex, task};
+use souvlaki::{
+    MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, MediaPosition, PlatformConfig,
+    SeekDirection,
+};
+
+use crate::{command::Command, library::track::Track};
+
+/// The OS media controls, which push the commands they are sent into the
+/// command queue
+pub struct MediaKeys {
+    controls: std::sync::Mutex<MediaControls>,
+}
+
+impl MediaKeys {
+    pub fn start(command_queue: Arc<Mutex<VecDeque<Command>>>) -> Result<Self> {
+        let config = PlatformConfig {
+            display_name: "RMuP",
+            dbus_name: "rmup",
+            hwnd: window()?,
+        };
+        let mut controls =
+            MediaControls::new(config).map_err(|e| anyhow!("Media controls: {e:?}"))?;
+        controls
+            .attach(move |event| {
+                if let Some(command) = command(event) {
+                    task::block_on(command_queue.lock()).push_back(command);
+                }
+            })
+            .map_err(|e| anyhow!("Media controls: {e:?}"))?;
+        Ok(Self {
+            controls: std::sync::Mutex::new(controls),
+        })
+    }
+
+    /// Show whether a track is playing, paused at `progress`, or stopped if
+    /// there is none
+    pub fn set_playback(&self, playing: Option<bool>, progress: Option<Duration>) {
+        let progress = progress.map(MediaPosition);
+        let playback = match playing {
+            Some(true) => MediaPlayback::Playing { progress },
+            Some(false) => MediaPlayback::Paused { progress },
+            None => MediaPlayback::Stopped,
+        };
+        if let Ok(mut controls) = self.controls.lock() {
+            let _ = controls.set_playback(playback);
+        }
+    }
+
+    /// Show a track as the one playing, with its cover art if it has been
+    /// extracted
+    pub fn set_metadata(&self, track: &Track, cover: Option<&Path>) {
+        let cover_url = cover.map(|cover| format!("file://{}", cover.display()));
+        let metadata = MediaMetadata {
+            title: Some(track.title.as_deref().unwrap_or(&track.file_path)),
+            album: Some(&track.album),
+            artist: Some(&track.artist),
+            cover_url: cover_url.as_deref(),
+            duration: Some(track.length),
+        };
+        if let Ok(mut controls) = self.controls.lock() {
+            let _ = controls.set_metadata(metadata);
+        }
+    }
+}
+
+/// The command a media control event runs, if it has one
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn command(event: MediaControlEvent) -> Option<Command> {
+    Some(match event {
+        MediaControlEvent::Play => Command::Play,
+        MediaControlEvent::Pause => Command::Pause,
+        MediaControlEvent::Toggle => Command::TogglePlay,
+        MediaControlEvent::Next => Command::NextTrack,
+        MediaControlEvent::Previous => Command::PrevTrack,
+        MediaControlEvent::Stop => Command::Stop,
+        MediaControlEvent::Seek(SeekDirection::Forward)
+        | MediaControlEvent::SeekBy(SeekDirection::Forward, _) => Command::SeekForward,
+        MediaControlEvent::Seek(SeekDirection::Backward)
+        | MediaControlEvent::SeekBy(SeekDirection::Backward, _) => Command::SeekBackward,
+        MediaControlEvent::SetVolume(volume) => {
+            Command::SetVolume((volume.clamp(0.0, 1.0) * 100.0).round() as u8)
+        }
+        MediaControlEvent::OpenUri(uri) => {
+            Command::PlayTrack(uri.strip_prefix("file://").unwrap_or(&uri).into())
+        }
+        MediaControlEvent::Quit => Command::Quit,
+        MediaControlEvent::SetPosition(_) | MediaControlEvent::Raise => return None,
+    })
+}
+
+/// The console window, which the Windows media controls are attached to
+#[cfg(target_os = "windows")]
+fn window() -> Result<Option<*mut std::ffi::c_void>> {
+    // SAFETY: GetConsoleWindow takes no arguments and only returns a handle
+    let hwnd = unsafe { windows_sys::Win32::System::Console::GetConsoleWindow() };
+    if hwnd.is_null() {
+        Err(anyhow!("Media controls: No console window"))
+    } else {
+        Ok(Some(hwnd))
+    }
+}
+
+/// Only Windows needs a window for its media controls
+#[cfg(not(target_os = "windows"))]
+#[allow(clippy::unnecessary_wraps)]
+fn window() -> Result<Option<*mut std::ffi::c_void>> {
+    Ok(None)
+}
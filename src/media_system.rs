@@ -2,31 +2,58 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::{collections::VecDeque, fs::File, io::BufReader, mem, sync::Arc, time::Duration};
+use std::{
+    fs::File,
+    io::BufReader,
+    mem,
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 
 use async_std::sync::Mutex;
 
 use crate::{
-    library::{album::Album, artist::Artist, track::Track},
+    library::{self, album::Album, artist::Artist, track::Track, GainSidecar},
+    notify,
+    output::Output,
     playlist::Playlist,
+    queue::PlayQueue,
+    scrobble::Scrobbler,
+    stream::{self, HttpStream},
+    util,
+    visualizer::Visualizer,
 };
-use anyhow::Result;
-use rand::prelude::*;
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+use anyhow::{anyhow, Result};
+use rodio::{Decoder, Sink, Source};
+use serde::{Deserialize, Serialize};
 
+#[cfg(not(target_os = "linux"))]
+use crate::media_controls::MediaKeys;
 #[cfg(target_os = "linux")]
-use crate::mpris::MprisPlayer;
+use crate::mpris::{self, MprisPlayer};
 #[cfg(target_os = "linux")]
-use mpris_server::{LoopStatus, Metadata, PlaybackStatus, Property, Server, Time};
+use mpris_server::{
+    LoopStatus, Metadata, PlaybackStatus, Property, Server, Signal, TrackId, TrackListSignal,
+};
+
+/// Slowest playback speed allowed
+pub const MIN_SPEED: f32 = 0.5;
+/// Fastest playback speed allowed
+pub const MAX_SPEED: f32 = 2.0;
 
-#[derive(PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Copy, Clone, Default)]
 pub enum Repeat {
     On,
+    #[default]
     Off,
     One,
 }
 
-#[derive(Default)]
 pub struct MediaState {
     pub current_track: Option<Track>,
     pub current_track_progress: Option<Duration>,
@@ -34,19 +61,221 @@ pub struct MediaState {
     pub stopped: bool,
     pub shuffle: bool,
     pub repeat: Repeat,
+    /// Number of tracks left to play, including the current one, before
+    /// playback stops
+    pub stop_after: Option<u32>,
+    /// When the sleep timer stops playback
+    pub sleep_deadline: Option<SystemTime>,
+    /// Start of the A-B loop in the current track
+    pub loop_a: Option<Duration>,
+    /// End of the A-B loop in the current track
+    pub loop_b: Option<Duration>,
+    /// Image file of the current track's embedded cover art
+    pub current_cover: Option<PathBuf>,
+    /// Playback volume, from 0.0 to 1.0. Kept while muted so it can be
+    /// restored.
+    pub volume: f32,
+    pub muted: bool,
+    /// Show the time remaining in the playback bar rather than the time
+    /// elapsed
+    pub show_remaining: bool,
+    /// Playback speed, where 1.0 is normal speed
+    pub speed: f32,
+    /// The tracks queued after the current one, as last published for MPRIS
+    pub upcoming: Vec<Track>,
+}
+
+impl Default for MediaState {
+    fn default() -> Self {
+        Self {
+            current_track: None,
+            current_track_progress: None,
+            playing: false,
+            stopped: false,
+            shuffle: false,
+            repeat: Repeat::default(),
+            stop_after: None,
+            sleep_deadline: None,
+            loop_a: None,
+            loop_b: None,
+            current_cover: None,
+            volume: 1.0,
+            muted: false,
+            show_remaining: false,
+            speed: 1.0,
+            upcoming: Vec::new(),
+        }
+    }
+}
+
+impl MediaState {
+    /// The volume that is actually heard, which is zero while muted
+    pub fn effective_volume(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.volume
+        }
+    }
+
+    /// The current track, if it is to play again under `Repeat::One`. Tracks
+    /// whose files have gone missing aren't repeated.
+    pub fn repeating_track(&self) -> Option<&Track> {
+        self.current_track
+            .as_ref()
+            .filter(|track| self.repeat == Repeat::One && track.exists())
+    }
+
+    /// Whether playback will stop once the current track ends
+    pub fn stop_after_pending(&self) -> bool {
+        self.stop_after == Some(1)
+    }
+
+    /// Count down the stop-after counter as a track ends, stopping playback
+    /// if it runs out. Returns whether it ran out.
+    pub fn count_down_stop_after(&mut self) -> bool {
+        match self.stop_after {
+            Some(count) if count <= 1 => {
+                self.stop_after = None;
+                self.playing = false;
+                self.stopped = true;
+                true
+            }
+            Some(count) => {
+                self.stop_after = Some(count - 1);
+                false
+            }
+            None => false,
+        }
+    }
 }
 
 pub struct MediaSystem {
     state: Arc<Mutex<MediaState>>,
     #[cfg(target_os = "linux")]
     mpris_server: Arc<Mutex<Server<MprisPlayer>>>,
+    /// The OS media controls, if they could be started
+    #[cfg(not(target_os = "linux"))]
+    media_keys: Option<MediaKeys>,
     sink: Sink,
-    stream_handle: OutputStreamHandle,
-    _stream: OutputStream,
-    queue: VecDeque<Track>,
-    ordered_queue: VecDeque<Track>,
-    history: Vec<Track>,
+    output: Output,
+    /// When the output was last found broken or failed to reopen, until it
+    /// is opened afresh
+    output_lost: Option<Instant>,
+    queue: PlayQueue,
+    /// Tracks that were skipped because their files could not be found
+    skipped: Vec<Track>,
+    /// Errors from tracks that could not be opened or decoded
+    play_errors: Vec<String>,
     gapless_playback: bool,
+    /// Whether to scale each track's volume by its ReplayGain
+    replay_gain: bool,
+    /// Gains for tracks whose tags have none
+    gain_sidecar: GainSidecar,
+    /// How long consecutive tracks overlap for. Zero disables crossfading.
+    crossfade: Duration,
+    /// The previous track, while it fades out during a crossfade
+    fading: Option<Fade>,
+    /// Whether the next track played should fade in
+    fade_in_next: bool,
+    /// The track being decoded in the background to be played next
+    loading: Option<Loading>,
+    /// The current track's cover art, being extracted in the background
+    cover: Option<Receiver<Option<PathBuf>>>,
+    /// The next track in the queue, prepared ahead of a gapless transition
+    preload: Option<Preload>,
+    /// Submits played tracks to Last.fm, if scrobbling is enabled
+    scrobbler: Option<Scrobbler>,
+    /// Unix time at which the current track started playing
+    track_started_at: u64,
+    /// Whether the current track has been scrobbled
+    scrobbled: bool,
+    /// Whether the current track has been counted as played
+    counted: bool,
+    /// Tracks that have been played through at least halfway
+    played: Vec<Track>,
+    /// Whether to show a desktop notification when a track starts playing
+    notifications: bool,
+    /// Measures the audio for drawing, if the visualizer is enabled
+    visualizer: Option<Visualizer>,
+}
+
+/// How long to wait between attempts to reopen a broken output
+const REOPEN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A sink fading out over a crossfade
+struct Fade {
+    sink: Sink,
+    elapsed: Duration,
+}
+
+/// A decoded track, ready to be appended to the sink
+type TrackSource = Box<dyn Source<Item = i16> + Send>;
+
+/// A track being opened and decoded on another thread, so that slow disks
+/// and streams don't hold up the UI
+struct Loading {
+    track: Track,
+    source: Receiver<Result<TrackSource>>,
+}
+
+impl Loading {
+    fn start(track: &Track) -> Self {
+        let (sender, source) = mpsc::channel();
+        let decode_track = track.clone();
+        thread::spawn(move || {
+            // Nothing is waiting for the result if the load was abandoned
+            let _ = sender.send(decode(&decode_track));
+        });
+        Self {
+            track: track.clone(),
+            source,
+        }
+    }
+}
+
+/// The next track in the queue, prepared before the current track ends
+enum Preload {
+    /// Being decoded in the background
+    Loading(Loading),
+    /// Appended to the sink to play after the current track
+    Appended(Track),
+    /// Failed to decode. The error is reported when the track is reached.
+    Failed,
+}
+
+/// Open a track's file or stream and decode it
+fn decode(track: &Track) -> Result<TrackSource> {
+    Ok(if stream::is_url(&track.file_path) {
+        Box::new(Decoder::new(HttpStream::open(&track.file_path)?)?)
+    } else {
+        Box::new(Decoder::new(BufReader::new(File::open(&track.file_path)?))?)
+    })
+}
+
+/// The current track's progress, going by the sink's position, or `None` if
+/// the position isn't the current track's: during a gapless handoff the sink
+/// can still be finishing the track before it, and it can have moved on to a
+/// preloaded track after it. The position is measured in real time, so it is
+/// scaled to the track's time at other speeds.
+fn sink_progress(sink: &Sink, preloaded: bool, speed: f32) -> Option<Duration> {
+    (sink.len() == 1 + usize::from(preloaded)).then(|| sink.get_pos().mul_f32(speed))
+}
+
+/// Where seeking by a number of seconds from `progress` lands, stopping at the
+/// start of the track and at its end unless its length is zero, meaning
+/// unknown
+fn seek_position(progress: Duration, seconds: f64, length: Duration) -> Duration {
+    if seconds < 0.0 {
+        progress.saturating_sub(Duration::from_secs_f64(-seconds))
+    } else {
+        let position = progress + Duration::from_secs_f64(seconds);
+        if length.is_zero() {
+            position
+        } else {
+            position.min(length)
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -70,17 +299,14 @@ impl Queueable {
     }
 }
 
-impl Default for Repeat {
-    fn default() -> Self {
-        Self::Off
-    }
-}
-
 impl MediaSystem {
     pub async fn new(
         #[cfg(target_os = "linux")] mpris_server: Arc<Mutex<Server<MprisPlayer>>>,
+        #[cfg(not(target_os = "linux"))] media_keys: Option<MediaKeys>,
         state: Arc<Mutex<MediaState>>,
         gapless_playback: bool,
+        replay_gain: bool,
+        crossfade: Duration,
     ) -> Result<Self> {
         #[cfg(target_os = "linux")]
         {
@@ -97,21 +323,37 @@ impl MediaSystem {
                 .await?;
         }
 
-        let (_stream, stream_handle) = OutputStream::try_default()?;
-        let sink = Sink::try_new(&stream_handle)?;
+        let output = Output::open()?;
+        let sink = output.sink();
 
-        #[allow(clippy::used_underscore_binding)]
         Ok(Self {
             state,
             #[cfg(target_os = "linux")]
             mpris_server,
-            stream_handle,
+            #[cfg(not(target_os = "linux"))]
+            media_keys,
             sink,
-            _stream,
-            queue: VecDeque::new(),
-            ordered_queue: VecDeque::new(),
-            history: Vec::new(),
+            output,
+            output_lost: None,
+            queue: PlayQueue::default(),
+            skipped: Vec::new(),
+            play_errors: Vec::new(),
             gapless_playback,
+            replay_gain,
+            gain_sidecar: GainSidecar::default(),
+            crossfade,
+            fading: None,
+            fade_in_next: false,
+            loading: None,
+            cover: None,
+            preload: None,
+            scrobbler: None,
+            track_started_at: 0,
+            scrobbled: false,
+            counted: false,
+            played: Vec::new(),
+            notifications: false,
+            visualizer: None,
         })
     }
 
@@ -120,10 +362,18 @@ impl MediaSystem {
         &self.state
     }
 
-    /// Add a track to the play queue
-    pub fn enqueue(&mut self, track: &Track) {
-        self.queue.push_back(track.clone());
-        self.ordered_queue.push_back(track.clone());
+    /// Add a track to the play queue. While shuffle is on it goes somewhere
+    /// random among the tracks still to play.
+    pub async fn enqueue(&mut self, track: &Track) {
+        let shuffle = self.state.lock().await.shuffle;
+        self.queue.enqueue(track, shuffle);
+    }
+
+    /// Queue tracks to play straight after the current track, in order and
+    /// ahead of the rest of the queue, even while shuffle is on. In the
+    /// unshuffled order they follow the current track too.
+    pub fn enqueue_front(&mut self, queueable: &Queueable) {
+        self.queue.enqueue_front(&queueable.get_tracks());
     }
 
     /// If there is a current track and it is paused, resume it. Otherwise does
@@ -134,6 +384,9 @@ impl MediaSystem {
             guard.playing = true;
             guard.stopped = false;
             self.sink.play();
+            if let Some(fade) = &self.fading {
+                fade.sink.play();
+            }
         }
         drop(guard);
         #[cfg(target_os = "linux")]
@@ -145,6 +398,8 @@ impl MediaSystem {
                 .properties_changed([Property::PlaybackStatus(PlaybackStatus::Playing)])
                 .await;
         }
+        #[cfg(not(target_os = "linux"))]
+        self.update_media_keys().await;
     }
 
     /// If there is a current track and it is playing, pause it. Otherwise does
@@ -154,6 +409,9 @@ impl MediaSystem {
         if guard.current_track.is_some() && guard.playing {
             guard.playing = false;
             self.sink.pause();
+            if let Some(fade) = &self.fading {
+                fade.sink.pause();
+            }
         }
         drop(guard);
         #[cfg(target_os = "linux")]
@@ -165,13 +423,21 @@ impl MediaSystem {
                 .properties_changed([Property::PlaybackStatus(PlaybackStatus::Paused)])
                 .await;
         }
+        #[cfg(not(target_os = "linux"))]
+        self.update_media_keys().await;
     }
 
     pub async fn stop(&mut self) -> Result<()> {
+        self.fading = None;
+        self.loading = None;
+        self.preload = None;
         if !self.sink.empty() {
             self.sink.stop();
-            self.state.lock().await.stopped = true;
-            self.sink = Sink::try_new(&self.stream_handle)?;
+            let mut guard = self.state.lock().await;
+            guard.stopped = true;
+            self.sink = self.output.sink();
+            self.sink.set_volume(guard.effective_volume());
+            self.sink.set_speed(guard.speed);
         }
 
         #[cfg(target_os = "linux")]
@@ -182,90 +448,368 @@ impl MediaSystem {
                 .properties_changed([Property::PlaybackStatus(PlaybackStatus::Stopped)])
                 .await?;
         }
+        #[cfg(not(target_os = "linux"))]
+        if let Some(media_keys) = &self.media_keys {
+            media_keys.set_playback(None, None);
+        }
 
         Ok(())
     }
 
+    /// Play the given track. If the track's file no longer exists, it is
+    /// recorded as skipped, and if it can't be opened or decoded, the error
+    /// is recorded. Either way the next track in the queue is played
+    /// instead.
+    ///
+    /// When interrupting, the track is decoded in the background and only
+    /// starts playing once `finish_loading` finds it ready. Otherwise it is
+    /// being queued gaplessly, so it is decoded straight away unless it was
+    /// already preloaded.
     pub async fn play_track(&mut self, track: &Track, interrupt: bool) -> Result<()> {
+        // If the queue changed after a different track was preloaded, that
+        // track has to be cleared out of the sink
+        let interrupt = interrupt
+            || matches!(&self.preload, Some(Preload::Appended(preloaded)) if preloaded != track);
         if interrupt {
             self.stop().await?;
         }
 
-        let file = BufReader::new(File::open(&track.file_path)?);
-        let source = Decoder::new(file)?;
+        let mut track = track.clone();
+        if !track.exists() {
+            self.skipped.push(track);
+            match self.pop_playable() {
+                Some(next_track) => track = next_track,
+                None => return Ok(()),
+            }
+        }
+
+        if interrupt {
+            self.loading = Some(Loading::start(&track));
+            return Ok(());
+        }
+
+        if let Some(Preload::Appended(_)) = self.preload.take() {
+            self.start_track(&track).await;
+            return Ok(());
+        }
+
+        // A bad file or stream is reported rather than returned, as it isn't
+        // fatal
+        let source = loop {
+            match decode(&track) {
+                Ok(source) => break source,
+                Err(e) => {
+                    self.play_errors
+                        .push(format!("Could not play {}: {e}", track.file_path));
+                    match self.pop_playable() {
+                        Some(next_track) => track = next_track,
+                        None => return Ok(()),
+                    }
+                }
+            }
+        };
+        self.append(&track, source);
+        self.start_track(&track).await;
+        Ok(())
+    }
+
+    /// Start playing the track being decoded in the background, if it is
+    /// ready. If it failed to decode, the error is recorded and the next track
+    /// in the queue is loaded instead. Returns whether a track started.
+    pub async fn finish_loading(&mut self) -> bool {
+        let Some(loading) = self.loading.take() else {
+            return false;
+        };
+        let result = match loading.source.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => {
+                self.loading = Some(loading);
+                return false;
+            }
+            Err(TryRecvError::Disconnected) => Err(anyhow!("Decoding stopped unexpectedly")),
+        };
+
+        match result {
+            Ok(source) => {
+                self.append(&loading.track, source);
+                self.start_track(&loading.track).await;
+                true
+            }
+            Err(e) => {
+                self.play_errors
+                    .push(format!("Could not play {}: {e}", loading.track.file_path));
+                self.loading = self.pop_playable().as_ref().map(Loading::start);
+                false
+            }
+        }
+    }
+
+    /// Whether a track is being decoded in the background
+    pub const fn loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Start decoding the next playable track in the queue in the
+    /// background, and append it to the sink once it is ready, so that the
+    /// gapless transition to it doesn't wait on slow disks or streams. Called
+    /// repeatedly as the current track nears its end.
+    pub async fn preload_next(&mut self) {
+        if self.state.lock().await.repeat == Repeat::One {
+            return;
+        }
+        self.preload = match self.preload.take() {
+            None => self
+                .queue
+                .tracks()
+                .find(|track| track.exists())
+                .map(|track| Preload::Loading(Loading::start(track))),
+            Some(Preload::Loading(loading)) => match loading.source.try_recv() {
+                Ok(Ok(source)) => {
+                    self.append(&loading.track, source);
+                    Some(Preload::Appended(loading.track))
+                }
+                Err(TryRecvError::Empty) => Some(Preload::Loading(loading)),
+                Ok(Err(_)) | Err(TryRecvError::Disconnected) => Some(Preload::Failed),
+            },
+            preload => preload,
+        };
+    }
+
+    /// Make a track that has been appended to the sink the current track.
+    /// Its cover art is extracted in the background, to be picked up by
+    /// `finish_cover`.
+    async fn start_track(&mut self, track: &Track) {
+        let (sender, receiver) = mpsc::channel();
+        let cover_track = track.clone();
+        thread::spawn(move || {
+            let _ = sender.send(library::cover_art(&cover_track));
+        });
+        self.cover = Some(receiver);
+        self.queue.start(track);
         let mut guard = self.state.lock().await;
 
         guard.current_track = Some(track.clone());
+        guard.current_cover = None;
         guard.current_track_progress = Some(Duration::from_millis(0));
         guard.playing = true;
+        guard.stopped = false;
+        guard.loop_a = None;
+        guard.loop_b = None;
         drop(guard);
-        self.sink.append(source);
 
-        #[allow(clippy::cast_possible_wrap)]
+        self.reset_play_start();
+
         #[cfg(target_os = "linux")]
         {
-            let mut metadata_builder = Metadata::builder()
-                .title(
-                    track
-                        .title
-                        .clone()
-                        .unwrap_or_else(|| track.file_path.clone()),
-                )
-                .artist([&track.artist])
-                .album(&track.album)
-                .length(Time::from_secs(track.length.as_secs() as i64));
-            if let Some(number) = track.number {
-                metadata_builder = metadata_builder.track_number(number as i32);
-            }
             let _ = self
                 .mpris_server
                 .lock()
                 .await
                 .properties_changed([
                     Property::PlaybackStatus(PlaybackStatus::Playing),
-                    Property::Metadata(metadata_builder.build()),
+                    Property::Metadata(mpris::track_metadata(track, 0, None)),
                 ])
                 .await;
         }
-        Ok(())
+        #[cfg(not(target_os = "linux"))]
+        if let Some(media_keys) = &self.media_keys {
+            media_keys.set_metadata(track, None);
+            media_keys.set_playback(Some(true), Some(Duration::ZERO));
+        }
     }
 
-    /// Play the next track in the queue
-    pub async fn play_next(&mut self, interrupt: bool) -> Result<()> {
+    /// Show the current track's cover art once it has been extracted, and
+    /// announce the track, cover and all, if notifications are on. Returns
+    /// whether the cover was ready.
+    pub async fn finish_cover(&mut self) -> bool {
+        let Some(receiver) = &self.cover else {
+            return false;
+        };
+        let cover = match receiver.try_recv() {
+            Ok(cover) => cover,
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => None,
+        };
+        self.cover = None;
+
         let mut guard = self.state.lock().await;
+        let Some(track) = guard.current_track.clone() else {
+            return false;
+        };
+        guard.current_cover.clone_from(&cover);
+        drop(guard);
 
-        let next_track = if guard.repeat == Repeat::One {
-            guard.current_track.clone()
+        #[cfg(target_os = "linux")]
+        if cover.is_some() {
+            let _ = self
+                .mpris_server
+                .lock()
+                .await
+                .properties_changed([Property::Metadata(mpris::track_metadata(
+                    &track,
+                    0,
+                    cover.as_deref(),
+                ))])
+                .await;
+        }
+        #[cfg(not(target_os = "linux"))]
+        if let (Some(media_keys), Some(_)) = (&self.media_keys, &cover) {
+            media_keys.set_metadata(&track, cover.as_deref());
+        }
+
+        if self.notifications {
+            notify::now_playing(&track, cover.as_deref());
+        }
+        true
+    }
+
+    fn append(&mut self, track: &Track, source: TrackSource) {
+        let source: TrackSource = match &self.visualizer {
+            Some(visualizer) => Box::new(visualizer.tap(source)),
+            None => source,
+        };
+        // ReplayGain is applied to the source rather than the sink so that
+        // it only affects this track when tracks are queued gaplessly
+        let gain = if self.replay_gain {
+            self.gain_sidecar.gain_factor(track)
         } else {
-            self.queue.pop_front()
+            1.0
+        };
+        if mem::take(&mut self.fade_in_next) {
+            self.sink
+                .append(source.amplify(gain).fade_in(self.crossfade));
+        } else {
+            self.sink.append(source.amplify(gain));
+        }
+    }
+
+    /// Record that the current track has just started playing, for
+    /// scrobbling
+    fn reset_play_start(&mut self) {
+        self.track_started_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.scrobbled = false;
+        self.counted = false;
+    }
+
+    /// Start the current track again from the beginning. The track is
+    /// seeked back to the start if it is still in the sink, which keeps
+    /// gapless playback gapless, and decoded again if it has already ended.
+    async fn restart_track(&mut self, track: &Track) -> Result<()> {
+        // A preloaded track would play after this one, so it's cleared out
+        if matches!(self.preload, Some(Preload::Appended(_))) {
+            self.stop().await?;
+        }
+        if self.sink.empty() {
+            self.append(track, decode(track)?);
+        } else {
+            self.sink
+                .try_seek(Duration::ZERO)
+                .map_err(|e| anyhow!("Seek failed: {e}"))?;
+        }
+        self.reset_play_start();
+
+        let mut guard = self.state.lock().await;
+        guard.current_track_progress = Some(Duration::ZERO);
+        guard.playing = true;
+        guard.stopped = false;
+        Ok(())
+    }
+
+    /// Play the next track in the queue. When a track ends on its own under
+    /// `Repeat::One` it is restarted instead.
+    pub async fn play_next(&mut self, interrupt: bool) -> Result<()> {
+        let state = self.state.clone();
+        let mut guard = state.lock().await;
+
+        if !interrupt {
+            if let Some(track) = guard.repeating_track().cloned() {
+                drop(guard);
+                return self.restart_track(&track).await;
+            }
+        }
+
+        let next_track = match guard.repeating_track().cloned() {
+            Some(track) => {
+                self.queue.repeat_current();
+                Some(track)
+            }
+            None => self.pop_playable(),
         };
 
         if let Some(track) = next_track {
-            if let Some(current_track) = mem::take(&mut guard.current_track) {
-                self.history.push(current_track.clone());
-                if guard.repeat == Repeat::On {
-                    self.queue.push_back(current_track);
-                }
+            if guard.current_track.take().is_some() {
+                self.queue.retire_current(guard.repeat == Repeat::On);
             }
             drop(guard);
             self.play_track(&track, interrupt || !self.gapless_playback)
                 .await?;
+        } else if matches!(self.preload, Some(Preload::Appended(_))) {
+            // The preloaded track was taken out of the queue, so it mustn't
+            // play
+            drop(guard);
+            self.stop().await?;
         }
 
         Ok(())
     }
 
-    pub async fn play_prev(&mut self) -> Result<()> {
+    /// Start playing the next track in the queue on a fresh sink, fading it
+    /// in while the current track fades out
+    pub async fn crossfade_next(&mut self) -> Result<()> {
+        let sink = self.output.sink();
         let guard = self.state.lock().await;
+        sink.set_volume(guard.effective_volume());
+        sink.set_speed(guard.speed);
+        drop(guard);
+        self.fading = Some(Fade {
+            sink: mem::replace(&mut self.sink, sink),
+            elapsed: Duration::ZERO,
+        });
+        self.fade_in_next = true;
+        self.play_next(false).await?;
+        self.fade_in_next = false;
+        Ok(())
+    }
 
-        if let Some(prev_track) = self.history.pop() {
-            if let Some(current_track) = &guard.current_track {
-                self.queue.push_front(current_track.clone());
+    /// Pop tracks off the front of the queue until one is found whose file
+    /// exists, recording any missing tracks as skipped.
+    fn pop_playable(&mut self) -> Option<Track> {
+        while let Some(track) = self.queue.pop_front() {
+            if track.exists() {
+                return Some(track);
             }
+            self.skipped.push(track);
+        }
+        None
+    }
+
+    /// The tracks played so far, most recent first
+    pub fn history_snapshot(&self) -> Vec<Track> {
+        self.queue.history().cloned().collect()
+    }
+
+    /// Take the list of tracks skipped since the last call
+    pub fn take_skipped(&mut self) -> Vec<Track> {
+        mem::take(&mut self.skipped)
+    }
+
+    /// Take the list of tracks played through at least halfway since the last
+    /// call
+    pub fn take_played(&mut self) -> Vec<Track> {
+        mem::take(&mut self.played)
+    }
+
+    pub async fn play_prev(&mut self) -> Result<()> {
+        let guard = self.state.lock().await;
+
+        if let Some(prev_track) = self.queue.step_back() {
             drop(guard);
             self.play_track(&prev_track, true).await?;
         } else if let Some(current_track) = guard.current_track.clone() {
             drop(guard);
+            self.queue.repeat_current();
             self.play_track(&current_track, true).await?;
         }
 
@@ -273,34 +817,30 @@ impl MediaSystem {
     }
 
     pub async fn enqueue_and_play(&mut self, queueable: &Queueable) -> Result<()> {
-        self.queue.clear();
+        self.clear_queue();
         let tracks = queueable.get_tracks();
         for t in tracks {
-            self.enqueue(&t);
+            self.enqueue(&t).await;
         }
         match queueable {
             Queueable::Artist(_) | Queueable::Album(_) | Queueable::Playlist(_) => {
                 if self.state.lock().await.shuffle {
-                    self.queue
-                        .make_contiguous()
-                        .shuffle(&mut rand::thread_rng());
+                    self.queue.shuffle();
                 }
 
-                if let Some(track) = self.queue.pop_front() {
+                if let Some(track) = self.pop_playable() {
                     self.play_track(&track, true).await
                 } else {
                     Ok(())
                 }
             }
             Queueable::TrackList(_) => {
-                if let Some(track) = self.queue.pop_front() {
+                if let Some(track) = self.pop_playable() {
                     self.play_track(&track, true).await?;
                 }
 
                 if self.state.lock().await.shuffle {
-                    self.queue
-                        .make_contiguous()
-                        .shuffle(&mut rand::thread_rng());
+                    self.queue.shuffle();
                 }
 
                 Ok(())
@@ -309,12 +849,225 @@ impl MediaSystem {
         }
     }
 
-    /// Add the given duration to the current track's playback progress
-    pub async fn update_progress(&self, duration: Duration) {
+    /// Seek to the given position in the current track
+    pub async fn seek_to(&self, position: Duration) -> Result<()> {
         let mut guard = self.state.lock().await;
+        let Some(track) = guard.current_track.as_ref() else {
+            return Err(anyhow!("Nothing is playing"));
+        };
+        // A length of zero means the length isn't known, as for streams, so
+        // the decoder is left to decide whether the position can be reached
+        if !track.length.is_zero() && position > track.length {
+            return Err(anyhow!(
+                "Track is only {} long",
+                util::format_duration(track.length)
+            ));
+        }
+        self.sink
+            .try_seek(position)
+            .map_err(|e| anyhow!("Seek failed: {e}"))?;
+        guard.current_track_progress = Some(position);
+        drop(guard);
 
-        if let Some(progress) = guard.current_track_progress.as_mut() {
-            *progress += duration;
+        #[cfg(target_os = "linux")]
+        {
+            let _ = self
+                .mpris_server
+                .lock()
+                .await
+                .emit(Signal::Seeked {
+                    position: mpris::time(position),
+                })
+                .await;
+        }
+        #[cfg(not(target_os = "linux"))]
+        self.update_media_keys().await;
+        Ok(())
+    }
+
+    /// Seek forward (or backward, if negative) by the given number of seconds
+    /// in the current track, stopping at its start or, if its length is known,
+    /// its end
+    pub async fn seek_by(&self, seconds: f64) -> Result<()> {
+        let guard = self.state.lock().await;
+        let (Some(track), Some(progress)) =
+            (guard.current_track.as_ref(), guard.current_track_progress)
+        else {
+            return Ok(());
+        };
+        let length = track.length;
+        drop(guard);
+
+        self.seek_to(seek_position(progress, seconds, length)).await
+    }
+
+    /// Raise (or lower, if negative) the volume by the given step, keeping it
+    /// between 0 and 1. While muted, this changes the level that will be
+    /// restored on unmuting. Returns the new volume.
+    pub async fn change_volume(&self, step: f32) -> f32 {
+        let volume = self.state.lock().await.volume;
+        self.set_volume(volume + step).await
+    }
+
+    /// Set the volume, keeping it between 0 and 1. While muted, this changes
+    /// the level that will be restored on unmuting. Returns the new volume.
+    pub async fn set_volume(&self, volume: f32) -> f32 {
+        let mut guard = self.state.lock().await;
+        guard.volume = volume.clamp(0.0, 1.0);
+        self.sink.set_volume(guard.effective_volume());
+        guard.volume
+    }
+
+    /// Speed playback up (or down, if negative) by the given step. Returns
+    /// the new speed.
+    pub async fn change_speed(&self, step: f32) -> f32 {
+        let speed = self.state.lock().await.speed;
+        self.set_speed(speed + step).await
+    }
+
+    /// Set the playback speed, keeping it between `MIN_SPEED` and
+    /// `MAX_SPEED`. Returns the new speed.
+    pub async fn set_speed(&self, speed: f32) -> f32 {
+        let mut guard = self.state.lock().await;
+        guard.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.sink.set_speed(guard.speed);
+        if let Some(fade) = &self.fading {
+            fade.sink.set_speed(guard.speed);
+        }
+        guard.speed
+    }
+
+    /// Toggle between muted and unmuted. Returns whether playback is now
+    /// muted.
+    pub async fn toggle_mute(&self) -> bool {
+        let mut guard = self.state.lock().await;
+        guard.muted = !guard.muted;
+        self.sink.set_volume(guard.effective_volume());
+        if let (Some(fade), true) = (&self.fading, guard.muted) {
+            fade.sink.set_volume(0.0);
+        }
+        guard.muted
+    }
+
+    /// Switch the playback bar between showing the time elapsed and the time
+    /// remaining. Returns whether it now shows the time remaining.
+    pub async fn toggle_show_remaining(&self) -> bool {
+        let mut guard = self.state.lock().await;
+        guard.show_remaining = !guard.show_remaining;
+        guard.show_remaining
+    }
+
+    /// Pause playback if the audio device has had an error since this was
+    /// last called, as when headphones are unplugged, so that the track
+    /// doesn't carry on unheard. Returns the error. Later checks reopen the
+    /// output on whichever device is then the default, with the current
+    /// track reloaded where it was paused. Playback is only resumed by hand.
+    pub async fn check_output(&mut self) -> Option<String> {
+        if let Some(error) = self.output.take_error() {
+            self.pause().await;
+            self.output_lost = Some(Instant::now());
+            return Some(error.to_string());
+        }
+        if self
+            .output_lost
+            .is_some_and(|since| since.elapsed() >= REOPEN_INTERVAL)
+        {
+            match Output::open() {
+                Ok(output) => {
+                    self.output = output;
+                    self.output_lost = None;
+                    self.restore_sink().await;
+                }
+                Err(_) => self.output_lost = Some(Instant::now()),
+            }
+        }
+        None
+    }
+
+    /// Move playback onto a fresh sink on the current output, with the
+    /// current track reloaded, paused, at its last position
+    async fn restore_sink(&mut self) {
+        self.fading = None;
+        self.preload = None;
+        let guard = self.state.lock().await;
+        let sink = self.output.sink();
+        sink.set_volume(guard.effective_volume());
+        sink.set_speed(guard.speed);
+        self.sink = sink;
+        let (Some(track), progress) = (guard.current_track.clone(), guard.current_track_progress)
+        else {
+            return;
+        };
+        drop(guard);
+
+        match decode(&track) {
+            Ok(source) => {
+                // Left empty, the sink plays whatever is appended next
+                self.sink.pause();
+                self.append(&track, source);
+                if let Some(progress) = progress {
+                    let _ = self.sink.try_seek(progress);
+                }
+            }
+            Err(e) => self
+                .play_errors
+                .push(format!("Could not play {}: {e}", track.file_path)),
+        }
+    }
+
+    /// Update the current track's playback progress from the sink's position.
+    /// If playback passes the end of an A-B loop, seek back to its start. Any
+    /// track fading out during a crossfade is turned down over the given time
+    /// elapsed.
+    pub async fn update_progress(&mut self, duration: Duration) {
+        let volume = self.state.lock().await.effective_volume();
+        if let Some(fade) = self.fading.as_mut() {
+            fade.elapsed += duration;
+            if fade.elapsed >= self.crossfade || fade.sink.empty() {
+                self.fading = None;
+            } else {
+                let remaining = 1.0 - fade.elapsed.as_secs_f32() / self.crossfade.as_secs_f32();
+                fade.sink.set_volume(volume * remaining);
+            }
+        }
+
+        let mut guard = self.state.lock().await;
+
+        // The sink's position doesn't drift from the audio like a running
+        // total of elapsed time does
+        if guard.current_track_progress.is_some() {
+            let preloaded = matches!(self.preload, Some(Preload::Appended(_)));
+            if let Some(progress) = sink_progress(&self.sink, preloaded, guard.speed) {
+                guard.current_track_progress = Some(progress);
+            }
+        }
+
+        if let (Some(scrobbler), Some(track), Some(progress)) = (
+            &self.scrobbler,
+            &guard.current_track,
+            guard.current_track_progress,
+        ) {
+            if !self.scrobbled && Scrobbler::should_scrobble(track, progress) {
+                scrobbler.scrobble(track, self.track_started_at);
+                self.scrobbled = true;
+            }
+        }
+
+        if let (Some(track), Some(progress)) = (&guard.current_track, guard.current_track_progress)
+        {
+            // Tracks of unknown length, like streams, are never counted
+            if !self.counted && !track.length.is_zero() && progress >= track.length / 2 {
+                self.played.push(track.clone());
+                self.counted = true;
+            }
+        }
+
+        if let (Some(a), Some(b), Some(progress)) =
+            (guard.loop_a, guard.loop_b, guard.current_track_progress)
+        {
+            if progress >= b && self.sink.try_seek(a).is_ok() {
+                guard.current_track_progress = Some(a);
+            }
         }
 
         if self.sink_empty() {
@@ -322,40 +1075,55 @@ impl MediaSystem {
             guard.current_track_progress = None;
             if guard.repeat != Repeat::One {
                 guard.current_track = None;
+                self.queue.clear_current();
             }
         }
     }
 
+    /// Show whether the current track is playing, and how far into it, in
+    /// the OS media controls
+    #[cfg(not(target_os = "linux"))]
+    async fn update_media_keys(&self) {
+        if let Some(media_keys) = &self.media_keys {
+            let guard = self.state.lock().await;
+            let playing = guard.current_track.as_ref().map(|_| guard.playing);
+            media_keys.set_playback(playing, guard.current_track_progress);
+        }
+    }
+
     /// Toggle between playing/paused
     pub async fn toggle_play(&self) {
         let guard = self.state.lock().await;
-        let status = if guard.current_track.is_some() {
-            if guard.playing {
+        match (guard.current_track.is_some(), guard.playing) {
+            (true, true) => {
                 drop(guard);
                 self.pause().await;
-                #[cfg(target_os = "linux")]
-                PlaybackStatus::Paused
-            } else {
+            }
+            (true, false) => {
                 drop(guard);
                 self.play().await;
+            }
+            (false, _) => {
+                drop(guard);
                 #[cfg(target_os = "linux")]
-                PlaybackStatus::Playing
+                {
+                    let _ = self
+                        .mpris_server
+                        .lock()
+                        .await
+                        .properties_changed([Property::PlaybackStatus(PlaybackStatus::Stopped)])
+                        .await;
+                }
             }
-        } else {
-            #[cfg(target_os = "linux")]
-            PlaybackStatus::Stopped
-        };
-        #[cfg(target_os = "linux")]
-        {
-            let _ = self
-                .mpris_server
-                .lock()
-                .await
-                .properties_changed([Property::PlaybackStatus(status)])
-                .await;
         }
     }
 
+    /// Shuffle the tracks still to play again, leaving the current track and
+    /// the unshuffled order alone
+    pub fn reshuffle(&mut self) {
+        self.queue.shuffle();
+    }
+
     pub async fn toggle_shuffle(&mut self) {
         let mut guard = self.state.lock().await;
         guard.shuffle = !guard.shuffle;
@@ -371,33 +1139,24 @@ impl MediaSystem {
         }
 
         if guard.shuffle {
-            drop(guard);
-            self.queue
-                .make_contiguous()
-                .shuffle(&mut rand::thread_rng());
+            self.queue.shuffle();
         } else {
-            let current_track = guard.current_track.clone();
-            drop(guard);
-            self.queue.clone_from(&self.ordered_queue);
-            let current_track_index = self
-                .queue
-                .iter()
-                .position(|t| Some(t) == current_track.as_ref());
-            if let Some(index) = current_track_index {
-                self.queue.rotate_left(index + 1);
-            }
+            self.queue.unshuffle(guard.repeat == Repeat::On);
         }
     }
 
     pub async fn toggle_repeat(&self) {
         use Repeat::{Off, On, One};
-        let mut guard = self.state.lock().await;
-
-        guard.repeat = match guard.repeat {
+        let repeat = match self.state.lock().await.repeat {
             Off => One,
             One => On,
             On => Off,
         };
+        self.set_repeat(repeat).await;
+    }
+
+    pub async fn set_repeat(&self, repeat: Repeat) {
+        self.state.lock().await.repeat = repeat;
 
         #[cfg(target_os = "linux")]
         {
@@ -405,17 +1164,111 @@ impl MediaSystem {
                 .mpris_server
                 .lock()
                 .await
-                .properties_changed([Property::LoopStatus(guard.repeat.into())])
+                .properties_changed([Property::LoopStatus(repeat.into())])
                 .await;
-            drop(guard);
         }
     }
 
+    /// Set the start of the A-B loop to the current position. If the loop is
+    /// already fully set, clear it instead.
+    pub async fn set_loop_a(&self) {
+        let mut guard = self.state.lock().await;
+        if guard.loop_a.is_some() && guard.loop_b.is_some() {
+            guard.loop_a = None;
+            guard.loop_b = None;
+        } else {
+            guard.loop_a = guard.current_track_progress;
+            if guard.loop_a >= guard.loop_b {
+                guard.loop_b = None;
+            }
+        }
+    }
+
+    /// Set the end of the A-B loop to the current position. If the loop is
+    /// already fully set, clear it instead. The end must come after the start.
+    pub async fn set_loop_b(&self) {
+        let mut guard = self.state.lock().await;
+        if guard.loop_a.is_some() && guard.loop_b.is_some() {
+            guard.loop_a = None;
+            guard.loop_b = None;
+        } else if guard.current_track_progress > guard.loop_a {
+            guard.loop_b = guard.current_track_progress;
+        }
+    }
+
+    pub async fn set_stop_after(&self, count: Option<u32>) {
+        self.state.lock().await.stop_after = count;
+    }
+
+    /// Stop playback after a while, or cancel the sleep timer if `None`.
+    /// Fails, leaving the timer as it was, if the time is too far off to be
+    /// represented.
+    pub async fn set_sleep_timer(&self, duration: Option<Duration>) -> Result<()> {
+        let deadline = match duration {
+            Some(duration) => Some(
+                SystemTime::now()
+                    .checked_add(duration)
+                    .ok_or_else(|| anyhow!("sleep: Time too long"))?,
+            ),
+            None => None,
+        };
+        self.state.lock().await.sleep_deadline = deadline;
+        Ok(())
+    }
+
+    /// Put the sleep timer's deadline back, so that time spent paused isn't
+    /// counted
+    pub async fn hold_sleep_timer(&self, elapsed: Duration) {
+        if let Some(deadline) = &mut self.state.lock().await.sleep_deadline {
+            *deadline = deadline.checked_add(elapsed).unwrap_or(*deadline);
+        }
+    }
+
+    /// Whether the sleep timer's deadline has passed, resetting the timer if
+    /// so
+    pub async fn sleep_timer_expired(&self) -> bool {
+        let mut guard = self.state.lock().await;
+        if guard
+            .sleep_deadline
+            .is_some_and(|deadline| SystemTime::now() >= deadline)
+        {
+            guard.sleep_deadline = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether playback will stop once the current track ends
+    pub async fn stop_after_pending(&self) -> bool {
+        self.state.lock().await.stop_after_pending()
+    }
+
+    /// Count down the stop-after counter as a track ends. Returns `true` if
+    /// the counter ran out and playback should stop.
+    pub async fn count_down_stop_after(&self) -> bool {
+        self.state.lock().await.count_down_stop_after()
+    }
+
+    pub async fn stopped(&self) -> bool {
+        self.state.lock().await.stopped
+    }
+
     pub fn sink_empty(&self) -> bool {
         self.sink.empty()
     }
 
+    /// Whether the sink has moved on from the current track to a preloaded
+    /// one, which means the current track has ended
+    fn preload_started(&self) -> bool {
+        matches!(self.preload, Some(Preload::Appended(_))) && self.sink.len() < 2
+    }
+
     pub async fn time_remaining(&self) -> Duration {
+        if self.preload_started() {
+            return Duration::ZERO;
+        }
+
         let guard = self.state.lock().await;
         guard
             .current_track
@@ -432,17 +1285,146 @@ impl MediaSystem {
             })
     }
 
-    pub fn queue_empty(&self) -> bool {
-        self.queue.is_empty()
+    /// Whether there is anything to play once the current track ends,
+    /// including the current track itself under `Repeat::One`. A preloaded
+    /// track counts too, even if it has since left the queue, as it then has
+    /// to be cleared out of the sink.
+    pub async fn has_next(&self) -> bool {
+        let guard = self.state.lock().await;
+        !self.queue.is_empty()
+            || (guard.repeat == Repeat::One && guard.current_track.is_some())
+            || matches!(self.preload, Some(Preload::Appended(_)))
     }
 
     pub fn clear_queue(&mut self) {
         self.queue.clear();
     }
 
+    /// The position of the current track in the queue, counting from 1, and
+    /// the number of tracks in the queue, or `None` if nothing is playing.
+    /// Under `Repeat::On` the position wraps around the queue.
+    pub async fn queue_position(&self) -> Option<(usize, usize)> {
+        let guard = self.state.lock().await;
+        guard.current_track.as_ref()?;
+        let played = self.queue.played_count();
+        if guard.repeat == Repeat::On && self.queue.ordered_len() > 0 {
+            let total = self.queue.ordered_len();
+            Some((played % total + 1, total))
+        } else {
+            Some((played + 1, played + 1 + self.queue.len()))
+        }
+    }
+
+    /// Insert a track into the queue of tracks still to play, at the given
+    /// index or the end. Returns the index it went in at.
+    pub fn insert_queued(&mut self, track: &Track, index: usize) -> usize {
+        self.queue.insert(track, index)
+    }
+
+    /// Take the track at the given index out of the queue of tracks still to
+    /// play
+    pub fn remove_queued(&mut self, index: usize) {
+        self.queue.remove(index);
+    }
+
+    /// Skip ahead to the track at the given index of the queue of tracks
+    /// still to play, leaving the tracks before it queued
+    pub async fn play_queued(&mut self, index: usize) -> Result<()> {
+        if self.queue.move_to_front(index) {
+            self.play_next(true).await?;
+        }
+        Ok(())
+    }
+
+    /// Copy the queue into the shared state for MPRIS if it has changed, and
+    /// signal that the track list was replaced
+    pub async fn publish_queue(&self) {
+        let mut guard = self.state.lock().await;
+        if guard.upcoming.iter().eq(self.queue.tracks()) {
+            return;
+        }
+        guard.upcoming = self.queue.tracks().cloned().collect();
+
+        #[cfg(target_os = "linux")]
+        {
+            let tracks = mpris::track_ids(&guard);
+            let current_track = if guard.current_track.is_some() {
+                mpris::track_id(0)
+            } else {
+                TrackId::NO_TRACK
+            };
+            drop(guard);
+            let _ = self
+                .mpris_server
+                .lock()
+                .await
+                .track_list_emit(TrackListSignal::TrackListReplaced {
+                    tracks,
+                    current_track,
+                })
+                .await;
+        }
+    }
+
+    /// Get the tracks of the play queue in their unshuffled order, including
+    /// the current track and any that have already been played.
+    pub fn queue_snapshot(&self) -> Vec<Track> {
+        self.queue.ordered_tracks()
+    }
+
     pub const fn gapless_playback(&self) -> bool {
         self.gapless_playback
     }
+
+    pub fn set_gapless_playback(&mut self, gapless_playback: bool) {
+        self.gapless_playback = gapless_playback;
+    }
+
+    pub fn set_replay_gain(&mut self, replay_gain: bool) {
+        self.replay_gain = replay_gain;
+    }
+
+    pub fn set_gain_sidecar(&mut self, gain_sidecar: GainSidecar) {
+        self.gain_sidecar = gain_sidecar;
+    }
+
+    pub const fn crossfade(&self) -> Duration {
+        self.crossfade
+    }
+
+    pub fn set_crossfade(&mut self, crossfade: Duration) {
+        self.crossfade = crossfade;
+    }
+
+    pub fn set_notifications(&mut self, notifications: bool) {
+        self.notifications = notifications;
+    }
+
+    /// Measure tracks for the visualizer from the next one appended on
+    pub fn set_visualizer(&mut self, visualizer: Option<Visualizer>) {
+        self.visualizer = visualizer;
+    }
+
+    pub fn set_scrobbler(&mut self, scrobbler: Option<Scrobbler>) {
+        self.scrobbler = scrobbler;
+    }
+
+    /// Take the errors from tracks that failed to play since the last call
+    pub fn take_play_errors(&mut self) -> Vec<String> {
+        mem::take(&mut self.play_errors)
+    }
+
+    /// Take the errors from scrobbles that have failed since the last call
+    pub fn take_scrobble_errors(&self) -> Vec<String> {
+        self.scrobbler
+            .as_ref()
+            .map_or_else(Vec::new, Scrobbler::take_errors)
+    }
+
+    /// Whether a track is currently fading out
+    pub const fn crossfading(&self) -> bool {
+        self.fading.is_some()
+    }
 }
 
 #[cfg(target_os = "linux")]
@@ -455,3 +1437,166 @@ impl From<Repeat> for LoopStatus {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+
+    use rodio::buffer::SamplesBuffer;
+
+    use crate::util::{write_wav, TestDir};
+
+    #[test]
+    fn stop_after_counts_down_to_a_stop() {
+        let mut state = MediaState {
+            stop_after: Some(3),
+            playing: true,
+            ..MediaState::default()
+        };
+        assert!(!state.count_down_stop_after());
+        assert!(!state.stop_after_pending());
+        assert!(!state.count_down_stop_after());
+        assert!(state.stop_after_pending());
+        assert!(state.playing);
+
+        assert!(state.count_down_stop_after());
+        assert_eq!(state.stop_after, None);
+        assert!(!state.playing);
+        assert!(state.stopped);
+    }
+
+    #[test]
+    fn stop_after_does_nothing_when_off() {
+        let mut state = MediaState {
+            playing: true,
+            ..MediaState::default()
+        };
+        assert!(!state.stop_after_pending());
+        assert!(!state.count_down_stop_after());
+        assert!(state.playing);
+        assert!(!state.stopped);
+    }
+
+    #[test]
+    fn repeat_one_repeats_the_current_track_while_it_exists() {
+        let dir = TestDir::new("repeat_one");
+        let path = dir.path().join("a.wav");
+        write_wav(&path);
+        let mut state = MediaState {
+            current_track: Some(Track::from_url(&path.to_string_lossy())),
+            repeat: Repeat::One,
+            ..MediaState::default()
+        };
+        assert_eq!(state.repeating_track(), state.current_track.as_ref());
+
+        state.repeat = Repeat::On;
+        assert_eq!(state.repeating_track(), None);
+
+        state.repeat = Repeat::One;
+        fs::remove_file(&path).unwrap();
+        assert_eq!(state.repeating_track(), None);
+
+        state.current_track = None;
+        assert_eq!(state.repeating_track(), None);
+    }
+
+    #[test]
+    fn decode_fails_on_files_that_are_not_audio() {
+        let dir = TestDir::new("decode");
+        let good = dir.path().join("good.wav");
+        let bad = dir.path().join("bad.wav");
+        write_wav(&good);
+        fs::write(&bad, "not audio").unwrap();
+        let track = |path: &Path| Track::from_url(&path.to_string_lossy());
+
+        let source = decode(&track(&good)).unwrap();
+        assert_eq!(source.count(), 800);
+        assert!(decode(&track(&bad)).is_err());
+        assert!(decode(&track(&dir.path().join("missing.wav"))).is_err());
+    }
+
+    #[test]
+    fn loading_reports_a_decode_failure() {
+        let dir = TestDir::new("loading");
+        let bad = dir.path().join("bad.wav");
+        fs::write(&bad, "not audio").unwrap();
+        let loading = Loading::start(&Track::from_url(&bad.to_string_lossy()));
+        assert!(loading.source.recv().unwrap().is_err());
+    }
+
+    /// A second of silence at 8 kHz
+    fn second_of_silence() -> SamplesBuffer<i16> {
+        SamplesBuffer::new(1, 8000, vec![0; 8000])
+    }
+
+    #[test]
+    fn progress_follows_the_sink_position_at_the_playback_speed() {
+        let (sink, mut output) = Sink::new_idle();
+        sink.append(second_of_silence());
+        output.by_ref().take(4000).for_each(drop);
+
+        let progress = sink_progress(&sink, false, 1.0).unwrap();
+        assert!(progress.abs_diff(Duration::from_millis(500)) <= Duration::from_millis(10));
+        let progress = sink_progress(&sink, false, 2.0).unwrap();
+        assert!(progress.abs_diff(Duration::from_secs(1)) <= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn progress_is_only_taken_from_the_current_track() {
+        let (sink, mut output) = Sink::new_idle();
+        sink.append(second_of_silence());
+        sink.append(second_of_silence());
+        assert!(sink_progress(&sink, true, 1.0).is_some());
+        // The track before the current one is still finishing
+        assert_eq!(sink_progress(&sink, false, 1.0), None);
+
+        // Once the sink moves on to the preloaded track, its position is no
+        // longer the current track's
+        output.by_ref().take(8100).for_each(drop);
+        assert_eq!(sink_progress(&sink, true, 1.0), None);
+    }
+
+    #[test]
+    fn progress_does_not_count_time_spent_paused() {
+        let (sink, mut output) = Sink::new_idle();
+        sink.append(second_of_silence());
+        output.by_ref().take(2000).for_each(drop);
+
+        // The output keeps running while paused, but the track doesn't
+        sink.pause();
+        output.by_ref().take(4000).for_each(drop);
+        let paused = sink_progress(&sink, false, 1.0).unwrap();
+        assert!(paused.abs_diff(Duration::from_millis(250)) <= Duration::from_millis(10));
+
+        sink.play();
+        output.by_ref().take(2000).for_each(drop);
+        let after = sink_progress(&sink, false, 1.0).unwrap();
+        assert!(after.abs_diff(Duration::from_millis(500)) <= Duration::from_millis(10));
+    }
+
+    #[test]
+    fn seeking_stops_at_the_ends_of_a_track_of_known_length() {
+        let length = Duration::from_secs(60);
+        let progress = Duration::from_secs(50);
+        assert_eq!(
+            seek_position(progress, 5.0, length),
+            Duration::from_secs(55)
+        );
+        assert_eq!(seek_position(progress, 30.0, length), length);
+        assert_eq!(seek_position(progress, -80.0, length), Duration::ZERO);
+    }
+
+    #[test]
+    fn seeking_forward_is_not_clamped_when_the_length_is_unknown() {
+        let progress = Duration::from_secs(50);
+        assert_eq!(
+            seek_position(progress, 30.0, Duration::ZERO),
+            Duration::from_secs(80)
+        );
+        assert_eq!(
+            seek_position(progress, -20.0, Duration::ZERO),
+            Duration::from_secs(30)
+        );
+    }
+}
@@ -4,40 +4,145 @@
 
 #![allow(clippy::cast_possible_wrap)]
 
-use std::{collections::VecDeque, sync::Arc};
+use std::{
+    collections::VecDeque,
+    path::Path,
+    process::{self, Stdio},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 
 use async_std::sync::Mutex;
 
 use mpris_server::{
     zbus::{fdo, Result},
     LoopStatus, Metadata, PlaybackRate, PlaybackStatus, PlayerInterface, RootInterface, Time,
-    TrackId, Volume,
+    TrackId, TrackListInterface, Uri, Volume,
 };
 
 use crate::{
     command::Command,
-    media_system::{MediaState, Repeat},
+    library::track::Track,
+    media_system::{MediaState, Repeat, MAX_SPEED, MIN_SPEED},
 };
 
+/// Prefix of the track IDs in the track list. A track's ID ends with its
+/// position in the list, where the current track is 0 and the queued tracks
+/// follow it.
+const TRACK_ID_PREFIX: &str = "/xyz/jcheatum/RMuP/Track/";
+
+/// The ID of the track at the given position in the track list
+pub fn track_id(index: usize) -> TrackId {
+    TrackId::try_from(format!("{TRACK_ID_PREFIX}{index}")).expect("Known valid object path")
+}
+
+/// The position in the track list of the track with the given ID
+fn track_index(track_id: &TrackId) -> Option<usize> {
+    track_id
+        .as_str()
+        .strip_prefix(TRACK_ID_PREFIX)?
+        .parse()
+        .ok()
+}
+
+/// The IDs of the current track, if there is one, and the queued tracks
+pub fn track_ids(state: &MediaState) -> Vec<TrackId> {
+    let first = usize::from(state.current_track.is_none());
+    (first..=state.upcoming.len()).map(track_id).collect()
+}
+
+/// A duration as an MPRIS time, which counts microseconds
+pub fn time(duration: Duration) -> Time {
+    Time::from_micros(i64::try_from(duration.as_micros()).unwrap_or(i64::MAX))
+}
+
+/// The metadata of the track at the given position in the track list
+pub fn track_metadata(track: &Track, index: usize, cover: Option<&Path>) -> Metadata {
+    let mut builder = Metadata::builder()
+        .trackid(track_id(index))
+        .artist([&track.artist])
+        .album(&track.album)
+        .title(
+            track
+                .title
+                .clone()
+                .unwrap_or_else(|| track.file_path.clone()),
+        )
+        .length(time(track.length));
+    if let Some(number) = track.number {
+        builder = builder.track_number(number as i32);
+    }
+    if let Some(cover) = cover {
+        builder = builder.art_url(format!("file://{}", cover.display()));
+    }
+    builder.build()
+}
+
 pub struct MprisPlayer {
     command_queue: Arc<Mutex<VecDeque<Command>>>,
     media_state: Arc<Mutex<MediaState>>,
+    /// Shell command that focuses the player's terminal
+    raise_command: Option<String>,
+    /// MIME types of the configured file extensions
+    mime_types: Vec<String>,
 }
 
 impl MprisPlayer {
-    pub const fn new(
+    pub fn new(
         command_queue: Arc<Mutex<VecDeque<Command>>>,
         media_state: Arc<Mutex<MediaState>>,
+        raise_command: Option<String>,
+        extensions: &[String],
     ) -> Self {
+        let mut mime_types = Vec::new();
+        for extension in extensions {
+            for mime_type in mime_types_of(&extension.to_lowercase()) {
+                if !mime_types.contains(&mime_type) {
+                    mime_types.push(mime_type);
+                }
+            }
+        }
         Self {
             command_queue,
             media_state,
+            raise_command,
+            mime_types,
         }
     }
 }
 
+/// The MIME types a file extension is known by
+fn mime_types_of(extension: &str) -> Vec<String> {
+    let types: &[&str] = match extension {
+        "mp3" => &["audio/mpeg"],
+        "flac" => &["audio/flac", "audio/x-flac"],
+        "aiff" | "aif" => &["audio/aiff", "audio/x-aiff"],
+        "m4a" | "m4b" | "mp4" => &["audio/mp4"],
+        "ogg" | "oga" => &["audio/ogg"],
+        "opus" => &["audio/opus"],
+        "aac" => &["audio/aac"],
+        "wav" => &["audio/wav", "audio/vnd.wav"],
+        _ => return vec![format!("audio/x-{extension}")],
+    };
+    types.iter().map(ToString::to_string).collect()
+}
+
 impl RootInterface for MprisPlayer {
+    /// Run the raise command, if one is configured, without waiting for it
     async fn raise(&self) -> fdo::Result<()> {
+        if let Some(raise_command) = &self.raise_command {
+            let mut child = process::Command::new("sh")
+                .arg("-c")
+                .arg(raise_command)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+            // Reap the child once it exits
+            thread::spawn(move || child.wait());
+        }
         Ok(())
     }
 
@@ -63,11 +168,11 @@ impl RootInterface for MprisPlayer {
     }
 
     async fn can_raise(&self) -> fdo::Result<bool> {
-        Ok(false)
+        Ok(self.raise_command.is_some())
     }
 
     async fn has_track_list(&self) -> fdo::Result<bool> {
-        Ok(false)
+        Ok(true)
     }
 
     async fn identity(&self) -> fdo::Result<String> {
@@ -79,22 +184,11 @@ impl RootInterface for MprisPlayer {
     }
 
     async fn supported_uri_schemes(&self) -> fdo::Result<Vec<String>> {
-        Ok(vec![])
+        Ok(vec!["file".into(), "http".into(), "https".into()])
     }
 
     async fn supported_mime_types(&self) -> fdo::Result<Vec<String>> {
-        Ok(vec![
-            "audio/mpeg".into(),
-            "audio/flac".into(),
-            "audio/x-flac".into(),
-            "audio/aiff".into(),
-            "audio/x-aiff".into(),
-            "audio/ogg".into(),
-            "audio/opus".into(),
-            "audio/aac".into(),
-            "audio/wav".into(),
-            "audio/vnd.wav".into(),
-        ])
+        Ok(self.mime_types.clone())
     }
 }
 
@@ -146,7 +240,12 @@ impl PlayerInterface for MprisPlayer {
         Ok(())
     }
 
-    async fn open_uri(&self, _uri: String) -> fdo::Result<()> {
+    async fn open_uri(&self, uri: String) -> fdo::Result<()> {
+        let path = uri.strip_prefix("file://").unwrap_or(&uri);
+        self.command_queue
+            .lock()
+            .await
+            .push_back(Command::PlayTrack(path.into()));
         Ok(())
     }
 
@@ -163,25 +262,34 @@ impl PlayerInterface for MprisPlayer {
     }
 
     async fn loop_status(&self) -> fdo::Result<LoopStatus> {
-        Ok(LoopStatus::None)
+        Ok(self.media_state.lock().await.repeat.into())
     }
 
     async fn set_loop_status(&self, loop_status: LoopStatus) -> Result<()> {
-        let mut guard = self.media_state.lock().await;
-        match loop_status {
-            LoopStatus::None => guard.repeat = Repeat::Off,
-            LoopStatus::Track => guard.repeat = Repeat::One,
-            LoopStatus::Playlist => guard.repeat = Repeat::On,
-        }
-        drop(guard);
+        let repeat = match loop_status {
+            LoopStatus::None => Repeat::Off,
+            LoopStatus::Track => Repeat::One,
+            LoopStatus::Playlist => Repeat::On,
+        };
+        self.command_queue
+            .lock()
+            .await
+            .push_back(Command::SetRepeat(repeat));
         Ok(())
     }
 
     async fn rate(&self) -> fdo::Result<PlaybackRate> {
-        Ok(PlaybackRate::default())
+        Ok(f64::from(self.media_state.lock().await.speed))
     }
 
-    async fn set_rate(&self, _rate: PlaybackRate) -> Result<()> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    async fn set_rate(&self, rate: PlaybackRate) -> Result<()> {
+        let percent =
+            (rate.clamp(f64::from(MIN_SPEED), f64::from(MAX_SPEED)) * 100.0).round() as u16;
+        self.command_queue
+            .lock()
+            .await
+            .push_back(Command::SetSpeed(percent));
         Ok(())
     }
 
@@ -196,37 +304,28 @@ impl PlayerInterface for MprisPlayer {
     }
 
     async fn metadata(&self) -> fdo::Result<Metadata> {
-        let metadata = self
-            .media_state
-            .lock()
-            .await
+        let guard = self.media_state.lock().await;
+        let metadata = guard
             .current_track
             .as_ref()
             .map_or_else(Metadata::default, |track| {
-                let mut builder = Metadata::builder()
-                    .artist([&track.artist])
-                    .album(&track.album)
-                    .title(
-                        track
-                            .title
-                            .clone()
-                            .unwrap_or_else(|| track.file_path.clone()),
-                    )
-                    .length(Time::from_secs(track.length.as_secs() as i64));
-                if let Some(number) = track.number {
-                    builder = builder.track_number(number as i32);
-                }
-                builder.build()
+                track_metadata(track, 0, guard.current_cover.as_deref())
             });
 
         Ok(metadata)
     }
 
     async fn volume(&self) -> fdo::Result<Volume> {
-        Ok(Volume::default())
+        Ok(f64::from(self.media_state.lock().await.effective_volume()))
     }
 
-    async fn set_volume(&self, _volume: Volume) -> Result<()> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    async fn set_volume(&self, volume: Volume) -> Result<()> {
+        let percent = (volume.clamp(0.0, 1.0) * 100.0).round() as u8;
+        self.command_queue
+            .lock()
+            .await
+            .push_back(Command::SetVolume(percent));
         Ok(())
     }
 
@@ -236,18 +335,16 @@ impl PlayerInterface for MprisPlayer {
             .lock()
             .await
             .current_track_progress
-            .map_or(Time::ZERO, |position| {
-                Time::from_secs(position.as_secs() as i64)
-            });
+            .map_or(Time::ZERO, time);
         Ok(pos)
     }
 
     async fn minimum_rate(&self) -> fdo::Result<PlaybackRate> {
-        Ok(PlaybackRate::default())
+        Ok(f64::from(MIN_SPEED))
     }
 
     async fn maximum_rate(&self) -> fdo::Result<PlaybackRate> {
-        Ok(PlaybackRate::default())
+        Ok(f64::from(MAX_SPEED))
     }
 
     async fn can_go_next(&self) -> fdo::Result<bool> {
@@ -274,3 +371,67 @@ impl PlayerInterface for MprisPlayer {
         Ok(true)
     }
 }
+
+impl TrackListInterface for MprisPlayer {
+    async fn get_tracks_metadata(&self, track_ids: Vec<TrackId>) -> fdo::Result<Vec<Metadata>> {
+        let guard = self.media_state.lock().await;
+        Ok(track_ids
+            .iter()
+            .filter_map(|track_id| match track_index(track_id)? {
+                0 => guard
+                    .current_track
+                    .as_ref()
+                    .map(|track| track_metadata(track, 0, guard.current_cover.as_deref())),
+                index => guard
+                    .upcoming
+                    .get(index - 1)
+                    .map(|track| track_metadata(track, index, None)),
+            })
+            .collect())
+    }
+
+    /// Tracks can only be added after the current track, as the tracks before
+    /// it aren't in the list
+    async fn add_track(
+        &self,
+        uri: Uri,
+        after_track: TrackId,
+        set_as_current: bool,
+    ) -> fdo::Result<()> {
+        let path = uri.strip_prefix("file://").unwrap_or(&uri);
+        let index = track_index(&after_track).unwrap_or(0);
+        self.command_queue
+            .lock()
+            .await
+            .push_back(Command::QueueTrack(path.into(), index, set_as_current));
+        Ok(())
+    }
+
+    async fn remove_track(&self, track_id: TrackId) -> fdo::Result<()> {
+        if let Some(index @ 1..) = track_index(&track_id) {
+            self.command_queue
+                .lock()
+                .await
+                .push_back(Command::RemoveQueued(index - 1));
+        }
+        Ok(())
+    }
+
+    async fn go_to(&self, track_id: TrackId) -> fdo::Result<()> {
+        if let Some(index @ 1..) = track_index(&track_id) {
+            self.command_queue
+                .lock()
+                .await
+                .push_back(Command::PlayQueued(index - 1));
+        }
+        Ok(())
+    }
+
+    async fn tracks(&self) -> fdo::Result<Vec<TrackId>> {
+        Ok(track_ids(&*self.media_state.lock().await))
+    }
+
+    async fn can_edit_tracks(&self) -> fdo::Result<bool> {
+        Ok(true)
+    }
+}
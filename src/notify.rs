@@ -0,0 +1,71 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Best-effort desktop notifications. Failures are ignored, and platforms
+//! without a supported notification service get none.
+
+use std::path::Path;
+
+use crate::library::track::Track;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+
+    use anyhow::Result;
+    use mpris_server::zbus::{zvariant::Value, Connection};
+
+    /// The id of the last notification sent, so that each new one replaces it
+    /// rather than piling up
+    static LAST_ID: AtomicU32 = AtomicU32::new(0);
+
+    /// Send a notification through the freedesktop notification service
+    pub async fn send(summary: String, body: String, icon: String) -> Result<()> {
+        let connection = Connection::session().await?;
+        let hints: HashMap<&str, Value> = HashMap::new();
+        let reply = connection
+            .call_method(
+                Some("org.freedesktop.Notifications"),
+                "/org/freedesktop/Notifications",
+                Some("org.freedesktop.Notifications"),
+                "Notify",
+                &(
+                    "RMuP",
+                    LAST_ID.load(Ordering::Relaxed),
+                    icon.as_str(),
+                    summary.as_str(),
+                    body.as_str(),
+                    Vec::<&str>::new(),
+                    hints,
+                    -1_i32,
+                ),
+            )
+            .await?;
+        let id: u32 = reply.body().deserialize()?;
+        LAST_ID.store(id, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Show a notification for a track that has started playing, without waiting
+/// for it to be delivered. `cover` is shown as the notification's icon.
+pub fn now_playing(track: &Track, cover: Option<&Path>) {
+    #[cfg(target_os = "linux")]
+    {
+        let summary = track.to_string();
+        let body = format!("{} - {}", track.artist, track.album);
+        let icon = cover.map_or_else(String::new, |cover| cover.display().to_string());
+        async_std::task::spawn(async move {
+            let _ = linux::send(summary, body, icon).await;
+        });
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (track, cover);
+    }
+}
@@ -0,0 +1,113 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! The audio output device. Unlike rodio's own output stream, errors on the
+//! device, such as it being unplugged, are kept for the player to act on
+//! rather than printed over the UI.
+
+use std::sync::{
+    mpsc::{self, Receiver, Sender},
+    Arc,
+};
+
+use anyhow::{anyhow, Result};
+use rodio::{
+    cpal::{
+        self,
+        traits::{DeviceTrait, HostTrait, StreamTrait},
+        FromSample, SampleFormat, SizedSample, StreamError, SupportedStreamConfig,
+    },
+    dynamic_mixer::{self, DynamicMixer, DynamicMixerController},
+    Sink,
+};
+
+pub struct Output {
+    /// Where sinks' audio is mixed together to be played
+    mixer: Arc<DynamicMixerController<f32>>,
+
+    /// Errors on the device since they were last checked
+    errors: Receiver<StreamError>,
+
+    /// Playback ends when the stream is dropped
+    _stream: cpal::Stream,
+}
+
+impl Output {
+    /// Open the default output device, or failing that the first other one
+    /// that works
+    pub fn open() -> Result<Self> {
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .ok_or_else(|| anyhow!("No audio output device found"))?;
+        Self::open_device(&device).or_else(|error| {
+            host.output_devices()
+                .ok()
+                .and_then(|mut devices| devices.find_map(|d| Self::open_device(&d).ok()))
+                .ok_or(error)
+        })
+    }
+
+    fn open_device(device: &cpal::Device) -> Result<Self> {
+        let config = device.default_output_config()?;
+        let (mixer, samples) = dynamic_mixer::mixer(config.channels(), config.sample_rate().0);
+        let (error_sender, errors) = mpsc::channel();
+        let stream = match config.sample_format() {
+            SampleFormat::I8 => build_stream::<i8>(device, &config, samples, error_sender),
+            SampleFormat::I16 => build_stream::<i16>(device, &config, samples, error_sender),
+            SampleFormat::I32 => build_stream::<i32>(device, &config, samples, error_sender),
+            SampleFormat::I64 => build_stream::<i64>(device, &config, samples, error_sender),
+            SampleFormat::U8 => build_stream::<u8>(device, &config, samples, error_sender),
+            SampleFormat::U16 => build_stream::<u16>(device, &config, samples, error_sender),
+            SampleFormat::U32 => build_stream::<u32>(device, &config, samples, error_sender),
+            SampleFormat::U64 => build_stream::<u64>(device, &config, samples, error_sender),
+            SampleFormat::F32 => build_stream::<f32>(device, &config, samples, error_sender),
+            SampleFormat::F64 => build_stream::<f64>(device, &config, samples, error_sender),
+            format => return Err(anyhow!("Unsupported sample format: {format}")),
+        }?;
+        stream.play()?;
+        Ok(Self {
+            mixer,
+            errors,
+            _stream: stream,
+        })
+    }
+
+    /// A new sink playing through the device
+    pub fn sink(&self) -> Sink {
+        let (sink, queue) = Sink::new_idle();
+        self.mixer.add(queue);
+        sink
+    }
+
+    /// The latest error on the device since this was last called, if any
+    pub fn take_error(&self) -> Option<StreamError> {
+        self.errors.try_iter().last()
+    }
+}
+
+/// Build a stream playing the mixer's samples in the device's sample format,
+/// with silence whenever nothing is playing
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &SupportedStreamConfig,
+    mut samples: DynamicMixer<f32>,
+    errors: Sender<StreamError>,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    Ok(device.build_output_stream::<T, _, _>(
+        &config.config(),
+        move |data, _| {
+            for sample in data.iter_mut() {
+                *sample = samples.next().map_or(T::EQUILIBRIUM, T::from_sample);
+            }
+        },
+        move |error| {
+            let _ = errors.send(error);
+        },
+        None,
+    )?)
+}
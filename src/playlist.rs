@@ -5,26 +5,48 @@
 use std::{
     collections::HashMap,
     fs::File,
-    io::{BufRead, BufReader, Lines, Write},
-    iter::Enumerate,
-    mem,
-    path::Path,
+    io::{self, BufRead, BufReader, Write},
+    iter, mem,
+    path::{Path, PathBuf},
     sync::LazyLock,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use anyhow::{anyhow, Result};
+use ratatui::widgets::ListItem;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    library::{album::Album, artist::Artist, track::Track},
+    library::{
+        album::Album,
+        artist::Artist,
+        get_track_data, parse_gain,
+        track::{Track, MAX_RATING},
+    },
+    smart_playlist::Query,
+    stream,
     traits::{Load, Save},
+    util::{half_panel_width, with_track_summary, write_atomically, write_json},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Playlist {
     pub name: String,
     pub tracks: Vec<Track>,
+
+    /// The query a smart playlist's tracks are chosen by
+    #[serde(skip)]
+    pub query: Option<Query>,
+
+    /// Entries skipped when the playlist was loaded because their files
+    /// couldn't be read, with the reasons why
+    #[serde(skip)]
+    skipped: Vec<(PathBuf, String)>,
+
+    /// The file the playlist was loaded from, which it is saved back to
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
 }
 
 impl Playlist {
@@ -32,6 +54,9 @@ impl Playlist {
         Self {
             name: name.to_owned(),
             tracks: Vec::new(),
+            query: None,
+            skipped: Vec::new(),
+            source: None,
         }
     }
 
@@ -39,6 +64,34 @@ impl Playlist {
         self.tracks.append(tracks);
     }
 
+    /// Take the entries skipped when the playlist was loaded, with the
+    /// reasons why
+    pub fn take_skipped(&mut self) -> Vec<(PathBuf, String)> {
+        mem::take(&mut self.skipped)
+    }
+
+    /// The combined length of all the tracks in the playlist
+    pub fn total_duration(&self) -> Duration {
+        self.tracks.iter().map(|t| t.length).sum()
+    }
+
+    /// Build the list item for this playlist, marking it with a `*` if it is
+    /// the selected playlist and a `~` if it is a smart playlist
+    pub fn list_item<'a>(&self, selected: bool) -> ListItem<'a> {
+        let name = format!(
+            "{}{}{}",
+            if selected { "*" } else { "" },
+            if self.query.is_some() { "~" } else { "" },
+            self.name
+        );
+        ListItem::new(with_track_summary(
+            &name,
+            self.tracks.len(),
+            self.total_duration(),
+            half_panel_width(),
+        ))
+    }
+
     /// Get the list of artists and albums of all the tracks in the playlist
     pub fn get_artists_albums(&self) -> (Vec<Artist>, Vec<Album>) {
         let mut artists: HashMap<String, Artist> = HashMap::new();
@@ -57,6 +110,8 @@ impl Playlist {
             all_albums.tracks.push(track.clone());
 
             if let Some(album) = albums.get_mut(&track.album) {
+                // The album's year is taken from whichever track has one
+                album.year = album.year.or(track.year);
                 album.tracks.push(track.clone());
             } else {
                 let album = Album {
@@ -73,7 +128,7 @@ impl Playlist {
         for album in albums.values_mut() {
             album.tracks.sort();
             for track in &album.tracks {
-                if let Some(artist) = artists.get_mut(&track.artist) {
+                if let Some(artist) = artists.get_mut(track.grouping_artist()) {
                     let all_index = artist
                         .get_album_index("All Albums")
                         .expect("'All Albums' pseudo-album should always exist");
@@ -88,7 +143,7 @@ impl Playlist {
                         tracks: vec![track.clone()],
                     };
                     let artist = Artist {
-                        name: track.artist.clone(),
+                        name: track.grouping_artist().to_owned(),
                         albums: vec![artist_all_albums, album.clone()],
                     };
                     artists.insert(artist.name.clone(), artist);
@@ -120,17 +175,248 @@ impl Playlist {
     }
 }
 
+impl Playlist {
+    /// Save the playlist in the format given by the file extension: `.pls`,
+    /// `.json`, or m3u8 otherwise.
+    pub fn save_as<P: AsRef<Path>>(&self, file_path: P) -> Result<()> {
+        match file_path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("pls") => self.save_pls(file_path),
+            Some("json") => self.save_json(file_path),
+            _ => self.save(file_path),
+        }
+    }
+
+    /// Save the playlist back to the file it was loaded from, keeping that
+    /// file's format, or as `<name>.m3u8` in the playlist directory if it
+    /// wasn't loaded from one. A plain m3u file is written as extended m3u.
+    pub fn save_to_dir<P: AsRef<Path>>(&self, playlist_dir: P) -> Result<()> {
+        match &self.source {
+            Some(source) => self.save_as(source),
+            None => self.save(playlist_dir.as_ref().join(format!("{}.m3u8", self.name))),
+        }
+    }
+
+    /// Save playlist to a PLS file
+    fn save_pls<P: AsRef<Path>>(&self, file_path: P) -> Result<()> {
+        write_atomically(file_path, |file| {
+            writeln!(file, "[playlist]")?;
+            for (i, track) in self.tracks.iter().enumerate() {
+                let n = i + 1;
+                writeln!(file, "File{n}={}", track.file_path)?;
+                writeln!(file, "Title{n}={track}")?;
+                writeln!(file, "Length{n}={}", track.length.as_secs())?;
+            }
+            writeln!(file, "NumberOfEntries={}", self.tracks.len())?;
+            writeln!(file, "Version=2")?;
+            Ok(())
+        })
+    }
+
+    /// Save playlist to a JSON file
+    fn save_json<P: AsRef<Path>>(&self, file_path: P) -> Result<()> {
+        let mut json = String::new();
+        write_json(&serde_yml::to_value(self)?, &mut json)?;
+        write_atomically(file_path, |file| Ok(writeln!(file, "{json}")?))
+    }
+
+    /// Load playlist from a JSON file. JSON is a subset of YAML, so the YAML
+    /// parser reads it directly.
+    fn load_json<P: AsRef<Path>>(file_path: P) -> Result<Self> {
+        let file = File::open(file_path)?;
+        let mut playlist: Self = serde_yml::from_reader(file)?;
+        for track in &mut playlist.tracks {
+            track.missing = !track.exists();
+        }
+        Ok(playlist)
+    }
+
+    /// Load playlist from a PLS file. Track lengths are read from the files
+    /// themselves rather than the `LengthN` entries. Entries whose files can't
+    /// be read are skipped, and can be had from `take_skipped`.
+    fn load_pls<P: AsRef<Path>>(file_path: P) -> Result<Self> {
+        let file = File::open(&file_path)?;
+        let mut files = HashMap::new();
+        let mut titles = HashMap::new();
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim().to_lowercase();
+            let entries = if key.starts_with("file") {
+                &mut files
+            } else if key.starts_with("title") {
+                &mut titles
+            } else {
+                continue;
+            };
+            if let Ok(n) = key.trim_start_matches(char::is_alphabetic).parse::<usize>() {
+                entries.insert(n, value.trim().to_owned());
+            }
+        }
+
+        let mut numbers: Vec<usize> = files.keys().copied().collect();
+        numbers.sort_unstable();
+        let mut tracks = Vec::new();
+        let mut skipped = Vec::new();
+        for n in numbers {
+            let path = resolve_entry_path(&files[&n], &file_path);
+            match get_track_data(&path) {
+                Ok((mut track, _, _)) => {
+                    if track.title.is_none() {
+                        track.title = titles.get(&n).cloned();
+                    }
+                    tracks.push(track);
+                }
+                Err(e) => skipped.push((path, e.to_string())),
+            }
+        }
+
+        Ok(Self {
+            name: playlist_name_from_path(&file_path),
+            tracks,
+            query: None,
+            skipped,
+            source: None,
+        })
+    }
+
+    /// Load playlist from a plain m3u file that is just a list of paths.
+    /// Comment lines, including any `#EXTINF`, are ignored and the track data
+    /// is read from the files themselves. Entries whose files can't be read
+    /// are skipped, and can be had from `take_skipped`.
+    fn load_plain_m3u<P: AsRef<Path>>(file_path: P) -> Result<Self> {
+        let file = File::open(&file_path)?;
+        let mut tracks = Vec::new();
+        let mut skipped = Vec::new();
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let path = resolve_entry_path(line, &file_path);
+            match get_track_data(&path) {
+                Ok((track, _, _)) => tracks.push(track),
+                Err(e) => skipped.push((path, e.to_string())),
+            }
+        }
+
+        Ok(Self {
+            name: playlist_name_from_path(&file_path),
+            tracks,
+            query: None,
+            skipped,
+            source: None,
+        })
+    }
+
+    /// Load playlist from an m3u8 file
+    fn load_m3u8<P: AsRef<Path>>(file_path: P) -> Result<Self> {
+        Self::read_m3u8(BufReader::new(File::open(&file_path)?), file_path)
+    }
+
+    /// Read an m3u8 playlist from a reader, naming `file_path` in errors
+    pub fn read_m3u8<P: AsRef<Path>>(reader: impl BufRead, file_path: P) -> Result<Self> {
+        let mut lines = reader.lines().enumerate();
+
+        if let Some((_, Ok(line))) = lines.next() {
+            check_header(&line, &file_path)?;
+        } else {
+            return Err(anyhow!(
+                "{}: Invalid m3u8 file",
+                file_path.as_ref().display()
+            ));
+        }
+
+        let (mut name, tracks) = parse_lines(&mut lines, file_path)?;
+
+        if name.is_empty() {
+            "Untitled".clone_into(&mut name);
+        }
+
+        Ok(Self {
+            name,
+            tracks,
+            query: None,
+            skipped: Vec::new(),
+            source: None,
+        })
+    }
+}
+
+/// Resolve a path from a playlist entry. Relative paths are relative to the
+/// directory containing the playlist.
+fn resolve_entry_path<P: AsRef<Path>>(entry: &str, playlist_path: P) -> PathBuf {
+    let entry = Path::new(entry);
+    if entry.is_relative() {
+        if let Some(dir) = playlist_path.as_ref().parent() {
+            return dir.join(entry);
+        }
+    }
+    entry.to_path_buf()
+}
+
+/// Name a playlist whose format has no name field after its file
+fn playlist_name_from_path<P: AsRef<Path>>(file_path: P) -> String {
+    file_path.as_ref().file_stem().map_or_else(
+        || "Untitled".to_owned(),
+        |stem| stem.to_string_lossy().into_owned(),
+    )
+}
+
 impl Save for Playlist {
     /// Save playlist to an m3u8 file
     fn save<P: AsRef<Path>>(&self, file_path: P) -> Result<()> {
-        let mut file = File::create(file_path)?;
+        write_atomically(file_path, |file| self.write_m3u8(file))
+    }
+}
+
+impl Playlist {
+    /// Write the playlist out in m3u8 format
+    pub fn write_m3u8(&self, file: &mut impl Write) -> Result<()> {
         writeln!(file, "#EXTM3U")?;
         writeln!(file, "#PLAYLIST:{}", self.name)?;
         for track in &self.tracks {
             writeln!(file, "#EXTART:{}", &track.artist)?;
+            if let Some(album_artist) = &track.album_artist {
+                writeln!(file, "#EXTALBART:{album_artist}")?;
+            }
             writeln!(file, "#EXTALB:{}", &track.album)?;
+            if let Some(disc) = track.disc {
+                writeln!(file, "#EXTDISC:{disc}")?;
+            }
+            if let Some(genre) = &track.genre {
+                writeln!(file, "#EXTGENRE:{genre}")?;
+            }
+            if let Some(gain) = track.gain {
+                writeln!(file, "#EXTGAIN:{gain}")?;
+            }
+            if track.play_count > 0 {
+                writeln!(file, "#EXTPLAYCOUNT:{}", track.play_count)?;
+            }
+            if let Some(last_played) = track
+                .last_played
+                .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            {
+                writeln!(file, "#EXTLASTPLAYED:{}", last_played.as_secs())?;
+            }
+            if let Some(rating) = track.rating {
+                writeln!(file, "#EXTRATING:{rating}")?;
+            }
+            if let Some(added) = track
+                .added
+                .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            {
+                writeln!(file, "#EXTADDED:{}", added.as_secs())?;
+            }
+            for tag in &track.extra_tags {
+                writeln!(file, "{tag}")?;
+            }
 
-            let mut extinf = format!("#EXTINF:{}", track.length.as_secs());
+            let mut extinf = format!("#EXTINF:{}", format_extinf_length(track.length));
             if let Some(year) = track.year {
                 extinf.push_str(format!(" year={year}").as_str());
             }
@@ -153,10 +439,27 @@ static COMMENT_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^#.*$").expect("Known valid regex"));
 static PLAYLIST_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^#PLAYLIST:.*$").expect("Known valid regex"));
-static INF_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^#EXTINF:[0-9]*(\s.+)*,.*$").expect("Known valid regex"));
+static INF_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^#EXTINF:-?[0-9]*(\.[0-9]*)?(\s.+)*,.*$").expect("Known valid regex")
+});
 static ALB_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^#EXTALB:.*$").expect("Known valid regex"));
+static ALBART_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^#EXTALBART:.*$").expect("Known valid regex"));
+static GAIN_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^#EXTGAIN:.*$").expect("Known valid regex"));
+static PLAYCOUNT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^#EXTPLAYCOUNT:.*$").expect("Known valid regex"));
+static LASTPLAYED_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^#EXTLASTPLAYED:.*$").expect("Known valid regex"));
+static RATING_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^#EXTRATING:.*$").expect("Known valid regex"));
+static ADDED_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^#EXTADDED:.*$").expect("Known valid regex"));
+static DISC_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^#EXTDISC:.*$").expect("Known valid regex"));
+static GENRE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^#EXTGENRE:.*$").expect("Known valid regex"));
 static ART_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^#EXTART:.*$").expect("Known valid regex"));
 
@@ -171,17 +474,26 @@ fn check_header<P: AsRef<Path>>(header: &str, file_path: P) -> Result<()> {
 }
 
 fn parse_lines<P: AsRef<Path>>(
-    lines: &mut Enumerate<Lines<BufReader<File>>>,
+    lines: &mut impl Iterator<Item = (usize, io::Result<String>)>,
     file_path: P,
 ) -> Result<(String, Vec<Track>)> {
     let mut name = String::new();
     let mut tracks = Vec::new();
     let mut track_artist = None;
+    let mut track_album_artist = None;
     let mut track_album = None;
     let mut track_duration = None;
     let mut track_name = None;
     let mut track_year = None;
     let mut track_number = None;
+    let mut track_disc = None;
+    let mut track_genre = None;
+    let mut track_gain = None;
+    let mut track_play_count = 0;
+    let mut track_last_played = None;
+    let mut track_rating = None;
+    let mut track_added = None;
+    let mut track_extra_tags = Vec::new();
 
     for (linenum, line) in lines {
         let line = line?;
@@ -207,6 +519,18 @@ fn parse_lines<P: AsRef<Path>>(
                     .1
                     .to_owned(),
             );
+        } else if ALBART_RE.is_match(&line) {
+            track_album_artist = Some(
+                line.split_once(':')
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Error parsing playlist '{}' line {linenum}: `#EXTALBART:` cannot be empty",
+                            file_path.as_ref().display()
+                        )
+                    })?
+                    .1
+                    .to_owned(),
+            );
         } else if ALB_RE.is_match(&line) {
             track_album = Some(
                 line.split_once(':')
@@ -219,6 +543,37 @@ fn parse_lines<P: AsRef<Path>>(
                     .1
                     .to_owned(),
             );
+        } else if DISC_RE.is_match(&line) {
+            track_disc = line.split_once(':').and_then(|(_, disc)| disc.parse().ok());
+        } else if GENRE_RE.is_match(&line) {
+            track_genre = line
+                .split_once(':')
+                .map(|(_, genre)| genre.to_owned())
+                .filter(|genre| !genre.is_empty());
+        } else if GAIN_RE.is_match(&line) {
+            // A malformed gain is ignored rather than failing the whole playlist
+            track_gain = line.split_once(':').and_then(|(_, gain)| parse_gain(gain));
+        } else if PLAYCOUNT_RE.is_match(&line) {
+            // Malformed stats are ignored rather than failing the whole playlist
+            track_play_count = line
+                .split_once(':')
+                .and_then(|(_, count)| count.parse().ok())
+                .unwrap_or(0);
+        } else if LASTPLAYED_RE.is_match(&line) {
+            track_last_played = line
+                .split_once(':')
+                .and_then(|(_, secs)| secs.parse().ok())
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        } else if RATING_RE.is_match(&line) {
+            track_rating = line
+                .split_once(':')
+                .and_then(|(_, rating)| rating.parse().ok())
+                .filter(|rating| (1..=MAX_RATING).contains(rating));
+        } else if ADDED_RE.is_match(&line) {
+            track_added = line
+                .split_once(':')
+                .and_then(|(_, secs)| secs.parse().ok())
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
         } else if INF_RE.is_match(&line) {
             let line = line
                 .split_once(':')
@@ -233,8 +588,8 @@ fn parse_lines<P: AsRef<Path>>(
             let track_info = parse_extinf(line, linenum)?;
 
             if let Some(dur) = track_info.get("duration") {
-                if let Ok(dur) = dur.parse() {
-                    track_duration = Some(Duration::from_secs(dur));
+                if let Some(dur) = parse_extinf_length(dur) {
+                    track_duration = Some(dur);
                 }
             }
 
@@ -252,29 +607,46 @@ fn parse_lines<P: AsRef<Path>>(
 
             track_name = track_info.get("title").cloned();
         } else if COMMENT_RE.is_match(&line) {
-            // do nothing
+            // Unknown directives are kept with the track they precede so that
+            // they survive being saved again
+            track_extra_tags.push(line);
         } else {
             let track_path = line;
+            let missing = !stream::is_url(&track_path) && !Path::new(&track_path).is_file();
             tracks.push(Track {
                 title: track_name.clone(),
-                artist: track_artist
-                    .clone()
-                    .map_or_else(|| "Unknown".to_owned(), |artist| artist),
-                album: track_album
-                    .clone()
-                    .map_or_else(|| "Unknown".to_owned(), |album| album),
+                artist: track_artist.clone().unwrap_or_else(|| "Unknown".to_owned()),
+                album_artist: track_album_artist.clone(),
+                album: track_album.clone().unwrap_or_else(|| "Unknown".to_owned()),
                 year: track_year,
                 number: track_number,
+                disc: track_disc,
                 length: track_duration.map_or(Duration::ZERO, |length| length),
                 file_path: track_path,
+                genre: track_genre.clone(),
+                gain: track_gain,
+                play_count: track_play_count,
+                last_played: track_last_played,
+                rating: track_rating,
+                added: track_added,
+                missing,
+                extra_tags: mem::take(&mut track_extra_tags),
             });
 
             track_artist = None;
+            track_album_artist = None;
             track_album = None;
             track_duration = None;
             track_name = None;
             track_year = None;
             track_number = None;
+            track_disc = None;
+            track_genre = None;
+            track_gain = None;
+            track_play_count = 0;
+            track_last_played = None;
+            track_rating = None;
+            track_added = None;
         }
     }
 
@@ -282,53 +654,314 @@ fn parse_lines<P: AsRef<Path>>(
 }
 
 impl Load for Playlist {
-    /// Load playlist from an m3u8 file
+    /// Load playlist from a file, choosing the format by its extension. m3u
+    /// files without an `#EXTM3U` header are read as plain lists of paths.
     fn load<P: AsRef<Path>>(file_path: P) -> Result<Self> {
-        let file = File::open(&file_path)?;
-        let mut lines = BufReader::new(file).lines().enumerate();
+        let mut playlist = match file_path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::load_json(&file_path),
+            Some("pls") => Self::load_pls(&file_path),
+            _ => {
+                let mut first_line = String::new();
+                BufReader::new(File::open(&file_path)?).read_line(&mut first_line)?;
+                if first_line.trim_end() == "#EXTM3U" {
+                    Self::load_m3u8(&file_path)
+                } else {
+                    Self::load_plain_m3u(&file_path)
+                }
+            }
+        }?;
+        playlist.source = Some(file_path.as_ref().to_path_buf());
+        Ok(playlist)
+    }
+}
 
-        if let Some((_, Ok(line))) = lines.next() {
-            check_header(&line, &file_path)?;
-        } else {
-            return Err(anyhow!(
-                "{}: Invalid m3u8 file",
-                file_path.as_ref().display()
-            ));
-        }
+/// Format a track length for `#EXTINF` in seconds, with milliseconds after a
+/// decimal point unless the length is a whole number of seconds
+fn format_extinf_length(length: Duration) -> String {
+    match length.subsec_millis() {
+        0 => length.as_secs().to_string(),
+        millis => format!("{}.{millis:03}", length.as_secs()),
+    }
+}
 
-        let (mut name, tracks) = parse_lines(&mut lines, file_path)?;
+/// Parse an `#EXTINF` length in seconds, keeping up to millisecond precision
+/// from a fractional part. Older playlists only have whole seconds.
+fn parse_extinf_length(length: &str) -> Option<Duration> {
+    let (secs, fraction) = length.split_once('.').unwrap_or((length, ""));
+    let millis: String = fraction.chars().chain(iter::repeat('0')).take(3).collect();
+    Some(Duration::from_secs(secs.parse().ok()?) + Duration::from_millis(millis.parse().ok()?))
+}
 
-        if name.is_empty() {
-            "Untitled".clone_into(&mut name);
+/// Split an `#EXTINF` line into its length, `key=value` properties, and
+/// title. The title is everything after the first comma that isn't inside a
+/// quoted property value, so it may itself hold commas and `=`. Properties
+/// are separated by any amount of whitespace; ones that aren't key-value
+/// pairs are skipped.
+fn parse_extinf(extinf: &str, linenum: usize) -> Result<HashMap<String, String>> {
+    let mut track_info = HashMap::new();
+
+    let mut quoted = false;
+    let title_comma = extinf
+        .char_indices()
+        .find(|&(_, c)| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            c == ',' && !quoted
+        })
+        .or_else(|| extinf.char_indices().find(|&(_, c)| c == ','))
+        .ok_or_else(|| {
+            anyhow!(
+                "Error parsing #EXTINF on line {linenum}: missing comma: '{}'",
+                extinf
+            )
+        })?
+        .0;
+    let (properties, title) = (&extinf[..title_comma], &extinf[title_comma + 1..]);
+    track_info.insert("title".to_string(), title.to_string());
+
+    let (duration, properties) = properties
+        .split_once(char::is_whitespace)
+        .unwrap_or((properties, ""));
+    track_info.insert("duration".to_string(), duration.to_string());
+    for p in split_properties(properties) {
+        if let Some((key, value)) = p.split_once('=') {
+            track_info.insert(key.to_string(), value.trim_matches('"').to_string());
         }
+    }
+
+    Ok(track_info)
+}
 
-        Ok(Self { name, tracks })
+/// Split `#EXTINF` properties on whitespace outside of double quotes
+fn split_properties(properties: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (i, c) in properties.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c.is_whitespace() && !quoted {
+            if let Some(s) = start.take() {
+                parts.push(&properties[s..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
     }
+    if let Some(s) = start {
+        parts.push(&properties[s..]);
+    }
+    parts
 }
 
-fn parse_extinf(extinf: &str, linenum: usize) -> Result<HashMap<String, String>> {
-    let mut track_info = HashMap::new();
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::util::{write_wav, TestDir};
+
+    #[test]
+    fn pls_entries_read_their_files_and_skip_unreadable_ones() {
+        let dir = TestDir::new("pls_entries");
+        write_wav(&dir.path().join("a.wav"));
+        let file_path = dir.path().join("mix.pls");
+        fs::write(
+            &file_path,
+            "[playlist]\nFile1=a.wav\nTitle1=First\nFile2=missing.wav\nNumberOfEntries=2\n",
+        )
+        .unwrap();
+
+        let mut playlist = Playlist::load(&file_path).unwrap();
+        assert_eq!(playlist.name, "mix");
+        assert_eq!(playlist.tracks.len(), 1);
+        assert_eq!(playlist.tracks[0].title.as_deref(), Some("First"));
+        assert_eq!(playlist.tracks[0].length, Duration::from_millis(100));
+        let skipped = playlist.take_skipped();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, dir.path().join("missing.wav"));
+        assert!(playlist.take_skipped().is_empty());
+    }
+
+    #[test]
+    fn playlists_are_saved_back_to_the_file_they_came_from() {
+        let dir = TestDir::new("playlists_saved_back");
+        write_wav(&dir.path().join("a.wav"));
+        write_wav(&dir.path().join("b.wav"));
+        let pls_path = dir.path().join("mix.pls");
+        fs::write(&pls_path, "[playlist]\nFile1=a.wav\nNumberOfEntries=1\n").unwrap();
+        let m3u_path = dir.path().join("plain.m3u");
+        fs::write(&m3u_path, "a.wav\n").unwrap();
+
+        for file_path in [pls_path, m3u_path] {
+            let mut playlist = Playlist::load(&file_path).unwrap();
+            let (track, _, _) = get_track_data(dir.path().join("b.wav")).unwrap();
+            playlist.add(&mut vec![track]);
+            playlist.save_to_dir(dir.path()).unwrap();
+
+            let reloaded = Playlist::load(&file_path).unwrap();
+            assert_eq!(reloaded.name, playlist.name);
+            assert_eq!(reloaded.tracks.len(), 2);
+            assert!(!dir.path().join(format!("{}.m3u8", playlist.name)).exists());
+        }
+    }
 
-    let (properties, title) = extinf.split_once(',').ok_or_else(|| {
-        anyhow!(
-            "Error parsing #EXTINF on line {linenum}: missing comma: '{}'",
-            extinf
+    #[test]
+    fn plain_m3u_ignores_comments_and_skips_unreadable_entries() {
+        let dir = TestDir::new("plain_m3u_entries");
+        write_wav(&dir.path().join("a.wav"));
+        let file_path = dir.path().join("list.m3u");
+        fs::write(
+            &file_path,
+            "# A list\n#EXTINF:1,Ignored\na.wav\n\nmissing.wav\n",
         )
-    })?;
-    track_info.insert("title".to_string(), title.to_string());
-    let mut properties = properties.split(|c: char| c.is_whitespace());
-    if let Some(duration) = properties.next() {
-        track_info.insert("duration".to_string(), duration.to_string());
+        .unwrap();
+
+        let mut playlist = Playlist::load(&file_path).unwrap();
+        assert_eq!(playlist.tracks.len(), 1);
+        assert_eq!(playlist.tracks[0].title, None);
+        assert_eq!(playlist.take_skipped()[0].0, dir.path().join("missing.wav"));
     }
-    for p in properties {
-        let (key, value) = p.split_once('=').ok_or_else(|| {
-            anyhow!(
-                "Error parsing #EXTINF on line {linenum}: property '{}' is not a key-value pair",
-                p
-            )
-        })?;
-        track_info.insert(key.to_string(), value.to_string());
+
+    /// Load an m3u8 playlist from the given text, then save it again
+    fn round_trip(dir: &TestDir, text: &str) -> (Playlist, String) {
+        let file_path = dir.path().join("in.m3u8");
+        fs::write(&file_path, text).unwrap();
+        let playlist = Playlist::load(&file_path).unwrap();
+        let saved_path = dir.path().join("out.m3u8");
+        playlist.save(&saved_path).unwrap();
+        (playlist, fs::read_to_string(saved_path).unwrap())
     }
 
-    Ok(track_info)
+    #[test]
+    fn m3u8_round_trip_keeps_unknown_directives() {
+        let dir = TestDir::new("m3u8_round_trip");
+        let text = "\
+#EXTM3U
+#PLAYLIST:Mix
+#EXTART:Artist
+#EXTALB:Album
+#EXTIMG:cover.jpg
+#X-VENDOR:key=value, more
+#EXTINF:200 year=2001 number=3,Song
+/music/a.flac
+#EXTART:Other
+#EXTALB:Unknown
+#EXTINF:0,
+/music/b.flac
+";
+        let (playlist, saved) = round_trip(&dir, text);
+        assert_eq!(
+            playlist.tracks[0].extra_tags,
+            ["#EXTIMG:cover.jpg", "#X-VENDOR:key=value, more"]
+        );
+        assert!(playlist.tracks[1].extra_tags.is_empty());
+        assert_eq!(saved, text);
+    }
+
+    #[test]
+    fn extinf_length_keeps_fractions_of_a_second() {
+        let dir = TestDir::new("extinf_length");
+        let mut playlist = Playlist::new("Lengths");
+        playlist.tracks.push(Track {
+            length: Duration::from_secs_f64(187.5),
+            ..Track::from_url("/music/a.flac")
+        });
+        let file_path = dir.path().join("lengths.m3u8");
+        playlist.save(&file_path).unwrap();
+        assert!(fs::read_to_string(&file_path)
+            .unwrap()
+            .contains("#EXTINF:187.500,"));
+
+        let loaded = Playlist::load(&file_path).unwrap();
+        assert_eq!(loaded.tracks[0].length, Duration::from_millis(187_500));
+    }
+
+    #[test]
+    fn extinf_length_reads_whole_and_fractional_seconds() {
+        assert_eq!(parse_extinf_length("187"), Some(Duration::from_secs(187)));
+        assert_eq!(
+            parse_extinf_length("187.5"),
+            Some(Duration::from_millis(187_500))
+        );
+        assert_eq!(
+            parse_extinf_length("1.23456"),
+            Some(Duration::from_millis(1234))
+        );
+        assert_eq!(parse_extinf_length("abc"), None);
+        assert_eq!(format_extinf_length(Duration::from_secs(187)), "187");
+        assert_eq!(
+            format_extinf_length(Duration::from_millis(187_050)),
+            "187.050"
+        );
+    }
+
+    #[test]
+    fn extinf_title_keeps_commas_and_equals_signs() {
+        let info = parse_extinf("187 year=2001,Song, Pt. 2 (Remix = Extended)", 1).unwrap();
+        assert_eq!(info["title"], "Song, Pt. 2 (Remix = Extended)");
+        assert_eq!(info["duration"], "187");
+        assert_eq!(info["year"], "2001");
+
+        let info = parse_extinf("187   note=\"a, b\"  number=2 ,Title", 1).unwrap();
+        assert_eq!(info["note"], "a, b");
+        assert_eq!(info["number"], "2");
+        assert_eq!(info["title"], "Title");
+    }
+
+    #[test]
+    fn extinf_title_survives_saving() {
+        let dir = TestDir::new("extinf_title");
+        let text = "\
+#EXTM3U
+#PLAYLIST:Titles
+#EXTART:Artist
+#EXTALB:Album
+#EXTINF:187.500,Song, Pt. 2 (Remix = Extended)
+/music/a.flac
+";
+        let (playlist, saved) = round_trip(&dir, text);
+        assert_eq!(
+            playlist.tracks[0].title.as_deref(),
+            Some("Song, Pt. 2 (Remix = Extended)")
+        );
+        assert_eq!(saved, text);
+    }
+
+    #[test]
+    fn extinf_with_unknown_length_is_not_kept_as_a_directive() {
+        let dir = TestDir::new("extinf_unknown_length");
+        let (playlist, saved) = round_trip(
+            &dir,
+            "#EXTM3U\n#PLAYLIST:Radio\n#EXTINF:-1,Live\nhttp://example.com/live\n",
+        );
+        assert_eq!(playlist.tracks[0].title.as_deref(), Some("Live"));
+        assert_eq!(playlist.tracks[0].length, Duration::ZERO);
+        assert!(playlist.tracks[0].extra_tags.is_empty());
+        assert_eq!(saved.matches("#EXTINF").count(), 1);
+    }
+
+    #[test]
+    fn disc_numbers_survive_saving() {
+        let dir = TestDir::new("disc_numbers");
+        let text = "\
+#EXTM3U
+#PLAYLIST:Discs
+#EXTART:Artist
+#EXTALB:Album
+#EXTDISC:2
+#EXTINF:0,
+/music/a.flac
+#EXTART:Artist
+#EXTALB:Album
+#EXTINF:0,
+/music/b.flac
+";
+        let (playlist, saved) = round_trip(&dir, text);
+        assert_eq!(playlist.tracks[0].disc, Some(2));
+        assert_eq!(playlist.tracks[1].disc, None);
+        assert_eq!(saved, text);
+    }
 }
@@ -0,0 +1,462 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! The play queue. The tracks still to play are kept in the order they will
+//! play in, alongside every track in the order it was queued in, so that
+//! turning shuffle off can put them back in order.
+
+use std::collections::{HashSet, VecDeque};
+
+use rand::prelude::*;
+
+use crate::library::track::Track;
+
+/// A queued track. Each is given an id of its own, so that copies of a track
+/// queued more than once can be told apart.
+#[derive(Clone, Debug)]
+struct Entry {
+    id: u64,
+    track: Track,
+}
+
+#[derive(Default)]
+pub struct PlayQueue {
+    /// The tracks still to play, next first
+    upcoming: VecDeque<Entry>,
+
+    /// Every track queued since the queue was last replaced, played or not,
+    /// in the order they were queued
+    ordered: VecDeque<Entry>,
+
+    /// The tracks played before the current one, oldest first
+    history: Vec<Entry>,
+
+    /// The length of the history when the queue was last replaced, so that
+    /// only tracks played from the current queue count towards its position
+    history_start: usize,
+
+    /// The track playing
+    current: Option<Entry>,
+
+    /// The track taken off the queue to play next, until it starts
+    next: Option<Entry>,
+
+    /// The id given to the next track queued
+    next_id: u64,
+}
+
+impl PlayQueue {
+    fn entry(&mut self, track: &Track) -> Entry {
+        self.next_id += 1;
+        Entry {
+            id: self.next_id,
+            track: track.clone(),
+        }
+    }
+
+    /// Add a track to the end of the queue, or somewhere random among the
+    /// tracks still to play if `shuffle` is set. In the unshuffled order it
+    /// goes at the end either way.
+    pub fn enqueue(&mut self, track: &Track, shuffle: bool) {
+        let entry = self.entry(track);
+        if shuffle {
+            let index = rand::thread_rng().gen_range(0..=self.upcoming.len());
+            self.upcoming.insert(index, entry.clone());
+        } else {
+            self.upcoming.push_back(entry.clone());
+        }
+        self.ordered.push_back(entry);
+    }
+
+    /// Queue tracks to play straight after the current track, in order and
+    /// ahead of the rest of the queue, even while shuffled. In the unshuffled
+    /// order they follow the current track too.
+    pub fn enqueue_front(&mut self, tracks: &[Track]) {
+        let index = self
+            .current
+            .as_ref()
+            .and_then(|current| self.ordered_index(current.id))
+            .map_or(0, |index| index + 1);
+        for track in tracks.iter().rev() {
+            let entry = self.entry(track);
+            self.upcoming.push_front(entry.clone());
+            self.ordered.insert(index, entry);
+        }
+    }
+
+    /// Take the next track to play off the front of the queue
+    pub fn pop_front(&mut self) -> Option<Track> {
+        self.next = self.upcoming.pop_front();
+        self.next.as_ref().map(|entry| entry.track.clone())
+    }
+
+    /// Play the current track again next, as under `Repeat::One`
+    pub fn repeat_current(&mut self) {
+        self.next.clone_from(&self.current);
+    }
+
+    /// Move the current track into the history as another starts. If
+    /// `requeue` is set, as under `Repeat::On`, it is queued again at the end.
+    pub fn retire_current(&mut self, requeue: bool) {
+        if let Some(current) = self.current.take() {
+            if requeue {
+                self.upcoming.push_back(current.clone());
+            }
+            self.history.push(current);
+        }
+    }
+
+    /// Forget the current track once playback has run out, without it
+    /// counting as played
+    pub fn clear_current(&mut self) {
+        self.current = None;
+    }
+
+    /// Take the last track played back out of the history to play again,
+    /// putting the current track back at the front of the queue
+    pub fn step_back(&mut self) -> Option<Track> {
+        let prev = self.history.pop()?;
+        if let Some(current) = self.current.take() {
+            self.upcoming.push_front(current);
+        }
+        let track = prev.track.clone();
+        self.next = Some(prev);
+        Some(track)
+    }
+
+    /// Make a track that has started playing the current track. It is the
+    /// one last taken off the queue, unless it was played from elsewhere.
+    pub fn start(&mut self, track: &Track) {
+        self.current = match self.next.take() {
+            Some(entry) if entry.track == *track => Some(entry),
+            _ => Some(self.entry(track)),
+        };
+    }
+
+    /// Empty the queue, leaving the current track and the history alone
+    pub fn clear(&mut self) {
+        self.upcoming.clear();
+        self.ordered.clear();
+        self.history_start = self.history.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.upcoming.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.upcoming.len()
+    }
+
+    /// The tracks still to play, next first
+    pub fn tracks(&self) -> impl Iterator<Item = &Track> {
+        self.upcoming.iter().map(|entry| &entry.track)
+    }
+
+    /// The tracks played so far, most recent first
+    pub fn history(&self) -> impl Iterator<Item = &Track> {
+        self.history.iter().rev().map(|entry| &entry.track)
+    }
+
+    /// How many tracks have been played from the queue since it was last
+    /// replaced
+    pub fn played_count(&self) -> usize {
+        self.history.len().saturating_sub(self.history_start)
+    }
+
+    /// How many tracks have been queued since the queue was last replaced,
+    /// played or not
+    pub fn ordered_len(&self) -> usize {
+        self.ordered.len()
+    }
+
+    /// The queue's tracks in their unshuffled order, including the current
+    /// track and any that have already been played
+    pub fn ordered_tracks(&self) -> Vec<Track> {
+        let mut tracks: Vec<Track> = self.ordered.iter().map(|e| e.track.clone()).collect();
+        if let Some(current) = &self.current {
+            if self.ordered_index(current.id).is_none() {
+                tracks.insert(0, current.track.clone());
+            }
+        }
+        tracks
+    }
+
+    /// Shuffle the tracks still to play, leaving the unshuffled order alone
+    pub fn shuffle(&mut self) {
+        self.upcoming
+            .make_contiguous()
+            .shuffle(&mut rand::thread_rng());
+    }
+
+    /// Put the tracks still to play back in their unshuffled order. See
+    /// `unshuffled_remaining`.
+    pub fn unshuffle(&mut self, repeat_on: bool) {
+        self.upcoming = self.unshuffled_remaining(repeat_on);
+    }
+
+    /// The tracks still to play in their unshuffled order: those queued and
+    /// not yet played from the queue. Under `Repeat::On` the queue comes
+    /// round again, so only the current pass counts as played, and its tracks
+    /// follow on after the rest, leaving out the current track, which is
+    /// requeued when it ends.
+    fn unshuffled_remaining(&self, repeat_on: bool) -> VecDeque<Entry> {
+        let current_id = self.current.as_ref().map(|entry| entry.id);
+        let mut played: HashSet<u64> = current_id.into_iter().collect();
+        for entry in self.history[self.history_start.min(self.history.len())..]
+            .iter()
+            .rev()
+        {
+            // A track played twice marks where the current pass began
+            if !played.insert(entry.id) && repeat_on {
+                break;
+            }
+        }
+        let (mut remaining, passed): (VecDeque<Entry>, VecDeque<Entry>) = self
+            .ordered
+            .iter()
+            .cloned()
+            .partition(|entry| !played.contains(&entry.id));
+        if repeat_on {
+            remaining.extend(
+                passed
+                    .into_iter()
+                    .filter(|entry| Some(entry.id) != current_id),
+            );
+        }
+        remaining
+    }
+
+    /// Where a track is in the unshuffled order
+    fn ordered_index(&self, id: u64) -> Option<usize> {
+        self.ordered.iter().position(|entry| entry.id == id)
+    }
+
+    /// Insert a track into the tracks still to play, at the given index or
+    /// the end. In the unshuffled order it goes before the track it was
+    /// inserted in front of. Returns the index it went in at.
+    pub fn insert(&mut self, track: &Track, index: usize) -> usize {
+        let index = index.min(self.upcoming.len());
+        let ordered_index = self
+            .upcoming
+            .get(index)
+            .and_then(|entry| self.ordered_index(entry.id))
+            .unwrap_or(self.ordered.len());
+        let entry = self.entry(track);
+        self.upcoming.insert(index, entry.clone());
+        self.ordered.insert(ordered_index, entry);
+        index
+    }
+
+    /// Take the track at the given index out of the tracks still to play
+    pub fn remove(&mut self, index: usize) {
+        if let Some(entry) = self.upcoming.remove(index) {
+            if let Some(ordered_index) = self.ordered_index(entry.id) {
+                self.ordered.remove(ordered_index);
+            }
+        }
+    }
+
+    /// Move the track at the given index of the tracks still to play to the
+    /// front. Returns whether there was one.
+    pub fn move_to_front(&mut self, index: usize) -> bool {
+        match self.upcoming.remove(index) {
+            Some(entry) => {
+                self.upcoming.push_front(entry);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(name: &str) -> Track {
+        Track::from_url(&format!("/music/{name}.flac"))
+    }
+
+    fn queue_of(names: &[&str]) -> PlayQueue {
+        let mut queue = PlayQueue::default();
+        for name in names {
+            queue.enqueue(&track(name), false);
+        }
+        queue
+    }
+
+    /// Play the next track from the queue, as `MediaSystem::play_next` does
+    fn advance(queue: &mut PlayQueue, repeat_on: bool) -> Option<Track> {
+        let next = queue.pop_front()?;
+        queue.retire_current(repeat_on);
+        queue.start(&next);
+        Some(next)
+    }
+
+    fn names<'a>(tracks: impl IntoIterator<Item = &'a Track>) -> Vec<String> {
+        tracks
+            .into_iter()
+            .map(|track| {
+                track
+                    .file_path
+                    .trim_start_matches("/music/")
+                    .replace(".flac", "")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn enqueue_front_follows_the_copy_playing() {
+        let mut queue = queue_of(&["a", "b", "a", "c"]);
+        // Play the second copy of `a` first
+        assert!(queue.move_to_front(2));
+        advance(&mut queue, false);
+        queue.enqueue_front(&[track("d")]);
+        assert_eq!(names(&queue.ordered_tracks()), ["a", "b", "a", "d", "c"]);
+        assert_eq!(names(queue.tracks()), ["d", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn unshuffle_leaves_out_only_the_copy_played() {
+        let mut queue = queue_of(&["a", "b", "a", "c"]);
+        assert!(queue.move_to_front(2));
+        advance(&mut queue, false);
+        queue.unshuffle(false);
+        assert_eq!(names(queue.tracks()), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn remove_takes_out_the_copy_at_the_index() {
+        let mut queue = queue_of(&["a", "b", "a"]);
+        queue.remove(2);
+        assert_eq!(names(&queue.ordered_tracks()), ["a", "b"]);
+        assert_eq!(names(queue.tracks()), ["a", "b"]);
+    }
+
+    #[test]
+    fn step_back_requeues_the_current_track() {
+        let mut queue = queue_of(&["a", "b", "c"]);
+        advance(&mut queue, false);
+        advance(&mut queue, false);
+        let prev = queue.step_back().unwrap();
+        queue.start(&prev);
+        assert_eq!(names([&prev]), ["a"]);
+        assert_eq!(names(queue.tracks()), ["b", "c"]);
+        assert_eq!(queue.played_count(), 0);
+    }
+
+    #[test]
+    fn unshuffle_restores_the_unplayed_tracks_in_order() {
+        let mut queue = queue_of(&["a", "b", "c", "d", "e"]);
+        queue.shuffle();
+        advance(&mut queue, false);
+        advance(&mut queue, false);
+        let played: Vec<Track> = queue.history().cloned().collect();
+        let current = queue.current.clone().unwrap().track;
+        queue.unshuffle(false);
+        let expected: Vec<String> = names(&queue.ordered_tracks())
+            .into_iter()
+            .filter(|name| !names(played.iter().chain([&current])).contains(name))
+            .collect();
+        assert_eq!(names(queue.tracks()), expected);
+        assert_eq!(queue.len(), 3);
+    }
+
+    #[test]
+    fn unshuffle_under_repeat_wraps_round_to_the_tracks_played() {
+        let mut queue = queue_of(&["a", "b", "c", "d"]);
+        advance(&mut queue, true);
+        advance(&mut queue, true);
+        queue.shuffle();
+        queue.unshuffle(true);
+        // `b` is playing and is requeued when it ends
+        assert_eq!(names(queue.tracks()), ["c", "d", "a"]);
+    }
+
+    #[test]
+    fn unshuffle_under_repeat_counts_only_the_current_pass() {
+        let mut queue = queue_of(&["a", "b", "c"]);
+        for _ in 0..4 {
+            advance(&mut queue, true);
+        }
+        // Second time round, `a` is playing again
+        queue.shuffle();
+        queue.unshuffle(true);
+        assert_eq!(names(queue.tracks()), ["b", "c"]);
+    }
+
+    #[test]
+    fn unshuffle_after_clearing_keeps_tracks_queued_since() {
+        let mut queue = queue_of(&["a", "b"]);
+        advance(&mut queue, false);
+        queue.clear();
+        queue.enqueue(&track("a"), true);
+        queue.enqueue(&track("c"), true);
+        queue.unshuffle(false);
+        assert_eq!(names(queue.tracks()), ["a", "c"]);
+    }
+
+    #[test]
+    fn insert_goes_in_the_same_place_in_the_unshuffled_order() {
+        let mut queue = queue_of(&["a", "b", "c"]);
+        advance(&mut queue, false);
+        assert_eq!(queue.insert(&track("d"), 1), 1);
+        assert_eq!(queue.insert(&track("e"), 9), 3);
+        assert_eq!(names(queue.tracks()), ["b", "d", "c", "e"]);
+        assert_eq!(names(&queue.ordered_tracks()), ["a", "b", "d", "c", "e"]);
+        queue.unshuffle(false);
+        assert_eq!(names(queue.tracks()), ["b", "d", "c", "e"]);
+    }
+
+    #[test]
+    fn shuffled_enqueue_goes_among_the_upcoming_tracks() {
+        let names_queued: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let mut queue = PlayQueue::default();
+        for name in &names_queued {
+            queue.enqueue(&track(name), true);
+        }
+        // The unshuffled order is the order queued
+        assert_eq!(names(&queue.ordered_tracks()), names_queued);
+        let mut upcoming = names(queue.tracks());
+        assert_ne!(upcoming, names_queued);
+        upcoming.sort();
+        let mut expected = names_queued.clone();
+        expected.sort();
+        assert_eq!(upcoming, expected);
+    }
+
+    #[test]
+    fn shuffle_leaves_the_current_track_and_unshuffled_order_alone() {
+        let mut queue = queue_of(&["a", "b", "c", "d", "e", "f"]);
+        advance(&mut queue, false);
+        queue.shuffle();
+        let mut upcoming = names(queue.tracks());
+        upcoming.sort();
+        assert_eq!(upcoming, ["b", "c", "d", "e", "f"]);
+        assert_eq!(names([&queue.current.clone().unwrap().track]), ["a"]);
+        assert_eq!(
+            names(&queue.ordered_tracks()),
+            ["a", "b", "c", "d", "e", "f"]
+        );
+    }
+
+    #[test]
+    fn repeating_the_current_track_leaves_the_queue_alone() {
+        let mut queue = queue_of(&["a", "b"]);
+        advance(&mut queue, false);
+        for _ in 0..2 {
+            // As `MediaSystem::play_next` does under `Repeat::One`
+            queue.repeat_current();
+            let current = queue.current.clone().unwrap();
+            queue.retire_current(false);
+            queue.start(&current.track);
+            assert_eq!(queue.current.as_ref().unwrap().id, current.id);
+        }
+        assert_eq!(names(queue.tracks()), ["b"]);
+        assert_eq!(names(queue.history()), ["a", "a"]);
+        queue.unshuffle(false);
+        assert_eq!(names(queue.tracks()), ["b"]);
+    }
+}
@@ -0,0 +1,185 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{
+    fmt::Write as _,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::library::track::Track;
+
+const API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Tracks shorter than this are never scrobbled
+const MIN_TRACK_LENGTH: Duration = Duration::from_secs(30);
+
+/// A track is scrobbled once it has played for half its length or this long,
+/// whichever comes first
+const MAX_PLAY_TIME: Duration = Duration::from_secs(240);
+
+/// Last.fm API credentials. The session key is obtained by authorizing the
+/// API account with a Last.fm user.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScrobbleCredentials {
+    pub api_key: String,
+    pub api_secret: String,
+    pub session_key: String,
+}
+
+/// Submits played tracks to Last.fm in the background. Failures are collected
+/// rather than returned so that they never hold up playback.
+pub struct Scrobbler {
+    credentials: ScrobbleCredentials,
+    error_sender: Sender<String>,
+    error_receiver: Receiver<String>,
+}
+
+impl Scrobbler {
+    pub fn new(credentials: ScrobbleCredentials) -> Self {
+        let (error_sender, error_receiver) = mpsc::channel();
+        Self {
+            credentials,
+            error_sender,
+            error_receiver,
+        }
+    }
+
+    /// Whether a track that has played for `progress` should be scrobbled
+    pub fn should_scrobble(track: &Track, progress: Duration) -> bool {
+        track.length > MIN_TRACK_LENGTH && progress >= (track.length / 2).min(MAX_PLAY_TIME)
+    }
+
+    /// Scrobble a track which started playing at the given Unix time, without
+    /// waiting for the request to finish
+    pub fn scrobble(&self, track: &Track, started_at: u64) {
+        let mut params = vec![
+            ("method", "track.scrobble".to_owned()),
+            ("artist", track.artist.clone()),
+            ("track", track.to_string()),
+            ("album", track.album.clone()),
+            ("timestamp", started_at.to_string()),
+            ("api_key", self.credentials.api_key.clone()),
+            ("sk", self.credentials.session_key.clone()),
+        ];
+        if let Some(album_artist) = &track.album_artist {
+            params.push(("albumArtist", album_artist.clone()));
+        }
+        let body = signed_body(params, &self.credentials.api_secret);
+
+        let error_sender = self.error_sender.clone();
+        let title = track.to_string();
+        thread::spawn(move || {
+            if let Err(e) = post(&body) {
+                let _ = error_sender.send(format!("Failed to scrobble \"{title}\": {e}"));
+            }
+        });
+    }
+
+    /// Take the errors from scrobbles that have failed since the last call
+    pub fn take_errors(&self) -> Vec<String> {
+        self.error_receiver.try_iter().collect()
+    }
+}
+
+/// Sign the request parameters as Last.fm requires and encode them as a form
+/// body
+fn signed_body(mut params: Vec<(&str, String)>, api_secret: &str) -> String {
+    params.sort_by_key(|(key, _)| *key);
+
+    let mut signature = String::new();
+    for (key, value) in &params {
+        signature.push_str(key);
+        signature.push_str(value);
+    }
+    signature.push_str(api_secret);
+    params.push(("api_sig", md5_hex(signature.as_bytes())));
+
+    params
+        .iter()
+        .map(|(key, value)| format!("{key}={}", url_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Post a form body to the Last.fm API, failing on any non-200 response
+fn post(body: &str) -> Result<()> {
+    let response = ureq::post(API_URL)
+        .timeout(TIMEOUT)
+        .set("Content-Type", "application/x-www-form-urlencoded")
+        .send_string(body)?;
+    match response.status() {
+        200 => Ok(()),
+        status => Err(anyhow!(
+            "Unexpected response: {status} {}",
+            response.status_text()
+        )),
+    }
+}
+
+/// Percent-encode everything but unreserved characters
+fn url_encode(s: &str) -> String {
+    let mut encoded = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(b as char);
+        } else {
+            let _ = write!(encoded, "%{b:02X}");
+        }
+    }
+    encoded
+}
+
+/// The MD5 digest of the input as lowercase hex, as used for API signatures
+fn md5_hex(input: &[u8]) -> String {
+    format!("{:x}", md5::compute(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn md5_matches_rfc_1321_test_suite() {
+        let cases = [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("a", "0cc175b9c0f1b6a831c399e269772661"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            (
+                "abcdefghijklmnopqrstuvwxyz",
+                "c3fcd3d76192e4007dfb496cca67e13b",
+            ),
+            (
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                "d174ab98d277d9f5a5611c2c9f419d9f",
+            ),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ];
+        for (input, digest) in cases {
+            assert_eq!(md5_hex(input.as_bytes()), digest, "MD5 of {input:?}");
+        }
+    }
+
+    #[test]
+    fn signed_body_sorts_params_and_signs_them() {
+        let body = signed_body(
+            vec![("track", "A & B".to_owned()), ("artist", "C".to_owned())],
+            "secret",
+        );
+        let signature = md5_hex(b"artistCtrackA & Bsecret");
+        assert_eq!(
+            body,
+            format!("artist=C&track=A%20%26%20B&api_sig={signature}")
+        );
+    }
+}
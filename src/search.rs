@@ -0,0 +1,137 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+
+/// How search text is matched against track, artist, and album names
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+pub enum MatchMode {
+    /// The text must appear exactly as typed
+    CaseSensitive,
+    /// The text must appear, ignoring case
+    #[default]
+    CaseInsensitive,
+    /// The characters of the text must appear in order, ignoring case, but
+    /// not necessarily next to each other
+    Fuzzy,
+}
+
+impl MatchMode {
+    /// The mode after this one, wrapping around to the first
+    pub const fn next(self) -> Self {
+        match self {
+            Self::CaseSensitive => Self::CaseInsensitive,
+            Self::CaseInsensitive => Self::Fuzzy,
+            Self::Fuzzy => Self::CaseSensitive,
+        }
+    }
+}
+
+impl FromStr for MatchMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "case-sensitive" | "exact" => Ok(Self::CaseSensitive),
+            "case-insensitive" | "ignore-case" => Ok(Self::CaseInsensitive),
+            "fuzzy" => Ok(Self::Fuzzy),
+            other => Err(anyhow!("Invalid match mode: {other}")),
+        }
+    }
+}
+
+impl Display for MatchMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::CaseSensitive => "case-sensitive",
+            Self::CaseInsensitive => "case-insensitive",
+            Self::Fuzzy => "fuzzy",
+        })
+    }
+}
+
+/// Score how well the text matches the query in the given mode, or `None` if
+/// it doesn't match. Lower scores are better. Substring modes score every
+/// match the same; fuzzy matches are scored by how spread out the matched
+/// characters are and how far into the text they start.
+pub fn match_score(query: &str, text: &str, mode: MatchMode) -> Option<usize> {
+    match mode {
+        MatchMode::CaseSensitive => text.contains(query).then_some(0),
+        MatchMode::CaseInsensitive => text
+            .to_lowercase()
+            .contains(&query.to_lowercase())
+            .then_some(0),
+        MatchMode::Fuzzy => fuzzy_score(&query.to_lowercase(), &text.to_lowercase()),
+    }
+}
+
+fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let mut query_chars = query.chars().peekable();
+    let mut score = 0;
+    let mut last_match: Option<usize> = None;
+
+    for (i, c) in text.chars().enumerate() {
+        let Some(&q) = query_chars.peek() else {
+            break;
+        };
+        if c == q {
+            // Penalize the gap since the previous match, or the distance from
+            // the start of the text for the first match
+            score += last_match.map_or(i, |last| i - last - 1);
+            last_match = Some(i);
+            query_chars.next();
+        }
+    }
+
+    query_chars.peek().is_none().then_some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substring_modes_differ_only_in_case() {
+        use MatchMode::{CaseInsensitive, CaseSensitive};
+        assert_eq!(match_score("Low", "Slowdive", CaseSensitive), None);
+        assert_eq!(match_score("low", "Slowdive", CaseSensitive), Some(0));
+        assert_eq!(match_score("LOW", "Slowdive", CaseInsensitive), Some(0));
+        assert_eq!(match_score("dive slow", "Slowdive", CaseInsensitive), None);
+        assert_eq!(match_score("", "anything", CaseSensitive), Some(0));
+    }
+
+    #[test]
+    fn fuzzy_matches_characters_in_order() {
+        assert_eq!(match_score("sdv", "Slowdive", MatchMode::Fuzzy), Some(4));
+        assert_eq!(match_score("SLOW", "slowdive", MatchMode::Fuzzy), Some(0));
+        assert_eq!(match_score("vs", "Slowdive", MatchMode::Fuzzy), None);
+        assert_eq!(match_score("slowdives", "Slowdive", MatchMode::Fuzzy), None);
+    }
+
+    #[test]
+    fn fuzzy_ranks_tighter_and_earlier_matches_first() {
+        let score = |text| match_score("abc", text, MatchMode::Fuzzy).unwrap();
+        assert!(score("abc") < score("a-b-c"));
+        assert!(score("abcxx") < score("xxabc"));
+        assert!(score("xabc") < score("a--bc"));
+    }
+
+    #[test]
+    fn match_mode_cycles_and_parses() {
+        let mut mode = MatchMode::default();
+        for _ in 0..3 {
+            assert_eq!(mode.to_string().parse::<MatchMode>().unwrap(), mode);
+            mode = mode.next();
+        }
+        assert_eq!(mode, MatchMode::default());
+        assert_eq!(
+            "exact".parse::<MatchMode>().unwrap(),
+            MatchMode::CaseSensitive
+        );
+        assert!("regex".parse::<MatchMode>().is_err());
+    }
+}
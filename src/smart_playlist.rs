@@ -0,0 +1,244 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{fmt::Display, fs, io::Write, path::Path, str::FromStr, sync::LazyLock};
+
+use anyhow::{anyhow, Error, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    library::{track::Track, Library},
+    playlist::Playlist,
+    traits::{Load, Save},
+    util::write_atomically,
+};
+
+/// A playlist defined by a query rather than a fixed list of tracks. Saved as
+/// a `.smart` file named after the playlist, containing just the query.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SmartPlaylist {
+    pub name: String,
+    pub query: Query,
+}
+
+impl SmartPlaylist {
+    /// Build the concrete playlist of the library's tracks matching the query
+    pub fn evaluate(&self, library: &Library) -> Playlist {
+        let mut playlist = Playlist::new(&self.name);
+        playlist.query = Some(self.query.clone());
+        playlist.tracks = self.query.matching(library);
+        playlist
+    }
+}
+
+impl Save for SmartPlaylist {
+    fn save<P: AsRef<Path>>(&self, file_path: P) -> Result<()> {
+        write_atomically(file_path, |file| Ok(writeln!(file, "{}", self.query)?))
+    }
+}
+
+/// Errors don't name the file, as it is logged along with them
+impl Load for SmartPlaylist {
+    fn load<P: AsRef<Path>>(file_path: P) -> Result<Self> {
+        let file_path = file_path.as_ref();
+        let name = file_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| anyhow!("Invalid smart playlist name"))?
+            .to_owned();
+        let query = fs::read_to_string(file_path)?.parse()?;
+        Ok(Self { name, query })
+    }
+}
+
+/// A single rule a track must satisfy. Text comparisons ignore case.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// The track or album artist is this
+    Artist(String),
+    Album(String),
+    Genre(String),
+    /// The track is from after this year
+    YearAfter(u32),
+}
+
+impl Condition {
+    fn matches(&self, track: &Track) -> bool {
+        match self {
+            Self::Artist(artist) => {
+                track.artist.eq_ignore_ascii_case(artist)
+                    || track
+                        .album_artist
+                        .as_deref()
+                        .is_some_and(|a| a.eq_ignore_ascii_case(artist))
+            }
+            Self::Album(album) => track.album.eq_ignore_ascii_case(album),
+            Self::Genre(genre) => track
+                .genre
+                .as_deref()
+                .is_some_and(|g| g.eq_ignore_ascii_case(genre)),
+            Self::YearAfter(year) => track.year.is_some_and(|y| y > *year),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some((key, value)) = s.split_once('=') {
+            let value = value.trim().to_owned();
+            match key.trim() {
+                "artist" => Ok(Self::Artist(value)),
+                "album" => Ok(Self::Album(value)),
+                "genre" => Ok(Self::Genre(value)),
+                other => Err(anyhow!("Invalid query field: {other}")),
+            }
+        } else if let Some((key, value)) = s.split_once('>') {
+            match key.trim() {
+                "year" => Ok(Self::YearAfter(
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid year: {}", value.trim()))?,
+                )),
+                other => Err(anyhow!("Invalid query field: {other}")),
+            }
+        } else {
+            Err(anyhow!("Invalid query condition: {s}"))
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Artist(artist) => write!(f, "artist={artist}"),
+            Self::Album(album) => write!(f, "album={album}"),
+            Self::Genre(genre) => write!(f, "genre={genre}"),
+            Self::YearAfter(year) => write!(f, "year>{year}"),
+        }
+    }
+}
+
+/// Conditions joined by `and`, e.g. `artist=Queen and year>1980`. A track
+/// matches if it satisfies all of them.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Query {
+    conditions: Vec<Condition>,
+}
+
+static AND_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s+and\s+").expect("Known valid regex"));
+
+impl Query {
+    /// The library's tracks that match the query, in library order
+    pub fn matching(&self, library: &Library) -> Vec<Track> {
+        library
+            .tracks
+            .tracks
+            .iter()
+            .filter(|t| self.conditions.iter().all(|c| c.matches(t)))
+            .cloned()
+            .collect()
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(anyhow!("Empty query"));
+        }
+        let conditions = AND_RE
+            .split(s)
+            .map(str::parse)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { conditions })
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, condition) in self.conditions.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" and ")?;
+            }
+            write!(f, "{condition}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions_parse_each_field() {
+        assert_eq!(
+            " artist = Queen ".parse::<Condition>().unwrap(),
+            Condition::Artist("Queen".to_owned())
+        );
+        assert_eq!(
+            "album=A Night at the Opera".parse::<Condition>().unwrap(),
+            Condition::Album("A Night at the Opera".to_owned())
+        );
+        assert_eq!(
+            "genre=Rock".parse::<Condition>().unwrap(),
+            Condition::Genre("Rock".to_owned())
+        );
+        assert_eq!(
+            "year > 1980".parse::<Condition>().unwrap(),
+            Condition::YearAfter(1980)
+        );
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        for condition in ["mood=happy", "title>3", "year>soon", "year=1980", "artist"] {
+            assert!(
+                condition.parse::<Condition>().is_err(),
+                "{condition} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn queries_join_conditions_with_and() {
+        let query: Query = "artist=Queen and  year>1980".parse().unwrap();
+        assert_eq!(
+            query.conditions,
+            [
+                Condition::Artist("Queen".to_owned()),
+                Condition::YearAfter(1980)
+            ]
+        );
+        assert!("".parse::<Query>().is_err());
+        assert!("   ".parse::<Query>().is_err());
+        assert!("artist=Queen and mood=happy".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn queries_survive_being_written_and_read_back() {
+        let query = Query {
+            conditions: vec![
+                Condition::Artist("Queen".to_owned()),
+                Condition::Album("Jazz".to_owned()),
+                Condition::Genre("Rock".to_owned()),
+                Condition::YearAfter(1975),
+            ],
+        };
+        let text = query.to_string();
+        assert_eq!(
+            text,
+            "artist=Queen and album=Jazz and genre=Rock and year>1975"
+        );
+        assert_eq!(text.parse::<Query>().unwrap(), query);
+    }
+}
@@ -0,0 +1,52 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{fs::File, io::Write, path::Path};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    library::track::Track,
+    traits::{Load, Save},
+    util::{write_atomically, write_json},
+};
+
+/// A named internet radio stream
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Station {
+    pub name: String,
+    pub url: String,
+}
+
+impl Station {
+    /// The track to play for this station
+    pub fn track(&self) -> Track {
+        let mut track = Track::from_url(&self.url);
+        track.title = Some(self.name.clone());
+        track
+    }
+}
+
+/// The saved radio stations, kept in a JSON file in the data directory
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StationList {
+    pub stations: Vec<Station>,
+}
+
+impl Save for StationList {
+    fn save<P: AsRef<Path>>(&self, file_path: P) -> Result<()> {
+        let mut json = String::new();
+        write_json(&serde_yml::to_value(self)?, &mut json)?;
+        write_atomically(file_path, |file| Ok(writeln!(file, "{json}")?))
+    }
+}
+
+impl Load for StationList {
+    /// JSON is a subset of YAML, so the YAML parser reads it directly
+    fn load<P: AsRef<Path>>(file_path: P) -> Result<Self> {
+        let file = File::open(file_path)?;
+        Ok(serde_yml::from_reader(file)?)
+    }
+}
@@ -0,0 +1,283 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Seek, SeekFrom},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use ureq::AgentBuilder;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: u32 = 5;
+
+/// Whether a track path is a URL to stream rather than a local file
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Remote audio downloaded in the background. Reads block until the data
+/// they need has arrived. When the server gives the stream's length,
+/// everything downloaded is kept so that the stream can be seeked like a
+/// file. Streams without a length, such as internet radio, may never end, so
+/// only a bounded window around the read position is kept and they can't be
+/// seeked outside it. The download stops once the stream is dropped.
+pub struct HttpStream {
+    download: Arc<(Mutex<Download>, Condvar)>,
+}
+
+/// The most of a stream without a length that is kept in memory at once
+const RING_CAPACITY: usize = 1024 * 1024;
+
+/// How much of a stream without a length is kept behind the read position,
+/// for decoders that step back to reread headers
+const RING_HISTORY: usize = 256 * 1024;
+
+#[derive(Default)]
+struct Download {
+    /// The downloaded bytes that are still kept
+    data: VecDeque<u8>,
+    /// The offset in the stream of the first byte kept
+    start: usize,
+    /// The offset in the stream that the next read starts at
+    position: usize,
+    /// Whether the download has ended, successfully or not
+    done: bool,
+    /// The length of the body, if the server gave it
+    length: Option<usize>,
+}
+
+impl Download {
+    /// The offset in the stream just past the last byte downloaded
+    fn end(&self) -> usize {
+        self.start + self.data.len()
+    }
+
+    /// Drop what was read long enough ago from a stream without a length,
+    /// making room for more of it
+    fn forget_read(&mut self) {
+        if self.length.is_none() {
+            let forgotten = (self.position - self.start).saturating_sub(RING_HISTORY);
+            self.data.drain(..forgotten);
+            self.start += forgotten;
+        }
+    }
+}
+
+impl HttpStream {
+    /// Request a URL, following redirects, and start downloading the
+    /// response body. Fails if the server can't be reached or doesn't respond
+    /// with success.
+    pub fn open(url: &str) -> Result<Self> {
+        let (reader, length) = request(url)?;
+        Ok(Self::from_reader(reader, length))
+    }
+
+    /// Start downloading a body of the given length, if known, from a reader
+    fn from_reader(reader: impl Read + Send + 'static, length: Option<usize>) -> Self {
+        let download = Arc::new((
+            Mutex::new(Download {
+                length,
+                ..Download::default()
+            }),
+            Condvar::new(),
+        ));
+        let download_thread = Arc::clone(&download);
+        thread::spawn(move || fetch(reader, &download_thread));
+        Self { download }
+    }
+}
+
+impl Read for HttpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (lock, condvar) = &*self.download;
+        let mut download = lock
+            .lock()
+            .map_err(|_| io::Error::other("Stream download failed"))?;
+        while download.end() <= download.position && !download.done {
+            download = condvar
+                .wait(download)
+                .map_err(|_| io::Error::other("Stream download failed"))?;
+        }
+        // Seeking by the given length can pass the end of a body that was cut
+        // short
+        if download.position >= download.end() {
+            return Ok(0);
+        }
+        let offset = download.position - download.start;
+        let mut n = 0;
+        for (byte, downloaded) in buf.iter_mut().zip(download.data.range(offset..)) {
+            *byte = *downloaded;
+            n += 1;
+        }
+        download.position += n;
+        // The download may be waiting for room in the ring
+        condvar.notify_all();
+        Ok(n)
+    }
+}
+
+impl Seek for HttpStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let mut download = self
+            .download
+            .0
+            .lock()
+            .map_err(|_| io::Error::other("Stream download failed"))?;
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => (download.position as u64).checked_add_signed(offset),
+            SeekFrom::End(offset) => {
+                let length = download
+                    .length
+                    .or_else(|| download.done.then(|| download.end()))
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::Unsupported, "Stream length is unknown")
+                    })?;
+                (length as u64).checked_add_signed(offset)
+            }
+        }
+        .and_then(|position| usize::try_from(position).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek"))?;
+        if download.length.is_none() && !(download.start..=download.end()).contains(&position) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Stream can't be seeked this far",
+            ));
+        }
+        download.position = position;
+        Ok(position as u64)
+    }
+}
+
+/// Read the response body into the shared download until it ends, fails, or
+/// the stream it belongs to is dropped. For a stream without a length, waits
+/// for the reader whenever the ring is full.
+fn fetch(mut reader: impl Read, download: &Arc<(Mutex<Download>, Condvar)>) {
+    let (lock, condvar) = &**download;
+    let mut buf = [0; 16 * 1024];
+    loop {
+        // A failed read ends the stream like the end of the body does
+        let n = if Arc::strong_count(download) > 1 {
+            reader.read(&mut buf).unwrap_or(0)
+        } else {
+            0
+        };
+        let Ok(mut download_guard) = lock.lock() else {
+            return;
+        };
+        if n == 0 {
+            download_guard.done = true;
+            condvar.notify_all();
+            return;
+        }
+        loop {
+            download_guard.forget_read();
+            if download_guard.length.is_some() || download_guard.data.len() + n <= RING_CAPACITY {
+                break;
+            }
+            if Arc::strong_count(download) == 1 {
+                return;
+            }
+            // Wake up now and then to notice the stream being dropped
+            download_guard = match condvar.wait_timeout(download_guard, TIMEOUT) {
+                Ok((guard, _)) => guard,
+                Err(_) => return,
+            };
+        }
+        download_guard.data.extend(&buf[..n]);
+        condvar.notify_all();
+    }
+}
+
+/// Send a GET request, following redirects. Returns a reader of the response
+/// body, along with the body's length if given.
+fn request(url: &str) -> Result<(impl Read + Send, Option<usize>)> {
+    let response = AgentBuilder::new()
+        .timeout_connect(TIMEOUT)
+        .timeout_read(TIMEOUT)
+        .redirects(MAX_REDIRECTS)
+        .user_agent(concat!("rmup/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .get(url)
+        .call()?;
+    let length = response
+        .header("Content-Length")
+        .and_then(|length| length.parse().ok());
+    Ok((response.into_reader(), length))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Bytes that don't repeat with any period a ring could hide
+    fn body(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    #[test]
+    fn stream_without_length_reads_through_the_ring() {
+        let data = body(3 * RING_CAPACITY + 12345);
+        let mut stream = HttpStream::from_reader(Cursor::new(data.clone()), None);
+        let mut read = Vec::new();
+        stream.read_to_end(&mut read).unwrap();
+        assert!(read == data);
+    }
+
+    #[test]
+    fn stream_without_length_keeps_a_bounded_window() {
+        let data = body(3 * RING_CAPACITY);
+        let mut stream = HttpStream::from_reader(Cursor::new(data.clone()), None);
+        let mut buf = vec![0; RING_HISTORY + 1000];
+        stream.read_exact(&mut buf).unwrap();
+        assert!(buf[..] == data[..buf.len()]);
+
+        // Give the download time to fill the ring
+        thread::sleep(Duration::from_millis(100));
+        let kept = stream.download.0.lock().unwrap().data.len();
+        assert!(kept <= RING_CAPACITY, "kept {kept} bytes");
+
+        assert!(stream.seek(SeekFrom::Start(0)).is_err());
+        assert!(stream
+            .seek(SeekFrom::Start(3 * RING_CAPACITY as u64))
+            .is_err());
+        stream.seek(SeekFrom::Current(-100)).unwrap();
+        let mut reread = [0; 100];
+        stream.read_exact(&mut reread).unwrap();
+        assert!(reread[..] == data[buf.len() - 100..buf.len()]);
+    }
+
+    #[test]
+    fn stream_with_length_seeks_anywhere() {
+        let data = body(2 * RING_CAPACITY);
+        let mut stream = HttpStream::from_reader(Cursor::new(data.clone()), Some(data.len()));
+        let mut buf = [0; 100];
+        stream.seek(SeekFrom::End(-100)).unwrap();
+        stream.read_exact(&mut buf).unwrap();
+        assert!(buf[..] == data[data.len() - 100..]);
+        stream.seek(SeekFrom::Start(0)).unwrap();
+        stream.read_exact(&mut buf).unwrap();
+        assert!(buf[..] == data[..100]);
+    }
+
+    #[test]
+    fn stream_cut_short_reads_nothing_past_its_end() {
+        let data = body(1000);
+        let mut stream = HttpStream::from_reader(Cursor::new(data.clone()), Some(4096));
+        stream.seek(SeekFrom::End(-128)).unwrap();
+        let mut buf = [0; 100];
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+        stream.seek(SeekFrom::Start(900)).unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        assert!(rest[..] == data[900..]);
+    }
+}
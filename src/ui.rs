@@ -2,37 +2,69 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_std::sync::Mutex;
-use crossterm::event::KeyEvent;
+use crossterm::event::{KeyEvent, MouseButton, MouseEvent, MouseEventKind};
 use ratatui::{
     backend::Backend,
-    layout::{Constraint, Direction, Layout, Rect},
-    style::Style,
-    text::Text,
-    widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph},
+    layout::{Constraint, Direction, Layout, Margin, Position, Rect},
+    style::{Modifier, Style},
+    text::{Line, Text},
+    widgets::{
+        Bar, BarChart, BarGroup, Block, Borders, Gauge, List, ListItem, ListState, Paragraph,
+        Scrollbar, ScrollbarOrientation, ScrollbarState,
+    },
     Frame, Terminal,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{
+    cell::Cell,
+    mem,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use unicode_width::UnicodeWidthStr;
 
 use crate::{
     command::Command,
     config::{ConfOption, Config, UiColor},
+    cover::{self, CoverLoader},
+    library::track::{SortDirection, SortKey, Track},
     media_system::{MediaState, Queueable, Repeat},
     playlist::Playlist,
+    search::MatchMode,
+    smart_playlist::SmartPlaylist,
+    station::Station,
+    util::{scroll_text, to_width},
+    visualizer::{Visualizer, BAND_COUNT},
     Library, Mode,
 };
 
+#[cfg(all(test, feature = "bench"))]
+mod benches;
 mod command_line;
+mod config_screen;
+mod error_screen;
+mod goto_time;
 mod help_screen;
+mod history_screen;
+mod lyrics_screen;
 mod main_screen;
 mod playlist_screen;
+mod station_screen;
+mod tree_screen;
 
 use command_line::CommandLine;
+use config_screen::ConfigScreen;
+use error_screen::ErrorScreen;
+use goto_time::GotoTimeOverlay;
 use help_screen::HelpScreen;
+use history_screen::HistoryScreen;
+use lyrics_screen::LyricsScreen;
 use main_screen::MainScreen;
 use playlist_screen::PlaylistScreen;
+use station_screen::StationScreen;
+use tree_screen::TreeScreen;
 
 #[derive(Clone, Copy)]
 pub enum MovementDirection {
@@ -40,6 +72,32 @@ pub enum MovementDirection {
     Next,
     Top,
     Bottom,
+    HalfUp,
+    HalfDown,
+    PageUp,
+    PageDown,
+}
+
+impl MovementDirection {
+    /// The index to select after moving from `selected` in a list of `len`
+    /// items drawn `height` rows tall, borders included. Single steps wrap
+    /// around the ends of the list if `wrap` is set, and otherwise stop at
+    /// them like page steps do.
+    pub fn apply(self, selected: usize, len: usize, height: u16, wrap: bool) -> usize {
+        let page = usize::from(height.saturating_sub(2)).max(1);
+        match self {
+            Self::Prev if selected == 0 && wrap => len - 1,
+            Self::Prev => selected.saturating_sub(1),
+            Self::Next if selected == len - 1 && wrap => 0,
+            Self::Next => (selected + 1).min(len - 1),
+            Self::Top => 0,
+            Self::Bottom => len - 1,
+            Self::HalfUp => selected.saturating_sub((page / 2).max(1)),
+            Self::HalfDown => (selected + (page / 2).max(1)).min(len - 1),
+            Self::PageUp => selected.saturating_sub(page),
+            Self::PageDown => (selected + page).min(len - 1),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
@@ -47,26 +105,314 @@ pub enum ScreenEnum {
     Main,
     Playlists,
     Help,
+    Tree,
+    History,
+    Stations,
+    Errors,
+    Config,
+    Lyrics,
 }
 
 trait Screen {
     fn ui(&self, f: &mut Frame, page_chunk: Rect);
     fn style_panels(&mut self, selected: &Style, unselected: &Style);
     fn switch_panel(&mut self, direction: MovementDirection);
-    fn switch_item(&mut self, direction: MovementDirection);
-    fn update_lists(&mut self, normal_style: &Style);
+    /// Move the selection, wrapping around the ends of the list if `wrap`
+    /// is set
+    fn switch_item(&mut self, direction: MovementDirection, wrap: bool);
+    /// Select the item at an index in the current list, or the last item if
+    /// the index is past the end
+    fn goto_index(&mut self, index: usize);
+    /// Select the panel and item at a position on the screen. Returns the
+    /// command a double click on that item runs, or `None` if there is no
+    /// item there.
+    fn handle_click(&mut self, col: u16, row: u16) -> Option<Command>;
+    fn update_lists(&mut self, theme: &Theme);
     fn get_selected(&self, tracks_current_only: bool) -> Queueable;
 }
 
+/// The styles panels and the command line are drawn in
+#[derive(Clone, Copy, Default)]
+pub struct Theme {
+    /// Base widget style
+    pub normal: Style,
+
+    /// Style of panel borders, drawn over the base style
+    pub border: Style,
+
+    /// Style of panel titles, drawn over the base style
+    pub title: Style,
+
+    /// Style of the command line prompt
+    pub prompt: Style,
+}
+
+impl Theme {
+    /// A bordered panel with a title
+    pub fn block<'b>(&self, title: impl Into<Line<'b>>) -> Block<'b> {
+        self.border_block().title(title).title_style(self.title)
+    }
+
+    /// A bordered panel without a title
+    pub fn border_block<'b>(&self) -> Block<'b> {
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(self.border)
+    }
+}
+
 pub struct UIList<'a, Item> {
     /// The items in the list
     list: Vec<Item>,
 
-    /// The TUI List widget
+    /// The TUI List widget the visible items are drawn in. It holds the
+    /// block and styles only; items are built as they come into view.
     display: List<'a>,
 
     /// The List widget state
     state: ListState,
+
+    /// Where the list was last drawn
+    view: Cell<ListView>,
+}
+
+/// Where a list was drawn and how far it was scrolled
+#[derive(Clone, Copy, Default)]
+struct ListView {
+    area: Rect,
+    offset: usize,
+}
+
+impl<'a, Item> UIList<'a, Item> {
+    /// Draw the list, remembering where it was drawn. Only the items that fit
+    /// are built, with `item` given each one's index and value, so drawing
+    /// takes the same time however long the list is. A scrollbar is drawn
+    /// over the right border if the items don't all fit.
+    fn render(
+        &self,
+        f: &mut Frame,
+        area: Rect,
+        mut item: impl FnMut(usize, &Item) -> ListItem<'a>,
+    ) {
+        let rows = usize::from(area.height.saturating_sub(2));
+        let offset = self.scroll_offset(rows);
+        let end = (offset + rows).min(self.list.len());
+        let items: Vec<ListItem> = self.list[offset..end]
+            .iter()
+            .enumerate()
+            .map(|(i, it)| item(offset + i, it))
+            .collect();
+
+        let mut state =
+            ListState::default().with_selected(self.state.selected().map(|s| s - offset));
+        f.render_stateful_widget(self.display.clone().items(items), area, &mut state);
+        self.view.set(ListView { area, offset });
+
+        if self.list.len() > rows {
+            let mut scrollbar_state = ScrollbarState::new(self.list.len() - rows + 1)
+                .viewport_content_length(rows)
+                .position(offset);
+            f.render_stateful_widget(
+                Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                    .begin_symbol(None)
+                    .end_symbol(None),
+                area.inner(Margin::new(0, 1)),
+                &mut scrollbar_state,
+            );
+        }
+    }
+
+    /// The index of the first item shown in `rows` rows. As in the List
+    /// widget, the offset the list was last drawn at is moved just far
+    /// enough to bring the selection into view.
+    fn scroll_offset(&self, rows: usize) -> usize {
+        let rows = rows.max(1);
+        let offset = self
+            .view
+            .get()
+            .offset
+            .min(self.list.len().saturating_sub(1));
+        match self.state.selected() {
+            Some(selected) if selected >= offset + rows => selected + 1 - rows,
+            Some(selected) if selected < offset => selected,
+            _ => offset,
+        }
+    }
+
+    /// The height the list was last drawn at, including its borders
+    fn height(&self) -> u16 {
+        self.view.get().area.height
+    }
+
+    /// Whether the list was last drawn over a position
+    fn contains(&self, col: u16, row: u16) -> bool {
+        self.view.get().area.contains(Position::new(col, row))
+    }
+
+    /// Select the item drawn at a position. Returns whether there was one.
+    fn click(&mut self, col: u16, row: u16) -> bool {
+        let view = self.view.get();
+        let inner = view.area.inner(Margin::new(1, 1));
+        if !inner.contains(Position::new(col, row)) {
+            return false;
+        }
+        let index = view.offset + usize::from(row - inner.y);
+        if index < self.list.len() {
+            self.state.select(Some(index));
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A page of text with headings that scrolls by line, for screens that show
+/// information rather than a list to pick from
+pub struct TextPage<'a> {
+    paragraph: Paragraph<'a>,
+
+    /// How many lines the page has
+    line_count: usize,
+
+    /// How many lines the page is scrolled down by
+    scroll: usize,
+
+    /// The height the page was last drawn at, borders included
+    height: Cell<u16>,
+}
+
+impl<'a> TextPage<'a> {
+    /// A page of sections, each a heading drawn in the title style followed
+    /// by its lines, with a blank line between sections
+    pub fn new(title: &'a str, sections: Vec<(String, Vec<String>)>, theme: &Theme) -> Self {
+        let mut lines = Vec::new();
+        for (heading, body) in sections {
+            if !lines.is_empty() {
+                lines.push(Line::default());
+            }
+            lines.push(Line::styled(heading, theme.title));
+            lines.extend(body.into_iter().map(Line::from));
+        }
+        Self {
+            line_count: lines.len(),
+            paragraph: Paragraph::new(Text::from(lines))
+                .block(theme.block(title))
+                .style(theme.normal),
+            scroll: 0,
+            height: Cell::default(),
+        }
+    }
+
+    /// Draw the page, remembering its height for scrolling
+    fn render(&self, f: &mut Frame, area: Rect) {
+        self.height.set(area.height);
+        let scroll = u16::try_from(self.scroll.min(self.max_scroll())).unwrap_or(u16::MAX);
+        f.render_widget(self.paragraph.clone().scroll((scroll, 0)), area);
+    }
+
+    /// The furthest the page can be scrolled, leaving its last line at the
+    /// bottom of the panel
+    fn max_scroll(&self) -> usize {
+        self.line_count
+            .saturating_sub(usize::from(self.height.get().saturating_sub(2)))
+    }
+
+    /// Scroll the page, stopping at the ends rather than wrapping
+    fn scroll(&mut self, direction: MovementDirection) {
+        let max_scroll = self.max_scroll();
+        self.scroll = direction.apply(
+            self.scroll.min(max_scroll),
+            max_scroll + 1,
+            self.height.get(),
+            false,
+        );
+    }
+
+    /// Scroll so that a line is at the top, or as near as it can be
+    fn scroll_to(&mut self, line: usize) {
+        self.scroll = line.min(self.max_scroll());
+    }
+}
+
+/// A track as shown in the info panel: "Artist — Title [Album]", leaving out
+/// the artist and album where they aren't known, and falling back on the file
+/// path without a title
+fn now_playing(track: &Track) -> String {
+    let mut text = String::new();
+    if track.artist != "Unknown" {
+        text.push_str(&track.artist);
+        text.push_str(" — ");
+    }
+    text.push_str(track.title.as_deref().unwrap_or(&track.file_path));
+    if track.album != "Unknown" {
+        text.push_str(&format!(" [{}]", track.album));
+    }
+    text
+}
+
+/// The scrolling of the now-playing text across the info panel
+struct Marquee {
+    /// Whether the text scrolls at all
+    enabled: bool,
+
+    /// How many characters the text has scrolled by
+    offset: usize,
+
+    /// When the text last moved
+    last_step: Instant,
+
+    /// The file of the track the text is for, so that a new track starts
+    /// from the beginning
+    track: Option<String>,
+}
+
+impl Marquee {
+    /// How long the text waits between moving one character
+    const STEP: Duration = Duration::from_millis(300);
+
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            offset: 0,
+            last_step: Instant::now(),
+            track: None,
+        }
+    }
+}
+
+/// Restrictions on which of the library's tracks the main screen shows
+#[derive(Clone, Default)]
+struct TrackFilter {
+    /// Only show tracks of this genre, which matches ignoring case
+    genre: Option<String>,
+
+    /// Only show tracks rated at least this many stars
+    min_rating: Option<u8>,
+}
+
+impl TrackFilter {
+    /// Whether the filter restricts the tracks at all
+    const fn is_active(&self) -> bool {
+        self.genre.is_some() || self.min_rating.is_some()
+    }
+
+    fn matches(&self, track: &Track) -> bool {
+        self.genre.as_deref().is_none_or(|genre| {
+            track
+                .genre
+                .as_deref()
+                .is_some_and(|g| g.eq_ignore_ascii_case(genre))
+        }) && self
+            .min_rating
+            .is_none_or(|min| track.rating.is_some_and(|rating| rating >= min))
+    }
+
+    /// The library's tracks that pass the filter
+    fn apply(&self, library: &Library) -> Playlist {
+        let mut tracks = library.tracks.clone();
+        tracks.tracks.retain(|t| self.matches(t));
+        tracks
+    }
 }
 
 pub struct UI<'a> {
@@ -76,14 +422,33 @@ pub struct UI<'a> {
 
     help_screen: HelpScreen<'a>,
 
+    tree_screen: TreeScreen<'a>,
+
+    history_screen: HistoryScreen<'a>,
+
+    station_screen: StationScreen<'a>,
+
+    error_screen: ErrorScreen<'a>,
+
+    config_screen: ConfigScreen<'a>,
+
+    lyrics_screen: LyricsScreen,
+
     /// Playback progress bar
     playback_bar: Gauge<'a>,
 
     /// The current screen
     screen: ScreenEnum,
 
-    /// Base widget style
-    normal_style: Style,
+    /// The screen the lyrics screen was opened from, to go back to when it
+    /// is toggled off
+    screen_before_lyrics: ScreenEnum,
+
+    /// Styles of the panels and command line
+    theme: Theme,
+
+    /// Progress bar style while playing
+    gauge_style: Style,
 
     /// Highlight style for the currently selected panel
     highlight_selected: Style,
@@ -91,13 +456,55 @@ pub struct UI<'a> {
     /// Highlight style for the unselected panels
     highlight_unselected: Style,
 
-    selected_playlist_index: Option<usize>,
+    /// Progress bar style while playback is paused
+    paused_style: Style,
 
     pub library: Library,
 
+    /// Whether the library screens are still waiting to be built from the
+    /// library
+    loading: bool,
+
+    /// Whether the first-run guide has been closed
+    guide_dismissed: bool,
+
+    /// Whether single steps wrap around the ends of lists
+    wrap_navigation: bool,
+
+    /// Measures the audio for the bars under the current screen, if the
+    /// visualizer is enabled
+    visualizer: Option<Visualizer>,
+
+    /// How far the now-playing text has scrolled
+    marquee: Marquee,
+
+    /// Finds albums' covers for the main screen, if the terminal can draw
+    /// them
+    covers: Option<CoverLoader>,
+
+    /// Which of the library's tracks the main screen shows
+    filter: TrackFilter,
+
     pub command_line: CommandLine<'a>,
+
+    /// Popup for seeking to a typed timecode
+    pub goto_time: GotoTimeOverlay<'a>,
+
+    /// When and where the left mouse button was last pressed, for detecting
+    /// double clicks
+    last_click: Option<(Instant, u16, u16)>,
 }
 
+/// The width of the panel showing the playback state and current track,
+/// borders included
+const INFO_WIDTH: u16 = 26;
+
+/// The height of the visualizer panel, borders included
+const VISUALIZER_HEIGHT: u16 = 8;
+
+/// The most time between the clicks of a double click
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
+
 const NF_PLAY: char = '\u{f040a}';
 const NF_PAUSE: char = '\u{f03e4}';
 const NF_SHUFFLE: char = '\u{f049d}';
@@ -105,13 +512,78 @@ const NF_SHUFFLE_OFF: char = '\u{f049e}';
 const NF_REPEAT: char = '\u{f0456}';
 const NF_REPEAT_OFF: char = '\u{f0457}';
 const NF_REPEAT_ONCE: char = '\u{f0458}';
+const NF_MUTE: char = '\u{f075f}';
 
 impl<'a> UI<'a> {
     /// Create a new UI object, constructing the artist, album, and track lists
     /// from the given library.
-    pub fn new(library: &'a Library, config: &'a Config, playlists: &[Playlist]) -> Self {
+    pub fn new(
+        library: &'a Library,
+        config: &Config,
+        playlists: &[Playlist],
+        stations: &[Station],
+    ) -> Self {
         use ScreenEnum::Main;
 
+        let (theme, highlight_selected, highlight_unselected, gauge_style, paused_style) =
+            Self::build_styles(config);
+
+        // When deferring, start with empty library screens until the first
+        // frame has been drawn
+        let loading = config.get_option(&ConfOption::DeferLibraryLoad);
+        let empty_library = Library::new();
+        let screen_library = if loading { &empty_library } else { library };
+
+        let playback_bar = Gauge::default()
+            .block(theme.border_block())
+            .gauge_style(gauge_style)
+            .ratio(0.0)
+            .label("--:--/--:--");
+
+        // Construct and configure UI
+        let mut ui = Self {
+            main_screen: MainScreen::new(
+                &screen_library.tracks,
+                &theme,
+                config.column_layout(),
+                config.match_mode,
+            ),
+            playlist_screen: PlaylistScreen::new(playlists, &theme),
+            help_screen: HelpScreen::new(config, &theme),
+            tree_screen: TreeScreen::new(screen_library, &theme),
+            history_screen: HistoryScreen::new(&theme),
+            station_screen: StationScreen::new(stations, &theme),
+            error_screen: ErrorScreen::new(&theme),
+            config_screen: ConfigScreen::new(config, &theme),
+            lyrics_screen: LyricsScreen::new(&theme),
+            playback_bar,
+            screen: Main,
+            screen_before_lyrics: Main,
+            theme,
+            gauge_style,
+            highlight_selected,
+            highlight_unselected,
+            paused_style,
+            library: library.clone(),
+            loading,
+            guide_dismissed: false,
+            wrap_navigation: config.get_option(&ConfOption::WrapNavigation),
+            marquee: Marquee::new(config.get_option(&ConfOption::MarqueeNowPlaying)),
+            covers: cover::supported().then(CoverLoader::start),
+            visualizer: None,
+            filter: TrackFilter::default(),
+            command_line: CommandLine::default(),
+            goto_time: GotoTimeOverlay::default(),
+            last_click: None,
+        };
+
+        ui.style_panels();
+        ui
+    }
+
+    /// Build the normal, selected highlight, unselected highlight, and paused
+    /// progress bar styles from the configured colors
+    fn build_styles(config: &Config) -> (Theme, Style, Style, Style, Style) {
         let mut normal_style = Style::default();
         if let Some(bg_color) = config.colors.get(&UiColor::Bg) {
             normal_style = normal_style.bg(*bg_color);
@@ -138,34 +610,235 @@ impl<'a> UI<'a> {
             highlight_unselected = highlight_unselected.fg(*off_panel_highlight_color);
         }
 
-        let playback_bar = Gauge::default()
-            .block(Block::default().borders(Borders::ALL))
-            .gauge_style(normal_style)
-            .ratio(0.0)
-            .label("--:--/--:--");
+        // The gauge fills with its foreground color over its background color
+        let mut gauge_style = normal_style;
+        if let Some(gauge_filled_color) = config.colors.get(&UiColor::GaugeFilled) {
+            gauge_style = gauge_style.fg(*gauge_filled_color);
+        }
+        if let Some(gauge_empty_color) = config.colors.get(&UiColor::GaugeEmpty) {
+            gauge_style = gauge_style.bg(*gauge_empty_color);
+        }
 
-        // Construct and configure UI
-        let mut ui = Self {
-            main_screen: MainScreen::new(library, &normal_style),
-            playlist_screen: PlaylistScreen::new(playlists, &normal_style),
-            help_screen: HelpScreen::new(config, &normal_style),
-            playback_bar,
-            screen: Main,
-            normal_style,
+        let mut paused_style = gauge_style;
+        if let Some(gauge_paused_color) = config.colors.get(&UiColor::GaugePaused) {
+            paused_style = paused_style.fg(*gauge_paused_color);
+        }
+
+        // Borders, titles, and the prompt are drawn over the normal style, so
+        // without their own colors they look as before
+        let mut border_style = Style::default();
+        if let Some(border_color) = config.colors.get(&UiColor::Border) {
+            border_style = border_style.fg(*border_color);
+        }
+        let mut title_style = Style::default();
+        if let Some(title_color) = config.colors.get(&UiColor::Title) {
+            title_style = title_style.fg(*title_color);
+        }
+        let mut prompt_style = normal_style.add_modifier(Modifier::BOLD);
+        if let Some(prompt_color) = config.colors.get(&UiColor::CommandPrompt) {
+            prompt_style = prompt_style.fg(*prompt_color);
+        }
+
+        (
+            Theme {
+                normal: normal_style,
+                border: border_style,
+                title: title_style,
+                prompt: prompt_style,
+            },
             highlight_selected,
             highlight_unselected,
-            selected_playlist_index: None,
-            library: library.clone(),
-            command_line: CommandLine::default(),
-        };
+            gauge_style,
+            paused_style,
+        )
+    }
 
-        ui.style_panels();
-        ui
+    /// Move the now-playing text on if it is time to, starting again from the
+    /// beginning when the track changes. Returns whether it moved and needs
+    /// drawing.
+    pub fn step_marquee(&mut self, track: Option<&str>) -> bool {
+        let marquee = &mut self.marquee;
+        if !marquee.enabled {
+            return false;
+        }
+        if marquee.track.as_deref() != track {
+            marquee.track = track.map(str::to_owned);
+            marquee.offset = 0;
+            marquee.last_step = Instant::now();
+            return true;
+        }
+        if track.is_none() || marquee.last_step.elapsed() < Marquee::STEP {
+            return false;
+        }
+        marquee.offset = marquee.offset.wrapping_add(1);
+        marquee.last_step = Instant::now();
+        true
     }
 
+    /// Apply a reloaded config, rebuilding the styles and help screen and
+    /// picking up the main screen's column thresholds and search match mode.
+    /// Keybinds and options are read from the config as they are used.
+    pub fn apply_config(&mut self, config: &Config) {
+        (
+            self.theme,
+            self.highlight_selected,
+            self.highlight_unselected,
+            self.gauge_style,
+            self.paused_style,
+        ) = Self::build_styles(config);
+
+        self.wrap_navigation = config.get_option(&ConfOption::WrapNavigation);
+        self.marquee.enabled = config.get_option(&ConfOption::MarqueeNowPlaying);
+        self.playback_bar = self
+            .playback_bar
+            .clone()
+            .block(self.theme.border_block())
+            .gauge_style(self.gauge_style);
+        self.help_screen = HelpScreen::new(config, &self.theme);
+        self.config_screen = ConfigScreen::new(config, &self.theme);
+        self.lyrics_screen.update_lists(&self.theme);
+
+        let selection = self.main_screen.selection();
+        let track_sort = self.main_screen.track_sort;
+        self.main_screen = MainScreen::new(
+            &self.filter.apply(&self.library),
+            &self.theme,
+            config.column_layout(),
+            config.match_mode,
+        );
+        self.main_screen.track_sort = track_sort;
+        self.main_screen.restore_selection(&selection, &self.theme);
+
+        self.playlist_screen.playlist_list.display = self
+            .playlist_screen
+            .playlist_list
+            .display
+            .clone()
+            .style(self.theme.normal);
+        self.playlist_screen.update_lists(&self.theme);
+        self.tree_screen.update_lists(&self.theme);
+        self.style_panels();
+    }
+
+    /// Whether the first-run guide is shown in place of the main screen. It
+    /// is shown until it is dismissed or there is something to play.
+    pub fn showing_guide(&self) -> bool {
+        !self.guide_dismissed
+            && self.library.tracks.tracks.is_empty()
+            && self.playlist_screen.playlist_list.list.is_empty()
+    }
+
+    /// Draw bars from a visualizer's measurements, or stop drawing them
+    pub fn set_visualizer(&mut self, visualizer: Option<Visualizer>) {
+        self.visualizer = visualizer;
+    }
+
+    /// Whether the visualizer's bars are being drawn
+    pub const fn showing_visualizer(&self) -> bool {
+        self.visualizer.is_some()
+    }
+
+    /// Close the first-run guide for the rest of the session
+    pub fn dismiss_guide(&mut self) {
+        self.guide_dismissed = true;
+    }
+
+    /// If the library screens were deferred, sort the library and build them.
+    /// Returns whether they were built.
+    pub fn finish_loading(&mut self) -> bool {
+        if !self.loading {
+            return false;
+        }
+        self.loading = false;
+        let mut library = mem::replace(&mut self.library, Library::new());
+        library.sort();
+        self.update_library(library);
+        true
+    }
+
+    /// Rebuild the main screen from the given library, keeping the current
+    /// selections as close as possible.
     pub fn update_library(&mut self, library: Library) {
-        self.main_screen = MainScreen::new(&library, &self.normal_style);
+        let selection = self.main_screen.selection();
+        let track_sort = self.main_screen.track_sort;
+        self.main_screen = MainScreen::new(
+            &self.filter.apply(&library),
+            &self.theme,
+            mem::take(&mut self.main_screen.column_layout),
+            self.main_screen.match_mode,
+        );
+        self.main_screen.track_sort = track_sort;
+        self.main_screen.restore_selection(&selection, &self.theme);
+        self.tree_screen = TreeScreen::new(&library, &self.theme);
+
+        // Smart playlists follow the library
+        let mut smart_changed = false;
+        for playlist in &mut self.playlist_screen.playlist_list.list {
+            if let Some(query) = &playlist.query {
+                playlist.tracks = query.matching(&library);
+                smart_changed = true;
+            }
+        }
         self.library = library;
+        if smart_changed {
+            self.update_playlist_list();
+        }
+        self.style_panels();
+    }
+
+    /// Restrict the main screen to the tracks of a genre, or lift the
+    /// restriction if none is given
+    pub fn set_genre_filter(&mut self, genre: Option<String>) -> Result<()> {
+        self.set_track_filter(TrackFilter {
+            genre,
+            ..self.filter.clone()
+        })
+    }
+
+    /// Restrict the main screen to tracks rated at least the given number of
+    /// stars, or lift the restriction if none is given
+    pub fn set_rating_filter(&mut self, min_rating: Option<u8>) -> Result<()> {
+        self.set_track_filter(TrackFilter {
+            min_rating,
+            ..self.filter.clone()
+        })
+    }
+
+    /// Show the whole library on the main screen again
+    pub fn clear_filters(&mut self) {
+        // The whole library is never filtered out, so this can't fail
+        let _ = self.set_track_filter(TrackFilter::default());
+    }
+
+    /// Replace the main screen's filter. Fails, leaving the main screen as it
+    /// was, if no tracks pass the new filter.
+    fn set_track_filter(&mut self, filter: TrackFilter) -> Result<()> {
+        let tracks = filter.apply(&self.library);
+        if filter.is_active() && tracks.tracks.is_empty() {
+            return Err(anyhow!("filter: No tracks match"));
+        }
+        self.filter = filter;
+
+        let selection = self.main_screen.selection();
+        let track_sort = self.main_screen.track_sort;
+        self.main_screen = MainScreen::new(
+            &tracks,
+            &self.theme,
+            mem::take(&mut self.main_screen.column_layout),
+            self.main_screen.match_mode,
+        );
+        self.main_screen.track_sort = track_sort;
+        self.main_screen.restore_selection(&selection, &self.theme);
+        self.style_panels();
+        Ok(())
+    }
+
+    /// The track the selection points at, if it is a single track
+    pub fn selected_track(&self) -> Option<Track> {
+        match self.get_selected(true) {
+            Queueable::TrackList(tracks) if tracks.len() == 1 => Some(tracks[0].clone()),
+            _ => None,
+        }
     }
 
     /// Set the selection highlight for each panel based on which one is
@@ -181,6 +854,24 @@ impl<'a> UI<'a> {
             ScreenEnum::Help => self
                 .help_screen
                 .style_panels(&self.highlight_selected, &self.highlight_unselected),
+            ScreenEnum::Tree => self
+                .tree_screen
+                .style_panels(&self.highlight_selected, &self.highlight_unselected),
+            ScreenEnum::History => self
+                .history_screen
+                .style_panels(&self.highlight_selected, &self.highlight_unselected),
+            ScreenEnum::Stations => self
+                .station_screen
+                .style_panels(&self.highlight_selected, &self.highlight_unselected),
+            ScreenEnum::Errors => self
+                .error_screen
+                .style_panels(&self.highlight_selected, &self.highlight_unselected),
+            ScreenEnum::Config => self
+                .config_screen
+                .style_panels(&self.highlight_selected, &self.highlight_unselected),
+            ScreenEnum::Lyrics => self
+                .lyrics_screen
+                .style_panels(&self.highlight_selected, &self.highlight_unselected),
         }
     }
 
@@ -189,13 +880,33 @@ impl<'a> UI<'a> {
         &self,
         terminal: &mut Terminal<B>,
         media_state: &Arc<Mutex<MediaState>>,
+        queue_position: Option<(usize, usize)>,
         config: &Config,
         mode: &Mode,
     ) -> Result<()> {
-        use ScreenEnum::{Help, Main, Playlists};
+        use ScreenEnum::{Config, Errors, Help, History, Lyrics, Main, Playlists, Stations, Tree};
 
-        let playback_bar = Self::build_playback_bar(self.playback_bar.clone(), media_state).await;
-        let info_widget = Self::build_info_widget(self.normal_style, media_state, config).await;
+        let playback_bar = Self::build_playback_bar(
+            self.playback_bar.clone(),
+            &*media_state.lock().await,
+            self.gauge_style,
+            self.paused_style,
+        );
+        let marquee_offset = self.marquee.enabled.then_some(self.marquee.offset);
+        let info_widget = Self::build_info_widget(
+            &self.theme,
+            media_state,
+            queue_position,
+            marquee_offset,
+            config,
+        )
+        .await;
+        // The bars drop to nothing while playback is paused or stopped
+        let visualizer = match &self.visualizer {
+            Some(visualizer) if media_state.lock().await.playing => Some(visualizer.bands()),
+            Some(_) => Some([0.0; BAND_COUNT]),
+            None => None,
+        };
 
         terminal.draw(|f| {
             let chunks = Layout::default()
@@ -209,46 +920,103 @@ impl<'a> UI<'a> {
                     .as_ref(),
                 )
                 .split(f.area());
+            let page_chunk = if let Some(bands) = visualizer {
+                let page_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(3), Constraint::Length(VISUALIZER_HEIGHT)])
+                    .split(chunks[0]);
+                f.render_widget(
+                    self.build_visualizer(&bands, page_chunks[1]),
+                    page_chunks[1],
+                );
+                page_chunks[0]
+            } else {
+                chunks[0]
+            };
             match &self.screen {
-                Main => self.main_screen.ui(f, chunks[0]),
-                Playlists => self.playlist_screen.ui(f, chunks[0]),
-                Help => self.help_screen.ui(f, chunks[0]),
+                Main | Tree if self.loading => f.render_widget(
+                    Paragraph::new("Loading library...")
+                        .block(self.theme.border_block())
+                        .style(self.theme.normal),
+                    page_chunk,
+                ),
+                Main if self.showing_guide() => {
+                    f.render_widget(self.help_screen.guide.clone(), page_chunk);
+                }
+                Main | Tree if self.library.tracks.tracks.is_empty() => f.render_widget(
+                    Paragraph::new("Your library is empty — press : then 'add <dir>'")
+                        .block(self.theme.border_block())
+                        .style(self.theme.normal),
+                    page_chunk,
+                ),
+                Main => self.main_screen.ui(f, page_chunk),
+                Playlists => self.playlist_screen.ui(f, page_chunk),
+                Help => self.help_screen.ui(f, page_chunk),
+                Tree => self.tree_screen.ui(f, page_chunk),
+                History => self.history_screen.ui(f, page_chunk),
+                Stations => self.station_screen.ui(f, page_chunk),
+                Errors => self.error_screen.ui(f, page_chunk),
+                Config => self.config_screen.ui(f, page_chunk),
+                Lyrics => self.lyrics_screen.ui(f, page_chunk),
             }
             let playback_chunk = Layout::default()
                 .direction(Direction::Horizontal)
-                .constraints([Constraint::Length(26), Constraint::Min(3)].as_ref())
+                .constraints([Constraint::Length(INFO_WIDTH), Constraint::Min(3)].as_ref())
                 .split(chunks[1]);
             f.render_widget(info_widget, playback_chunk[0]);
             f.render_widget(playback_bar, playback_chunk[1]);
             let cursor = match mode {
                 Mode::Normal => false,
-                Mode::PlaylistEntry | Mode::CommandEntry => true,
+                Mode::PlaylistEntry
+                | Mode::QueueSaveEntry
+                | Mode::CommandEntry
+                | Mode::SearchEntry => true,
+                Mode::GotoTimeEntry => false,
             };
-            self.command_line
-                .render(f, chunks[2], cursor, &self.normal_style);
+            self.command_line.render(f, chunks[2], cursor, &self.theme);
+            if let Mode::GotoTimeEntry = mode {
+                self.goto_time.render(f, chunks[0], &self.theme);
+            }
         })?;
 
         Ok(())
     }
 
     async fn build_info_widget(
-        normal_style: Style,
+        theme: &Theme,
         media_state: &Arc<Mutex<MediaState>>,
+        queue_position: Option<(usize, usize)>,
+        marquee_offset: Option<usize>,
         config: &Config,
     ) -> Paragraph<'a> {
-        let default_config = Config::default();
-        let nerd_font_icons = *config
-            .options
-            .get(&ConfOption::NerdFontIcons)
-            .unwrap_or_else(|| {
-                default_config
-                    .options
-                    .get(&ConfOption::NerdFontIcons)
-                    .expect("Default Config has NerdFontIcons option set")
-            });
+        let nerd_font_icons = config.get_option(&ConfOption::NerdFontIcons);
         let guard = media_state.lock().await;
-        let playback_info = format!(
-            " {} {} {} | {}",
+        let stop_after = guard
+            .stop_after
+            .map_or_else(String::new, |count| format!(" {count}"));
+        // The sleep timer's time left, in whole minutes rounded up
+        let sleep = guard.sleep_deadline.map_or_else(String::new, |deadline| {
+            let left = deadline
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            format!(" z{}m", left.as_secs().div_ceil(60))
+        });
+        let ab_loop = match (guard.loop_a, guard.loop_b) {
+            (Some(_), Some(_)) => " A-B",
+            (Some(_), None) => " A-",
+            _ => "",
+        };
+        let mute = match (guard.muted, nerd_font_icons) {
+            (true, true) => format!(" {NF_MUTE}"),
+            (true, false) => " M".to_owned(),
+            (false, _) => String::new(),
+        };
+        let position = queue_position.map_or_else(
+            || "--/--".to_owned(),
+            |(position, total)| format!("{position}/{total}"),
+        );
+        let status = format!(
+            " {} {} {}{}{}{}{} {} | ",
             match guard.repeat {
                 Repeat::On =>
                     if nerd_font_icons {
@@ -291,56 +1059,97 @@ impl<'a> UI<'a> {
             } else {
                 '-'
             },
-            guard
-                .current_track
-                .as_ref()
-                .map_or("Not Playing", |track| track
-                    .title
-                    .as_ref()
-                    .map_or(&track.file_path, |title| title))
+            mute,
+            stop_after,
+            sleep,
+            ab_loop,
+            position,
         );
+        // Whatever is left of the panel inside its borders
+        let available = usize::from(INFO_WIDTH - 2).saturating_sub(status.width());
+        let now_playing = guard
+            .current_track
+            .as_ref()
+            .map_or_else(|| "Not Playing".to_owned(), now_playing);
+        let now_playing = match marquee_offset {
+            Some(offset) => scroll_text(&now_playing, offset, available),
+            None => to_width(&now_playing, available, false),
+        };
+        let playback_info = format!("{status}{now_playing}");
         let info_text = Text::from(playback_info);
         let info_widget = Paragraph::new(info_text)
-            .block(Block::default().borders(Borders::ALL))
-            .style(normal_style);
+            .block(theme.border_block())
+            .style(theme.normal);
         info_widget
     }
 
-    async fn build_playback_bar(
+    /// Build the visualizer's bars, spread across the width of its panel
+    fn build_visualizer(&self, bands: &[f32; BAND_COUNT], area: Rect) -> BarChart<'a> {
+        let inner_width = area.width.saturating_sub(2);
+        let gap = 1;
+        #[allow(clippy::cast_possible_truncation)]
+        let bar_width = (inner_width / BAND_COUNT as u16).saturating_sub(gap).max(1);
+        let bars: Vec<Bar> = bands
+            .iter()
+            .map(|level| {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                Bar::default()
+                    .value((level * 100.0).round() as u64)
+                    .text_value(String::new())
+            })
+            .collect();
+        BarChart::default()
+            .block(self.theme.border_block())
+            .style(self.theme.normal)
+            .bar_style(self.gauge_style)
+            .bar_width(bar_width)
+            .bar_gap(gap)
+            .max(100)
+            .data(BarGroup::default().bars(&bars))
+    }
+
+    /// Build the progress bar. While a track is paused, the bar takes on the
+    /// paused style and its label is marked with a pause symbol.
+    fn build_playback_bar(
         playback_bar: Gauge<'a>,
-        media_state: &Arc<Mutex<MediaState>>,
+        state: &MediaState,
+        gauge_style: Style,
+        paused_style: Style,
     ) -> Gauge<'a> {
-        let guard = media_state.lock().await;
+        let paused = state.current_track.is_some() && !state.playing && !state.stopped;
+        let length = state
+            .current_track
+            .as_ref()
+            .map(|track| track.length)
+            .filter(|length| !length.is_zero());
+        let hours = length.is_some_and(|length| length.as_secs() >= 3600);
+        let progress = state.current_track_progress.map_or_else(
+            || "--:--".to_owned(),
+            |progress| match length {
+                Some(length) if state.show_remaining => {
+                    format!("-{}", format_clock(length.saturating_sub(progress), hours))
+                }
+                _ => format_clock(progress, hours),
+            },
+        );
+        // Tracks of unknown length, like streams, only show the time elapsed
+        let length = match (&state.current_track, length) {
+            (None, _) => "/--:--".to_owned(),
+            (Some(_), None) => String::new(),
+            (Some(_), Some(length)) => format!("/{}", format_clock(length, hours)),
+        };
         playback_bar
+            .gauge_style(if paused { paused_style } else { gauge_style })
             .label(format!(
-                "{}:{}/{}:{}",
-                // Minutes into playback
-                guard.current_track_progress.map_or_else(
-                    || "--".to_owned(),
-                    |duration| format!("{:02}", duration.as_secs() / 60)
-                ),
-                // Seconds into playback
-                guard.current_track_progress.map_or_else(
-                    || "--".to_owned(),
-                    |duration| format!("{:02}", duration.as_secs() % 60)
-                ),
-                // Track length minutes
-                guard.current_track.as_ref().map_or_else(
-                    || "--".to_owned(),
-                    |track| format!("{:02}", track.length.as_secs() / 60)
-                ),
-                // Track length seconds
-                guard.current_track.as_ref().map_or_else(
-                    || "--".to_owned(),
-                    |track| format!("{:02}", track.length.as_secs() % 60)
-                ),
+                "{}{progress}{length}",
+                if paused { "⏸ " } else { "" },
             ))
             .ratio(
                 if let (Some(progress), Some(track)) =
-                    (guard.current_track_progress, &guard.current_track)
+                    (state.current_track_progress, &state.current_track)
                 {
                     let ratio = progress.as_secs_f64() / track.length.as_secs_f64();
-                    if ratio < 0.0 || ratio.is_nan() {
+                    if ratio < 0.0 || !ratio.is_finite() {
                         0.0
                     } else if ratio > 1.0 {
                         1.0
@@ -353,13 +1162,71 @@ impl<'a> UI<'a> {
             )
     }
 
-    /// Move the selection up or down the list in the current panel, cycling
-    /// back around after the beginning or end of the list.
+    /// Move the selection up or down the list in the current panel. Single
+    /// steps cycle back around after the beginning or end of the list unless
+    /// the `WrapNavigation` option is off.
     pub fn switch_item(&mut self, direction: MovementDirection) {
+        let wrap = self.wrap_navigation;
+        match self.screen {
+            ScreenEnum::Main => self.main_screen.switch_item(direction, wrap),
+            ScreenEnum::Playlists => self.playlist_screen.switch_item(direction, wrap),
+            ScreenEnum::Help => self.help_screen.switch_item(direction, wrap),
+            ScreenEnum::Tree => self.tree_screen.switch_item(direction, wrap),
+            ScreenEnum::History => self.history_screen.switch_item(direction, wrap),
+            ScreenEnum::Stations => self.station_screen.switch_item(direction, wrap),
+            ScreenEnum::Errors => self.error_screen.switch_item(direction, wrap),
+            ScreenEnum::Config => self.config_screen.switch_item(direction, wrap),
+            ScreenEnum::Lyrics => self.lyrics_screen.switch_item(direction, wrap),
+        }
+    }
+
+    /// Handle a mouse event, returning the command it runs. Clicking selects
+    /// an item, double clicking plays it, and scrolling moves the selection.
+    pub fn handle_mouse(&mut self, me: MouseEvent) -> Command {
+        match me.kind {
+            MouseEventKind::ScrollDown => Command::Down,
+            MouseEventKind::ScrollUp => Command::Up,
+            MouseEventKind::Down(MouseButton::Left) => {
+                let command = match self.screen {
+                    ScreenEnum::Main => self.main_screen.handle_click(me.column, me.row),
+                    ScreenEnum::Playlists => self.playlist_screen.handle_click(me.column, me.row),
+                    ScreenEnum::Help => self.help_screen.handle_click(me.column, me.row),
+                    ScreenEnum::Tree => self.tree_screen.handle_click(me.column, me.row),
+                    ScreenEnum::History => self.history_screen.handle_click(me.column, me.row),
+                    ScreenEnum::Stations => self.station_screen.handle_click(me.column, me.row),
+                    ScreenEnum::Errors => self.error_screen.handle_click(me.column, me.row),
+                    ScreenEnum::Config => self.config_screen.handle_click(me.column, me.row),
+                    ScreenEnum::Lyrics => self.lyrics_screen.handle_click(me.column, me.row),
+                };
+                self.style_panels();
+
+                let double_click = self.last_click.is_some_and(|(at, col, row)| {
+                    at.elapsed() < DOUBLE_CLICK_TIME && (col, row) == (me.column, me.row)
+                });
+                if double_click {
+                    self.last_click = None;
+                    command.unwrap_or(Command::Nop)
+                } else {
+                    self.last_click = Some((Instant::now(), me.column, me.row));
+                    Command::Nop
+                }
+            }
+            _ => Command::Nop,
+        }
+    }
+
+    /// Select the item at an index in the current panel's list
+    pub fn goto_index(&mut self, index: usize) {
         match self.screen {
-            ScreenEnum::Main => self.main_screen.switch_item(direction),
-            ScreenEnum::Playlists => self.playlist_screen.switch_item(direction),
-            ScreenEnum::Help => self.help_screen.switch_item(direction),
+            ScreenEnum::Main => self.main_screen.goto_index(index),
+            ScreenEnum::Playlists => self.playlist_screen.goto_index(index),
+            ScreenEnum::Help => self.help_screen.goto_index(index),
+            ScreenEnum::Tree => self.tree_screen.goto_index(index),
+            ScreenEnum::History => self.history_screen.goto_index(index),
+            ScreenEnum::Stations => self.station_screen.goto_index(index),
+            ScreenEnum::Errors => self.error_screen.goto_index(index),
+            ScreenEnum::Config => self.config_screen.goto_index(index),
+            ScreenEnum::Lyrics => self.lyrics_screen.goto_index(index),
         }
     }
 
@@ -369,6 +1236,12 @@ impl<'a> UI<'a> {
             ScreenEnum::Main => self.main_screen.switch_panel(direction),
             ScreenEnum::Playlists => self.playlist_screen.switch_panel(direction),
             ScreenEnum::Help => self.help_screen.switch_panel(direction),
+            ScreenEnum::Tree => self.tree_screen.switch_panel(direction),
+            ScreenEnum::History => self.history_screen.switch_panel(direction),
+            ScreenEnum::Stations => self.station_screen.switch_panel(direction),
+            ScreenEnum::Errors => self.error_screen.switch_panel(direction),
+            ScreenEnum::Config => self.config_screen.switch_panel(direction),
+            ScreenEnum::Lyrics => self.lyrics_screen.switch_panel(direction),
         }
         self.style_panels();
     }
@@ -380,13 +1253,93 @@ impl<'a> UI<'a> {
         }
     }
 
+    /// Switch to the lyrics screen, or back to the screen it was opened from
+    pub fn toggle_lyrics(&mut self) {
+        if self.screen == ScreenEnum::Lyrics {
+            self.switch_screen(self.screen_before_lyrics);
+        } else {
+            self.screen_before_lyrics = self.screen;
+            self.switch_screen(ScreenEnum::Lyrics);
+        }
+    }
+
+    /// Keep the lyrics screen up to date with the playing track, while it is
+    /// shown. Returns whether it changed and needs drawing.
+    pub fn follow_lyrics(&mut self, track: Option<&Track>, progress: Option<Duration>) -> bool {
+        self.screen == ScreenEnum::Lyrics && self.lyrics_screen.follow(track, progress)
+    }
+
+    /// Show the cover of the album selected on the main screen, once it has
+    /// been found. Returns whether the cover shown changed.
+    pub fn follow_cover(&mut self) -> bool {
+        let Some(covers) = &mut self.covers else {
+            return false;
+        };
+        if self.screen != ScreenEnum::Main {
+            return false;
+        }
+        covers.receive();
+        let cover = self
+            .main_screen
+            .selected_album()
+            .and_then(|album| covers.get(album));
+        let changed = match (&cover, &self.main_screen.cover) {
+            (Some(cover), Some(shown)) => !Arc::ptr_eq(cover, shown),
+            (None, None) => false,
+            _ => true,
+        };
+        self.main_screen.cover = cover;
+        changed
+    }
+
+    /// Show the given recently played tracks, most recent first, keeping at
+    /// most `max` of them
+    pub fn update_history(&mut self, tracks: Vec<Track>, max: usize) {
+        self.history_screen.set_tracks(tracks, max, &self.theme);
+        self.style_panels();
+    }
+
+    /// Add an error to the log shown on the error screen
+    pub fn log_error(&mut self, error: String) {
+        self.error_screen.push(error, &self.theme);
+        self.style_panels();
+    }
+
+    /// The screen currently shown
+    pub const fn current_screen(&self) -> ScreenEnum {
+        self.screen
+    }
+
+    /// The track highlighted on the current screen if it is one whose
+    /// entries are played on their own rather than replacing the queue: the
+    /// history or station screen
+    pub fn standalone_track(&self) -> Option<Track> {
+        match self.screen {
+            ScreenEnum::History => self.history_screen.selected_track().cloned(),
+            ScreenEnum::Stations => self.station_screen.selected_station().map(Station::track),
+            _ => None,
+        }
+    }
+
+    /// Show the given radio stations
+    pub fn set_stations(&mut self, stations: &[Station]) {
+        self.station_screen.set_stations(stations, &self.theme);
+        self.style_panels();
+    }
+
     /// Change which album and track lists will be shown in the UI based on
     /// which artist and album list items are selected.
     pub fn update_lists(&mut self) {
         match self.screen {
-            ScreenEnum::Main => self.main_screen.update_lists(&self.normal_style),
-            ScreenEnum::Playlists => self.playlist_screen.update_lists(&self.normal_style),
-            ScreenEnum::Help => self.help_screen.update_lists(&self.normal_style),
+            ScreenEnum::Main => self.main_screen.update_lists(&self.theme),
+            ScreenEnum::Playlists => self.playlist_screen.update_lists(&self.theme),
+            ScreenEnum::Help => self.help_screen.update_lists(&self.theme),
+            ScreenEnum::Tree => self.tree_screen.update_lists(&self.theme),
+            ScreenEnum::History => self.history_screen.update_lists(&self.theme),
+            ScreenEnum::Stations => self.station_screen.update_lists(&self.theme),
+            ScreenEnum::Errors => self.error_screen.update_lists(&self.theme),
+            ScreenEnum::Config => self.config_screen.update_lists(&self.theme),
+            ScreenEnum::Lyrics => self.lyrics_screen.update_lists(&self.theme),
         }
 
         // Ensure panels are styled correctly after replacing them
@@ -403,6 +1356,12 @@ impl<'a> UI<'a> {
             ScreenEnum::Main => self.main_screen.get_selected(tracks_current_only),
             ScreenEnum::Playlists => self.playlist_screen.get_selected(tracks_current_only),
             ScreenEnum::Help => self.help_screen.get_selected(tracks_current_only),
+            ScreenEnum::Tree => self.tree_screen.get_selected(tracks_current_only),
+            ScreenEnum::History => self.history_screen.get_selected(tracks_current_only),
+            ScreenEnum::Stations => self.station_screen.get_selected(tracks_current_only),
+            ScreenEnum::Errors => self.error_screen.get_selected(tracks_current_only),
+            ScreenEnum::Config => self.config_screen.get_selected(tracks_current_only),
+            ScreenEnum::Lyrics => self.lyrics_screen.get_selected(tracks_current_only),
         }
     }
 
@@ -410,7 +1369,7 @@ impl<'a> UI<'a> {
     pub fn get_key_command(&self, ke: KeyEvent, config: &Config) -> Command {
         config
             .keybinds
-            .get(&ke.code)
+            .get(&ke.into())
             .map_or(Command::Nop, |command| {
                 let command = command.clone();
                 match (self.screen, &self.playlist_screen.panel, &command) {
@@ -419,6 +1378,12 @@ impl<'a> UI<'a> {
                         playlist_screen::Panel::Playlists,
                         Command::SelectPlaylist,
                     ) => command,
+                    // Enter expands collapsed rows of the tree
+                    (ScreenEnum::Tree, _, Command::QueueAndPlay)
+                        if self.tree_screen.selected_collapsed() =>
+                    {
+                        Command::Expand
+                    }
                     (
                         ScreenEnum::Playlists,
                         playlist_screen::Panel::Playlists,
@@ -435,21 +1400,92 @@ impl<'a> UI<'a> {
             .playlist_list
             .list
             .push(playlist.clone());
-        let listitems: Vec<ListItem> = self
+        self.update_playlist_list();
+    }
+
+    /// Add a smart playlist, evaluated against the library, or redefine the
+    /// smart playlist of the same name. Fails if a normal playlist has the
+    /// name.
+    pub fn set_smart_playlist(&mut self, smart_playlist: &SmartPlaylist) -> Result<()> {
+        let playlist = smart_playlist.evaluate(&self.library);
+        match self
             .playlist_screen
             .playlist_list
             .list
-            .iter()
-            .map(|pl| ListItem::new(pl.name.clone()))
-            .collect();
-        let list_display = List::new(listitems)
-            .block(Block::default().title("Playlists").borders(Borders::ALL))
-            .style(self.normal_style);
-        self.playlist_screen.playlist_list.display = list_display;
+            .iter_mut()
+            .find(|pl| pl.name == playlist.name)
+        {
+            Some(existing) if existing.query.is_some() => *existing = playlist,
+            Some(_) => {
+                return Err(anyhow!(
+                    "smart-playlist: Playlist already exists: {}",
+                    playlist.name
+                ))
+            }
+            None => self.playlist_screen.playlist_list.list.push(playlist),
+        }
+        self.update_playlist_list();
+        Ok(())
+    }
+
+    /// Restyle the playlist list and rebuild the track list of the
+    /// highlighted playlist
+    fn update_playlist_list(&mut self) {
+        self.playlist_screen.playlist_list.display = List::default()
+            .block(self.theme.block("Playlists"))
+            .style(self.theme.normal);
+        self.playlist_screen.update_lists(&self.theme);
+        self.style_panels();
+    }
+
+    /// Remove all tracks from the playlist highlighted in the playlist screen.
+    /// Returns the emptied playlist, if any, so that it can be saved.
+    pub fn clear_highlighted_playlist(&mut self) -> Option<&Playlist> {
+        let index = self.playlist_screen.playlist_list.state.selected()?;
+        if let Some(name) = self.smart_playlist_name(index) {
+            self.command_line
+                .show_message(&format!("clear-playlist: {name} is a smart playlist"));
+            return None;
+        }
+        self.playlist_screen
+            .playlist_list
+            .list
+            .get_mut(index)?
+            .tracks
+            .clear();
+        self.update_playlist_list();
+        self.playlist_screen.playlist_list.list.get(index)
+    }
+
+    /// Add a copy of the playlist highlighted in the playlist screen, named
+    /// "<name> (copy)", or "<name> (copy N)" if that name is taken. Returns
+    /// the copy, if any, so that it can be saved.
+    pub fn duplicate_highlighted_playlist(&mut self) -> Option<&Playlist> {
+        let index = self.playlist_screen.playlist_list.state.selected()?;
+        let mut copy = self.playlist_screen.playlist_list.list.get(index)?.clone();
+        // The copy is a fixed snapshot of a smart playlist's tracks, saved to
+        // a file of its own
+        copy.query = None;
+        copy.source = None;
+        let base_name = copy.name.clone();
+        copy.name = format!("{base_name} (copy)");
+        let mut n = 2;
+        while self.find_playlist(&copy.name).is_some() {
+            copy.name = format!("{base_name} (copy {n})");
+            n += 1;
+        }
+        self.add_playlist(&copy);
+        self.playlist_screen.playlist_list.list.last()
     }
 
     pub fn add_selected_to_playlist(&mut self) {
-        let message = if let Some(index) = self.selected_playlist_index {
+        let smart_name = self
+            .playlist_screen
+            .selected_playlist
+            .and_then(|index| self.smart_playlist_name(index));
+        let message = if let Some(name) = smart_name {
+            format!("Can't add tracks to smart playlist {name}")
+        } else if let Some(index) = self.playlist_screen.selected_playlist {
             let mut tracks = self.get_selected(true).get_tracks();
             if let Some(playlist) = self.playlist_screen.playlist_list.list.get_mut(index) {
                 let msg = if tracks.len() == 1 {
@@ -471,34 +1507,209 @@ impl<'a> UI<'a> {
         self.command_line.textarea.insert_str(&message);
     }
 
+    /// The name of the playlist at an index in the playlist list, if it is a
+    /// smart playlist
+    fn smart_playlist_name(&self, index: usize) -> Option<String> {
+        self.playlist_screen
+            .playlist_list
+            .list
+            .get(index)
+            .filter(|pl| pl.query.is_some())
+            .map(|pl| pl.name.clone())
+    }
+
     /// Set the currently highlighted item in the playlist list to the selected
     /// playlist.
     pub fn select_current_playlist(&mut self) {
         if let Some(new_index) = self.playlist_screen.playlist_list.state.selected() {
-            let listitems: Vec<ListItem> = self
-                .playlist_screen
-                .playlist_list
-                .list
-                .iter()
-                .enumerate()
-                .map(|(i, p)| {
-                    if i == new_index {
-                        ListItem::new(format!("*{}", p.name))
-                    } else {
-                        ListItem::new(p.name.clone())
-                    }
-                })
-                .collect();
-            let list_display = List::new(listitems)
-                .block(Block::default().title("Playlists").borders(Borders::ALL))
-                .style(self.normal_style);
-            self.playlist_screen.playlist_list.display = list_display;
-            self.selected_playlist_index = Some(new_index);
+            self.playlist_screen.selected_playlist = Some(new_index);
+            self.update_playlist_list();
+        }
+    }
+
+    /// Expand the selected row of the library tree
+    pub fn expand(&mut self) {
+        if self.screen == ScreenEnum::Tree {
+            self.tree_screen.expand(&self.theme);
+            self.style_panels();
+        }
+    }
+
+    /// Collapse the selected row of the library tree
+    pub fn collapse(&mut self) {
+        if self.screen == ScreenEnum::Tree {
+            self.tree_screen.collapse(&self.theme);
+            self.style_panels();
         }
     }
 
+    /// Filter the main screen's panels by the given search text
+    pub fn set_filter(&mut self, filter: &str) {
+        self.main_screen.set_filter(filter, &self.theme);
+        self.style_panels();
+    }
+
+    /// Set how search text is matched, or cycle to the next mode if none is
+    /// given. Returns the new mode.
+    pub fn set_match_mode(&mut self, match_mode: Option<MatchMode>) -> MatchMode {
+        let match_mode = match_mode.unwrap_or_else(|| self.main_screen.match_mode.next());
+        self.main_screen.set_match_mode(match_mode, &self.theme);
+        self.style_panels();
+        match_mode
+    }
+
+    /// Sort the main screen's track panel. Returns the direction used.
+    pub fn sort_tracks(&mut self, key: SortKey, direction: Option<SortDirection>) -> SortDirection {
+        let direction = self.main_screen.sort_tracks(key, direction, &self.theme);
+        self.style_panels();
+        direction
+    }
+
+    /// Get all of the tracks left visible by the main screen's filter
+    pub fn visible_tracks(&self) -> Queueable {
+        self.main_screen.visible_tracks()
+    }
+
+    /// Find a playlist by name
+    pub fn find_playlist(&self, name: &str) -> Option<&Playlist> {
+        self.playlist_screen
+            .playlist_list
+            .list
+            .iter()
+            .find(|pl| pl.name == name)
+    }
+
     pub fn selected_playlist(&self) -> Option<&Playlist> {
-        self.selected_playlist_index
+        self.playlist_screen
+            .selected_playlist
             .map(|index| &self.playlist_screen.playlist_list.list[index])
     }
 }
+
+/// Format a time in the playback bar as `MM:SS`, or `H:MM:SS` if `hours`
+fn format_clock(time: Duration, hours: bool) -> String {
+    let secs = time.as_secs();
+    if hours {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ratatui::{backend::TestBackend, buffer::Buffer, style::Color, widgets::Widget};
+
+    /// Draw the progress bar for the state and return its text and the
+    /// style of its first cell
+    fn draw_bar(state: &MediaState) -> (String, Style) {
+        let gauge = UI::build_playback_bar(
+            Gauge::default(),
+            state,
+            Style::default().fg(Color::White),
+            Style::default().fg(Color::Yellow),
+        );
+        let area = Rect::new(0, 0, 30, 1);
+        let mut buf = Buffer::empty(area);
+        gauge.render(area, &mut buf);
+        let text = (0..area.width).map(|x| buf[(x, 0)].symbol()).collect();
+        (text, buf[(0, 0)].style())
+    }
+
+    fn playing(playing: bool) -> MediaState {
+        MediaState {
+            current_track: Some(Track {
+                length: Duration::from_secs(200),
+                ..Track::from_url("/music/a.flac")
+            }),
+            current_track_progress: Some(Duration::from_secs(65)),
+            playing,
+            ..MediaState::default()
+        }
+    }
+
+    #[test]
+    fn paused_bar_is_marked_and_restyled() {
+        let (text, style) = draw_bar(&playing(false));
+        assert!(text.contains("⏸ 01:05/03:20"), "{text}");
+        assert_eq!(style.fg, Some(Color::Yellow));
+
+        let (text, style) = draw_bar(&playing(true));
+        assert!(
+            !text.contains('⏸') && text.contains("01:05/03:20"),
+            "{text}"
+        );
+        assert_eq!(style.fg, Some(Color::White));
+    }
+
+    #[test]
+    fn stopped_bar_is_not_marked_paused() {
+        let stopped = MediaState {
+            stopped: true,
+            ..playing(false)
+        };
+        assert!(!draw_bar(&stopped).0.contains('⏸'));
+        assert!(!draw_bar(&MediaState::default()).0.contains('⏸'));
+    }
+
+    #[test]
+    fn single_steps_wrap_only_when_asked() {
+        use MovementDirection::{Next, Prev};
+        assert_eq!(Prev.apply(0, 5, 10, true), 4);
+        assert_eq!(Prev.apply(0, 5, 10, false), 0);
+        assert_eq!(Next.apply(4, 5, 10, true), 0);
+        assert_eq!(Next.apply(4, 5, 10, false), 4);
+        assert_eq!(Next.apply(2, 5, 10, false), 3);
+        assert_eq!(Prev.apply(2, 5, 10, true), 1);
+    }
+
+    #[test]
+    fn page_steps_stop_at_the_ends() {
+        use MovementDirection::{Bottom, HalfDown, HalfUp, PageDown, PageUp, Top};
+        // 10 rows tall with borders leaves a page of 8
+        assert_eq!(PageDown.apply(0, 20, 10, true), 8);
+        assert_eq!(PageDown.apply(15, 20, 10, true), 19);
+        assert_eq!(PageUp.apply(5, 20, 10, true), 0);
+        assert_eq!(HalfDown.apply(0, 20, 10, true), 4);
+        assert_eq!(HalfUp.apply(2, 20, 10, true), 0);
+        assert_eq!(Top.apply(7, 20, 10, true), 0);
+        assert_eq!(Bottom.apply(7, 20, 10, true), 19);
+        // Panels too short to show a row still move a row at a time
+        assert_eq!(PageDown.apply(0, 20, 2, false), 1);
+        assert_eq!(HalfDown.apply(0, 20, 0, false), 1);
+    }
+
+    /// Draw a list in a terminal with room for `rows` items and return the
+    /// first item shown
+    fn first_row_shown(list: &UIList<usize>, rows: u16) -> usize {
+        let mut terminal = Terminal::new(TestBackend::new(10, rows + 2)).unwrap();
+        terminal
+            .draw(|f| list.render(f, f.area(), |_, i| ListItem::new(i.to_string())))
+            .unwrap();
+        list.view.get().offset
+    }
+
+    #[test]
+    fn moving_up_after_scrolling_down_keeps_the_view_still() {
+        let mut list = UIList {
+            list: (0..20).collect(),
+            display: List::default().block(Block::bordered()),
+            state: ListState::default().with_selected(Some(0)),
+            view: Cell::default(),
+        };
+        assert_eq!(first_row_shown(&list, 5), 0);
+
+        list.state.select(Some(9));
+        assert_eq!(first_row_shown(&list, 5), 5);
+
+        // Moving up within the view leaves it where it is
+        list.state.select(Some(7));
+        assert_eq!(first_row_shown(&list, 5), 5);
+
+        // and moving above it scrolls just far enough
+        list.state.select(Some(3));
+        assert_eq!(first_row_shown(&list, 5), 3);
+    }
+}
@@ -0,0 +1,158 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Benchmarks of the UI over a large synthetic library. None of them need an
+//! audio device, and they draw to a test backend. They use the unstable
+//! bench harness, so run them on nightly with
+//! `cargo +nightly bench --features bench`, from a terminal: some panels ask
+//! the terminal for its size, and without one crossterm runs `tput` to find
+//! out, which swamps the timings.
+
+extern crate test;
+
+use std::{cell::Cell, sync::Arc};
+
+use async_std::{sync::Mutex, task};
+use ratatui::{
+    backend::TestBackend,
+    layout::Rect,
+    widgets::{List, ListItem, ListState},
+    Terminal,
+};
+use test::Bencher;
+
+use crate::{
+    config::Config,
+    library::{track::Track, Library},
+    media_system::MediaState,
+    search::MatchMode,
+    Mode,
+};
+
+use super::{main_screen::MainScreen, tree_screen::TreeScreen, Screen, Theme, UIList, UI};
+
+/// Tracks in the synthetic library
+const LIBRARY_SIZE: usize = 50_000;
+
+/// A library of `LIBRARY_SIZE` tracks by 100 artists, with 10 tracks to an
+/// album, added out of order
+fn library() -> Library {
+    let mut library = Library::new();
+    library.tracks.tracks = (0..LIBRARY_SIZE)
+        .rev()
+        .map(|i| Track {
+            title: Some(format!("Track {i}")),
+            artist: format!("Artist {}", i % 100),
+            album: format!("Album {}", i / 10),
+            number: u32::try_from(i % 10 + 1).ok(),
+            ..Track::from_url(&format!("/music/{i}.flac"))
+        })
+        .collect();
+    library
+}
+
+/// The work deferred past the first frame by `DeferLibraryLoad`
+#[bench]
+fn build_library_screens(b: &mut Bencher) {
+    let library = library();
+    let theme = Theme::default();
+    b.iter(|| {
+        let mut library = library.clone();
+        library.sort();
+        let main_screen = MainScreen::new(
+            &library.tracks,
+            &theme,
+            Default::default(),
+            MatchMode::default(),
+        );
+        let tree_screen = TreeScreen::new(&library, &theme);
+        (main_screen.artist_list.list.len(), tree_screen)
+    });
+}
+
+/// A whole frame, as drawn every time round the main loop before redraws
+/// were limited to when something changed
+#[bench]
+fn draw_frame(b: &mut Bencher) {
+    let library = library();
+    let config = Config::default();
+    let ui = UI::new(&library, &config, &[], &[]);
+    let media_state = Arc::new(Mutex::new(MediaState::default()));
+    let mut terminal = Terminal::new(TestBackend::new(160, 48)).unwrap();
+    b.iter(|| {
+        task::block_on(ui.draw(&mut terminal, &media_state, None, &config, &Mode::Normal)).unwrap();
+    });
+}
+
+/// Rebuilding the lists, as done after every key press. Before that it was
+/// done every time round the main loop.
+#[bench]
+fn update_lists(b: &mut Bencher) {
+    let library = library();
+    let config = Config::default();
+    let mut ui = UI::new(&library, &config, &[], &[]);
+    b.iter(|| ui.update_lists());
+}
+
+/// Draw the main screen, at a different width each time if `resize` is set
+fn draw_main_screen(b: &mut Bencher, resize: bool) {
+    let library = library();
+    let screen = MainScreen::new(
+        &library.tracks,
+        &Theme::default(),
+        Config::default().column_layout(),
+        MatchMode::default(),
+    );
+    let mut terminal = Terminal::new(TestBackend::new(160, 48)).unwrap();
+    let mut width = 160;
+    b.iter(|| {
+        if resize {
+            width = if width == 160 { 159 } else { 160 };
+        }
+        terminal
+            .draw(|f| screen.ui(f, Rect::new(0, 0, width, 48)))
+            .unwrap();
+    });
+}
+
+/// Drawing the main screen with its track rows already laid out
+#[bench]
+fn draw_main_screen_cached_rows(b: &mut Bencher) {
+    draw_main_screen(b, false);
+}
+
+/// Drawing the main screen with its track rows laid out afresh, as happens
+/// whenever the terminal is resized
+#[bench]
+fn draw_main_screen_new_rows(b: &mut Bencher) {
+    draw_main_screen(b, true);
+}
+
+/// Draw a list of `len` items scrolled to the middle. Only the rows in view
+/// are built, so this should take as long however long the list is.
+fn draw_list(b: &mut Bencher, len: usize) {
+    let mut list = UIList {
+        list: (0..len).map(|i| format!("Item {i}")).collect::<Vec<_>>(),
+        display: List::default().block(Theme::default().block("List")),
+        state: ListState::default(),
+        view: Cell::default(),
+    };
+    list.state.select(Some(len / 2));
+    let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+    b.iter(|| {
+        terminal
+            .draw(|f| list.render(f, f.area(), |_, item| ListItem::new(item.clone())))
+            .unwrap();
+    });
+}
+
+#[bench]
+fn draw_list_of_a_thousand(b: &mut Bencher) {
+    draw_list(b, 1_000);
+}
+
+#[bench]
+fn draw_list_of_a_million(b: &mut Bencher) {
+    draw_list(b, 1_000_000);
+}
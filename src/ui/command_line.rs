@@ -2,8 +2,14 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::rc::Rc;
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::Path,
+    rc::Rc,
+};
 
+use anyhow::Result;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Modifier, Style},
@@ -13,10 +19,32 @@ use ratatui::{
 use tui_textarea::{CursorMove, TextArea};
 use unicode_width::UnicodeWidthStr;
 
+use crate::command;
+
+use super::Theme;
+
+/// The most commands kept in the history
+const HISTORY_LIMIT: usize = 1000;
+
 #[derive(Debug, Default)]
 pub struct CommandLine<'a> {
     prompt: Rc<str>,
     pub textarea: TextArea<'a>,
+
+    /// Previously entered commands, oldest first
+    history: Vec<String>,
+
+    /// The history entry currently shown, if any
+    history_index: Option<usize>,
+
+    /// What was typed before scrolling into the history
+    draft: String,
+
+    /// The candidates from the last tab completion
+    completions: Vec<String>,
+
+    /// The completion candidate currently shown, if cycling through them
+    completion_index: Option<usize>,
 }
 
 impl<'a> CommandLine<'a> {
@@ -25,7 +53,7 @@ impl<'a> CommandLine<'a> {
         f: &mut Frame,
         command_line_chunk: Rect,
         show_cursor: bool,
-        style: &Style,
+        theme: &Theme,
     ) {
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
@@ -39,12 +67,12 @@ impl<'a> CommandLine<'a> {
             )
             .split(command_line_chunk);
         let mut textarea = self.textarea.clone();
-        textarea.set_style(*style);
-        textarea.set_cursor_line_style(*style);
+        textarea.set_style(theme.normal);
+        textarea.set_cursor_line_style(theme.normal);
         if !show_cursor {
             textarea.set_cursor_style(Style::default().add_modifier(Modifier::HIDDEN));
         }
-        let prompt = Line::styled(self.prompt.as_ref(), style.add_modifier(Modifier::BOLD));
+        let prompt = Line::styled(self.prompt.as_ref(), theme.prompt);
 
         f.render_widget(prompt, chunks[0]);
         f.render_widget(&textarea, chunks[1]);
@@ -61,6 +89,7 @@ impl<'a> CommandLine<'a> {
     pub fn reset(&mut self) {
         self.clear_prompt();
         self.clear_contents();
+        self.history_index = None;
     }
 
     pub fn clear_contents(&mut self) {
@@ -68,7 +97,125 @@ impl<'a> CommandLine<'a> {
         while self.textarea.delete_char() {}
     }
 
+    /// Clear the command line and display the given message in it
+    pub fn show_message(&mut self, message: &str) {
+        self.reset();
+        self.textarea.insert_str(message);
+    }
+
     pub fn get_contents(&self) -> String {
         self.textarea.lines()[0].clone()
     }
+
+    fn set_contents(&mut self, contents: &str) {
+        self.clear_contents();
+        self.textarea.insert_str(contents);
+    }
+
+    /// Load the command history from a file with one command per line. A
+    /// missing file is an empty history.
+    pub fn load_history<P: AsRef<Path>>(&mut self, file_path: P) -> Result<()> {
+        if !file_path.as_ref().exists() {
+            return Ok(());
+        }
+        self.history = BufReader::new(fs::File::open(file_path)?)
+            .lines()
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Add a command to the history and save the history to a file
+    pub fn push_history<P: AsRef<Path>>(&mut self, command: &str, file_path: P) -> Result<()> {
+        if command.trim().is_empty() || self.history.last().is_some_and(|last| last == command) {
+            return Ok(());
+        }
+        self.history.push(command.to_owned());
+        if self.history.len() > HISTORY_LIMIT {
+            self.history.drain(..self.history.len() - HISTORY_LIMIT);
+        }
+
+        let mut contents = self.history.join("\n");
+        contents.push('\n');
+        fs::write(file_path, contents)?;
+        Ok(())
+    }
+
+    /// Show the previous command in the history, keeping what was typed so
+    /// far to come back to
+    pub fn history_prev(&mut self) {
+        let index = match self.history_index {
+            Some(0) => return,
+            Some(index) => index - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.get_contents();
+                self.history.len() - 1
+            }
+        };
+        self.history_index = Some(index);
+        let command = self.history[index].clone();
+        self.set_contents(&command);
+    }
+
+    /// Show the next command in the history, or what was typed before
+    /// scrolling into the history once past the newest command
+    pub fn history_next(&mut self) {
+        let Some(index) = self.history_index else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.history_index = Some(index + 1);
+            let command = self.history[index + 1].clone();
+            self.set_contents(&command);
+        } else {
+            self.history_index = None;
+            let draft = std::mem::take(&mut self.draft);
+            self.set_contents(&draft);
+        }
+    }
+
+    /// Complete the command being typed. A single candidate is filled in,
+    /// several fill in their common prefix, and pressing Tab again once there
+    /// is nothing left in common cycles through them.
+    pub fn complete(&mut self) {
+        let contents = self.get_contents();
+        if let Some(index) = self.completion_index {
+            if self.completions.get(index) == Some(&contents) {
+                let index = (index + 1) % self.completions.len();
+                self.completion_index = Some(index);
+                let candidate = self.completions[index].clone();
+                self.set_contents(&candidate);
+                return;
+            }
+        }
+
+        self.completions = command::complete(&contents);
+        self.completion_index = None;
+        match self.completions.as_slice() {
+            [] => {}
+            [candidate] => {
+                // Finished command names are followed by their arguments
+                let separator = if contents.contains(' ') { "" } else { " " };
+                let completed = format!("{candidate}{separator}");
+                self.set_contents(&completed);
+            }
+            [first, rest @ ..] => {
+                let prefix_len = rest.iter().fold(first.len(), |len, candidate| {
+                    first[..len]
+                        .char_indices()
+                        .zip(candidate.chars())
+                        .find(|((_, a), b)| a != b)
+                        .map_or(len.min(candidate.len()), |((i, _), _)| i)
+                });
+                let prefix = first[..prefix_len].to_owned();
+                if prefix != contents {
+                    self.set_contents(&prefix);
+                } else {
+                    self.completion_index = Some(0);
+                    let candidate = first.clone();
+                    self.set_contents(&candidate);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,51 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use ratatui::{layout::Rect, style::Style, Frame};
+
+use crate::{command::Command, config::Config, media_system::Queueable};
+
+use super::{MovementDirection, Screen, TextPage, Theme};
+
+/// A scrollable page of the settings in effect, for checking what the config
+/// file and any `:set` commands have added up to
+pub struct ConfigScreen<'a> {
+    page: TextPage<'a>,
+}
+
+impl<'a> ConfigScreen<'a> {
+    pub fn new(config: &Config, theme: &Theme) -> Self {
+        Self {
+            page: TextPage::new("Config", config.summary(), theme),
+        }
+    }
+}
+
+impl<'a> Screen for ConfigScreen<'a> {
+    fn ui(&self, f: &mut Frame, page_chunk: Rect) {
+        self.page.render(f, page_chunk);
+    }
+
+    fn style_panels(&mut self, _selected: &Style, _unselected: &Style) {}
+
+    fn switch_panel(&mut self, _direction: MovementDirection) {}
+
+    fn switch_item(&mut self, direction: MovementDirection, _wrap: bool) {
+        self.page.scroll(direction);
+    }
+
+    fn goto_index(&mut self, index: usize) {
+        self.page.scroll_to(index);
+    }
+
+    fn handle_click(&mut self, _col: u16, _row: u16) -> Option<Command> {
+        None
+    }
+
+    fn update_lists(&mut self, _theme: &Theme) {}
+
+    fn get_selected(&self, _tracks_current_only: bool) -> Queueable {
+        Queueable::Empty
+    }
+}
@@ -0,0 +1,112 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::cell::Cell;
+
+use ratatui::{
+    layout::Rect,
+    style::Style,
+    widgets::{List, ListItem, ListState},
+    Frame,
+};
+
+use crate::{command::Command, media_system::Queueable};
+
+use super::{MovementDirection, Screen, Theme, UIList};
+
+/// The most errors kept in the log. The oldest are dropped first.
+const MAX_ERRORS: usize = 1000;
+
+/// A single-panel log of the import and playback errors seen this session,
+/// oldest first
+pub struct ErrorScreen<'a> {
+    error_list: UIList<'a, String>,
+}
+
+impl<'a> ErrorScreen<'a> {
+    pub fn new(theme: &Theme) -> Self {
+        let mut screen = Self {
+            error_list: UIList {
+                list: Vec::new(),
+                display: List::default(),
+                state: ListState::default(),
+                view: Cell::default(),
+            },
+        };
+        screen.update_lists(theme);
+        screen
+    }
+
+    /// Add an error to the end of the log
+    pub fn push(&mut self, error: String, theme: &Theme) {
+        let list = &mut self.error_list.list;
+        list.push(error);
+        if list.len() > MAX_ERRORS {
+            list.drain(..list.len() - MAX_ERRORS);
+        }
+        self.update_lists(theme);
+    }
+}
+
+impl<'a> Screen for ErrorScreen<'a> {
+    fn ui(&self, f: &mut Frame, page_chunk: Rect) {
+        self.error_list
+            .render(f, page_chunk, |_, error| ListItem::new(error.clone()));
+    }
+
+    fn style_panels(&mut self, selected: &Style, _unselected: &Style) {
+        self.error_list.display = self.error_list.display.clone().highlight_style(*selected);
+    }
+
+    fn switch_panel(&mut self, _direction: MovementDirection) {}
+
+    fn switch_item(&mut self, direction: MovementDirection, wrap: bool) {
+        let len = self.error_list.list.len();
+        if len == 0 {
+            return;
+        }
+
+        let selected = self.error_list.state.selected().unwrap_or_default();
+        self.error_list.state.select(Some(direction.apply(
+            selected,
+            len,
+            self.error_list.height(),
+            wrap,
+        )));
+    }
+
+    fn goto_index(&mut self, index: usize) {
+        let len = self.error_list.list.len();
+        if len > 0 {
+            self.error_list.state.select(Some(index.min(len - 1)));
+        }
+    }
+
+    fn handle_click(&mut self, col: u16, row: u16) -> Option<Command> {
+        self.error_list.click(col, row);
+        None
+    }
+
+    fn update_lists(&mut self, theme: &Theme) {
+        self.error_list.display = List::default()
+            .block(theme.block("Errors"))
+            .style(theme.normal);
+
+        // If selected index is past the end of the list, put it at the end of the list
+        if self.error_list.list.is_empty() {
+            self.error_list.state.select(None);
+        } else if self.error_list.state.selected().unwrap_or_default() >= self.error_list.list.len()
+        {
+            self.error_list
+                .state
+                .select(Some(self.error_list.list.len() - 1));
+        } else if self.error_list.state.selected().is_none() {
+            self.error_list.state.select(Some(0));
+        }
+    }
+
+    fn get_selected(&self, _tracks_current_only: bool) -> Queueable {
+        Queueable::Empty
+    }
+}
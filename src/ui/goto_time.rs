@@ -0,0 +1,62 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use ratatui::{
+    layout::{Constraint, Direction, Flex, Layout, Rect},
+    style::Modifier,
+    text::Line,
+    widgets::Clear,
+    Frame,
+};
+
+use super::{command_line::CommandLine, Theme};
+
+/// A small popup for typing a timecode to seek to in the current track
+#[derive(Debug, Default)]
+pub struct GotoTimeOverlay<'a> {
+    pub input: CommandLine<'a>,
+
+    /// Feedback about the last rejected input
+    error: Option<String>,
+}
+
+impl<'a> GotoTimeOverlay<'a> {
+    pub fn render(&self, f: &mut Frame, area: Rect, theme: &Theme) {
+        let [popup] = Layout::horizontal([Constraint::Length(36)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [popup] = Layout::vertical([Constraint::Length(4)])
+            .flex(Flex::Center)
+            .areas(popup);
+
+        let block = theme.block("Go to time").style(theme.normal);
+        let inner = block.inner(popup);
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Length(1)].as_ref())
+            .split(inner);
+
+        f.render_widget(Clear, popup);
+        f.render_widget(block, popup);
+        self.input.render(f, chunks[0], true, theme);
+        if let Some(error) = &self.error {
+            f.render_widget(
+                Line::styled(error.as_str(), theme.normal.add_modifier(Modifier::ITALIC)),
+                chunks[1],
+            );
+        }
+    }
+
+    /// Clear the input and any error, ready to be opened
+    pub fn reset(&mut self) {
+        self.input.clear_contents();
+        self.input.set_prompt("m:ss ");
+        self.error = None;
+    }
+
+    /// Show why the input was rejected, keeping it for editing
+    pub fn show_error(&mut self, error: &str) {
+        self.error = Some(error.to_owned());
+    }
+}
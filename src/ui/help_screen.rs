@@ -2,74 +2,177 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use crossterm::event::KeyCode;
-use ratatui::{
-    style::Style,
-    text::Text,
-    widgets::{Block, Borders, Paragraph},
+use ratatui::{text::Text, widgets::Paragraph};
+
+use crate::{
+    command::Command,
+    config::{Config, KeyBind},
+    media_system::Queueable,
 };
 
-use crate::{command::Command, config::Config, media_system::Queueable};
+use super::{Screen, ScreenEnum, TextPage, Theme};
 
-use super::{Screen, ScreenEnum};
+/// The commands listed under each heading of the help page, in order. Any
+/// other command bound to a key is listed under the last heading.
+const SECTIONS: [(&str, &[Command]); 4] = [
+    (
+        "Navigation",
+        &[
+            Command::Up,
+            Command::Down,
+            Command::HalfPageDown,
+            Command::HalfPageUp,
+            Command::PageDown,
+            Command::PageUp,
+            Command::GotoTop,
+            Command::GotoBottom,
+            Command::NextPanel,
+            Command::PrevPanel,
+            Command::Expand,
+            Command::Collapse,
+            Command::GotoScreen(ScreenEnum::Main),
+            Command::GotoScreen(ScreenEnum::Playlists),
+            Command::GotoScreen(ScreenEnum::Tree),
+            Command::GotoScreen(ScreenEnum::History),
+            Command::GotoScreen(ScreenEnum::Stations),
+            Command::ToggleLyrics,
+            Command::ShowErrors,
+            Command::GotoScreen(ScreenEnum::Config),
+            Command::GotoScreen(ScreenEnum::Help),
+            Command::Search,
+            Command::SetMatchMode(None),
+        ],
+    ),
+    (
+        "Playback",
+        &[
+            Command::TogglePlay,
+            Command::Play,
+            Command::Pause,
+            Command::Stop,
+            Command::PrevTrack,
+            Command::NextTrack,
+            Command::QueueAndPlay,
+            Command::PlayNext,
+            Command::ToggleRepeat,
+            Command::ToggleShuffle,
+            Command::Reshuffle,
+            Command::SetLoopA,
+            Command::SetLoopB,
+            Command::GotoTime,
+            Command::SeekBackward,
+            Command::SeekForward,
+            Command::VolumeDown,
+            Command::VolumeUp,
+            Command::ToggleMute,
+            Command::SpeedDown,
+            Command::SpeedUp,
+            Command::SpeedReset,
+            Command::ToggleRemaining,
+        ],
+    ),
+    (
+        "Playlists",
+        &[
+            Command::NewPlaylist(None),
+            Command::SelectPlaylist,
+            Command::PlaylistAdd,
+            Command::ToggleFavorite,
+            Command::SaveQueue(None),
+        ],
+    ),
+    (
+        "Other",
+        &[
+            Command::EnterCommand,
+            Command::Rescan,
+            Command::ReloadConfig,
+            Command::ToggleIcons,
+            Command::Quit,
+        ],
+    ),
+];
 
 pub struct HelpScreen<'a> {
-    help_page: Paragraph<'a>,
+    help_page: TextPage<'a>,
+
+    /// A short guide to getting started, shown in place of the main screen
+    /// on a first run
+    pub guide: Paragraph<'a>,
 }
 
 impl<'a> HelpScreen<'a> {
-    pub fn new(config: &'a Config, normal_style: &Style) -> Self {
-        let help_text = Text::from(format!(
-            "Up:                {}\n\
-             Down:              {}\n\
-             Play/Pause:        {}\n\
-             Previous track:    {}\n\
-             Next track:        {}\n\
-             Enqueue:           {}\n\
-             Repeat:            {}\n\
-             Shuffle:           {}\n\
-             Goto top:          {}\n\
-             Goto bottom:       {}\n\
-             Next panel:        {}\n\
-             Previous panel:    {}\n\
-             Main screen:       {}\n\
-             Playlist screen:   {}\n\
-             Help screen:       {}\n\
-             New playlist:      {} (Playlist screen only)\n\
-             Select playlist:   {} (Playlist screen only)\n\
-             Add to playlist:   {}\n\
-             Quit:              {}",
-            display_keys(&config.get_command_keys(&Command::Up)),
-            display_keys(&config.get_command_keys(&Command::Down)),
-            display_keys(&config.get_command_keys(&Command::TogglePlay)),
-            display_keys(&config.get_command_keys(&Command::PrevTrack)),
-            display_keys(&config.get_command_keys(&Command::NextTrack)),
-            display_keys(&config.get_command_keys(&Command::QueueAndPlay)),
-            display_keys(&config.get_command_keys(&Command::ToggleRepeat)),
-            display_keys(&config.get_command_keys(&Command::ToggleShuffle)),
-            display_keys(&config.get_command_keys(&Command::GotoTop)),
-            display_keys(&config.get_command_keys(&Command::GotoBottom)),
-            display_keys(&config.get_command_keys(&Command::NextPanel)),
-            display_keys(&config.get_command_keys(&Command::PrevPanel)),
-            display_keys(&config.get_command_keys(&Command::GotoScreen(ScreenEnum::Main))),
-            display_keys(&config.get_command_keys(&Command::GotoScreen(ScreenEnum::Playlists))),
+    pub fn new(config: &Config, theme: &Theme) -> Self {
+        let mut sections: Vec<(&str, Vec<Command>)> = SECTIONS
+            .iter()
+            .map(|(header, commands)| (*header, commands.to_vec()))
+            .collect();
+        // Commands bound to a key but not listed elsewhere still get a line
+        let mut others: Vec<Command> = Vec::new();
+        for command in config.keybinds.values() {
+            let listed = sections.iter().any(|(_, listed)| listed.contains(command));
+            if !listed && !others.contains(command) && command.label().is_some() {
+                others.push(command.clone());
+            }
+        }
+        others.sort_by_key(|command| (command.label(), config.get_command_keys(command)));
+        if let Some((_, commands)) = sections.last_mut() {
+            commands.extend(others);
+        }
+
+        let width = sections
+            .iter()
+            .flat_map(|(_, commands)| commands.iter().filter_map(Command::label))
+            .map(str::len)
+            .max()
+            .unwrap_or_default()
+            + 1;
+        let sections = sections
+            .into_iter()
+            .map(|(header, commands)| {
+                let lines = commands
+                    .iter()
+                    .filter_map(|command| {
+                        let label = command.label()?;
+                        let keys = config.get_command_keys(command);
+                        let keys = if keys.is_empty() {
+                            "(unbound)".to_owned()
+                        } else {
+                            display_keys(&keys)
+                        };
+                        Some(format!("{:<width$} {keys}", format!("{label}:")))
+                    })
+                    .collect();
+                (header.to_owned(), lines)
+            })
+            .collect();
+        let help_page = TextPage::new("Help", sections, theme);
+
+        let guide_text = Text::from(format!(
+            "Your library and playlists are empty.\n\
+             \n\
+             To add music, press {} and enter 'add <dir>' with the path of a\n\
+             file or folder. Folders are scanned for audio files, and the\n\
+             library is saved for next time.\n\
+             \n\
+             Press {} for the list of keys, or {} to quit.\n\
+             \n\
+             Press Esc to dismiss this guide.",
+            display_keys(&config.get_command_keys(&Command::EnterCommand)),
             display_keys(&config.get_command_keys(&Command::GotoScreen(ScreenEnum::Help))),
-            display_keys(&config.get_command_keys(&Command::NewPlaylist(None))),
-            display_keys(&config.get_command_keys(&Command::SelectPlaylist)),
-            display_keys(&config.get_command_keys(&Command::PlaylistAdd)),
             display_keys(&config.get_command_keys(&Command::Quit)),
         ));
-        let help_page = Paragraph::new(help_text)
-            .block(Block::default().title("Help").borders(Borders::ALL))
-            .style(*normal_style);
+        let guide = Paragraph::new(guide_text)
+            .block(theme.block("Welcome to RMuP"))
+            .style(theme.normal);
 
-        Self { help_page }
+        Self { help_page, guide }
     }
 }
 
 impl<'a> Screen for HelpScreen<'a> {
     fn ui(&self, f: &mut ratatui::Frame, page_chunk: ratatui::layout::Rect) {
-        f.render_widget(self.help_page.clone(), page_chunk);
+        self.help_page.render(f, page_chunk);
     }
 
     fn style_panels(
@@ -81,51 +184,28 @@ impl<'a> Screen for HelpScreen<'a> {
 
     fn switch_panel(&mut self, _direction: super::MovementDirection) {}
 
-    fn switch_item(&mut self, _direction: super::MovementDirection) {}
+    fn switch_item(&mut self, direction: super::MovementDirection, _wrap: bool) {
+        self.help_page.scroll(direction);
+    }
 
-    fn update_lists(&mut self, _normal_style: &ratatui::style::Style) {}
+    fn goto_index(&mut self, index: usize) {
+        self.help_page.scroll_to(index);
+    }
+
+    fn handle_click(&mut self, _col: u16, _row: u16) -> Option<Command> {
+        None
+    }
+
+    fn update_lists(&mut self, _theme: &Theme) {}
 
     fn get_selected(&self, _tracks_current_only: bool) -> Queueable {
         Queueable::Empty
     }
 }
 
-fn display_keys(keys: &[KeyCode]) -> String {
-    let mut s = String::new();
-    for (i, k) in keys.iter().enumerate() {
-        let key_string = match k {
-            KeyCode::Char(' ') => "Space".to_owned(),
-            KeyCode::Char(c) => c.to_string(),
-            KeyCode::Backspace => "Backspace".to_owned(),
-            KeyCode::Enter => "Enter".to_owned(),
-            KeyCode::Left => "Left".to_owned(),
-            KeyCode::Right => "Right".to_owned(),
-            KeyCode::Up => "Up".to_owned(),
-            KeyCode::Down => "Down".to_owned(),
-            KeyCode::Home => "Home".to_owned(),
-            KeyCode::End => "End".to_owned(),
-            KeyCode::PageUp => "Page Up".to_owned(),
-            KeyCode::PageDown => "Page Down".to_owned(),
-            KeyCode::Tab => "Tab".to_owned(),
-            KeyCode::BackTab => "Shift+Tab".to_owned(),
-            KeyCode::Delete => "Delete".to_owned(),
-            KeyCode::Insert => "Insert".to_owned(),
-            KeyCode::F(n) => format!("F{n}"),
-            KeyCode::Null => "Null".to_owned(),
-            KeyCode::Esc => "Esc".to_owned(),
-            KeyCode::CapsLock => "Caps Lock".to_owned(),
-            KeyCode::ScrollLock => "Scroll Lock".to_owned(),
-            KeyCode::NumLock => "Num Lock".to_owned(),
-            KeyCode::PrintScreen => "Print Screen".to_owned(),
-            KeyCode::Pause => "Pause".to_owned(),
-            KeyCode::Menu => "Menu".to_owned(),
-            _ => "Invalid Key Code".to_owned(),
-        };
-        if i == 0 {
-            s.push_str(&key_string);
-        } else {
-            s.push_str(&format!(", {key_string}"));
-        }
-    }
-    s
+fn display_keys(keys: &[KeyBind]) -> String {
+    keys.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
 }
@@ -0,0 +1,119 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::cell::Cell;
+
+use ratatui::{
+    layout::Rect,
+    style::Style,
+    widgets::{List, ListItem, ListState},
+    Frame,
+};
+
+use crate::{command::Command, library::track::Track, media_system::Queueable};
+
+use super::{MovementDirection, Screen, Theme, UIList};
+
+/// A single-panel list of recently played tracks, most recent first
+pub struct HistoryScreen<'a> {
+    track_list: UIList<'a, Track>,
+}
+
+impl<'a> HistoryScreen<'a> {
+    pub fn new(theme: &Theme) -> Self {
+        let mut screen = Self {
+            track_list: UIList {
+                list: Vec::new(),
+                display: List::default(),
+                state: ListState::default(),
+                view: Cell::default(),
+            },
+        };
+        screen.update_lists(theme);
+        screen
+    }
+
+    /// Replace the listed tracks, keeping at most `max` of them. Does nothing
+    /// if they haven't changed.
+    pub fn set_tracks(&mut self, mut tracks: Vec<Track>, max: usize, theme: &Theme) {
+        tracks.truncate(max);
+        if tracks != self.track_list.list {
+            self.track_list.list = tracks;
+            self.update_lists(theme);
+        }
+    }
+
+    /// The highlighted track, if there is one
+    pub fn selected_track(&self) -> Option<&Track> {
+        self.track_list
+            .state
+            .selected()
+            .and_then(|i| self.track_list.list.get(i))
+    }
+}
+
+impl<'a> Screen for HistoryScreen<'a> {
+    fn ui(&self, f: &mut Frame, page_chunk: Rect) {
+        self.track_list
+            .render(f, page_chunk, |_, track| ListItem::new(track.to_string()));
+    }
+
+    fn style_panels(&mut self, selected: &Style, _unselected: &Style) {
+        self.track_list.display = self.track_list.display.clone().highlight_style(*selected);
+    }
+
+    fn switch_panel(&mut self, _direction: MovementDirection) {}
+
+    fn switch_item(&mut self, direction: MovementDirection, wrap: bool) {
+        let len = self.track_list.list.len();
+        if len == 0 {
+            return;
+        }
+
+        let selected = self.track_list.state.selected().unwrap_or_default();
+        self.track_list.state.select(Some(direction.apply(
+            selected,
+            len,
+            self.track_list.height(),
+            wrap,
+        )));
+    }
+
+    fn goto_index(&mut self, index: usize) {
+        let len = self.track_list.list.len();
+        if len > 0 {
+            self.track_list.state.select(Some(index.min(len - 1)));
+        }
+    }
+
+    fn handle_click(&mut self, col: u16, row: u16) -> Option<Command> {
+        self.track_list
+            .click(col, row)
+            .then_some(Command::QueueAndPlay)
+    }
+
+    fn update_lists(&mut self, theme: &Theme) {
+        self.track_list.display = List::default()
+            .block(theme.block("Recently Played"))
+            .style(theme.normal);
+
+        // If selected index is past the end of the list, put it at the end of the list
+        if self.track_list.list.is_empty() {
+            self.track_list.state.select(None);
+        } else if self.track_list.state.selected().unwrap_or_default() >= self.track_list.list.len()
+        {
+            self.track_list
+                .state
+                .select(Some(self.track_list.list.len() - 1));
+        } else if self.track_list.state.selected().is_none() {
+            self.track_list.state.select(Some(0));
+        }
+    }
+
+    fn get_selected(&self, _tracks_current_only: bool) -> Queueable {
+        self.selected_track().map_or(Queueable::Empty, |track| {
+            Queueable::TrackList(vec![track.clone()].into())
+        })
+    }
+}
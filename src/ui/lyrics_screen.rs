@@ -0,0 +1,177 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{cell::Cell, time::Duration};
+
+use ratatui::{
+    layout::{Alignment, Rect},
+    style::Style,
+    text::{Line, Text},
+    widgets::Paragraph,
+    Frame,
+};
+
+use crate::{
+    command::Command,
+    library::{self, track::Track},
+    lyrics::Lyrics,
+    media_system::Queueable,
+};
+
+use super::{MovementDirection, Screen, Theme};
+
+/// The lyrics of the playing track. Timed lyrics follow the track, keeping
+/// the line being sung in the middle of the panel; plain lyrics are shown
+/// from the top and scroll by hand.
+pub struct LyricsScreen {
+    /// The file of the track the lyrics are for
+    track: Option<String>,
+
+    lyrics: Option<Lyrics>,
+
+    /// The index of the line being sung, if the lyrics are timed
+    current_line: Option<usize>,
+
+    /// How many lines plain lyrics are scrolled down by
+    scroll: usize,
+
+    /// The height the panel was last drawn at, borders included
+    height: Cell<u16>,
+
+    theme: Theme,
+
+    /// The style the line being sung is drawn in
+    highlight: Style,
+}
+
+impl LyricsScreen {
+    pub fn new(theme: &Theme) -> Self {
+        Self {
+            track: None,
+            lyrics: None,
+            current_line: None,
+            scroll: 0,
+            height: Cell::default(),
+            theme: *theme,
+            highlight: Style::default(),
+        }
+    }
+
+    /// Show the lyrics of the playing track at a point in it, reading them
+    /// afresh only when the track changes. Returns whether anything shown
+    /// changed.
+    pub fn follow(&mut self, track: Option<&Track>, progress: Option<Duration>) -> bool {
+        let mut changed = false;
+        let path = track.map(|track| &track.file_path);
+        if self.track.as_ref() != path {
+            self.track = path.cloned();
+            self.lyrics = track.and_then(library::read_lyrics);
+            self.scroll = 0;
+            changed = true;
+        }
+        let current_line = self
+            .lyrics
+            .as_ref()
+            .zip(progress)
+            .and_then(|(lyrics, progress)| lyrics.current_line(progress));
+        if current_line != self.current_line {
+            self.current_line = current_line;
+            changed = true;
+        }
+        changed
+    }
+
+    /// How many lines fit inside the panel's borders
+    fn rows(&self) -> usize {
+        usize::from(self.height.get().saturating_sub(2))
+    }
+
+    /// The furthest plain lyrics can be scrolled, leaving their last line at
+    /// the bottom of the panel
+    fn max_scroll(&self) -> usize {
+        self.lyrics
+            .as_ref()
+            .map_or(0, |lyrics| lyrics.lines().len().saturating_sub(self.rows()))
+    }
+}
+
+impl Screen for LyricsScreen {
+    fn ui(&self, f: &mut Frame, page_chunk: Rect) {
+        self.height.set(page_chunk.height);
+        let block = self.theme.block("Lyrics");
+        let Some(lyrics) = &self.lyrics else {
+            let message = if self.track.is_some() {
+                "No lyrics found for this track"
+            } else {
+                "Nothing is playing"
+            };
+            f.render_widget(
+                Paragraph::new(message)
+                    .block(block)
+                    .style(self.theme.normal),
+                page_chunk,
+            );
+            return;
+        };
+
+        let lines: Vec<Line> = lyrics
+            .lines()
+            .into_iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let style = if Some(i) == self.current_line {
+                    self.highlight
+                } else {
+                    Style::default()
+                };
+                Line::styled(line.to_owned(), style)
+            })
+            .collect();
+        let scroll = match lyrics {
+            Lyrics::Synced(_) => self
+                .current_line
+                .unwrap_or_default()
+                .saturating_sub(self.rows() / 2),
+            Lyrics::Unsynced(_) => self.scroll.min(self.max_scroll()),
+        };
+        let paragraph = Paragraph::new(Text::from(lines))
+            .block(block)
+            .style(self.theme.normal)
+            .alignment(Alignment::Center)
+            .scroll((u16::try_from(scroll).unwrap_or(u16::MAX), 0));
+        f.render_widget(paragraph, page_chunk);
+    }
+
+    fn style_panels(&mut self, selected: &Style, _unselected: &Style) {
+        self.highlight = *selected;
+    }
+
+    fn switch_panel(&mut self, _direction: MovementDirection) {}
+
+    fn switch_item(&mut self, direction: MovementDirection, _wrap: bool) {
+        let max_scroll = self.max_scroll();
+        self.scroll = direction.apply(
+            self.scroll.min(max_scroll),
+            max_scroll + 1,
+            self.height.get(),
+            false,
+        );
+    }
+
+    fn goto_index(&mut self, index: usize) {
+        self.scroll = index.min(self.max_scroll());
+    }
+
+    fn handle_click(&mut self, _col: u16, _row: u16) -> Option<Command> {
+        None
+    }
+
+    fn update_lists(&mut self, theme: &Theme) {
+        self.theme = *theme;
+    }
+
+    fn get_selected(&self, _tracks_current_only: bool) -> Queueable {
+        Queueable::Empty
+    }
+}
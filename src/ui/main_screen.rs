@@ -2,28 +2,50 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::mem;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    mem,
+    sync::Arc,
+};
 
 use ratatui::{
     layout::{Constraint, Layout, Rect},
     style::Style,
-    widgets::{Block, Borders, List, ListItem, ListState},
+    widgets::{List, ListState},
 };
 
 use crate::{
-    library::{album::Album, artist::Artist, track::Track},
+    command::Command,
+    cover::CoverImage,
+    library::{
+        album::Album,
+        artist::Artist,
+        track::{ColumnLayout, SortDirection, SortKey, Track},
+    },
     media_system::Queueable,
-    Library,
+    playlist::Playlist,
+    search::{self, MatchMode},
 };
 
-use super::{MovementDirection, Screen, UIList};
+use super::{MovementDirection, Screen, Theme, UIList};
 
+#[derive(Clone, Copy)]
 enum Panel {
     Artists,
     Albums,
     Tracks,
 }
 
+/// The selected panel and the selected item of each list in the main screen,
+/// along with its index
+pub struct Selection {
+    panel: Panel,
+    artist: Option<(usize, String)>,
+    album: Option<(usize, String)>,
+    track: Option<(usize, String)>,
+}
+
 pub struct MainScreen<'a> {
     /// The list of artists that will display in the UI
     pub artist_list: UIList<'a, Artist>,
@@ -35,66 +57,242 @@ pub struct MainScreen<'a> {
     pub track_list: UIList<'a, Track>,
 
     panel: Panel,
+
+    /// The columns of the track list
+    pub column_layout: ColumnLayout,
+
+    /// The laid out columns of each track drawn so far, by file path, at
+    /// `row_cache_width`. Tracks don't change over the screen's lifetime, as
+    /// the screen is rebuilt whenever the library is.
+    row_cache: RefCell<HashMap<String, String>>,
+    row_cache_width: Cell<u16>,
+
+    /// All of the tracks in the library, before filtering
+    library_tracks: Playlist,
+
+    /// Search text the panels are filtered by
+    filter: String,
+
+    /// How the search text is matched against the library
+    pub match_mode: MatchMode,
+
+    /// What the track panel is sorted by, if not the library order. Only the
+    /// panel is sorted; the artist and album grouping is left alone.
+    pub track_sort: Option<(SortKey, SortDirection)>,
+
+    /// The selected album's cover, shown beside the albums when there is one
+    pub cover: Option<Arc<CoverImage>>,
+
+    theme: Theme,
 }
 
 impl<'a> MainScreen<'a> {
-    pub fn new(library: &Library, normal_style: &Style) -> Self {
-        let (artist_list, album_list) = library.tracks.get_artists_albums();
-
-        // Create artist list from library
-        let artist_listitems: Vec<ListItem> = artist_list
-            .iter()
-            .map(|e| ListItem::new(e.name.clone()))
-            .collect();
-        let artist_list_display = List::new(artist_listitems)
-            .block(Block::default().title("Artist").borders(Borders::ALL))
-            .style(*normal_style);
-        let artist_list_state = ListState::default();
+    pub fn new(
+        tracks: &Playlist,
+        theme: &Theme,
+        column_layout: ColumnLayout,
+        match_mode: MatchMode,
+    ) -> Self {
+        let (artist_list, album_list) = tracks.get_artists_albums();
 
         let mut artist_list = UIList {
             list: artist_list,
-            display: artist_list_display,
-            state: artist_list_state,
+            display: List::default()
+                .block(theme.block("Artist"))
+                .style(theme.normal),
+            state: ListState::default(),
+            view: Cell::default(),
         };
 
-        // Create album list from library
-        let album_listitems: Vec<ListItem> =
-            album_list.iter().map(std::convert::Into::into).collect();
-        let album_list_display = List::new(album_listitems)
-            .block(Block::default().title("Album").borders(Borders::ALL))
-            .style(*normal_style);
-        let album_list_state = ListState::default();
-
         let mut album_list = UIList {
             list: album_list,
-            display: album_list_display,
-            state: album_list_state,
+            display: List::default()
+                .block(theme.block("Album"))
+                .style(theme.normal),
+            state: ListState::default(),
+            view: Cell::default(),
         };
 
-        // Create track list from library
-        let track_list = library.tracks.tracks.clone();
-        let track_listitems: Vec<ListItem> =
-            track_list.iter().map(std::convert::Into::into).collect();
-        let track_list_display = List::new(track_listitems)
-            .block(Block::default().title("Track").borders(Borders::ALL))
-            .style(*normal_style);
-        let track_list_state = ListState::default();
-
+        // The track list is filled in from the selected album below
         let mut track_list = UIList {
-            list: track_list.clone(),
-            display: track_list_display.clone(),
-            state: track_list_state,
+            list: Vec::new(),
+            display: List::default(),
+            state: ListState::default(),
+            view: Cell::default(),
         };
 
         artist_list.state.select(Some(0));
         album_list.state.select(Some(0));
         track_list.state.select(Some(0));
 
-        Self {
+        let mut screen = Self {
             artist_list,
             album_list,
             track_list,
             panel: Panel::Artists,
+            column_layout,
+            row_cache: RefCell::default(),
+            row_cache_width: Cell::default(),
+            library_tracks: tracks.clone(),
+            filter: String::new(),
+            match_mode,
+            track_sort: None,
+            cover: None,
+            theme: *theme,
+        };
+        screen.update_lists(theme);
+        screen
+    }
+
+    /// Filter all three panels down to the tracks whose title, artist, or
+    /// album match the given text in the current match mode. An empty filter
+    /// shows the whole library. In fuzzy mode, the best matches come first in
+    /// the "All Albums" track lists. Selections are reset to the top of each
+    /// list.
+    pub fn set_filter(&mut self, filter: &str, theme: &Theme) {
+        filter.clone_into(&mut self.filter);
+
+        let mut scored: Vec<(usize, &Track)> = self
+            .library_tracks
+            .tracks
+            .iter()
+            .filter_map(|t| self.match_score(t).map(|score| (score, t)))
+            .collect();
+        if self.match_mode == MatchMode::Fuzzy {
+            scored.sort_by_key(|(score, _)| *score);
+        }
+
+        let mut filtered = Playlist::new(&self.library_tracks.name);
+        filtered.tracks = scored.into_iter().map(|(_, t)| t.clone()).collect();
+        let (list, _) = filtered.get_artists_albums();
+
+        let title = self.panel_title("Artist", list.len().saturating_sub(1));
+        let list_display = List::default()
+            .block(theme.block(title))
+            .style(theme.normal);
+        self.artist_list = UIList {
+            list,
+            display: list_display,
+            state: ListState::default(),
+            view: mem::take(&mut self.artist_list.view),
+        };
+
+        self.artist_list.state.select(Some(0));
+        self.album_list.state.select(Some(0));
+        self.track_list.state.select(Some(0));
+        self.update_lists(theme);
+    }
+
+    /// Get the selected item in each of the artist, album, and track lists
+    pub fn selection(&self) -> Selection {
+        fn selected<T>(list: &UIList<T>, key: impl Fn(&T) -> String) -> Option<(usize, String)> {
+            list.state
+                .selected()
+                .and_then(|i| list.list.get(i).map(|item| (i, key(item))))
+        }
+
+        Selection {
+            panel: self.panel,
+            artist: selected(&self.artist_list, |a| a.name.clone()),
+            album: selected(&self.album_list, |a| a.name.clone()),
+            track: selected(&self.track_list, |t| t.file_path.clone()),
+        }
+    }
+
+    /// Select the same panel, and the same items in the artist, album, and
+    /// track lists, as in the given selection. Items that no longer exist are replaced by whatever
+    /// is now at their old position, clamped to the end of the list.
+    pub fn restore_selection(&mut self, selection: &Selection, theme: &Theme) {
+        fn find<T>(
+            list: &UIList<T>,
+            selected: Option<&(usize, String)>,
+            key: impl Fn(&T) -> &str,
+        ) -> Option<usize> {
+            selected.map(|(index, name)| {
+                list.list
+                    .iter()
+                    .position(|item| key(item) == name)
+                    .unwrap_or_else(|| (*index).min(list.list.len().saturating_sub(1)))
+            })
+        }
+
+        self.panel = selection.panel;
+
+        let artist = find(&self.artist_list, selection.artist.as_ref(), |a| &a.name);
+        self.artist_list.state.select(artist);
+        self.update_lists(theme);
+
+        let album = find(&self.album_list, selection.album.as_ref(), |a| &a.name);
+        self.album_list.state.select(album);
+        self.update_lists(theme);
+
+        let track = find(&self.track_list, selection.track.as_ref(), |t| &t.file_path);
+        if track.is_some() {
+            self.track_list.state.select(track);
+        }
+    }
+
+    /// The album selected in the album panel
+    pub fn selected_album(&self) -> Option<&Album> {
+        self.album_list
+            .state
+            .selected()
+            .and_then(|i| self.album_list.list.get(i))
+    }
+
+    /// Get all of the tracks left visible by the current filter
+    pub fn visible_tracks(&self) -> Queueable {
+        // The "All Artists" pseudo-artist is always first, and its first album
+        // is "All Albums"
+        self.artist_list
+            .list
+            .first()
+            .map_or(Queueable::Empty, |artist| Queueable::Artist(artist.clone()))
+    }
+
+    /// Sort the track panel by the given key. Without a direction, sorting
+    /// by the same key again flips the direction; a new key sorts ascending.
+    /// Returns the direction used.
+    pub fn sort_tracks(
+        &mut self,
+        key: SortKey,
+        direction: Option<SortDirection>,
+        theme: &Theme,
+    ) -> SortDirection {
+        let direction = direction.unwrap_or(match self.track_sort {
+            Some((sort_key, direction)) if sort_key == key => direction.reversed(),
+            _ => SortDirection::Ascending,
+        });
+        self.track_sort = Some((key, direction));
+        self.update_lists(theme);
+        direction
+    }
+
+    /// Change how the search text is matched and reapply the current filter
+    pub fn set_match_mode(&mut self, match_mode: MatchMode, theme: &Theme) {
+        self.match_mode = match_mode;
+        let filter = mem::take(&mut self.filter);
+        self.set_filter(&filter, theme);
+    }
+
+    /// Score a track by the best match among its title, artist, and album, or
+    /// `None` if none of them match
+    fn match_score(&self, track: &Track) -> Option<usize> {
+        if self.filter.is_empty() {
+            return Some(0);
+        }
+        [track.to_string().as_str(), &track.artist, &track.album]
+            .into_iter()
+            .filter_map(|text| search::match_score(&self.filter, text, self.match_mode))
+            .min()
+    }
+
+    /// Add the match count to a panel title while a filter is active
+    fn panel_title(&self, name: &str, count: usize) -> String {
+        if self.filter.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{name} ({count} matches)")
         }
     }
 }
@@ -116,26 +314,48 @@ impl<'a> Screen for MainScreen<'a> {
             .split(chunks[0]);
 
         // Render artist list in top left
-        let mut artist_list_state = self.artist_list.state.clone();
-        f.render_stateful_widget(
-            self.artist_list.display.clone(),
-            upper_chunk[0],
-            &mut artist_list_state,
-        );
-        // Render album list in top right
-        let mut album_list_state = self.album_list.state.clone();
-        f.render_stateful_widget(
-            self.album_list.display.clone(),
-            upper_chunk[1],
-            &mut album_list_state,
-        );
-        // Render track list in bottom
-        let mut track_list_state = self.track_list.state.clone();
-        f.render_stateful_widget(
-            self.track_list.display.clone(),
-            chunks[1],
-            &mut track_list_state,
-        );
+        self.artist_list
+            .render(f, upper_chunk[0], |_, artist| artist.into());
+        // Render album list in top right, with the selected album's cover to
+        // its right. The cover is drawn square, each row being two pixels, in
+        // at most half of the space.
+        let mut album_chunk = upper_chunk[1];
+        if let Some(cover) = &self.cover {
+            let cover_width =
+                (album_chunk.height.saturating_sub(2) * 2 + 2).min(album_chunk.width / 2);
+            let chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Min(0), Constraint::Length(cover_width)])
+                .split(album_chunk);
+            album_chunk = chunks[0];
+            let block = self.theme.block("Cover").style(self.theme.normal);
+            let inner = block.inner(chunks[1]);
+            f.render_widget(block, chunks[1]);
+            f.render_widget(cover.as_ref(), inner);
+        }
+        self.album_list
+            .render(f, album_chunk, |_, album| album.into());
+
+        // Render track list in bottom, laying out each track's columns only
+        // once per width. The 2 sides of the block take up 1 char each.
+        let width = chunks[1].width.saturating_sub(2);
+        if width != self.row_cache_width.get() {
+            self.row_cache.borrow_mut().clear();
+            self.row_cache_width.set(width);
+        }
+        let mut row_cache = self.row_cache.borrow_mut();
+        self.track_list.render(f, chunks[1], |_, track| {
+            let text = row_cache.entry(track.file_path.clone()).or_insert_with(|| {
+                let album_year = self
+                    .album_list
+                    .list
+                    .iter()
+                    .find(|album| album.name == track.album)
+                    .and_then(|album| album.year);
+                track.columns_text(width, &self.column_layout, album_year)
+            });
+            track.list_item(text.clone())
+        });
     }
 
     fn style_panels(&mut self, selected: &Style, unselected: &Style) {
@@ -196,14 +416,13 @@ impl<'a> Screen for MainScreen<'a> {
         }
     }
 
-    fn switch_item(&mut self, direction: MovementDirection) {
-        use MovementDirection::{Bottom, Next, Prev, Top};
+    fn switch_item(&mut self, direction: MovementDirection, wrap: bool) {
         use Panel::{Albums, Artists, Tracks};
 
-        let current_list_len = match self.panel {
-            Artists => self.artist_list.list.len(),
-            Albums => self.album_list.list.len(),
-            Tracks => self.track_list.list.len(),
+        let (current_list_len, height) = match self.panel {
+            Artists => (self.artist_list.list.len(), self.artist_list.height()),
+            Albums => (self.album_list.list.len(), self.album_list.height()),
+            Tracks => (self.track_list.list.len(), self.track_list.height()),
         };
 
         if current_list_len == 0 {
@@ -216,48 +435,67 @@ impl<'a> Screen for MainScreen<'a> {
             Tracks => &mut self.track_list.state,
         };
 
-        let mut selected = current_list_state.selected().unwrap_or_default();
+        let selected = current_list_state.selected().unwrap_or_default();
+        current_list_state.select(Some(direction.apply(
+            selected,
+            current_list_len,
+            height,
+            wrap,
+        )));
+    }
 
-        match direction {
-            Prev => {
-                if selected == 0 {
-                    selected = current_list_len - 1;
-                } else {
-                    selected -= 1;
-                }
-            }
-            Next => {
-                if selected == current_list_len - 1 {
-                    selected = 0;
-                } else {
-                    selected += 1;
-                }
-            }
-            Top => selected = 0,
-            Bottom => selected = current_list_len - 1,
+    fn goto_index(&mut self, index: usize) {
+        let (len, state) = match self.panel {
+            Panel::Artists => (self.artist_list.list.len(), &mut self.artist_list.state),
+            Panel::Albums => (self.album_list.list.len(), &mut self.album_list.state),
+            Panel::Tracks => (self.track_list.list.len(), &mut self.track_list.state),
+        };
+        if len > 0 {
+            state.select(Some(index.min(len - 1)));
         }
-        current_list_state.select(Some(selected));
     }
 
-    fn update_lists(&mut self, normal_style: &Style) {
+    fn handle_click(&mut self, col: u16, row: u16) -> Option<Command> {
+        let (panel, clicked) = if self.artist_list.contains(col, row) {
+            (Panel::Artists, self.artist_list.click(col, row))
+        } else if self.album_list.contains(col, row) {
+            (Panel::Albums, self.album_list.click(col, row))
+        } else if self.track_list.contains(col, row) {
+            (Panel::Tracks, self.track_list.click(col, row))
+        } else {
+            return None;
+        };
+        self.panel = panel;
+        clicked.then_some(Command::QueueAndPlay)
+    }
+
+    fn update_lists(&mut self, theme: &Theme) {
+        self.theme = *theme;
+
         // Get the albums list of the currently selected artist
         let artist_selected_index = self.artist_list.state.selected().unwrap_or_default();
-        let list = self.artist_list.list[artist_selected_index].albums.clone();
-
-        // Convert that albums list into a Vec of ListItems to create a List widget
-        let listitems: Vec<ListItem> = list.iter().map(std::convert::Into::into).collect();
-        let list_display = List::new(listitems)
-            .block(Block::default().title("Album").borders(Borders::ALL))
-            .style(*normal_style);
+        let list = self
+            .artist_list
+            .list
+            .get(artist_selected_index)
+            .map_or_else(Vec::new, |artist| artist.albums.clone());
+
+        let title = self.panel_title("Album", list.len().saturating_sub(1));
+        let list_display = List::default()
+            .block(theme.block(title))
+            .style(theme.normal);
         // Overwrite the album list in the UI, keeping the same ListState to preserve selected index
         self.album_list = UIList {
             list,
             display: list_display,
             state: mem::take(&mut self.album_list.state),
+            view: mem::take(&mut self.album_list.view),
         };
 
         // If selected index is past the end of the list, put it at the end of the list
-        if self.album_list.state.selected().unwrap_or_default() >= self.album_list.list.len() {
+        if self.album_list.state.selected().unwrap_or_default() >= self.album_list.list.len()
+            && !self.album_list.list.is_empty()
+        {
             self.album_list
                 .state
                 .select(Some(self.album_list.list.len() - 1));
@@ -265,21 +503,27 @@ impl<'a> Screen for MainScreen<'a> {
 
         // Get the track list of the currently selected album
 
-        let list = {
+        let mut list = {
             let album_selected_index = self.album_list.state.selected().unwrap_or_default();
-            self.album_list.list[album_selected_index].tracks.clone()
+            self.album_list
+                .list
+                .get(album_selected_index)
+                .map_or_else(Vec::new, |album| album.tracks.clone())
         };
+        if let Some((key, direction)) = self.track_sort {
+            list.sort_by(|a, b| direction.apply(key.compare(a, b)));
+        }
 
-        // Convert that track list into a Vec of ListItems to create a List widget
-        let listitems: Vec<ListItem> = list.iter().map(std::convert::Into::into).collect();
-        let list_display = List::new(listitems)
-            .block(Block::default().title("Track").borders(Borders::ALL))
-            .style(*normal_style);
+        let title = self.panel_title("Track", list.len());
+        let list_display = List::default()
+            .block(theme.block(title))
+            .style(theme.normal);
         // Overwrite the track list in the UI, keeping the same ListState to preserve selected index
         self.track_list = UIList {
             list,
             display: list_display,
             state: mem::take(&mut self.track_list.state),
+            view: mem::take(&mut self.track_list.view),
         };
 
         // If selected index is past the end of the list, put it at the end of the list
@@ -300,16 +544,23 @@ impl<'a> Screen for MainScreen<'a> {
         match self.panel {
             Artists => {
                 let artist_index = self.artist_list.state.selected().unwrap_or_default();
-                Queueable::Artist(self.artist_list.list[artist_index].clone())
+                self.artist_list
+                    .list
+                    .get(artist_index)
+                    .map_or(Queueable::Empty, |artist| Queueable::Artist(artist.clone()))
             }
             Albums => {
                 let album_index = self.album_list.state.selected().unwrap_or_default();
-                Queueable::Album(self.album_list.list[album_index].clone())
+                self.album_list
+                    .list
+                    .get(album_index)
+                    .map_or(Queueable::Empty, |album| Queueable::Album(album.clone()))
             }
             Tracks => {
                 let track_index = self.track_list.state.selected().unwrap_or_default();
-
-                if tracks_current_only {
+                if track_index >= self.track_list.list.len() {
+                    Queueable::Empty
+                } else if tracks_current_only {
                     Queueable::TrackList(vec![self.track_list.list[track_index].clone()].into())
                 } else {
                     let mut v = self.track_list.list[track_index..].to_vec();
@@ -320,3 +571,198 @@ impl<'a> Screen for MainScreen<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(artist: &str, album: &str, title: &str) -> Track {
+        Track {
+            title: Some(title.to_owned()),
+            artist: artist.to_owned(),
+            album: album.to_owned(),
+            ..Track::from_url(&format!("/music/{artist}/{album}/{title}.flac"))
+        }
+    }
+
+    fn screen(tracks: Vec<Track>) -> MainScreen<'static> {
+        let mut library = Playlist::new("Library");
+        library.tracks = tracks;
+        MainScreen::new(
+            &library,
+            &Theme::default(),
+            ColumnLayout::default(),
+            MatchMode::CaseInsensitive,
+        )
+    }
+
+    fn library() -> Vec<Track> {
+        vec![
+            track("Low", "Things We Lost", "Sunflower"),
+            track("Low", "Secret Name", "Starfire"),
+            track("Slint", "Spiderland", "Breadcrumb Trail"),
+        ]
+    }
+
+    fn names<T>(list: &UIList<T>, name: impl Fn(&T) -> &str) -> Vec<String> {
+        list.list.iter().map(|item| name(item).to_owned()).collect()
+    }
+
+    #[test]
+    fn filter_narrows_every_panel() {
+        let mut screen = screen(library());
+        screen.set_filter("star", &Theme::default());
+
+        assert_eq!(
+            names(&screen.artist_list, |a| &a.name),
+            ["All Artists", "Low"]
+        );
+        assert_eq!(
+            names(&screen.album_list, |a| &a.name),
+            ["All Albums", "Secret Name"]
+        );
+        assert_eq!(names(&screen.track_list, |t| &t.file_path).len(), 1);
+        assert_eq!(screen.panel_title("Track", 1), "Track (1 matches)");
+        assert_eq!(screen.visible_tracks().get_tracks().len(), 1);
+    }
+
+    #[test]
+    fn filter_matches_artists_and_albums() {
+        let mut screen = screen(library());
+        screen.set_filter("spider", &Theme::default());
+        assert_eq!(
+            names(&screen.artist_list, |a| &a.name),
+            ["All Artists", "Slint"]
+        );
+
+        screen.set_filter("LOW", &Theme::default());
+        assert_eq!(screen.visible_tracks().get_tracks().len(), 2);
+
+        screen.set_match_mode(MatchMode::CaseSensitive, &Theme::default());
+        assert!(screen.visible_tracks().get_tracks().is_empty());
+    }
+
+    #[test]
+    fn clearing_the_filter_shows_the_whole_library() {
+        let mut screen = screen(library());
+        screen.set_filter("star", &Theme::default());
+        screen.set_filter("", &Theme::default());
+        assert_eq!(screen.artist_list.list.len(), 3);
+        assert_eq!(screen.visible_tracks().get_tracks().len(), 3);
+        assert_eq!(screen.panel_title("Track", 3), "Track");
+    }
+
+    #[test]
+    fn selection_follows_items_across_a_rebuild() {
+        let theme = Theme::default();
+        let mut old = screen(library());
+        // All Artists, Low, Slint
+        old.artist_list.state.select(Some(2));
+        old.update_lists(&theme);
+        old.album_list.state.select(Some(1));
+        old.update_lists(&theme);
+        old.switch_panel(MovementDirection::Next);
+        let selection = old.selection();
+
+        let mut tracks = library();
+        tracks.push(track("Lungfish", "Talking Songs", "Abandon Yourself"));
+        let mut new = screen(tracks);
+        new.restore_selection(&selection, &theme);
+
+        assert_eq!(new.artist_list.state.selected(), Some(3));
+        assert_eq!(new.selected_album().unwrap().name, "Spiderland");
+        assert!(matches!(new.panel, Panel::Albums));
+    }
+
+    #[test]
+    fn selection_of_a_removed_item_stays_in_place() {
+        let theme = Theme::default();
+        let mut old = screen(library());
+        old.artist_list.state.select(Some(2));
+        old.update_lists(&theme);
+        let selection = old.selection();
+
+        let mut tracks = library();
+        tracks.pop();
+        let mut new = screen(tracks);
+        new.restore_selection(&selection, &theme);
+
+        // Slint is gone, so the last artist is selected instead
+        assert_eq!(new.artist_list.state.selected(), Some(1));
+        assert_eq!(new.selected_album().unwrap().name, "All Albums");
+    }
+
+    #[test]
+    fn sorting_by_the_same_key_again_flips_the_direction() {
+        let theme = Theme::default();
+        let mut screen = screen(library());
+        let titles =
+            |screen: &MainScreen| names(&screen.track_list, |t| t.title.as_deref().unwrap());
+
+        assert_eq!(
+            screen.sort_tracks(SortKey::Title, None, &theme),
+            SortDirection::Ascending
+        );
+        assert_eq!(
+            titles(&screen),
+            ["Breadcrumb Trail", "Starfire", "Sunflower"]
+        );
+
+        assert_eq!(
+            screen.sort_tracks(SortKey::Title, None, &theme),
+            SortDirection::Descending
+        );
+        assert_eq!(
+            titles(&screen),
+            ["Sunflower", "Starfire", "Breadcrumb Trail"]
+        );
+
+        // A new key starts ascending, unless told otherwise
+        assert_eq!(
+            screen.sort_tracks(SortKey::Artist, None, &theme),
+            SortDirection::Ascending
+        );
+        assert_eq!(
+            screen.sort_tracks(SortKey::Title, Some(SortDirection::Descending), &theme),
+            SortDirection::Descending
+        );
+
+        // Only the track panel is sorted
+        assert_eq!(
+            names(&screen.artist_list, |a| &a.name),
+            ["All Artists", "Low", "Slint"]
+        );
+    }
+
+    #[test]
+    fn empty_library_can_be_browsed_without_panicking() {
+        use ratatui::{backend::TestBackend, Terminal};
+
+        let theme = Theme::default();
+        let mut screen = screen(Vec::new());
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        for _ in 0..3 {
+            for direction in [
+                MovementDirection::Next,
+                MovementDirection::Prev,
+                MovementDirection::PageDown,
+                MovementDirection::Bottom,
+            ] {
+                screen.switch_item(direction, true);
+            }
+            screen.goto_index(5);
+            assert!(screen.get_selected(false).get_tracks().is_empty());
+            assert!(screen.get_selected(true).get_tracks().is_empty());
+            terminal.draw(|f| screen.ui(f, f.area())).unwrap();
+            screen.switch_panel(MovementDirection::Next);
+        }
+
+        screen.set_filter("anything", &theme);
+        screen.sort_tracks(SortKey::Title, None, &theme);
+        assert!(screen.visible_tracks().get_tracks().is_empty());
+        assert!(screen
+            .selected_album()
+            .is_none_or(|album| album.tracks.is_empty()));
+        terminal.draw(|f| screen.ui(f, f.area())).unwrap();
+    }
+}
@@ -2,18 +2,18 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::mem;
+use std::{cell::Cell, mem};
 
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::Style,
-    widgets::{Block, Borders, List, ListItem, ListState},
+    widgets::{List, ListItem, ListState},
     Frame,
 };
 
-use crate::{library::track::Track, media_system::Queueable, playlist::Playlist};
+use crate::{command::Command, library::track::Track, media_system::Queueable, playlist::Playlist};
 
-use super::{MovementDirection, Screen, UIList};
+use super::{MovementDirection, Screen, Theme, UIList};
 
 #[derive(PartialEq, Eq)]
 pub enum Panel {
@@ -27,41 +27,33 @@ pub struct PlaylistScreen<'a> {
 
     pub playlist_list: UIList<'a, Playlist>,
 
+    /// The index of the playlist tracks are added to, marked in the list
+    pub selected_playlist: Option<usize>,
+
     pub panel: Panel,
 }
 
 impl<'a> PlaylistScreen<'a> {
-    pub fn new(playlists: &[Playlist], normal_style: &Style) -> Self {
-        let playlist_listitems: Vec<ListItem> = playlists
-            .iter()
-            .map(|pl| ListItem::new(pl.name.clone()))
-            .collect();
+    pub fn new(playlists: &[Playlist], theme: &Theme) -> Self {
         let mut playlist_list = UIList {
             list: playlists.to_owned(),
-            display: List::new(playlist_listitems)
-                .block(Block::default().title("Playlist").borders(Borders::ALL))
-                .style(*normal_style),
+            display: List::default()
+                .block(theme.block("Playlist"))
+                .style(theme.normal),
             state: ListState::default(),
+            view: Cell::default(),
         };
 
         let tracks: Vec<Track> = playlists
             .first()
             .map_or_else(Vec::new, |pl| pl.tracks.clone());
-        let track_listitems: Vec<ListItem> = tracks
-            .iter()
-            .map(|t| {
-                t.title.as_ref().map_or_else(
-                    || ListItem::new(t.file_path.clone()),
-                    |title| ListItem::new(title.clone()),
-                )
-            })
-            .collect();
         let mut track_list = UIList {
             list: tracks,
-            display: List::new(track_listitems)
-                .block(Block::default().title("Track").borders(Borders::ALL))
-                .style(*normal_style),
+            display: List::default()
+                .block(theme.block("Track"))
+                .style(theme.normal),
             state: ListState::default(),
+            view: Cell::default(),
         };
 
         playlist_list.state.select(Some(0));
@@ -70,6 +62,7 @@ impl<'a> PlaylistScreen<'a> {
         Self {
             track_list,
             playlist_list,
+            selected_playlist: None,
             panel: Panel::Playlists,
         }
     }
@@ -84,20 +77,19 @@ impl<'a> Screen for PlaylistScreen<'a> {
             .split(page_chunk);
 
         // Render artist list in top left
-        let mut playlist_list_state = self.playlist_list.state.clone();
-        f.render_stateful_widget(
-            self.playlist_list.display.clone(),
-            upper_chunk[0],
-            &mut playlist_list_state,
-        );
+        self.playlist_list.render(f, upper_chunk[0], |i, playlist| {
+            playlist.list_item(Some(i) == self.selected_playlist)
+        });
 
         // Render track list in bottom
-        let mut track_list_state = self.track_list.state.clone();
-        f.render_stateful_widget(
-            self.track_list.display.clone(),
-            upper_chunk[1],
-            &mut track_list_state,
-        );
+        self.track_list.render(f, upper_chunk[1], |_, track| {
+            ListItem::new(
+                track
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| track.file_path.clone()),
+            )
+        });
     }
 
     fn style_panels(&mut self, selected: &Style, unselected: &Style) {
@@ -141,13 +133,12 @@ impl<'a> Screen for PlaylistScreen<'a> {
         }
     }
 
-    fn switch_item(&mut self, direction: MovementDirection) {
-        use MovementDirection::{Bottom, Next, Prev, Top};
+    fn switch_item(&mut self, direction: MovementDirection, wrap: bool) {
         use Panel::{Playlists, Tracks};
 
-        let current_list_len = match self.panel {
-            Tracks => self.track_list.list.len(),
-            Playlists => self.playlist_list.list.len(),
+        let (current_list_len, height) = match self.panel {
+            Tracks => (self.track_list.list.len(), self.track_list.height()),
+            Playlists => (self.playlist_list.list.len(), self.playlist_list.height()),
         };
 
         if current_list_len == 0 {
@@ -159,30 +150,42 @@ impl<'a> Screen for PlaylistScreen<'a> {
             Playlists => &mut self.playlist_list.state,
         };
 
-        let mut selected = current_list_state.selected().unwrap_or_default();
+        let selected = current_list_state.selected().unwrap_or_default();
+        current_list_state.select(Some(direction.apply(
+            selected,
+            current_list_len,
+            height,
+            wrap,
+        )));
+    }
 
-        match direction {
-            Prev => {
-                if selected == 0 {
-                    selected = current_list_len - 1;
-                } else {
-                    selected -= 1;
-                }
-            }
-            Next => {
-                if selected == current_list_len - 1 {
-                    selected = 0;
-                } else {
-                    selected += 1;
-                }
-            }
-            Top => selected = 0,
-            Bottom => selected = current_list_len - 1,
+    fn goto_index(&mut self, index: usize) {
+        let (len, state) = match self.panel {
+            Panel::Tracks => (self.track_list.list.len(), &mut self.track_list.state),
+            Panel::Playlists => (self.playlist_list.list.len(), &mut self.playlist_list.state),
+        };
+        if len > 0 {
+            state.select(Some(index.min(len - 1)));
+        }
+    }
+
+    fn handle_click(&mut self, col: u16, row: u16) -> Option<Command> {
+        if self.playlist_list.contains(col, row) {
+            self.panel = Panel::Playlists;
+            self.playlist_list
+                .click(col, row)
+                .then_some(Command::SelectPlaylist)
+        } else if self.track_list.contains(col, row) {
+            self.panel = Panel::Tracks;
+            self.track_list
+                .click(col, row)
+                .then_some(Command::QueueAndPlay)
+        } else {
+            None
         }
-        current_list_state.select(Some(selected));
     }
 
-    fn update_lists(&mut self, normal_style: &Style) {
+    fn update_lists(&mut self, theme: &Theme) {
         let list = {
             let playlist_selected_index = self.playlist_list.state.selected().unwrap_or_default();
             self.playlist_list
@@ -191,25 +194,15 @@ impl<'a> Screen for PlaylistScreen<'a> {
                 .map_or_else(Vec::new, |playlist| playlist.tracks.clone())
         };
 
-        // Convert that track list into a Vec of ListItems to create a List widget
-        let listitems: Vec<ListItem> = list
-            .iter()
-            .map(|track| {
-                let title = track
-                    .title
-                    .clone()
-                    .unwrap_or_else(|| track.file_path.clone());
-                ListItem::new(title)
-            })
-            .collect();
-        let list_display = List::new(listitems)
-            .block(Block::default().title("Track").borders(Borders::ALL))
-            .style(*normal_style);
+        let list_display = List::default()
+            .block(theme.block("Track"))
+            .style(theme.normal);
         // Overwrite the track list in the UI, keeping the same ListState to preserve selected index
         self.track_list = UIList {
             list,
             display: list_display,
             state: mem::take(&mut self.track_list.state),
+            view: mem::take(&mut self.track_list.view),
         };
 
         // If selected index is past the end of the list, put it at the end of the list
@@ -230,8 +223,9 @@ impl<'a> Screen for PlaylistScreen<'a> {
         match self.panel {
             Tracks => {
                 let track_index = self.track_list.state.selected().unwrap_or_default();
-
-                if tracks_current_only {
+                if track_index >= self.track_list.list.len() {
+                    Queueable::Empty
+                } else if tracks_current_only {
                     Queueable::TrackList(vec![self.track_list.list[track_index].clone()].into())
                 } else {
                     let mut v = self.track_list.list[track_index..].to_vec();
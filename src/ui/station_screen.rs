@@ -0,0 +1,117 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::cell::Cell;
+
+use ratatui::{
+    layout::Rect,
+    style::Style,
+    widgets::{List, ListItem, ListState},
+    Frame,
+};
+
+use crate::{command::Command, media_system::Queueable, station::Station};
+
+use super::{MovementDirection, Screen, Theme, UIList};
+
+/// A single-panel list of internet radio stations
+pub struct StationScreen<'a> {
+    station_list: UIList<'a, Station>,
+}
+
+impl<'a> StationScreen<'a> {
+    pub fn new(stations: &[Station], theme: &Theme) -> Self {
+        let mut screen = Self {
+            station_list: UIList {
+                list: stations.to_vec(),
+                display: List::default(),
+                state: ListState::default(),
+                view: Cell::default(),
+            },
+        };
+        screen.update_lists(theme);
+        screen
+    }
+
+    /// Replace the listed stations
+    pub fn set_stations(&mut self, stations: &[Station], theme: &Theme) {
+        self.station_list.list = stations.to_vec();
+        self.update_lists(theme);
+    }
+
+    /// The highlighted station, if there is one
+    pub fn selected_station(&self) -> Option<&Station> {
+        self.station_list
+            .state
+            .selected()
+            .and_then(|i| self.station_list.list.get(i))
+    }
+}
+
+impl<'a> Screen for StationScreen<'a> {
+    fn ui(&self, f: &mut Frame, page_chunk: Rect) {
+        self.station_list.render(f, page_chunk, |_, station| {
+            ListItem::new(format!("{} ({})", station.name, station.url))
+        });
+    }
+
+    fn style_panels(&mut self, selected: &Style, _unselected: &Style) {
+        self.station_list.display = self.station_list.display.clone().highlight_style(*selected);
+    }
+
+    fn switch_panel(&mut self, _direction: MovementDirection) {}
+
+    fn switch_item(&mut self, direction: MovementDirection, wrap: bool) {
+        let len = self.station_list.list.len();
+        if len == 0 {
+            return;
+        }
+
+        let selected = self.station_list.state.selected().unwrap_or_default();
+        self.station_list.state.select(Some(direction.apply(
+            selected,
+            len,
+            self.station_list.height(),
+            wrap,
+        )));
+    }
+
+    fn goto_index(&mut self, index: usize) {
+        let len = self.station_list.list.len();
+        if len > 0 {
+            self.station_list.state.select(Some(index.min(len - 1)));
+        }
+    }
+
+    fn handle_click(&mut self, col: u16, row: u16) -> Option<Command> {
+        self.station_list
+            .click(col, row)
+            .then_some(Command::QueueAndPlay)
+    }
+
+    fn update_lists(&mut self, theme: &Theme) {
+        self.station_list.display = List::default()
+            .block(theme.block("Stations"))
+            .style(theme.normal);
+
+        // If selected index is past the end of the list, put it at the end of the list
+        if self.station_list.list.is_empty() {
+            self.station_list.state.select(None);
+        } else if self.station_list.state.selected().unwrap_or_default()
+            >= self.station_list.list.len()
+        {
+            self.station_list
+                .state
+                .select(Some(self.station_list.list.len() - 1));
+        } else if self.station_list.state.selected().is_none() {
+            self.station_list.state.select(Some(0));
+        }
+    }
+
+    fn get_selected(&self, _tracks_current_only: bool) -> Queueable {
+        self.selected_station().map_or(Queueable::Empty, |station| {
+            Queueable::TrackList(vec![station.track()].into())
+        })
+    }
+}
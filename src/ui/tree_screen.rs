@@ -0,0 +1,245 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{cell::Cell, collections::HashSet};
+
+use ratatui::{
+    layout::Rect,
+    style::Style,
+    widgets::{List, ListItem, ListState},
+    Frame,
+};
+
+use crate::{command::Command, library::artist::Artist, media_system::Queueable, Library};
+
+use super::{MovementDirection, Screen, Theme, UIList};
+
+/// A row in the tree, identified by its artist, album, and track indices
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Node {
+    Artist(usize),
+    Album(usize, usize),
+    Track(usize, usize, usize),
+}
+
+/// A single-panel view of the library as a collapsible tree of artists,
+/// albums, and tracks
+pub struct TreeScreen<'a> {
+    /// The artists in the library, without the "All Artists" pseudo-artist.
+    /// Each artist's first album is still the "All Albums" pseudo-album,
+    /// which is left out of the tree.
+    artists: Vec<Artist>,
+
+    /// The currently visible rows of the tree
+    node_list: UIList<'a, Node>,
+
+    /// Artists which are expanded to show their albums
+    expanded_artists: HashSet<usize>,
+
+    /// Albums which are expanded to show their tracks
+    expanded_albums: HashSet<(usize, usize)>,
+}
+
+impl<'a> TreeScreen<'a> {
+    pub fn new(library: &Library, theme: &Theme) -> Self {
+        let (artists, _) = library.tracks.get_artists_albums();
+        let artists = artists
+            .into_iter()
+            .filter(|artist| artist.name != "All Artists")
+            .collect();
+
+        let mut screen = Self {
+            artists,
+            node_list: UIList {
+                list: Vec::new(),
+                display: List::default(),
+                state: ListState::default(),
+                view: Cell::default(),
+            },
+            expanded_artists: HashSet::new(),
+            expanded_albums: HashSet::new(),
+        };
+        screen.node_list.state.select(Some(0));
+        screen.update_lists(theme);
+        screen
+    }
+
+    fn selected_node(&self) -> Option<Node> {
+        self.node_list
+            .state
+            .selected()
+            .and_then(|i| self.node_list.list.get(i).copied())
+    }
+
+    /// Whether the selected row is an artist or album that can be expanded
+    pub fn selected_collapsed(&self) -> bool {
+        match self.selected_node() {
+            Some(Node::Artist(ar)) => !self.expanded_artists.contains(&ar),
+            Some(Node::Album(ar, al)) => !self.expanded_albums.contains(&(ar, al)),
+            Some(Node::Track(..)) | None => false,
+        }
+    }
+
+    /// Expand the selected artist or album
+    pub fn expand(&mut self, theme: &Theme) {
+        match self.selected_node() {
+            Some(Node::Artist(ar)) => {
+                self.expanded_artists.insert(ar);
+            }
+            Some(Node::Album(ar, al)) => {
+                self.expanded_albums.insert((ar, al));
+            }
+            Some(Node::Track(..)) | None => return,
+        }
+        self.update_lists(theme);
+    }
+
+    /// Collapse the selected artist or album. If a track is selected, or an
+    /// album that is already collapsed, collapse its parent instead and select
+    /// it.
+    pub fn collapse(&mut self, theme: &Theme) {
+        let target = match self.selected_node() {
+            Some(Node::Artist(ar)) => Node::Artist(ar),
+            Some(Node::Album(ar, al)) if self.expanded_albums.contains(&(ar, al)) => {
+                Node::Album(ar, al)
+            }
+            Some(Node::Album(ar, _)) => Node::Artist(ar),
+            Some(Node::Track(ar, al, _)) => Node::Album(ar, al),
+            None => return,
+        };
+        match target {
+            Node::Artist(ar) => {
+                self.expanded_artists.remove(&ar);
+            }
+            Node::Album(ar, al) => {
+                self.expanded_albums.remove(&(ar, al));
+            }
+            Node::Track(..) => {}
+        }
+        self.update_lists(theme);
+        let index = self.node_list.list.iter().position(|n| *n == target);
+        self.node_list.state.select(index);
+    }
+
+    fn node_text(&self, node: Node) -> String {
+        match node {
+            Node::Artist(ar) => {
+                let marker = if self.expanded_artists.contains(&ar) {
+                    '▾'
+                } else {
+                    '▸'
+                };
+                format!("{marker} {}", self.artists[ar].name)
+            }
+            Node::Album(ar, al) => {
+                let marker = if self.expanded_albums.contains(&(ar, al)) {
+                    '▾'
+                } else {
+                    '▸'
+                };
+                format!("  {marker} {}", self.artists[ar].albums[al].name)
+            }
+            Node::Track(ar, al, tr) => {
+                format!("      {}", self.artists[ar].albums[al].tracks[tr])
+            }
+        }
+    }
+}
+
+impl<'a> Screen for TreeScreen<'a> {
+    fn ui(&self, f: &mut Frame, page_chunk: Rect) {
+        self.node_list.render(f, page_chunk, |_, node| {
+            ListItem::new(self.node_text(*node))
+        });
+    }
+
+    fn style_panels(&mut self, selected: &Style, _unselected: &Style) {
+        self.node_list.display = self.node_list.display.clone().highlight_style(*selected);
+    }
+
+    fn switch_panel(&mut self, _direction: MovementDirection) {}
+
+    fn switch_item(&mut self, direction: MovementDirection, wrap: bool) {
+        let len = self.node_list.list.len();
+        if len == 0 {
+            return;
+        }
+
+        let selected = self.node_list.state.selected().unwrap_or_default();
+        self.node_list.state.select(Some(direction.apply(
+            selected,
+            len,
+            self.node_list.height(),
+            wrap,
+        )));
+    }
+
+    fn goto_index(&mut self, index: usize) {
+        let len = self.node_list.list.len();
+        if len > 0 {
+            self.node_list.state.select(Some(index.min(len - 1)));
+        }
+    }
+
+    fn handle_click(&mut self, col: u16, row: u16) -> Option<Command> {
+        if !self.node_list.click(col, row) {
+            return None;
+        }
+        // Double clicking a collapsed row expands it, like Enter
+        Some(if self.selected_collapsed() {
+            Command::Expand
+        } else {
+            Command::QueueAndPlay
+        })
+    }
+
+    fn update_lists(&mut self, theme: &Theme) {
+        let mut list = Vec::new();
+        for (ar, artist) in self.artists.iter().enumerate() {
+            list.push(Node::Artist(ar));
+            if !self.expanded_artists.contains(&ar) {
+                continue;
+            }
+            for (al, album) in artist.albums.iter().enumerate().skip(1) {
+                list.push(Node::Album(ar, al));
+                if !self.expanded_albums.contains(&(ar, al)) {
+                    continue;
+                }
+                list.extend((0..album.tracks.len()).map(|tr| Node::Track(ar, al, tr)));
+            }
+        }
+
+        self.node_list.display = List::default()
+            .block(theme.block("Library"))
+            .style(theme.normal);
+        self.node_list.list = list;
+
+        // If selected index is past the end of the list, put it at the end of the list
+        if self.node_list.list.is_empty() {
+            self.node_list.state.select(None);
+        } else if self.node_list.state.selected().unwrap_or_default() >= self.node_list.list.len() {
+            self.node_list
+                .state
+                .select(Some(self.node_list.list.len() - 1));
+        }
+    }
+
+    fn get_selected(&self, tracks_current_only: bool) -> Queueable {
+        match self.selected_node() {
+            Some(Node::Artist(ar)) => Queueable::Artist(self.artists[ar].clone()),
+            Some(Node::Album(ar, al)) => Queueable::Album(self.artists[ar].albums[al].clone()),
+            Some(Node::Track(ar, al, tr)) => {
+                let tracks = &self.artists[ar].albums[al].tracks;
+                if tracks_current_only {
+                    Queueable::TrackList(vec![tracks[tr].clone()].into())
+                } else {
+                    let mut v = tracks[tr..].to_vec();
+                    v.append(&mut tracks[..tr].to_vec());
+                    Queueable::TrackList(v.into())
+                }
+            }
+            None => Queueable::Empty,
+        }
+    }
+}
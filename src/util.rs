@@ -2,26 +2,156 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use unicode_width::UnicodeWidthStr;
+use std::{
+    env,
+    ffi::OsString,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{BufWriter, Write as _},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serde_yml::Value;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// The directory to keep the library, playlists, and other data in. See
+/// `resolve_dir` for the fallbacks.
+pub fn resolve_data_dir() -> Result<PathBuf> {
+    resolve_dir(dirs_next::data_dir(), "XDG_DATA_HOME")
+}
+
+/// The directory to keep the config file in. See `resolve_dir` for the
+/// fallbacks.
+pub fn resolve_config_dir() -> Result<PathBuf> {
+    resolve_dir(dirs_next::config_dir(), "XDG_CONFIG_HOME")
+}
+
+/// `rmup` in the platform's directory if it has one, otherwise in the XDG
+/// directory named by the environment variable. Failing that, `.rmup` in the
+/// home directory, then in the current directory.
+fn resolve_dir(platform_dir: Option<PathBuf>, xdg_var: &str) -> Result<PathBuf> {
+    platform_dir
+        .or_else(|| {
+            env::var_os(xdg_var)
+                .map(PathBuf::from)
+                .filter(|dir| dir.is_absolute())
+        })
+        .map(|dir| dir.join("rmup"))
+        .or_else(|| dirs_next::home_dir().map(|home| home.join(".rmup")))
+        .or_else(|| env::current_dir().ok().map(|dir| dir.join(".rmup")))
+        .ok_or_else(|| {
+            anyhow!("Could not find a directory for RMuP's files. Try setting ${xdg_var} or $HOME.")
+        })
+}
+
+/// The width available for text in a panel taking up half of the terminal
+pub fn half_panel_width() -> usize {
+    let term_width = crossterm::terminal::size().unwrap_or((80, 24)).0 as usize;
+    // The 2 sides of the block take up 1 char each
+    (term_width / 2).saturating_sub(2)
+}
+
+/// Format a duration as `m:ss`, or `h:mm:ss` if it is an hour or longer
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Parse a timecode of the form `m:ss`, `h:mm:ss`, or a plain number of seconds
+pub fn parse_timecode(timecode: &str) -> Result<Duration> {
+    let invalid = || anyhow!("Invalid time: {timecode} (expected m:ss)");
+    let mut secs: u64 = 0;
+    for (i, part) in timecode.trim().split(':').enumerate() {
+        if i > 2 || part.is_empty() || (i > 0 && part.len() != 2) {
+            return Err(invalid());
+        }
+        let value: u64 = part.parse().map_err(|_| invalid())?;
+        if i > 0 && value >= 60 {
+            return Err(invalid());
+        }
+        secs = secs * 60 + value;
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// Lay out a list entry as a name followed by a right-aligned summary of the
+/// number of tracks and their total length, filling exactly `width` columns.
+/// The summary is dropped if it would leave too little room for the name.
+pub fn with_track_summary(
+    name: &str,
+    track_count: usize,
+    total_duration: Duration,
+    width: usize,
+) -> String {
+    const MIN_NAME_WIDTH: usize = 10;
+
+    let summary = format!(
+        " {} {} · {}",
+        track_count,
+        if track_count == 1 { "track" } else { "tracks" },
+        format_duration(total_duration)
+    );
+    let summary_width = UnicodeWidthStr::width(summary.as_str());
+
+    if width < summary_width + MIN_NAME_WIDTH {
+        to_width(name, width, false)
+    } else {
+        format!("{}{summary}", to_width(name, width - summary_width, false))
+    }
+}
+
+/// Show `width` columns of a string as it scrolls past, starting `offset`
+/// characters in and wrapping around to the start after a gap. Strings that
+/// fit are padded rather than scrolled.
+pub fn scroll_text(s: &str, offset: usize, width: usize) -> String {
+    if s.width() <= width {
+        return format!("{s:width$}");
+    }
+    let looped: Vec<char> = format!("{s}   ").chars().collect();
+    let mut text = String::new();
+    let mut text_width = 0;
+    for c in looped.iter().cycle().skip(offset % looped.len()) {
+        let c_width = c.width().unwrap_or(0);
+        if text_width + c_width > width {
+            break;
+        }
+        text.push(*c);
+        text_width += c_width;
+    }
+    text.push_str(&" ".repeat(width - text_width));
+    text
+}
 
 /// Make the given string exactly the width specified, truncating with elipses
 /// or adding padding as necessary.
 pub fn to_width(s: &str, width: usize, right_align: bool) -> String {
     let mut s_width = UnicodeWidthStr::width(s);
     let mut s = s.to_owned();
-    let elipses = s_width >= width - 1;
 
-    while elipses && s_width >= width - 4 {
-        s.pop();
-        s_width = UnicodeWidthStr::width(s.as_str());
-    }
+    if s_width >= width.saturating_sub(1) {
+        // Too narrow for an ellipsis, so the text is just cut short
+        let elipses = width > 4;
+        let max_width = if elipses { width - 5 } else { width };
+
+        while s_width > max_width {
+            s.pop();
+            s_width = UnicodeWidthStr::width(s.as_str());
+        }
 
-    if elipses {
-        s.push_str("...");
-        s_width = UnicodeWidthStr::width(s.as_str());
+        if elipses {
+            s.push_str("...");
+            s_width = UnicodeWidthStr::width(s.as_str());
+        }
     }
 
-    for _ in 0..(width - s_width) {
+    for _ in 0..width.saturating_sub(s_width) {
         if right_align {
             s.insert(0, ' ');
         } else {
@@ -31,3 +161,229 @@ pub fn to_width(s: &str, width: usize, right_align: bool) -> String {
 
     s
 }
+
+/// Save a file by writing it to a hidden temporary file in the same
+/// directory, then renaming that over the target. An interrupted or failed
+/// save leaves the existing file as it was.
+pub fn write_atomically<P: AsRef<Path>>(
+    file_path: P,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+) -> Result<()> {
+    let file_path = file_path.as_ref();
+    let file_name = file_path
+        .file_name()
+        .ok_or_else(|| anyhow!("Not a file path: {}", file_path.display()))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(".tmp");
+    let temp_path = file_path.with_file_name(temp_name);
+
+    let result = (|| {
+        let mut out = BufWriter::new(File::create(&temp_path)?);
+        write(&mut out)?;
+        out.flush()?;
+        out.get_ref().sync_all()?;
+        fs::rename(&temp_path, file_path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        // The temporary file is incomplete, and the target is untouched
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Write a serialized value out as JSON
+pub fn write_json(value: &Value, out: &mut String) -> Result<()> {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => write!(out, "{b}")?,
+        Value::Number(n) => write!(out, "{n}")?,
+        Value::String(s) => {
+            out.push('"');
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+        }
+        Value::Sequence(seq) => {
+            out.push('[');
+            for (i, v) in seq.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(v, out)?;
+            }
+            out.push(']');
+        }
+        Value::Mapping(map) => {
+            out.push('{');
+            for (i, (k, v)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                match k {
+                    Value::String(_) => write_json(k, out)?,
+                    _ => return Err(anyhow!("JSON object keys must be strings")),
+                }
+                out.push(':');
+                write_json(v, out)?;
+            }
+            out.push('}');
+        }
+        Value::Tagged(tagged) => write_json(&tagged.value, out)?,
+    }
+    Ok(())
+}
+
+/// An empty directory for a test to write files in, removed again when
+/// dropped
+#[cfg(test)]
+pub struct TestDir(PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    /// Make a directory named for the test, so that tests running at the
+    /// same time don't share one
+    pub fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("rmup-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Temporary directory should be writable");
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Write a short silent WAV file, which reads as a track without tags
+#[cfg(test)]
+pub fn write_wav(path: &Path) {
+    let samples: u32 = 800;
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + samples * 2).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&8000u32.to_le_bytes());
+    wav.extend_from_slice(&16000u32.to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(samples * 2).to_le_bytes());
+    wav.resize(wav.len() + samples as usize * 2, 0);
+    fs::write(path, wav).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_width_fills_exactly_the_width() {
+        for s in ["", "a", "abc", "abcdefgh", "日本語", "a日b"] {
+            for width in 0..=5 {
+                for right_align in [false, true] {
+                    let fitted = to_width(s, width, right_align);
+                    assert_eq!(fitted.width(), width, "{s:?} at width {width}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn to_width_cuts_text_short_below_ellipsis_width() {
+        assert_eq!(to_width("abc", 0, false), "");
+        assert_eq!(to_width("abc", 1, false), "a");
+        assert_eq!(to_width("abc", 2, false), "ab");
+        assert_eq!(to_width("abc", 3, false), "abc");
+        assert_eq!(to_width("abc", 4, false), "abc ");
+        assert_eq!(to_width("abcdefgh", 4, false), "abcd");
+    }
+
+    #[test]
+    fn to_width_pads_or_adds_an_ellipsis() {
+        assert_eq!(to_width("abc", 5, false), "abc  ");
+        assert_eq!(to_width("abc", 5, true), "  abc");
+        assert_eq!(to_width("abcdefgh", 5, false), "...  ");
+        assert_eq!(to_width("abcdefghijkl", 10, false), "abcde...  ");
+    }
+
+    #[test]
+    fn to_width_never_splits_wide_characters() {
+        assert_eq!(to_width("日本語", 0, false), "");
+        assert_eq!(to_width("日本語", 1, false), " ");
+        assert_eq!(to_width("日本語", 3, false), "日 ");
+        assert_eq!(to_width("日本語", 3, true), " 日");
+        assert_eq!(to_width("日本語", 5, false), "...  ");
+        assert_eq!(to_width("日本語", 10, false), "日本語    ");
+    }
+
+    #[test]
+    fn resolve_dir_prefers_the_platform_dir_then_the_xdg_var() {
+        let var = "RMUP_TEST_RESOLVE_DIR_XDG";
+        env::set_var(var, "/xdg");
+        assert_eq!(
+            resolve_dir(Some(PathBuf::from("/platform")), var).unwrap(),
+            Path::new("/platform/rmup")
+        );
+        assert_eq!(resolve_dir(None, var).unwrap(), Path::new("/xdg/rmup"));
+    }
+
+    #[test]
+    fn resolve_dir_falls_back_on_the_home_dir() {
+        let var = "RMUP_TEST_RESOLVE_DIR_HOME";
+        let home = dirs_next::home_dir().unwrap().join(".rmup");
+        env::remove_var(var);
+        assert_eq!(resolve_dir(None, var).unwrap(), home);
+
+        // Relative XDG directories are to be ignored
+        env::set_var(var, "relative/dir");
+        assert_eq!(resolve_dir(None, var).unwrap(), home);
+    }
+
+    #[test]
+    fn write_atomically_replaces_the_file() {
+        let dir = TestDir::new("write_atomically");
+        let file_path = dir.path().join("library.m3u8");
+        fs::write(&file_path, "old").unwrap();
+        write_atomically(&file_path, |out| Ok(out.write_all(b"new")?)).unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn write_atomically_failure_leaves_the_file_alone() {
+        let dir = TestDir::new("write_atomically_failure");
+        let file_path = dir.path().join("library.m3u8");
+        fs::write(&file_path, "old").unwrap();
+        let result = write_atomically(&file_path, |out| {
+            out.write_all(b"partial")?;
+            Err(anyhow!("disk full"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "old");
+        // The temporary file is cleaned up
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        assert!(write_atomically(dir.path().join(".."), |_| Ok(())).is_err());
+    }
+}
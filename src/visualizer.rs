@@ -0,0 +1,190 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Measures how loud the audio being played is across a spread of
+//! frequencies, for drawing as bars.
+
+use std::{
+    f32::consts::TAU,
+    mem,
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use rodio::{source::SeekError, Source};
+
+/// How many frequency bands the audio is split into
+pub const BAND_COUNT: usize = 16;
+
+/// How many samples are measured at a time
+const WINDOW_LEN: usize = 1024;
+
+/// The lowest and highest frequencies measured, in Hz
+const MIN_FREQ: f32 = 50.0;
+const MAX_FREQ: f32 = 16_000.0;
+
+/// How far below full scale, in dB, a band reads as empty
+const FLOOR_DB: f32 = 60.0;
+
+/// How much of its level a band keeps from one window to the next, so that
+/// the bars fall smoothly rather than flickering
+const DECAY: f32 = 0.8;
+
+/// Samples of one channel's worth of audio, mixed down from all channels
+struct Window {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+/// The level of each band, from 0.0 to 1.0, kept up to date by a background
+/// thread. Windows of samples are handed to the thread by `Tap`s, so the
+/// audio never waits on the analysis. The thread stops once the visualizer
+/// and all its taps are dropped.
+#[derive(Clone)]
+pub struct Visualizer {
+    bands: Arc<Mutex<[f32; BAND_COUNT]>>,
+    windows: SyncSender<Window>,
+}
+
+impl Visualizer {
+    pub fn start() -> Self {
+        let bands = Arc::new(Mutex::new([0.0; BAND_COUNT]));
+        // Only a few windows are held, and any more are dropped if the
+        // analysis falls behind
+        let (windows, receiver) = mpsc::sync_channel::<Window>(4);
+        let thread_bands = Arc::clone(&bands);
+        thread::spawn(move || {
+            for window in receiver {
+                let levels = analyze(&window.samples, window.sample_rate);
+                if let Ok(mut bands) = thread_bands.lock() {
+                    for (band, level) in bands.iter_mut().zip(levels) {
+                        *band = level.max(*band * DECAY);
+                    }
+                }
+            }
+        });
+        Self { bands, windows }
+    }
+
+    /// The latest level of each band, lowest frequency first
+    pub fn bands(&self) -> [f32; BAND_COUNT] {
+        self.bands.lock().map_or([0.0; BAND_COUNT], |bands| *bands)
+    }
+
+    /// Pass a source's samples through unchanged while measuring them
+    pub fn tap<S: Source<Item = i16>>(&self, source: S) -> Tap<S> {
+        Tap {
+            source,
+            windows: self.windows.clone(),
+            samples: Vec::with_capacity(WINDOW_LEN),
+            frame_sum: 0.0,
+            frame_channel: 0,
+        }
+    }
+}
+
+/// A source that forwards another's samples, sending them off in windows to
+/// be measured
+pub struct Tap<S> {
+    source: S,
+    windows: SyncSender<Window>,
+    samples: Vec<f32>,
+
+    /// The sum of the current frame's samples so far, to be mixed down
+    frame_sum: f32,
+
+    /// Which channel of the current frame is next
+    frame_channel: u16,
+}
+
+impl<S: Source<Item = i16>> Iterator for Tap<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.source.next()?;
+        let channels = self.source.channels().max(1);
+        self.frame_sum += f32::from(sample) / f32::from(i16::MAX);
+        self.frame_channel += 1;
+        if self.frame_channel >= channels {
+            self.samples.push(self.frame_sum / f32::from(channels));
+            self.frame_sum = 0.0;
+            self.frame_channel = 0;
+            if self.samples.len() == WINDOW_LEN {
+                let samples = mem::replace(&mut self.samples, Vec::with_capacity(WINDOW_LEN));
+                // Nothing is lost but a frame of bars if the window is dropped
+                let _ = self.windows.try_send(Window {
+                    samples,
+                    sample_rate: self.source.sample_rate(),
+                });
+            }
+        }
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Tap<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.samples.clear();
+        self.frame_sum = 0.0;
+        self.frame_channel = 0;
+        self.source.try_seek(pos)
+    }
+}
+
+/// The level of each band in a window of samples, measured with the
+/// Goertzel algorithm at frequencies spaced evenly on a log scale
+#[allow(clippy::cast_precision_loss)]
+fn analyze(samples: &[f32], sample_rate: u32) -> [f32; BAND_COUNT] {
+    let rate = sample_rate as f32;
+    let len = samples.len() as f32;
+    let max_freq = MAX_FREQ.min(rate * 0.45);
+    // A Hann window keeps loud bands from spilling into their neighbours
+    let windowed: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| sample * (0.5 - 0.5 * (TAU * i as f32 / (len - 1.0)).cos()))
+        .collect();
+
+    let mut levels = [0.0; BAND_COUNT];
+    for (i, level) in levels.iter_mut().enumerate() {
+        let freq = MIN_FREQ * (max_freq / MIN_FREQ).powf(i as f32 / (BAND_COUNT - 1) as f32);
+        let coeff = 2.0 * (TAU * freq / rate).cos();
+        let (mut prev, mut prev2) = (0.0, 0.0);
+        for sample in &windowed {
+            let current = sample + coeff * prev - prev2;
+            prev2 = prev;
+            prev = current;
+        }
+        let power = coeff.mul_add(-prev * prev2, prev * prev + prev2 * prev2);
+        // A full-scale sine reads as 1.0, the window halving its amplitude
+        let amplitude = power.max(0.0).sqrt() / (len / 4.0);
+        let db = 20.0 * amplitude.max(f32::MIN_POSITIVE).log10();
+        *level = ((db + FLOOR_DB) / FLOOR_DB).clamp(0.0, 1.0);
+    }
+    levels
+}
@@ -0,0 +1,264 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Watches the library's directories for audio files being added, changed,
+//! or removed. The operating system reports changes as they happen, so an
+//! idle library costs nothing; once changes stop, the directories are
+//! listed again to find which files changed.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Result;
+use async_std::{sync::Mutex, task};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{
+    command::Command,
+    library::{self, Library},
+};
+
+/// How long the directories must go without changing before the changes
+/// are sent
+const SETTLE_TIME: Duration = Duration::from_secs(5);
+
+/// Watches the library's directories, with a background thread turning the
+/// changes into `RescanPaths` commands. Changes are held back until the
+/// directories have stopped changing for a while, so that copying in a whole
+/// album results in a single command. The thread stops when the watcher is
+/// dropped.
+pub struct LibraryWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl LibraryWatcher {
+    /// Watch the directories holding the library's files as of now
+    pub fn start(library: &Library, command_queue: Arc<Mutex<VecDeque<Command>>>) -> Result<Self> {
+        let scanner = Scanner {
+            roots: library.root_dirs(),
+            extensions: library.extensions().clone(),
+            max_depth: library.max_depth(),
+        };
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            // Reading files, as rescanning them does, mustn't count as a change
+            if event.is_ok_and(|event| !event.kind.is_access()) {
+                let _ = sender.send(());
+            }
+        })?;
+        // A directory that can't be watched, such as one that has since been
+        // removed, is left out rather than stopping the others being watched
+        for root in &scanner.roots {
+            let _ = watcher.watch(root, RecursiveMode::Recursive);
+        }
+        thread::spawn(move || watch(&scanner, &events, &command_queue));
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+/// Wait for changes to settle, then send the files that changed. Ends when
+/// the watcher sending the events is dropped.
+fn watch(scanner: &Scanner, events: &Receiver<()>, command_queue: &Mutex<VecDeque<Command>>) {
+    let mut files = scanner.scan();
+    let mut pending = PendingChanges::default();
+    loop {
+        let event = if pending.is_empty() {
+            events.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            events.recv_timeout(SETTLE_TIME)
+        };
+        match event {
+            Ok(()) => pending.note(Instant::now()),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if pending.take_settled(Instant::now()) {
+            let new_files = scanner.scan();
+            let changed = changed_files(&files, &new_files);
+            files = new_files;
+            if !changed.is_empty() {
+                task::block_on(command_queue.lock()).push_back(Command::RescanPaths(changed));
+            }
+        }
+    }
+}
+
+/// The files added, modified, or removed between two scans
+fn changed_files(
+    old: &HashMap<PathBuf, SystemTime>,
+    new: &HashMap<PathBuf, SystemTime>,
+) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = new
+        .iter()
+        .filter(|&(path, modified)| old.get(path) != Some(modified))
+        .map(|(path, _)| path.clone())
+        .collect();
+    changed.extend(old.keys().filter(|path| !new.contains_key(*path)).cloned());
+    changed
+}
+
+/// Changes seen that are waiting for the directories to settle
+#[derive(Default)]
+struct PendingChanges {
+    /// When a change was last seen
+    last_change: Option<Instant>,
+}
+
+impl PendingChanges {
+    /// Whether no changes are waiting
+    const fn is_empty(&self) -> bool {
+        self.last_change.is_none()
+    }
+
+    /// Note a change seen at the given time. A file still being written
+    /// counts as changing again each time it is written to.
+    fn note(&mut self, now: Instant) {
+        self.last_change = Some(now);
+    }
+
+    /// Clear the waiting changes and return true if nothing has changed for
+    /// `SETTLE_TIME`
+    fn take_settled(&mut self, now: Instant) -> bool {
+        let settled = self
+            .last_change
+            .is_some_and(|last_change| now.duration_since(last_change) >= SETTLE_TIME);
+        if settled {
+            self.last_change = None;
+        }
+        settled
+    }
+}
+
+/// Finds the audio files under the library's directories, following
+/// symlinked directories as the library scan does
+struct Scanner {
+    roots: Vec<PathBuf>,
+    extensions: HashSet<String>,
+    max_depth: usize,
+}
+
+impl Scanner {
+    /// The modification times of the audio files under the directories
+    fn scan(&self) -> HashMap<PathBuf, SystemTime> {
+        let mut files = HashMap::new();
+        let mut visited = HashSet::new();
+        for root in &self.roots {
+            self.scan_dir(root, 0, &mut visited, &mut files);
+        }
+        files
+    }
+
+    /// Directories past the maximum depth are skipped, as are directories
+    /// already visited under another name, so that symlink cycles end
+    fn scan_dir(
+        &self,
+        dir: &Path,
+        depth: usize,
+        visited: &mut HashSet<PathBuf>,
+        files: &mut HashMap<PathBuf, SystemTime>,
+    ) {
+        if depth > self.max_depth || !fs::canonicalize(dir).is_ok_and(|dir| visited.insert(dir)) {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // Unlike the entry's own metadata, this follows symlinks
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            if metadata.is_dir() {
+                self.scan_dir(&path, depth + 1, visited, files);
+            } else if library::is_audio_file(&path, &self.extensions) {
+                if let Ok(modified) = metadata.modified() {
+                    files.insert(path, modified);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::util::TestDir;
+
+    #[test]
+    fn changes_wait_until_nothing_has_changed_for_a_while() {
+        let start = Instant::now();
+        let mut pending = PendingChanges::default();
+        pending.note(start);
+        assert!(!pending.take_settled(start + Duration::from_secs(1)));
+
+        // Changing again holds the changes back further
+        let later = start + SETTLE_TIME - Duration::from_secs(1);
+        pending.note(later);
+        assert!(!pending.take_settled(start + SETTLE_TIME));
+
+        assert!(pending.take_settled(later + SETTLE_TIME));
+        assert!(pending.is_empty());
+        assert!(!pending.take_settled(later + SETTLE_TIME * 2));
+    }
+
+    #[test]
+    fn changed_files_are_those_added_modified_or_removed() {
+        let start = SystemTime::UNIX_EPOCH;
+        let later = start + Duration::from_secs(1);
+        let old = HashMap::from([
+            (PathBuf::from("/music/kept.flac"), start),
+            (PathBuf::from("/music/modified.flac"), start),
+            (PathBuf::from("/music/removed.flac"), start),
+        ]);
+        let new = HashMap::from([
+            (PathBuf::from("/music/kept.flac"), start),
+            (PathBuf::from("/music/modified.flac"), later),
+            (PathBuf::from("/music/added.flac"), later),
+        ]);
+        let mut changed = changed_files(&old, &new);
+        changed.sort();
+        assert_eq!(
+            changed,
+            [
+                PathBuf::from("/music/added.flac"),
+                PathBuf::from("/music/modified.flac"),
+                PathBuf::from("/music/removed.flac"),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn scan_follows_symlinked_directories_once() {
+        let dir = TestDir::new("watch_scan_symlinks");
+        let music = dir.path().join("music");
+        let elsewhere = dir.path().join("elsewhere");
+        fs::create_dir(&music).unwrap();
+        fs::create_dir(&elsewhere).unwrap();
+        fs::write(music.join("a.flac"), "").unwrap();
+        fs::write(elsewhere.join("b.flac"), "").unwrap();
+        std::os::unix::fs::symlink(&elsewhere, music.join("linked")).unwrap();
+        std::os::unix::fs::symlink(&music, music.join("loop")).unwrap();
+
+        let scanner = Scanner {
+            roots: vec![music.clone()],
+            extensions: HashSet::from(["flac".to_owned()]),
+            max_depth: 32,
+        };
+        let mut files: Vec<PathBuf> = scanner.scan().into_keys().collect();
+        files.sort();
+        assert_eq!(files, [music.join("a.flac"), music.join("linked/b.flac")]);
+    }
+}